@@ -0,0 +1,61 @@
+//! Benchmarks of nearest-neighbor searches, exhaustive and through the indexes.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use vemcache::index::IndexKind;
+use vemcache::Vemcache;
+
+/// The number of vectors searched.
+const SIZE: usize = 10_000;
+
+/// The dimension of the vectors searched.
+const DIM: usize = 128;
+
+/// Returns a database of `SIZE` vectors under the `docs:` prefix, indexed with
+/// `index` if given.
+fn database(index: Option<IndexKind>) -> Vemcache {
+    let mut db = Vemcache::new();
+    let mut state = 0x853c_49e6_748f_ea9bu64;
+    for i in 0..SIZE {
+        let vector = (0..DIM)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 40) as f32 / (1u64 << 24) as f32
+            })
+            .collect();
+        db.insert_with_key(format!("docs:{}", i), vector);
+    }
+    if let Some(kind) = index {
+        db.create_index("docs:".to_string(), kind.build(db.default_metric()));
+    }
+    db
+}
+
+/// Exhaustive searches, whose cost beyond scoring is the selection of the top k.
+fn exhaustive(c: &mut Criterion) {
+    let db = database(None);
+    let query = db.get("docs:0".to_string()).unwrap();
+    let mut group = c.benchmark_group("knn_exhaustive");
+    for k in [1, 10, 100] {
+        group.bench_function(BenchmarkId::from_parameter(k), |b| {
+            b.iter(|| db.search("docs:0", black_box(&query), k, None))
+        });
+    }
+    group.finish();
+}
+
+fn indexed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("knn_index");
+    for (name, kind) in [("flat", IndexKind::Flat), ("hnsw", IndexKind::Hnsw)] {
+        let db = database(Some(kind));
+        let query = db.get("docs:0".to_string()).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| db.search("docs:0", black_box(&query), 10, None))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, exhaustive, indexed);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+//! Benchmarks of the distance kernels, one vector at a time and a block at a time.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use vemcache::metric::{Cosine, Dot, Euclidean, Metric};
+
+/// The dimensions of common embedding models.
+const DIMS: &[usize] = &[128, 384, 1536];
+
+/// The number of vectors scored by a call to `distances`.
+const BLOCK: usize = 256;
+
+/// Returns `count` vectors of `dim` components drawn from a fixed sequence.
+fn vectors(count: usize, dim: usize) -> Vec<Vec<f32>> {
+    let mut state = 0x853c_49e6_748f_ea9bu64;
+    (0..count)
+        .map(|_| {
+            (0..dim)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (state >> 40) as f32 / (1u64 << 24) as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn metrics() -> Vec<Box<dyn Metric>> {
+    vec![Box::new(Euclidean), Box::new(Cosine), Box::new(Dot)]
+}
+
+fn distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("distance");
+    for &dim in DIMS {
+        let pair = vectors(2, dim);
+        group.throughput(Throughput::Elements(dim as u64));
+        for metric in metrics() {
+            group.bench_with_input(BenchmarkId::new(metric.name(), dim), &pair, |b, pair| {
+                b.iter(|| metric.distance(black_box(&pair[0]), black_box(&pair[1])))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn distances(c: &mut Criterion) {
+    let mut group = c.benchmark_group("distances");
+    for &dim in DIMS {
+        let query = vectors(1, dim).remove(0);
+        let block = vectors(BLOCK, dim);
+        let slices = block.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        group.throughput(Throughput::Elements(BLOCK as u64));
+        for metric in metrics() {
+            let mut out = Vec::with_capacity(BLOCK);
+            group.bench_function(BenchmarkId::new(metric.name(), dim), |b| {
+                b.iter(|| {
+                    out.clear();
+                    metric.distances(black_box(&query), black_box(&slices), &mut out);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, distance, distances);
+criterion_main!(benches);
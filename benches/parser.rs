@@ -0,0 +1,38 @@
+//! Benchmarks of the parser of the text protocol.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use vemcache::commands::parse_command;
+
+fn parser(c: &mut Criterion) {
+    let values = (0..384)
+        .map(|i| format!("{:.6}", i as f32 / 384.0))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let payload = (0..384)
+        .flat_map(|i| (i as f32 / 384.0).to_le_bytes())
+        .collect::<Vec<u8>>();
+    let payload = BASE64.encode(payload);
+    let commands = [
+        ("get", "get vector1".to_string()),
+        ("knn", "knn docs:vector1 10 cosine offset 10".to_string()),
+        (
+            "named_insert_384",
+            format!("named_insert docs:vector1 {}", values),
+        ),
+        (
+            "binsert_384",
+            format!("binsert docs:vector1 384 {}", payload),
+        ),
+    ];
+    let mut group = c.benchmark_group("parse_command");
+    for (name, command) in &commands {
+        group.throughput(Throughput::Bytes(command.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| parse_command(black_box(command))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parser);
+criterion_main!(benches);
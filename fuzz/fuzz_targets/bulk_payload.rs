@@ -0,0 +1,27 @@
+//! Reads arbitrary binary payloads of the `bulk` command, whose header is taken
+//! from the first bytes of the input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vemcache::bulk::{self, BulkHeader};
+
+fuzz_target!(|data: &[u8]| {
+    let [count, dim, keyed, payload @ ..] = data else {
+        return;
+    };
+    // Single-byte sizes keep the payloads small; the server bounds them by size
+    let header = BulkHeader {
+        count: *count as usize,
+        dim: *dim as usize,
+        keyed: keyed & 1 == 1,
+    };
+    let mut reader = payload;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    if let Ok(Ok(vectors)) = runtime.block_on(bulk::read_payload(&mut reader, &header)) {
+        assert_eq!(vectors.len(), header.count);
+        assert!(vectors.iter().all(|(_, vector)| vector.len() == header.dim));
+    }
+});
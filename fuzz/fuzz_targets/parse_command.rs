@@ -0,0 +1,25 @@
+//! Parses arbitrary command lines, as received from clients of the text protocol.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vemcache::commands::{command_docs, parse_command};
+
+fuzz_target!(|data: &[u8]| {
+    // The server rejects lines that are not UTF-8 before parsing them
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(command) = parse_command(line) else {
+        return;
+    };
+    // Every command the parser accepts is documented, and the server dispatches
+    // on what it is and what it touches
+    assert!(
+        !command_docs(command.name()).is_empty(),
+        "{} is not documented",
+        command.name()
+    );
+    let _ = command.keys();
+    let _ = command.required_role();
+});
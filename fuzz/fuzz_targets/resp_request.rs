@@ -0,0 +1,33 @@
+//! Reads arbitrary RESP requests: a `*<count>` header line followed by the bytes
+//! of the bulk strings.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vemcache::limits::DEFAULT_MAX_LINE_LENGTH;
+use vemcache::resp;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(end) = data.iter().position(|&byte| byte == b'\n') else {
+        return;
+    };
+    let Ok(header) = std::str::from_utf8(&data[..=end]) else {
+        return;
+    };
+    if !header.starts_with('*') {
+        return;
+    }
+    let mut reader = &data[end + 1..];
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let request = runtime.block_on(resp::read_request(
+        &mut reader,
+        header,
+        DEFAULT_MAX_LINE_LENGTH,
+    ));
+    if let Ok(Ok(line)) = request {
+        // The request is turned into a command line the text parser handles
+        let _ = vemcache::commands::parse_command(&line);
+    }
+});
@@ -0,0 +1,98 @@
+//! Access control: the roles clients are granted by authenticating with a token.
+//!
+//! Without tokens, every client may run every command. Once `acl_tokens` lists
+//! tokens, each bound to a role, clients must authenticate with `auth <token>`
+//! before running anything but `auth`, `hello`, `help` and `health`, and may then
+//! only run the commands their role allows:
+//!
+//! - `reader` may read and search the database;
+//! - `writer` may also change the vectors, indexes, projections and pipelines;
+//! - `admin` may also manage the server and write files on it, with `dump` and
+//!   `export`.
+
+/// The roles a client can be granted, from the least to the most privileged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Reads and searches the database.
+    Reader,
+    /// Also changes the contents of the database.
+    Writer,
+    /// Also manages the server.
+    Admin,
+}
+
+impl Role {
+    /// Returns the role named `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reader" => Some(Role::Reader),
+            "writer" => Some(Role::Writer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the role.
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Writer => "writer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// Splits an entry of `acl_tokens`, written `role:token`, into its role and token.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::acl::{self, Role};
+///
+/// assert_eq!(acl::parse_entry("reader:s3cret"), Some((Role::Reader, "s3cret")));
+/// assert_eq!(acl::parse_entry("owner:s3cret"), None);
+/// assert_eq!(acl::parse_entry("admin:"), None);
+/// ```
+pub fn parse_entry(entry: &str) -> Option<(Role, &str)> {
+    let (role, token) = entry.split_once(':')?;
+    let role = Role::from_name(role)?;
+    Some((role, token)).filter(|(_, token)| !token.is_empty())
+}
+
+/// Returns the role granted by `token` according to the `role:token` entries of
+/// `acl_tokens`, or `None` if it is not one of them.
+pub fn authenticate(entries: &[String], token: &str) -> Option<Role> {
+    entries
+        .iter()
+        .filter_map(|entry| parse_entry(entry))
+        // Every entry is compared, so the time taken does not tell which one matched
+        .fold(None, |granted, (role, expected)| {
+            match constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+                true => granted.max(Some(role)),
+                false => granted,
+            }
+        })
+}
+
+/// Checks that a client granted `role`, or `None` if it has not authenticated, may
+/// run a command requiring `required`.
+///
+/// # Errors
+///
+/// Returns the error sent to the client if it may not.
+pub fn authorize(role: Option<Role>, required: Option<Role>) -> Result<(), String> {
+    match (role, required) {
+        (_, None) => Ok(()),
+        (None, Some(_)) => Err("NOAUTH Authentication required".to_string()),
+        (Some(role), Some(required)) if role < required => Err(format!(
+            "NOPERM This command requires the {} role",
+            required.name()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Compares two byte strings in a time that only depends on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,61 @@
+//! The audit log, an append-only file recording every command that changes the
+//! database or the server.
+//!
+//! Every entry is a line holding the Unix time with microseconds, the database
+//! selected by the client and its address, and the command, like the lines streamed
+//! by `monitor`:
+//!
+//! ```text
+//! 1700000000.123456 [0 127.0.0.1:52210] named_insert docs:1 0.1 0.2 0.3
+//! ```
+//!
+//! Commands are recorded once accepted, before they run, so commands that fail are
+//! recorded as well. Requests to the REST API are recorded as their method and path.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An append-only file recording the commands that change the database or the server.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens the audit log at `path`, creating it if it does not exist. Entries are
+    /// appended to the ones already in the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened for appending.
+    pub fn open(path: &str) -> IoResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records a command received from `client` while `database` was selected.
+    /// Errors are reported on the standard output rather than failing the command.
+    pub fn record(&self, client: SocketAddr, database: usize, command: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // Line breaks in the command would forge entries
+        let command = command.replace(['\r', '\n'], " ");
+        let line = format!(
+            "{}.{:06} [{} {}] {}\n",
+            now.as_secs(),
+            now.subsec_micros(),
+            database,
+            client,
+            command
+        );
+        // A single write per entry keeps entries whole when other processes append
+        if let Err(err) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            println!("Error writing to the audit log: {}", err);
+        }
+    }
+}
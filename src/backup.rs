@@ -0,0 +1,405 @@
+//! Backups: snapshots of every database written to `backup_dir`, on a schedule or
+//! with `backup now`, of which only the most recent `backup_retention` are kept.
+//!
+//! Backups of the first database are written to `backup_dir` itself, and those of
+//! the others to its `db<index>` subdirectories, like in the data directory. Every
+//! backup is a zstd-compressed dump named after the UTC time it was taken, e.g.
+//! `backup-20240102T030405Z.json.zst`, saved along with the indexes, projections
+//! and pipelines of the database, so restoring one only takes copying it to the
+//! data directory.
+//!
+//! With `backup_deltas` set, only one backup in `backup_deltas + 1` is a full
+//! one; the others are delta backups, holding the vectors inserted and removed
+//! since the last full backup, named after it and the time they were taken, e.g.
+//! `backup-20240102T030405Z.delta-20240102T040405Z.json.zst`. A delta backup is
+//! restored by loading its full backup, then the deltas of it up to this one, in
+//! order. The first backup after a restart is always a full one, since changes are
+//! only recorded in memory.
+
+use crate::config::Config;
+use crate::persistence;
+#[cfg(feature = "s3")]
+use crate::s3;
+use crate::vemcache::{
+    DeltaSnapshot, Snapshot, INDEX_FILE_SUFFIX, PIPELINE_FILE_SUFFIX, PROJECTION_FILE_SUFFIX,
+    TTL_FILE_SUFFIX,
+};
+use crate::Vemcache;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// The number of backups of every database kept when none is configured.
+pub const DEFAULT_BACKUP_RETENTION: usize = 7;
+
+/// The prefix of the names of backups.
+const BACKUP_PREFIX: &str = "backup-";
+
+/// The extension of backups.
+const BACKUP_EXTENSION: &str = ".json.zst";
+
+/// What separates the time of the full backup a delta backup was taken against
+/// from its own in its name.
+const DELTA_INFIX: &str = ".delta-";
+
+/// The suffixes of the files holding the indexes, projections, pipelines and TTLs
+/// saved along with a backup.
+pub const SIDECAR_SUFFIXES: [&str; 4] = [
+    INDEX_FILE_SUFFIX,
+    PROJECTION_FILE_SUFFIX,
+    PIPELINE_FILE_SUFFIX,
+    TTL_FILE_SUFFIX,
+];
+
+/// Returns the name of a backup taken at `time`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use vemcache::backup::backup_name;
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_704_164_645);
+/// assert_eq!(backup_name(time), "backup-20240102T030405Z.json.zst");
+/// ```
+pub fn backup_name(time: SystemTime) -> String {
+    format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        utc_timestamp(time),
+        BACKUP_EXTENSION
+    )
+}
+
+/// Returns the name of a delta backup taken at `time` against the full backup
+/// named `base`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use vemcache::backup::{delta_base, delta_name};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_704_168_245);
+/// let name = delta_name("backup-20240102T030405Z.json.zst", time);
+/// assert_eq!(name, "backup-20240102T030405Z.delta-20240102T040405Z.json.zst");
+/// assert_eq!(delta_base(&name).unwrap(), "backup-20240102T030405Z.json.zst");
+/// assert_eq!(delta_base("backup-20240102T030405Z.json.zst"), None);
+/// ```
+pub fn delta_name(base: &str, time: SystemTime) -> String {
+    format!(
+        "{}{}{}{}",
+        base.strip_suffix(BACKUP_EXTENSION).unwrap_or(base),
+        DELTA_INFIX,
+        utc_timestamp(time),
+        BACKUP_EXTENSION
+    )
+}
+
+/// Returns the name of the full backup the delta backup named `name` was taken
+/// against, or `None` if `name` is not the name of a delta backup.
+pub fn delta_base(name: &str) -> Option<String> {
+    let (base, _) = name
+        .strip_suffix(BACKUP_EXTENSION)?
+        .split_once(DELTA_INFIX)?;
+    Some(format!("{}{}", base, BACKUP_EXTENSION))
+}
+
+/// Returns the key backups are sorted by, oldest first, with the delta backups of
+/// every full backup right after it.
+pub fn backup_order(name: &str) -> (&str, &str) {
+    let stem = name.strip_suffix(BACKUP_EXTENSION).unwrap_or(name);
+    stem.split_once(DELTA_INFIX).unwrap_or((stem, ""))
+}
+
+/// Returns how many of `backups`, sorted by `backup_order`, precede the last
+/// `retention` full backups and should be removed, or 0 if `retention` is 0.
+/// Delta backups go along with the full backup they were taken against.
+pub fn expired<S: AsRef<str>>(backups: &[S], retention: usize) -> usize {
+    let full = backups
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| delta_base(name.as_ref()).is_none())
+        .map(|(position, _)| position)
+        .collect::<Vec<usize>>();
+    match full.len().checked_sub(retention) {
+        Some(excess) if retention > 0 && excess > 0 => full[excess],
+        _ => 0,
+    }
+}
+
+/// Returns `true` if `name` is the file name of a backup.
+pub fn is_backup_name(name: &str) -> bool {
+    name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_EXTENSION)
+}
+
+/// Formats `time` as a UTC timestamp in the ISO 8601 basic format, e.g.
+/// `20240102T030405Z`.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_date(secs / 86400);
+    let secs = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parses a UTC timestamp in the ISO 8601 basic format written by `utc_timestamp`,
+/// or in the extended format, e.g. `2024-01-02T03:04:05Z`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use vemcache::backup::parse_utc_timestamp;
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_704_164_645);
+/// assert_eq!(parse_utc_timestamp("20240102T030405Z"), Some(time));
+/// assert_eq!(parse_utc_timestamp("2024-01-02T03:04:05Z"), Some(time));
+/// assert_eq!(parse_utc_timestamp("2024-01-02"), None);
+/// ```
+pub fn parse_utc_timestamp(text: &str) -> Option<SystemTime> {
+    let basic = text.replace(['-', ':'], "");
+    let (date, time) = basic.strip_suffix('Z')?.split_once('T')?;
+    if date.len() != 8
+        || time.len() != 6
+        || !date
+            .bytes()
+            .chain(time.bytes())
+            .all(|byte| byte.is_ascii_digit())
+    {
+        return None;
+    }
+    let number = |digits: &str| digits.parse::<u64>().ok();
+    let (year, month, day) = (
+        number(&date[..4])?,
+        number(&date[4..6])?,
+        number(&date[6..])?,
+    );
+    let (hours, minutes, seconds) = (
+        number(&time[..2])?,
+        number(&time[2..4])?,
+        number(&time[4..])?,
+    );
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 59
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Days past the end of their month would silently roll over
+    if civil_date(days) != (year, month, day) {
+        return None;
+    }
+    let secs = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + std::time::Duration::from_secs(secs))
+}
+
+/// Converts a number of days since the Unix epoch into a year, month and day.
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Counted from 0000-03-01, so leap days end the year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+    (year, month, day)
+}
+
+/// Converts a year, month and day into a number of days since the Unix epoch, the
+/// inverse of `civil_date`.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - (month <= 2) as u64;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the backups in `dir`, sorted by `backup_order`.
+pub fn list_backups(dir: &Path) -> IoResult<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_backup = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_backup_name);
+        if is_backup {
+            backups.push(path);
+        }
+    }
+    // The names hold the time the backups were taken, in an order that sorts
+    backups.sort_by(|a, b| {
+        backup_order(&a.to_string_lossy()).cmp(&backup_order(&b.to_string_lossy()))
+    });
+    Ok(backups)
+}
+
+/// Returns the name of the newest full backup in `dir`, if fewer than `deltas`
+/// delta backups were taken against it.
+fn current_base(dir: &Path, deltas: usize) -> IoResult<Option<String>> {
+    let names = list_backups(dir)?
+        .iter()
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .collect::<Vec<String>>();
+    let Some(position) = names.iter().rposition(|name| delta_base(name).is_none()) else {
+        return Ok(None);
+    };
+    // Only its deltas sort after the newest full backup
+    let taken = names.len() - position - 1;
+    Ok((taken < deltas).then(|| names[position].clone()))
+}
+
+/// Removes the oldest backups in `dir` along with the files saved with them, so
+/// that at most `retention` full backups remain, along with their delta backups,
+/// unless `retention` is 0. Returns the backups removed.
+pub fn rotate(dir: &Path, retention: usize) -> IoResult<Vec<PathBuf>> {
+    let mut backups = list_backups(dir)?;
+    let names = backups
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    backups.truncate(expired(&names, retention));
+    for backup in &backups {
+        fs::remove_file(backup)?;
+        for suffix in SIDECAR_SUFFIXES {
+            let mut sidecar = backup.clone().into_os_string();
+            sidecar.push(suffix);
+            match fs::remove_file(sidecar) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+    }
+    Ok(backups)
+}
+
+/// A backup taken while a database is locked, to be written afterwards.
+enum Backup {
+    Full(Snapshot),
+    Delta(DeltaSnapshot),
+}
+
+/// Backs up every database that is not empty into `backup_dir`, then removes the
+/// backups exceeding `backup_retention`. A delta backup is taken instead of a full
+/// one when `backup_deltas` allows it and the changes since the last full backup
+/// were recorded. With an S3 bucket configured, every backup
+/// is then pushed to the bucket, where old backups are removed as well. Returns
+/// the paths of the new backups.
+///
+/// Every database is snapshotted while it is locked, then written on a blocking
+/// thread, so clients are only held up while the snapshot is taken.
+///
+/// # Errors
+///
+/// Returns an error if no backup directory is configured, or if a backup cannot
+/// be written or pushed or an old one cannot be removed; the backups written so
+/// far are kept.
+pub async fn back_up(
+    databases: &[Arc<Mutex<Vemcache>>],
+    config: &RwLock<Config>,
+) -> IoResult<Vec<PathBuf>> {
+    let (backup_dir, retention, deltas, data_dir) = {
+        let config = config.read().unwrap();
+        (
+            config.backup_dir.clone(),
+            config.backup_retention,
+            config.backup_deltas,
+            config.data_dir.clone(),
+        )
+    };
+    let backup_dir = backup_dir
+        .map(PathBuf::from)
+        .ok_or_else(|| std::io::Error::other("No backup directory is configured"))?;
+    #[cfg(feature = "s3")]
+    let bucket = s3::Bucket::from_config(&config.read().unwrap())
+        .map_err(std::io::Error::other)?
+        .map(Arc::new);
+    let now = SystemTime::now();
+    let name = backup_name(now);
+    let mut backups = Vec::new();
+    for (index, db) in databases.iter().enumerate() {
+        let dir = persistence::database_dir(&backup_dir, index);
+        let base = match deltas {
+            0 => None,
+            deltas => current_base(&dir, deltas)?,
+        };
+        let (backup, path, seq) = {
+            let mut db = db.lock().await;
+            if db.is_empty() {
+                continue;
+            }
+            let seq = db.last_seq();
+            match base.and_then(|base| Some((db.delta_snapshot(&base)?, base))) {
+                Some((delta, base)) => {
+                    (Backup::Delta(delta), dir.join(delta_name(&base, now)), seq)
+                }
+                None => {
+                    let snapshot = db.snapshot_with_indexes()?;
+                    db.track_delta(Some(name.as_str()).filter(|_| deltas > 0));
+                    (Backup::Full(snapshot), dir.join(&name), seq)
+                }
+            }
+        };
+        let written = path.clone();
+        #[cfg(feature = "s3")]
+        let bucket = bucket.clone();
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&dir)?;
+            match backup {
+                Backup::Full(snapshot) => snapshot.dump(&path.to_string_lossy())?,
+                Backup::Delta(delta) => delta.dump(&path.to_string_lossy())?,
+            }
+            rotate(&dir, retention)?;
+            #[cfg(feature = "s3")]
+            if let Some(bucket) = bucket {
+                bucket
+                    .push_backup(index, &path, retention)
+                    .map_err(std::io::Error::other)?;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap_or_else(|err| Err(std::io::Error::other(err)))?;
+        // Backups written to the data directory are restored like dumps
+        let restored = data_dir.as_deref().is_some_and(|data_dir| {
+            persistence::is_restored_from(Path::new(data_dir), index, &written)
+        });
+        if restored {
+            if let Err(err) = db.lock().await.compact_wal(seq) {
+                println!("Could not compact the write-ahead log: {}", err);
+            }
+        }
+        backups.push(written);
+    }
+    Ok(backups)
+}
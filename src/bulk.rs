@@ -0,0 +1,81 @@
+use std::io::Result as IoResult;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The maximum size of the binary payload of a single `bulk` command, in bytes.
+pub const MAX_PAYLOAD_SIZE: usize = 256 * 1024 * 1024;
+
+/// The header of a `bulk` command, describing the binary payload that follows it.
+///
+/// The payload holds `count` vectors of `dim` little-endian `f32` components each.
+/// If `keyed` is set, every vector is preceded by its key, encoded as a little-endian
+/// `u16` length followed by that many bytes of UTF-8.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BulkHeader {
+    /// The number of vectors in the payload.
+    pub count: usize,
+    /// The number of components of every vector.
+    pub dim: usize,
+    /// Whether every vector is preceded by its key.
+    pub keyed: bool,
+}
+
+impl BulkHeader {
+    /// Returns the size of the payload without keys, in bytes, or `None` on overflow.
+    pub fn vector_bytes(&self) -> Option<usize> {
+        self.count.checked_mul(self.dim)?.checked_mul(4)
+    }
+}
+
+/// Reads the payload described by `header` from `reader`.
+///
+/// Components are read straight into the memory of the vectors that end up in
+/// storage, without going through text parsing or an intermediate buffer. Keys are
+/// `None` unless the payload is keyed.
+///
+/// The whole payload is always consumed so the connection stays in sync with the
+/// client. The inner error describes a payload that was read but is invalid, such
+/// as a key that is empty or is not UTF-8.
+///
+/// # Errors
+///
+/// Returns an error if the payload cannot be read from `reader`.
+pub async fn read_payload<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    header: &BulkHeader,
+) -> IoResult<Result<Vec<(Option<String>, Vec<f32>)>, String>> {
+    let mut vectors = Vec::with_capacity(header.count);
+    let mut error = None;
+    for _ in 0..header.count {
+        let key = if header.keyed {
+            let len = reader.read_u16_le().await? as usize;
+            let mut key = vec![0; len];
+            reader.read_exact(&mut key).await?;
+            match String::from_utf8(key) {
+                Ok(key) if !key.is_empty() => Some(key),
+                _ => {
+                    error.get_or_insert_with(|| "Invalid key in bulk payload".to_string());
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut vector = vec![0f32; header.dim];
+        // SAFETY: the byte slice covers exactly the memory of `vector`, and every bit
+        // pattern is a valid `f32`.
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(vector.as_mut_ptr().cast::<u8>(), vector.len() * 4)
+        };
+        reader.read_exact(bytes).await?;
+        // The payload is little-endian, which makes this a no-op on most platforms
+        for value in vector.iter_mut() {
+            *value = f32::from_bits(u32::from_le(value.to_bits()));
+        }
+        vectors.push((key, vector));
+    }
+    Ok(match error {
+        Some(error) => Err(error),
+        None => Ok(vectors),
+    })
+}
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::io::Result as IoResult;
+
+use crate::wal::Wal;
+
+/// The default number of changes retained in memory before the oldest are discarded.
+pub const DEFAULT_CHANGELOG_CAPACITY: usize = 10_000;
+
+/// A single mutation recorded in the change log.
+///
+/// The `command` is stored in the same text form accepted by the server, so a
+/// consumer can replay it verbatim against another Vemcache instance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// The monotonically increasing sequence number of the change, starting at 1.
+    pub seq: u64,
+    /// The write command that reproduces the change.
+    pub command: String,
+}
+
+/// A bounded, replayable log of every mutation applied to the database.
+///
+/// Each change is assigned a sequence number that is strictly greater than the
+/// previous one. Consumers remember the last sequence number they processed and
+/// ask for the changes since it, allowing them to resume after a disconnect
+/// without missing mutations, as long as the log has not wrapped past them.
+///
+/// When a write-ahead log is attached, every change is also appended to it so it
+/// survives a restart. Once a change cannot be appended, the error is kept, so the
+/// server can stop accepting writes that would not survive a restart either.
+pub struct ChangeLog {
+    entries: VecDeque<Change>,
+    capacity: usize,
+    last_seq: u64,
+    wal: Option<Wal>,
+    wal_error: Option<String>,
+}
+
+impl ChangeLog {
+    /// Creates an empty change log retaining at most `capacity` changes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity,
+            last_seq: 0,
+            wal: None,
+            wal_error: None,
+        }
+    }
+
+    /// Attaches a write-ahead log to which every subsequent change is appended.
+    pub fn attach_wal(&mut self, wal: Wal) {
+        self.wal = Some(wal);
+    }
+
+    /// Waits until every change appended to the write-ahead log, if any, has reached
+    /// the disk.
+    pub fn sync_wal(&mut self) -> IoResult<()> {
+        match &mut self.wal {
+            Some(wal) => wal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the changes before `seq` from the write-ahead log, if any, once a
+    /// snapshot holding every change up to it is written. See `Wal::compact`.
+    pub fn compact_wal(&mut self, seq: u64) -> IoResult<()> {
+        match &mut self.wal {
+            Some(wal) => wal.compact(seq),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends a change to the log and returns its sequence number.
+    ///
+    /// If the log is full, the oldest change is discarded.
+    pub fn append(&mut self, command: String) -> u64 {
+        self.last_seq += 1;
+        if let Some(wal) = &mut self.wal {
+            if let Err(err) = wal.append(self.last_seq, &command) {
+                println!("Error writing to the write-ahead log: {}", err);
+                self.wal_error.get_or_insert(err.to_string());
+            }
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        if self.capacity > 0 {
+            self.entries.push_back(Change {
+                seq: self.last_seq,
+                command,
+            });
+        }
+        self.last_seq
+    }
+
+    /// Returns the error of the first change that could not be appended to the
+    /// write-ahead log, if any.
+    pub fn wal_error(&self) -> Option<&str> {
+        self.wal_error.as_deref()
+    }
+
+    /// Returns the sequence number of the most recent change, or 0 if nothing has been logged.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Changes the number of retained changes, discarding the oldest ones if the
+    /// log currently holds more than `capacity`.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Discards every retained change and continues numbering after `seq`.
+    ///
+    /// Used after restoring the database from disk, so sequence numbers keep
+    /// increasing across restarts.
+    pub fn resume_from(&mut self, seq: u64) {
+        self.entries.clear();
+        self.last_seq = seq;
+    }
+
+    /// Discards every retained change while keeping the sequence number.
+    ///
+    /// Consumers that have not caught up will be told to resynchronize.
+    pub fn truncate(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns every change with a sequence number strictly greater than `seq`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the oldest sequence number still available if changes after `seq`
+    /// have already been discarded, meaning the consumer can no longer resume
+    /// from `seq` and must resynchronize from a full dump.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::changelog::ChangeLog;
+    ///
+    /// let mut log = ChangeLog::new(2);
+    /// for key in ["a", "b", "c"] {
+    ///     log.append(format!("remove {}", key));
+    /// }
+    ///
+    /// assert_eq!(log.since(1).unwrap().len(), 2);
+    /// assert_eq!(log.since(0), Err(2));
+    /// assert!(log.since(u64::MAX).unwrap().is_empty());
+    /// ```
+    pub fn since(&self, seq: u64) -> Result<Vec<&Change>, u64> {
+        let oldest = self.last_seq - self.entries.len() as u64 + 1;
+        if seq.saturating_add(1) < oldest {
+            return Err(oldest);
+        }
+        Ok(self
+            .entries
+            .iter()
+            .filter(|change| change.seq > seq)
+            .collect())
+    }
+}
@@ -0,0 +1,266 @@
+//! The versioned, checksummed format of database dumps.
+//!
+//! A dump starts with a header made of `MAGIC` and the version of its format as a
+//! 32-bit little-endian integer, then, since version 3, the sequence number of the
+//! last change it holds as a 64-bit little-endian integer and, since version 4, its
+//! flags as a 32-bit little-endian integer (see `Header`). The header is followed
+//! by the JSON document, and the dump ends with a trailer holding the length of
+//! the JSON document as a 64-bit little-endian integer and its CRC32 as a 32-bit
+//! little-endian integer. The trailer tells a
+//! truncated or corrupted dump from a valid one, so it is rejected with a clear
+//! error rather than loaded partially or reported as invalid JSON.
+//!
+//! The framing applies to the JSON document, before compression and encryption.
+//! Dumps written before it was introduced are plain JSON documents, which are
+//! still loaded as version 1 of the format.
+
+use flate2::Crc;
+use std::io::{Error, ErrorKind, Read, Result as IoResult, Write};
+
+/// The bytes a dump starts with.
+pub const MAGIC: &[u8] = b"VEMDUMP\0";
+
+/// The version of the format of the dumps written.
+pub const FORMAT_VERSION: u32 = 4;
+
+/// The first version of the format whose header holds a sequence number.
+const SEQ_VERSION: u32 = 3;
+
+/// The first version of the format whose header holds flags.
+const FLAGS_VERSION: u32 = 4;
+
+/// The flag of the dumps that only hold part of a database.
+const PARTIAL: u32 = 1;
+
+/// What the header of a dump tells about its JSON document.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Header {
+    /// The sequence number of the last change the dump holds, or 0 if it holds
+    /// none, is not a snapshot of a database, or was written before dumps
+    /// recorded it.
+    pub seq: u64,
+    /// Whether the dump only holds part of a database, like the vectors under a
+    /// prefix, so it cannot be restored as a snapshot of the database.
+    pub partial: bool,
+}
+
+/// The length of the trailer of a dump, in bytes.
+const TRAILER_LENGTH: usize = 12;
+
+/// Writes a dump to `output` with `header`, whose JSON document is written through
+/// `write`.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+/// use vemcache::checksum::{self, Header};
+///
+/// let mut dump = Vec::new();
+/// let header = Header { seq: 42, partial: false };
+/// checksum::write(&mut dump, header, |json| json.write_all(b"{}")).unwrap();
+/// assert!(dump.starts_with(checksum::MAGIC));
+/// assert_eq!(checksum::read_header(&dump[..]).unwrap(), header);
+///
+/// let json = checksum::read(&dump[..], |json| {
+///     let mut text = String::new();
+///     json.read_to_string(&mut text)?;
+///     Ok(text)
+/// });
+/// assert_eq!(json.unwrap(), "{}");
+///
+/// dump.truncate(dump.len() - 1);
+/// assert!(checksum::read(&dump[..], |json| std::io::copy(json, &mut std::io::sink())).is_err());
+/// ```
+pub fn write<F>(output: &mut dyn Write, header: Header, write: F) -> IoResult<()>
+where
+    F: FnOnce(&mut dyn Write) -> IoResult<()>,
+{
+    let flags = if header.partial { PARTIAL } else { 0 };
+    output.write_all(MAGIC)?;
+    output.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    output.write_all(&header.seq.to_le_bytes())?;
+    output.write_all(&flags.to_le_bytes())?;
+    let mut writer = ChecksumWriter {
+        inner: output,
+        crc: Crc::new(),
+        length: 0,
+    };
+    write(&mut writer)?;
+    let ChecksumWriter { inner, crc, length } = writer;
+    inner.write_all(&length.to_le_bytes())?;
+    inner.write_all(&crc.sum().to_le_bytes())
+}
+
+/// Reads a dump from `input`, whose JSON document is read through `read`, then
+/// checks that it is complete and intact. Dumps without a header are read as they
+/// are.
+///
+/// # Errors
+///
+/// Returns an error if the dump is truncated or corrupted, even if `read` failed
+/// because of it, if its format is newer than this build supports, or if `read`
+/// fails.
+pub fn read<T, R, F>(mut input: R, read: F) -> IoResult<T>
+where
+    R: Read,
+    F: FnOnce(&mut dyn Read) -> IoResult<T>,
+{
+    let mut header = [0; MAGIC.len() + 4];
+    let mut length = 0;
+    while length < header.len() {
+        match input.read(&mut header[length..])? {
+            0 => break,
+            n => length += n,
+        }
+    }
+    if !header[..length].starts_with(MAGIC) {
+        // A dump written before the format was versioned
+        return read(&mut std::io::Cursor::new(&header[..length]).chain(input));
+    }
+    if length < header.len() {
+        return Err(truncated());
+    }
+    let version = u32::from_le_bytes(header[MAGIC.len()..].try_into().unwrap());
+    if version > FORMAT_VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "The dump has format version {}, but this build only reads up to version {}",
+                version, FORMAT_VERSION
+            ),
+        ));
+    }
+    let fields = match version {
+        version if version >= FLAGS_VERSION => 12,
+        version if version >= SEQ_VERSION => 8,
+        _ => 0,
+    };
+    let mut fields = vec![0; fields];
+    input.read_exact(&mut fields).map_err(|_| truncated())?;
+
+    let mut reader = ChecksumReader {
+        inner: input,
+        crc: Crc::new(),
+        length: 0,
+        pending: Vec::new(),
+        verified: false,
+    };
+    let result = read(&mut reader);
+    // A corrupted document is reported as such rather than as invalid JSON
+    let verified = std::io::copy(&mut reader, &mut std::io::sink());
+    match (result, verified) {
+        (_, Err(err)) => Err(err),
+        (result, Ok(_)) => result,
+    }
+}
+
+/// Reads the header of the dump in `input`. The fields a dump written before the
+/// header held them are left to their default.
+///
+/// # Errors
+///
+/// Returns an error if the header cannot be read or is truncated.
+pub fn read_header<R: Read>(input: R) -> IoResult<Header> {
+    let mut header = Vec::new();
+    input
+        .take((MAGIC.len() + 4 + 8 + 4) as u64)
+        .read_to_end(&mut header)?;
+    let Some(rest) = header.strip_prefix(MAGIC) else {
+        return Ok(Header::default());
+    };
+    let version = rest.get(..4).ok_or_else(truncated)?;
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    let field = |range: std::ops::Range<usize>| rest.get(range).ok_or_else(truncated);
+    let mut parsed = Header::default();
+    if version >= SEQ_VERSION {
+        parsed.seq = u64::from_le_bytes(field(4..12)?.try_into().unwrap());
+    }
+    if version >= FLAGS_VERSION {
+        let flags = u32::from_le_bytes(field(12..16)?.try_into().unwrap());
+        parsed.partial = flags & PARTIAL != 0;
+    }
+    Ok(parsed)
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::InvalidData, "The dump is truncated")
+}
+
+/// A writer computing the length and CRC32 of what is written through it.
+struct ChecksumWriter<'a> {
+    inner: &'a mut dyn Write,
+    crc: Crc,
+    length: u64,
+}
+
+impl Write for ChecksumWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc.update(&buf[..written]);
+        self.length += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader returning the JSON document of a dump, which checks the trailer once
+/// the end of the dump is reached.
+struct ChecksumReader<R> {
+    inner: R,
+    crc: Crc,
+    length: u64,
+    // The bytes read but not returned yet, which may be part of the trailer
+    pending: Vec<u8>,
+    verified: bool,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Checks the trailer left in `pending` once the end of the dump is reached.
+    fn verify(&mut self) -> IoResult<()> {
+        if self.pending.len() < TRAILER_LENGTH {
+            return Err(truncated());
+        }
+        let length = u64::from_le_bytes(self.pending[..8].try_into().unwrap());
+        let crc = u32::from_le_bytes(self.pending[8..].try_into().unwrap());
+        if length > self.length {
+            return Err(truncated());
+        }
+        if length != self.length || crc != self.crc.sum() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "The dump is corrupted, its checksum does not match",
+            ));
+        }
+        self.verified = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        loop {
+            if self.pending.len() > TRAILER_LENGTH || buf.is_empty() {
+                let length = buf
+                    .len()
+                    .min(self.pending.len().saturating_sub(TRAILER_LENGTH));
+                buf[..length].copy_from_slice(&self.pending[..length]);
+                self.pending.drain(..length);
+                self.crc.update(&buf[..length]);
+                self.length += length as u64;
+                return Ok(length);
+            }
+            if self.verified {
+                return Ok(0);
+            }
+            let mut chunk = [0; 8192];
+            match self.inner.read(&mut chunk)? {
+                0 => self.verify()?,
+                n => self.pending.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
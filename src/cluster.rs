@@ -0,0 +1,93 @@
+use uuid::Uuid;
+
+/// The number of points each node occupies on the hash ring.
+///
+/// Spreading every node over many points keeps the keyspace evenly balanced and
+/// limits how many keys move when a node is added or removed.
+const VIRTUAL_NODES: u32 = 64;
+
+/// Partitions the keyspace across the nodes of a cluster using consistent hashing.
+///
+/// Every node of the cluster is started with the same list of nodes, so they all
+/// agree on which node owns a key. Commands on keys owned by another node are
+/// redirected to it.
+pub struct Cluster {
+    nodes: Vec<String>,
+    this_node: String,
+    ring: Vec<(u64, usize)>,
+}
+
+impl Cluster {
+    /// Creates the cluster topology from the addresses of all nodes and the
+    /// address of this node, which must be one of them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if `this_node` is not part of `nodes`.
+    pub fn new(nodes: Vec<String>, this_node: String) -> Result<Self, String> {
+        if !nodes.contains(&this_node) {
+            return Err(format!("{} is not one of the cluster nodes", this_node));
+        }
+
+        let mut ring = Vec::with_capacity(nodes.len() * VIRTUAL_NODES as usize);
+        for (index, node) in nodes.iter().enumerate() {
+            for replica in 0..VIRTUAL_NODES {
+                ring.push((hash(format!("{}#{}", node, replica).as_bytes()), index));
+            }
+        }
+        ring.sort_unstable();
+
+        Ok(Self {
+            nodes,
+            this_node,
+            ring,
+        })
+    }
+
+    /// Returns the addresses of all nodes in the cluster.
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Returns the address of the node owning `key`.
+    pub fn owner(&self, key: &str) -> &str {
+        let point = hash(key.as_bytes());
+        let index = self
+            .ring
+            .partition_point(|(node_point, _)| *node_point < point);
+        let (_, node) = self.ring[index % self.ring.len()];
+        &self.nodes[node]
+    }
+
+    /// Returns the address of the node to redirect to if `key` is not owned by this node.
+    pub fn redirect(&self, key: &str) -> Option<&str> {
+        let owner = self.owner(key);
+        if owner == self.this_node {
+            None
+        } else {
+            Some(owner)
+        }
+    }
+
+    /// Generates a new UUID key owned by this node.
+    pub fn generate_local_key(&self) -> String {
+        loop {
+            let key = Uuid::new_v4().to_string();
+            if self.redirect(&key).is_none() {
+                return key;
+            }
+        }
+    }
+}
+
+/// Hashes bytes with 64-bit FNV-1a followed by a final avalanche step. The hash is
+/// stable across builds and platforms so every node places keys on the ring identically.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash
+}
@@ -1,9 +1,685 @@
+use crate::acl::Role;
+use crate::bulk::BulkHeader;
+use crate::format::{Format, Notation};
+use crate::import::CsvOptions;
+use crate::index::IndexKind;
+use crate::persistence::RecoveryPoint;
+use crate::pipeline::Precision;
+use crate::quote;
+use crate::raft::VoteRequest;
+use crate::reduce::ReductionKind;
+use crate::vemcache::Aggregation;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// The number of commands returned by `slowlog get` when no count is given.
+const DEFAULT_SLOWLOG_GET: usize = 10;
+
+/// The number of keys returned by `scan` when no count is given.
+const DEFAULT_SCAN_COUNT: usize = 100;
+
+/// The largest number of keys a single `scan` may return.
+pub const MAX_SCAN_COUNT: usize = 10000;
+
+/// The keywords introducing the options of `knn` after the metric.
+const KNN_OPTIONS: &[&str] = &["offset", "rerank", "timeout"];
+
+/// The options of a `knn` query.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KnnOptions {
+    /// The name of the metric to rank vectors by instead of the configured one.
+    pub metric: Option<String>,
+    /// The number of nearest neighbors skipped, to page through the results.
+    pub offset: usize,
+    /// The time budget of the search in milliseconds instead of the configured one.
+    pub timeout: Option<u64>,
+    /// The number of candidates fetched from the index per neighbor and re-scored
+    /// exactly, to re-rank the results of an approximate index.
+    pub rerank: Option<usize>,
+}
+
+/// The options of a `get`, choosing what the response holds besides the vector.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GetOptions {
+    /// Whether the metadata of the vector is included: its dimension, when it was
+    /// inserted and last retrieved, and when it expires.
+    pub meta: bool,
+    /// Whether the Euclidean norm of the vector is included.
+    pub norm: bool,
+    /// Whether the vector is given as the base64 of its little-endian components
+    /// rather than as a list of numbers.
+    pub raw: bool,
+    /// The precision the raw components are rounded to, `f32` if `None`.
+    pub precision: Option<Precision>,
+}
+
+impl GetOptions {
+    /// Returns whether the options ask for more than the vector as a list of numbers.
+    pub fn is_extended(&self) -> bool {
+        self.meta || self.norm || self.raw
+    }
+}
+
+/// The query of a `knn_in` search.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KnnQuery {
+    /// The vector stored under a key.
+    Key(String),
+    /// A vector given with the command.
+    Vector(Vec<f32>),
+}
+
+/// The documentation of a command, as returned by `help`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CommandDoc {
+    /// The name of the command, followed by its subcommand if it has one.
+    pub name: &'static str,
+    /// The arguments of the command: `<required>`, `[optional]`, `a|b` for
+    /// alternatives and `...` for arguments that can be repeated.
+    pub syntax: &'static str,
+    /// The number of words of the command, name included, or minus the smallest
+    /// number of words if it takes a variable number of arguments, as in Redis.
+    pub arity: i32,
+    /// A one-line description of the command.
+    pub summary: &'static str,
+}
+
+/// The documentation of every command.
+pub const COMMAND_DOCS: &[CommandDoc] = &[
+    CommandDoc {
+        name: "ping",
+        syntax: "ping",
+        arity: 1,
+        summary: "Check the connection to the server",
+    },
+    CommandDoc {
+        name: "health",
+        syntax: "health",
+        arity: 1,
+        summary: "Report whether the server is ready to serve commands",
+    },
+    CommandDoc {
+        name: "hello",
+        syntax: "hello [version]",
+        arity: -1,
+        summary: "Report the server and the protocol of the connection, or switch protocols",
+    },
+    CommandDoc {
+        name: "format",
+        syntax: "format [text [shortest|hex|<decimals>]|msgpack|cbor]",
+        arity: -1,
+        summary: "Report or choose the encoding of the responses holding vectors",
+    },
+    CommandDoc {
+        name: "auth",
+        syntax: "auth <token>",
+        arity: 2,
+        summary: "Authenticate the connection with a token",
+    },
+    CommandDoc {
+        name: "help",
+        syntax: "help [command]",
+        arity: -1,
+        summary: "Describe a command, or list every command",
+    },
+    CommandDoc {
+        name: "monitor",
+        syntax: "monitor",
+        arity: 1,
+        summary: "Stream every command received by the server",
+    },
+    CommandDoc {
+        name: "subscribe",
+        syntax: "subscribe <channel>...",
+        arity: -2,
+        summary: "Stream the messages published on channels",
+    },
+    CommandDoc {
+        name: "select",
+        syntax: "select <index>",
+        arity: 2,
+        summary: "Switch the connection to another logical database",
+    },
+    CommandDoc {
+        name: "insert",
+        syntax: "insert <value>...",
+        arity: -2,
+        summary: "Insert a vector under a generated key",
+    },
+    CommandDoc {
+        name: "named_insert",
+        syntax: "named_insert <key> <value>...",
+        arity: -3,
+        summary: "Insert a vector under a key",
+    },
+    CommandDoc {
+        name: "binsert",
+        syntax: "binsert <key> <dim> <base64>",
+        arity: 4,
+        summary: "Insert a vector under a key, given as base64 of little-endian f32s",
+    },
+    CommandDoc {
+        name: "alias",
+        syntax: "alias <new_key> <existing_key>",
+        arity: 3,
+        summary: "Share the vector of a key with another key",
+    },
+    CommandDoc {
+        name: "get",
+        syntax: "get <key> [withmeta] [withnorm] [raw [f16|bf16]]",
+        arity: -2,
+        summary: "Retrieve a vector, optionally with its metadata and norm, or in binary",
+    },
+    CommandDoc {
+        name: "dim",
+        syntax: "dim <key>",
+        arity: 2,
+        summary: "Retrieve the number of components of a vector",
+    },
+    CommandDoc {
+        name: "describe",
+        syntax: "describe <key>",
+        arity: 2,
+        summary: "Retrieve the properties of a vector",
+    },
+    CommandDoc {
+        name: "randomkey",
+        syntax: "randomkey",
+        arity: 1,
+        summary: "Retrieve the key of a random vector",
+    },
+    CommandDoc {
+        name: "sample",
+        syntax: "sample <n>",
+        arity: 2,
+        summary: "Retrieve n random vectors",
+    },
+    CommandDoc {
+        name: "count",
+        syntax: "count [prefix]",
+        arity: -1,
+        summary: "Count the vectors of a collection",
+    },
+    CommandDoc {
+        name: "aggregate",
+        syntax: "aggregate <sum|mean> <prefix>",
+        arity: 3,
+        summary: "Sum or average the vectors of a collection",
+    },
+    CommandDoc {
+        name: "remove",
+        syntax: "remove <key>",
+        arity: 2,
+        summary: "Remove a vector",
+    },
+    CommandDoc {
+        name: "del",
+        syntax: "del <key> [key ...]",
+        arity: -2,
+        summary: "Remove several vectors at once",
+    },
+    CommandDoc {
+        name: "delprefix",
+        syntax: "delprefix <prefix>",
+        arity: 2,
+        summary: "Remove every vector of a collection",
+    },
+    CommandDoc {
+        name: "knn",
+        syntax: "knn <key> <k> [metric] [offset <o>] [timeout <ms>] [rerank <n>]",
+        arity: -3,
+        summary: "Find the k nearest neighbors of a vector",
+    },
+    CommandDoc {
+        name: "knn_in",
+        syntax: "knn_in <k> <key>|<value>... keys <key>...",
+        arity: -5,
+        summary: "Find the k nearest neighbors of a vector among candidate keys",
+    },
+    CommandDoc {
+        name: "explain knn",
+        syntax: "explain knn <key> <k> [metric] [offset <o>] [timeout <ms>] [rerank <n>]",
+        arity: -4,
+        summary: "Report how a knn query is carried out",
+    },
+    CommandDoc {
+        name: "bench recall",
+        syntax: "bench recall <sample_size> <k>",
+        arity: 4,
+        summary: "Measure the recall@k of the indexes against exact searches",
+    },
+    CommandDoc {
+        name: "scan",
+        syntax: "scan [after <key>] [count <n>]",
+        arity: -1,
+        summary: "Retrieve the keys of the database in order, a page at a time",
+    },
+    CommandDoc {
+        name: "vadd",
+        syntax: "vadd <key1> <key2>",
+        arity: 3,
+        summary: "Add two vectors",
+    },
+    CommandDoc {
+        name: "vsub",
+        syntax: "vsub <key1> <key2>",
+        arity: 3,
+        summary: "Subtract two vectors",
+    },
+    CommandDoc {
+        name: "vscale",
+        syntax: "vscale <key> <scalar>",
+        arity: 3,
+        summary: "Scale a vector",
+    },
+    CommandDoc {
+        name: "vcosine",
+        syntax: "vcosine <key1> <key2>",
+        arity: 3,
+        summary: "Compute the cosine similarity of two vectors",
+    },
+    CommandDoc {
+        name: "dump",
+        syntax: "dump <filename> [prefix <prefix>]",
+        arity: -2,
+        summary: "Dump the database to a JSON file",
+    },
+    CommandDoc {
+        name: "changes",
+        syntax: "changes <seq>",
+        arity: 2,
+        summary: "Retrieve the mutations applied after a sequence number",
+    },
+    CommandDoc {
+        name: "sync",
+        syntax: "sync",
+        arity: 1,
+        summary: "Retrieve a snapshot of the database for a replica",
+    },
+    CommandDoc {
+        name: "replicaof",
+        syntax: "replicaof <host> <port>|no one",
+        arity: 3,
+        summary: "Replicate from a primary, or stop replicating",
+    },
+    CommandDoc {
+        name: "cluster nodes",
+        syntax: "cluster nodes",
+        arity: 2,
+        summary: "List the nodes of the cluster",
+    },
+    CommandDoc {
+        name: "raft status",
+        syntax: "raft status",
+        arity: 2,
+        summary: "Describe the role, the term and the leader of the server in its Raft group",
+    },
+    CommandDoc {
+        name: "raft vote",
+        syntax: "raft vote <term> <candidate> <last-term> <last-seq>",
+        arity: 6,
+        summary: "Ask for the vote of a node of the Raft group",
+    },
+    CommandDoc {
+        name: "raft heartbeat",
+        syntax: "raft heartbeat <term> <leader>",
+        arity: 4,
+        summary: "Assert the leadership of a term over a node of the Raft group",
+    },
+    CommandDoc {
+        name: "raft changes",
+        syntax: "raft changes <term> <node> <seq>",
+        arity: 5,
+        summary: "Retrieve the mutations applied after a sequence number, for a Raft follower",
+    },
+    CommandDoc {
+        name: "config get",
+        syntax: "config get <pattern>",
+        arity: 3,
+        summary: "Read the configuration parameters matching a pattern",
+    },
+    CommandDoc {
+        name: "config set",
+        syntax: "config set <name> <value>",
+        arity: 4,
+        summary: "Change a configuration parameter",
+    },
+    CommandDoc {
+        name: "import csv",
+        syntax: "import csv <filename> [--key-column <index>] [--header]",
+        arity: -3,
+        summary: "Load vectors from a CSV file",
+    },
+    CommandDoc {
+        name: "import npy",
+        syntax: "import npy <filename> [keys <keys_filename>]",
+        arity: -3,
+        summary: "Load vectors from a NumPy file",
+    },
+    CommandDoc {
+        name: "import jsonl",
+        syntax: "import jsonl <filename>",
+        arity: 3,
+        summary: "Load vectors from a JSON Lines file",
+    },
+    CommandDoc {
+        name: "export npy",
+        syntax: "export npy <filename> [keys <keys_filename>]",
+        arity: -3,
+        summary: "Export the database to a NumPy file",
+    },
+    CommandDoc {
+        name: "export jsonl",
+        syntax: "export jsonl <filename>",
+        arity: 3,
+        summary: "Export the database to a JSON Lines file",
+    },
+    CommandDoc {
+        name: "export vec",
+        syntax: "export vec <filename>",
+        arity: 3,
+        summary: "Export the database to a vector file",
+    },
+    CommandDoc {
+        name: "bulk",
+        syntax: "bulk <count> <dim> [keys]",
+        arity: -3,
+        summary: "Insert vectors sent as raw f32 values",
+    },
+    CommandDoc {
+        name: "arrow upload",
+        syntax: "arrow upload <size>",
+        arity: 3,
+        summary: "Import vectors from an Arrow IPC stream",
+    },
+    CommandDoc {
+        name: "arrow download",
+        syntax: "arrow download",
+        arity: 2,
+        summary: "Retrieve the database as an Arrow IPC stream",
+    },
+    CommandDoc {
+        name: "metric upload",
+        syntax: "metric upload <name> <size>",
+        arity: 4,
+        summary: "Register a metric implemented by a WebAssembly module",
+    },
+    CommandDoc {
+        name: "metric list",
+        syntax: "metric list",
+        arity: 2,
+        summary: "List the metrics vectors can be ranked by",
+    },
+    CommandDoc {
+        name: "index create",
+        syntax: "index create <prefix> flat|hnsw|ivf [metric]",
+        arity: -4,
+        summary: "Index a collection",
+    },
+    CommandDoc {
+        name: "index rebuild",
+        syntax: "index rebuild <prefix> [param value ...]",
+        arity: -3,
+        summary: "Build a fresh index of a collection and swap it in",
+    },
+    CommandDoc {
+        name: "index drop",
+        syntax: "index drop <prefix>",
+        arity: 3,
+        summary: "Drop the index of a collection",
+    },
+    CommandDoc {
+        name: "index list",
+        syntax: "index list",
+        arity: 2,
+        summary: "List the indexed collections",
+    },
+    CommandDoc {
+        name: "index status",
+        syntax: "index status",
+        arity: 2,
+        summary: "Report the progress of the index builds",
+    },
+    CommandDoc {
+        name: "compact",
+        syntax: "compact",
+        arity: 1,
+        summary: "Rebuild the indexes holding removed vectors",
+    },
+    CommandDoc {
+        name: "reduce",
+        syntax: "reduce pca|random <prefix> <dim>",
+        arity: 4,
+        summary: "Project a collection onto fewer dimensions",
+    },
+    CommandDoc {
+        name: "reduce drop",
+        syntax: "reduce drop <prefix>",
+        arity: 3,
+        summary: "Stop projecting the vectors of a collection",
+    },
+    CommandDoc {
+        name: "reduce list",
+        syntax: "reduce list",
+        arity: 2,
+        summary: "List the projected collections",
+    },
+    CommandDoc {
+        name: "pipeline set",
+        syntax: "pipeline set <prefix> <transform>...",
+        arity: -4,
+        summary: "Set the transforms applied to the vectors inserted into a collection",
+    },
+    CommandDoc {
+        name: "pipeline drop",
+        syntax: "pipeline drop <prefix>",
+        arity: 3,
+        summary: "Remove the pipeline of a collection",
+    },
+    CommandDoc {
+        name: "pipeline list",
+        syntax: "pipeline list",
+        arity: 2,
+        summary: "List the pipelines of the collections",
+    },
+    CommandDoc {
+        name: "ttl set",
+        syntax: "ttl set <prefix> <seconds>",
+        arity: 4,
+        summary: "Expire the vectors inserted into a collection after a time to live",
+    },
+    CommandDoc {
+        name: "ttl drop",
+        syntax: "ttl drop <prefix>",
+        arity: 3,
+        summary: "Stop expiring the vectors of a collection",
+    },
+    CommandDoc {
+        name: "ttl list",
+        syntax: "ttl list",
+        arity: 2,
+        summary: "List the times to live of the collections",
+    },
+    CommandDoc {
+        name: "embed",
+        syntax: "embed <key> <text>",
+        arity: 3,
+        summary: "Insert the embedding of a text computed by the embedding endpoint",
+    },
+    CommandDoc {
+        name: "search_text",
+        syntax: "search_text <text> <k> [prefix]",
+        arity: -3,
+        summary: "Find the nearest neighbors of a text embedded by the embedding endpoint",
+    },
+    CommandDoc {
+        name: "insert_text",
+        syntax: "insert_text <key> <text>",
+        arity: 3,
+        summary: "Insert the embedding of a text computed by the local model",
+    },
+    CommandDoc {
+        name: "query_text",
+        syntax: "query_text <text> <k> [prefix]",
+        arity: -3,
+        summary: "Find the nearest neighbors of a text embedded by the local model",
+    },
+    CommandDoc {
+        name: "eval",
+        syntax: "eval <script> [arg]...",
+        arity: -2,
+        summary: "Run a Rhai script against the database",
+    },
+    CommandDoc {
+        name: "slowlog get",
+        syntax: "slowlog get [count]",
+        arity: -2,
+        summary: "Retrieve the slowest recent commands",
+    },
+    CommandDoc {
+        name: "slowlog len",
+        syntax: "slowlog len",
+        arity: 2,
+        summary: "Retrieve the number of entries of the slow log",
+    },
+    CommandDoc {
+        name: "slowlog reset",
+        syntax: "slowlog reset",
+        arity: 2,
+        summary: "Clear the slow log",
+    },
+    CommandDoc {
+        name: "stats",
+        syntax: "stats",
+        arity: 1,
+        summary: "Retrieve the statistics of every command",
+    },
+    CommandDoc {
+        name: "stats reset",
+        syntax: "stats reset",
+        arity: 2,
+        summary: "Clear the command statistics",
+    },
+    CommandDoc {
+        name: "stats dims",
+        syntax: "stats dims [prefix]",
+        arity: -2,
+        summary: "Compute statistics of every component of the vectors",
+    },
+    CommandDoc {
+        name: "collection stats",
+        syntax: "collection stats <prefix>",
+        arity: 3,
+        summary: "Report the size of a collection and the state of its index",
+    },
+    CommandDoc {
+        name: "warmup",
+        syntax: "warmup [prefix] [withindexes]",
+        arity: -1,
+        summary: "Load the vectors of a collection, and optionally its indexes, into memory",
+    },
+    CommandDoc {
+        name: "backup now",
+        syntax: "backup now",
+        arity: 2,
+        summary: "Back up every database to the backup directory",
+    },
+    CommandDoc {
+        name: "restore until",
+        syntax: "restore until <seq|timestamp>",
+        arity: 3,
+        summary: "Roll the database back to a change or time in the write-ahead log",
+    },
+    CommandDoc {
+        name: "snapshots list",
+        syntax: "snapshots list",
+        arity: 2,
+        summary: "List the snapshots in the directory of the database",
+    },
+    CommandDoc {
+        name: "snapshots info",
+        syntax: "snapshots info <name>",
+        arity: 3,
+        summary: "Describe a snapshot in the directory of the database",
+    },
+    CommandDoc {
+        name: "snapshots delete",
+        syntax: "snapshots delete <name>",
+        arity: 3,
+        summary: "Remove a snapshot and the files saved with it",
+    },
+    CommandDoc {
+        name: "shutdown",
+        syntax: "shutdown [nosave]",
+        arity: -1,
+        summary: "Stop the server once every change is on disk",
+    },
+];
+
+/// Returns the documentation of the command `name`, or of every subcommand of
+/// `name` if it is a group of commands such as `index`.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::commands::command_docs;
+///
+/// assert_eq!(command_docs("named_insert")[0].arity, -3);
+/// assert_eq!(command_docs("slowlog").len(), 3);
+/// assert!(command_docs("nope").is_empty());
+/// ```
+pub fn command_docs(name: &str) -> Vec<&'static CommandDoc> {
+    let name = name.to_lowercase();
+    COMMAND_DOCS
+        .iter()
+        .filter(|doc| {
+            doc.name == name
+                || doc
+                    .name
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|subcommand| subcommand.starts_with(' '))
+        })
+        .collect()
+}
+
 /// Represents the various commands that can be executed by the Vemcache server.
 /// Each variant corresponds to a specific command and its associated parameters.
 pub enum Command {
     /// The `Ping` command is used to check the connection to the Vemcache server.
     /// The server responds with "pong" when it receives this command.
     Ping,
+    /// The `Health` command is used to check whether the server is ready to serve commands.
+    /// It is answered without waiting for the database, even while a dataset is being loaded.
+    Health,
+    /// The `Monitor` command is used to stream every command processed by the server,
+    /// with the time it was received and the address of the client, until the client
+    /// disconnects.
+    Monitor,
+    /// The `Subscribe` command is used to stream the messages published on channels,
+    /// such as the keys of expired vectors, until the client disconnects.
+    /// Parameters: Names (Vec<String>) of the channels.
+    Subscribe(Vec<String>),
+    /// The `Hello` command is used to negotiate the protocol of the connection: 1 for
+    /// the text protocol and 2 for RESP2.
+    /// Parameters: Version (Option<u8>) of the protocol to switch to, if any.
+    Hello(Option<u8>),
+    /// The `Format` command is used to choose how the responses holding vectors are
+    /// encoded on the connection: as text, MessagePack or CBOR, and how the
+    /// components of vectors are written in text.
+    /// Parameters: Format (Option<Format>) to switch to, if any, and Notation
+    /// (Option<Notation>) of the components in text, if any.
+    Format(Option<Format>, Option<Notation>),
+    /// The `Help` command is used to describe a command, or to list every command.
+    /// It is answered without waiting for the database.
+    /// Parameters: Name (Option<String>) of the command or group of commands, if any.
+    Help(Option<String>),
+    /// The `Auth` command is used to authenticate the connection, granting it the
+    /// role bound to the token.
+    /// Parameters: Token (String) to authenticate with.
+    Auth(String),
+    /// The `Select` command is used to switch the connection to another logical database.
+    /// Parameters: Number (usize) of the database.
+    Select(usize),
     /// The `Insert` command is used to insert a vector into the database.
     /// The server generates a unique identifier (UUID) for the vector.
     /// Parameters: Vector to be inserted.
@@ -11,15 +687,69 @@ pub enum Command {
     /// The `NamedInsert` command is used to insert a vector into the database with a specified key.
     /// Parameters: Key (String) and Vector to be inserted.
     NamedInsert(String, Vec<f32>),
+    /// The `BinaryInsert` command is used to insert a vector into the database with a
+    /// specified key, its components given as base64 of little-endian `f32`s so they
+    /// are stored with the exact bits the client sent.
+    /// Parameters: Key (String) and Vector to be inserted.
+    BinaryInsert(String, Vec<f32>),
+    /// The `Alias` command is used to store the vector of a key under another key,
+    /// shared by both rather than copied.
+    /// Parameters: New key (String) and key (String) of the vector to share.
+    Alias(String, String),
     /// The `Get` command is used to retrieve a vector from the database using its key.
-    /// Parameters: Key (String) of the vector to be retrieved.
-    Get(String),
+    /// Parameters: Key (String) of the vector to be retrieved, and options
+    /// (GetOptions) choosing what the response holds.
+    Get(String, GetOptions),
+    /// The `Dim` command is used to retrieve the number of components of a vector.
+    /// Parameters: Key (String) of the vector.
+    Dim(String),
+    /// The `Describe` command is used to retrieve the properties of a vector, such as
+    /// its number of components and its norm.
+    /// Parameters: Key (String) of the vector.
+    Describe(String),
+    /// The `RandomKey` command is used to retrieve the key of a vector drawn at random.
+    RandomKey,
+    /// The `Sample` command is used to retrieve vectors drawn at random, e.g. to inspect
+    /// representative vectors or build a training set.
+    /// Parameters: Number (usize) of vectors to draw.
+    Sample(usize),
+    /// The `Count` command is used to count the vectors of a collection.
+    /// Parameters: Prefix (String) of the keys of the collection, empty for the whole database.
+    Count(String),
+    /// The `Aggregate` command is used to sum or average the vectors of a collection
+    /// component by component, e.g. to compute the centroid of a group.
+    /// Parameters: Aggregation (Aggregation) to compute and prefix (String) of the keys of
+    /// the collection, empty for the whole database.
+    Aggregate(Aggregation, String),
     /// The `Remove` command is used to remove a vector from the database using its key.
     /// Parameters: Key (String) of the vector to be removed.
     Remove(String),
+    /// The `Delete` command is used to remove several vectors at once, as one operation.
+    /// Parameters: Keys (`Vec<String>`) of the vectors to remove.
+    Delete(Vec<String>),
+    /// The `RemovePrefix` command is used to remove every vector of a collection, as one
+    /// operation, e.g. before ingesting the chunks of a document again.
+    /// Parameters: Prefix (String) of the keys of the collection, empty for the whole database.
+    RemovePrefix(String),
     /// The `KNearestNeighbors` command is used to find the k nearest neighbors of a vector.
-    /// Parameters: Key (String) of the query vector and k value (usize) specifying the number of neighbors.
-    KNearestNeighbors(String, usize),
+    /// Parameters: Key (String) of the query vector, k value (usize) specifying the number of neighbors,
+    /// and the options (KnnOptions) of the query.
+    KNearestNeighbors(String, usize, KnnOptions),
+    /// The `KnnIn` command is used to find the k nearest neighbors of a vector among
+    /// the vectors of candidate keys.
+    /// Parameters: k value (usize), the query (KnnQuery), and the candidate keys (Vec<String>).
+    KnnIn(usize, KnnQuery, Vec<String>),
+    /// The `ExplainKnn` command is used to run a `KNearestNeighbors` search and report
+    /// how it was carried out instead of its results.
+    /// Parameters: the same as `KNearestNeighbors`.
+    ExplainKnn(String, usize, KnnOptions),
+    /// The `BenchRecall` command is used to measure the recall@k of the indexes
+    /// against exact searches.
+    /// Parameters: the number of vectors (usize) of each collection used as queries, and k (usize).
+    BenchRecall(usize, usize),
+    /// The `Scan` command is used to page through the keys of the database, in order.
+    /// Parameters: the key (Option<String>) after which to start, and the number of keys (usize).
+    Scan(Option<String>, usize),
     /// The `VectorAddition` command is used to perform element-wise addition of two vectors.
     /// Parameters: Keys (Strings) of the two vectors to be added.
     VectorAddition(String, String),
@@ -34,11 +764,499 @@ pub enum Command {
     CosineSimilarity(String, String),
     /// The `Dump` command is used to create a JSON dump of the database.
     /// The server responds with a success or error message based on the result.
-    Dump(String),
+    /// Parameters: Path (String) of the dump and an optional key prefix (String) restricting
+    /// the dump to the matching vectors.
+    Dump(String, Option<String>),
+    /// The `Changes` command is used to retrieve the mutations applied after a sequence number.
+    /// Parameters: Last sequence number (u64) already seen by the client.
+    Changes(u64),
+    /// The `Sync` command is used by replicas to fetch a full snapshot of the database.
+    /// The server responds with the current change sequence number followed by a JSON snapshot.
+    Sync,
+    /// The `ReplicaOf` command is used to make the server replicate from a primary.
+    /// Parameters: Address (String) of the primary, or `None` to stop replicating.
+    ReplicaOf(Option<String>),
+    /// The `ClusterNodes` command is used to list the addresses of the nodes in the cluster.
+    ClusterNodes,
+    /// The `RaftStatus` command is used to describe the part the server plays in its
+    /// Raft group: its role, its term and the leader of the term.
+    RaftStatus,
+    /// The `RaftVote` command is used by a candidate to ask another node of the Raft
+    /// group for its vote.
+    /// Parameters: The request (VoteRequest) of the candidate.
+    RaftVote(VoteRequest),
+    /// The `RaftHeartbeat` command is used by the leader of a Raft group to assert
+    /// its leadership over the other nodes.
+    /// Parameters: Term (u64) of the leader and its address (String).
+    RaftHeartbeat(u64, String),
+    /// The `RaftChanges` command is used by the followers of a Raft leader to
+    /// retrieve its changes, like `Changes`, while reporting how far they got.
+    /// Parameters: Term (u64) of the leader, address (String) of the follower and
+    /// last sequence number (u64) it holds.
+    RaftChanges(u64, String, u64),
+    /// The `ConfigGet` command is used to read configuration parameters.
+    /// Parameters: Name (String) of the parameter, where `*` matches any sequence of characters.
+    ConfigGet(String),
+    /// The `ConfigSet` command is used to change a configuration parameter at runtime.
+    /// Parameters: Name (String) of the parameter and its new value (String).
+    ConfigSet(String, String),
+    /// The `ImportCsv` command is used to bulk-load vectors from a CSV file.
+    /// Parameters: Path (String) of the file and the CSV options.
+    ImportCsv(String, CsvOptions),
+    /// The `ImportNpy` command is used to bulk-load vectors from a NumPy `.npy` or `.npz` file.
+    /// Parameters: Path (String) of the file and an optional path (String) of the file holding
+    /// the keys of the vectors.
+    ImportNpy(String, Option<String>),
+    /// The `ExportNpy` command is used to export the database to a NumPy `.npy` or `.npz` file.
+    /// Parameters: Path (String) of the file and an optional path (String) of the file the keys
+    /// of the vectors are written to.
+    ExportNpy(String, Option<String>),
+    /// The `ImportJsonl` command is used to bulk-load vectors from a JSON Lines file.
+    /// Parameters: Path (String) of the file.
+    ImportJsonl(String),
+    /// The `ExportJsonl` command is used to export the database to a JSON Lines file.
+    /// Parameters: Path (String) of the file.
+    ExportJsonl(String),
+    /// The `ExportVec` command is used to export the database to a vector file, which can be
+    /// served by the memory-mapped storage.
+    /// Parameters: Path (String) of the file.
+    ExportVec(String),
+    /// The `Bulk` command announces a binary payload of vectors following the command line.
+    /// Parameters: The header describing the payload.
+    Bulk(BulkHeader),
+    /// The `BulkInsert` command is produced by the server once the payload of a `Bulk`
+    /// command has been read. It is never parsed from text.
+    /// Parameters: Keys (Strings) and vectors to be inserted.
+    BulkInsert(Vec<(String, Vec<f32>)>),
+    /// The `ArrowUpload` command announces an Arrow IPC stream of vectors following the
+    /// command line.
+    /// Parameters: Size (usize) of the stream in bytes.
+    ArrowUpload(usize),
+    /// The `ArrowImport` command is produced by the server once the stream of an
+    /// `ArrowUpload` command has been read. It is never parsed from text.
+    /// Parameters: The Arrow IPC stream.
+    ArrowImport(Vec<u8>),
+    /// The `MetricUpload` command announces a WebAssembly module implementing a metric
+    /// following the command line.
+    /// Parameters: Name (String) of the metric and size (usize) of the module in bytes.
+    MetricUpload(String, usize),
+    /// The `MetricRegister` command is produced by the server once the module of a
+    /// `MetricUpload` command has been read. It is never parsed from text.
+    /// Parameters: Name (String) of the metric and the module.
+    MetricRegister(String, Vec<u8>),
+    /// The `MetricList` command is used to list the metrics vectors can be ranked by.
+    MetricList,
+    /// The `ArrowDownload` command is used to retrieve the database as an Arrow IPC stream.
+    /// The server responds with the size of the stream followed by the stream itself.
+    ArrowDownload,
+    /// The `IndexCreate` command is used to index the collection of vectors whose key starts
+    /// with a prefix.
+    /// Parameters: Prefix (String) of the collection, kind of index (IndexKind) and the name
+    /// of the metric (Option<String>) it ranks vectors by instead of the configured one.
+    IndexCreate(String, IndexKind, Option<String>),
+    /// The `IndexRebuild` command is used to build a fresh index of an indexed
+    /// collection in the background and replace its index with it once built.
+    /// Parameters: Prefix (String) of the collection and the parameters
+    /// (Vec<(String, usize)>) that differ from those of its index.
+    IndexRebuild(String, Vec<(String, usize)>),
+    /// The `IndexDrop` command is used to drop the index of a collection.
+    /// Parameters: Prefix (String) of the collection.
+    IndexDrop(String),
+    /// The `IndexList` command is used to list the indexed collections.
+    IndexList,
+    /// The `IndexStatus` command is used to retrieve the progress of the index builds.
+    IndexStatus,
+    /// The `Compact` command is used to rebuild the indexes holding removed vectors.
+    Compact,
+    /// The `Reduce` command is used to reduce the dimension of the vectors of a collection,
+    /// and of those inserted into it afterwards.
+    /// Parameters: Prefix (String) of the collection, kind of projection (ReductionKind) and
+    /// the dimension (usize) to reduce vectors to.
+    Reduce(String, ReductionKind, usize),
+    /// The `ReduceDrop` command is used to stop projecting the vectors inserted into a
+    /// collection.
+    /// Parameters: Prefix (String) of the collection.
+    ReduceDrop(String),
+    /// The `ReduceList` command is used to list the collections whose vectors are projected.
+    ReduceList,
+    /// The `PipelineSet` command is used to set the transforms applied to the vectors
+    /// inserted into a collection.
+    /// Parameters: Prefix (String) of the collection and the words (Vec<String>) of its
+    /// pipeline, parsed when the command is run.
+    PipelineSet(String, Vec<String>),
+    /// The `PipelineDrop` command is used to remove the pipeline of a collection.
+    /// Parameters: Prefix (String) of the collection.
+    PipelineDrop(String),
+    /// The `PipelineList` command is used to list the pipelines of the collections.
+    PipelineList,
+    /// The `TtlSet` command is used to set the time to live applied to every vector
+    /// inserted into a collection.
+    /// Parameters: Prefix (String) of the collection and the time to live (u64), in
+    /// seconds.
+    TtlSet(String, u64),
+    /// The `TtlDrop` command is used to stop expiring the vectors of a collection.
+    /// Parameters: Prefix (String) of the collection.
+    TtlDrop(String),
+    /// The `TtlList` command is used to list the times to live of the collections.
+    TtlList,
+    /// The `Embed` command is used to insert the embedding of a text, computed by the
+    /// configured embedding endpoint.
+    /// Parameters: Key (String) of the vector and the text (String) to embed.
+    Embed(String, String),
+    /// The `SearchText` command is used to find the k nearest neighbors of the embedding
+    /// of a text.
+    /// Parameters: the text (String) to embed, k value (usize) specifying the number of
+    /// neighbors, and the prefix (String) of the collection to search, empty for the
+    /// whole database.
+    SearchText(String, usize, String),
+    /// The `InsertText` command is used to insert the embedding of a text, computed by
+    /// the model loaded by the server.
+    /// Parameters: Key (String) of the vector and the text (String) to embed.
+    InsertText(String, String),
+    /// The `QueryText` command is used to find the k nearest neighbors of the embedding
+    /// of a text, computed by the model loaded by the server.
+    /// Parameters: the same as `SearchText`.
+    QueryText(String, usize, String),
+    /// The `Eval` command is used to run a script against the database in one step.
+    /// Parameters: Source (String) of the script and its arguments (Vec<String>).
+    Eval(String, Vec<String>),
+    /// The `SlowlogGet` command is used to retrieve the commands that took longest to run,
+    /// newest first.
+    /// Parameters: Maximum number (usize) of commands to retrieve.
+    SlowlogGet(usize),
+    /// The `SlowlogLen` command is used to retrieve the number of commands in the slow log.
+    SlowlogLen,
+    /// The `SlowlogReset` command is used to clear the slow log.
+    SlowlogReset,
+    /// The `Stats` command is used to retrieve the number of calls, the number of errors
+    /// and latency percentiles of every type of command run by the server.
+    Stats,
+    /// The `StatsReset` command is used to clear the statistics of all commands.
+    StatsReset,
+    /// The `StatsDims` command is used to compute the mean, standard deviation, minimum and
+    /// maximum of every component across the vectors of a collection.
+    /// Parameters: Prefix (String) of the keys of the collection, empty for the whole database.
+    StatsDims(String),
+    /// The `CollectionStats` command is used to report the number, dimension and memory
+    /// of the vectors of a collection, along with the parameters, memory and removed
+    /// vectors of the index its searches use.
+    /// Parameters: Prefix (String) of the keys of the collection.
+    CollectionStats(String),
+    /// The `Warmup` command is used to read every vector of a collection, and
+    /// optionally search its indexes once, so the first searches after startup do
+    /// not wait for them to be loaded from disk.
+    /// Parameters: Prefix (String) of the keys of the collection, empty for the whole
+    /// database, and whether (bool) to search its indexes.
+    Warmup(String, bool),
+    /// The `BackupNow` command is used to back up every database to the backup
+    /// directory right away.
+    BackupNow,
+    /// The `RestoreUntil` command is used to roll the database back to how it was
+    /// right after a change, or at a time, recorded in the write-ahead log.
+    /// Parameters: Point (RecoveryPoint) to roll the database back to.
+    RestoreUntil(RecoveryPoint),
+    /// The `SnapshotsList` command is used to list the snapshots in the directory of
+    /// the database, with their size, modification time and format version.
+    SnapshotsList,
+    /// The `SnapshotsInfo` command is used to describe a snapshot in the directory of
+    /// the database.
+    /// Parameters: Name (String) of the snapshot file.
+    SnapshotsInfo(String),
+    /// The `SnapshotsDelete` command is used to remove a snapshot from the directory
+    /// of the database, along with the files saved with it.
+    /// Parameters: Name (String) of the snapshot file.
+    SnapshotsDelete(String),
+    /// The `Shutdown` command is used to stop the server once every change is on disk.
+    /// Parameters: Whether (bool) to write a final snapshot of every database first.
+    Shutdown(bool),
+}
+
+impl Command {
+    /// Returns `true` if the command is answered right away, without waiting for
+    /// the database, even while a dataset is being loaded.
+    pub fn is_immediate(&self) -> bool {
+        matches!(
+            self,
+            Command::Health | Command::Hello(_) | Command::Format(_, _) | Command::Help(_)
+        )
+    }
+
+    /// Returns `true` if the command modifies the contents of the database.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Insert(_)
+                | Command::NamedInsert(_, _)
+                | Command::BinaryInsert(_, _)
+                | Command::Alias(_, _)
+                | Command::Remove(_)
+                | Command::Delete(_)
+                | Command::RemovePrefix(_)
+                | Command::ImportCsv(_, _)
+                | Command::ImportNpy(_, _)
+                | Command::ImportJsonl(_)
+                | Command::BulkInsert(_)
+                | Command::ArrowImport(_)
+                | Command::Reduce(_, _, _)
+                | Command::Embed(_, _)
+                | Command::InsertText(_, _)
+                | Command::Eval(_, _)
+                | Command::RestoreUntil(_)
+                | Command::TtlSet(_, _)
+                | Command::TtlDrop(_)
+        )
+    }
+
+    /// Returns `true` if the command may make the server use more memory, in which
+    /// case it is rejected once the memory used reaches `max_memory`. Removals and
+    /// time to live changes are still accepted, as they free memory or add none.
+    pub fn uses_memory(&self) -> bool {
+        match self {
+            Command::Remove(_)
+            | Command::Delete(_)
+            | Command::RemovePrefix(_)
+            | Command::TtlSet(_, _)
+            | Command::TtlDrop(_) => false,
+            Command::IndexCreate(_, _, _) | Command::IndexRebuild(_, _) => true,
+            command => command.is_write(),
+        }
+    }
+
+    /// Returns `true` if the command is followed by a payload, read by the connection
+    /// before the command is dispatched.
+    pub fn has_payload(&self) -> bool {
+        matches!(
+            self,
+            Command::Bulk(_) | Command::ArrowUpload(_) | Command::MetricUpload(_, _)
+        )
+    }
+
+    /// Returns the role a client needs to run the command, or `None` if clients may
+    /// run it without authenticating. See the `acl` module.
+    pub fn required_role(&self) -> Option<Role> {
+        match self {
+            Command::Health
+            | Command::Hello(_)
+            | Command::Format(_, _)
+            | Command::Help(_)
+            | Command::Auth(_) => None,
+            Command::Dump(_, _)
+            | Command::BackupNow
+            | Command::RestoreUntil(_)
+            | Command::SnapshotsList
+            | Command::SnapshotsInfo(_)
+            | Command::SnapshotsDelete(_)
+            | Command::ExportNpy(_, _)
+            | Command::ExportJsonl(_)
+            | Command::ExportVec(_)
+            | Command::SlowlogReset
+            | Command::StatsReset
+            | Command::Warmup(_, _)
+            | Command::BenchRecall(_, _)
+            | Command::RaftVote(_)
+            | Command::RaftHeartbeat(_, _)
+            | Command::RaftChanges(_, _, _) => Some(Role::Admin),
+            command if command.is_admin() => Some(Role::Admin),
+            command if command.is_audited() => Some(Role::Writer),
+            Command::Bulk(_) | Command::ArrowUpload(_) => Some(Role::Writer),
+            _ => Some(Role::Reader),
+        }
+    }
+
+    /// Returns `true` if the command changes the database or the server, in which
+    /// case it is recorded in the audit log.
+    pub fn is_audited(&self) -> bool {
+        self.is_write()
+            || matches!(
+                self,
+                Command::IndexCreate(_, _, _)
+                    | Command::IndexRebuild(_, _)
+                    | Command::IndexDrop(_)
+                    | Command::Compact
+                    | Command::ReduceDrop(_)
+                    | Command::PipelineSet(_, _)
+                    | Command::PipelineDrop(_)
+                    | Command::TtlSet(_, _)
+                    | Command::TtlDrop(_)
+                    | Command::MetricRegister(_, _)
+                    | Command::ReplicaOf(_)
+                    | Command::ConfigSet(_, _)
+                    | Command::BackupNow
+                    | Command::SnapshotsDelete(_)
+                    | Command::Shutdown(_)
+            )
+    }
+
+    /// Returns `true` if the command inspects or changes the server rather than the
+    /// database, in which case it is only accepted on the admin port, if there is one.
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            Command::Monitor
+                | Command::ReplicaOf(_)
+                | Command::ConfigGet(_)
+                | Command::ConfigSet(_, _)
+                | Command::Shutdown(_)
+                | Command::MetricUpload(_, _)
+                | Command::MetricRegister(_, _)
+                | Command::SnapshotsList
+                | Command::SnapshotsInfo(_)
+                | Command::SnapshotsDelete(_)
+                | Command::RestoreUntil(_)
+                | Command::BackupNow
+                | Command::Dump(_, _)
+        )
+    }
+
+    /// Returns `true` if the command writes vectors under keys that are only known
+    /// once it runs, like imports and scripts, so a cluster cannot redirect it to
+    /// the nodes owning them.
+    pub fn has_unknown_keys(&self) -> bool {
+        matches!(
+            self,
+            Command::ImportCsv(_, _)
+                | Command::ImportNpy(_, _)
+                | Command::ImportJsonl(_)
+                | Command::ArrowImport(_)
+                | Command::Eval(_, _)
+        )
+    }
+
+    /// Returns the keys of the vectors the command operates on.
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::NamedInsert(key, _)
+            | Command::BinaryInsert(key, _)
+            | Command::Embed(key, _)
+            | Command::InsertText(key, _)
+            | Command::Get(key, _)
+            | Command::Dim(key)
+            | Command::Describe(key)
+            | Command::Remove(key)
+            | Command::KNearestNeighbors(key, _, _)
+            | Command::ExplainKnn(key, _, _)
+            | Command::VectorScaling(key, _) => vec![key],
+            Command::Alias(key1, key2)
+            | Command::VectorAddition(key1, key2)
+            | Command::VectorSubtraction(key1, key2)
+            | Command::CosineSimilarity(key1, key2) => vec![key1, key2],
+            Command::KnnIn(_, query, candidates) => {
+                let query = match query {
+                    KnnQuery::Key(key) => Some(key.as_str()),
+                    KnnQuery::Vector(_) => None,
+                };
+                query
+                    .into_iter()
+                    .chain(candidates.iter().map(String::as_str))
+                    .collect()
+            }
+            Command::BulkInsert(vectors) => vectors.iter().map(|(key, _)| key.as_str()).collect(),
+            Command::Delete(keys) => keys.iter().map(String::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the name of the command, as typed by clients. Commands produced by the
+    /// server once a payload has been read are named after the command announcing it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Ping => "ping",
+            Command::Health => "health",
+            Command::Hello(_) => "hello",
+            Command::Format(_, _) => "format",
+            Command::Help(_) => "help",
+            Command::Auth(_) => "auth",
+            Command::Monitor => "monitor",
+            Command::Subscribe(_) => "subscribe",
+            Command::Select(_) => "select",
+            Command::Insert(_) => "insert",
+            Command::NamedInsert(_, _) => "named_insert",
+            Command::BinaryInsert(_, _) => "binsert",
+            Command::Alias(_, _) => "alias",
+            Command::Get(_, _) => "get",
+            Command::Dim(_) => "dim",
+            Command::Describe(_) => "describe",
+            Command::RandomKey => "randomkey",
+            Command::Sample(_) => "sample",
+            Command::Count(_) => "count",
+            Command::Aggregate(_, _) => "aggregate",
+            Command::Remove(_) => "remove",
+            Command::Delete(_) => "del",
+            Command::RemovePrefix(_) => "delprefix",
+            Command::KNearestNeighbors(_, _, _) => "knn",
+            Command::KnnIn(_, _, _) => "knn_in",
+            Command::ExplainKnn(_, _, _) => "explain knn",
+            Command::BenchRecall(_, _) => "bench recall",
+            Command::Scan(_, _) => "scan",
+            Command::VectorAddition(_, _) => "vadd",
+            Command::VectorSubtraction(_, _) => "vsub",
+            Command::VectorScaling(_, _) => "vscale",
+            Command::CosineSimilarity(_, _) => "vcosine",
+            Command::Dump(_, _) => "dump",
+            Command::Changes(_) => "changes",
+            Command::Sync => "sync",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::ClusterNodes => "cluster nodes",
+            Command::RaftStatus => "raft status",
+            Command::RaftVote(_) => "raft vote",
+            Command::RaftHeartbeat(_, _) => "raft heartbeat",
+            Command::RaftChanges(_, _, _) => "raft changes",
+            Command::ConfigGet(_) => "config get",
+            Command::ConfigSet(_, _) => "config set",
+            Command::ImportCsv(_, _) => "import csv",
+            Command::ImportNpy(_, _) => "import npy",
+            Command::ExportNpy(_, _) => "export npy",
+            Command::ImportJsonl(_) => "import jsonl",
+            Command::ExportJsonl(_) => "export jsonl",
+            Command::ExportVec(_) => "export vec",
+            Command::Bulk(_) | Command::BulkInsert(_) => "bulk",
+            Command::ArrowUpload(_) | Command::ArrowImport(_) => "arrow upload",
+            Command::ArrowDownload => "arrow download",
+            Command::MetricUpload(_, _) | Command::MetricRegister(_, _) => "metric upload",
+            Command::MetricList => "metric list",
+            Command::IndexCreate(_, _, _) => "index create",
+            Command::IndexRebuild(_, _) => "index rebuild",
+            Command::IndexDrop(_) => "index drop",
+            Command::IndexList => "index list",
+            Command::IndexStatus => "index status",
+            Command::Compact => "compact",
+            Command::Reduce(_, _, _) => "reduce",
+            Command::ReduceDrop(_) => "reduce drop",
+            Command::ReduceList => "reduce list",
+            Command::PipelineSet(_, _) => "pipeline set",
+            Command::PipelineDrop(_) => "pipeline drop",
+            Command::PipelineList => "pipeline list",
+            Command::TtlSet(_, _) => "ttl set",
+            Command::TtlDrop(_) => "ttl drop",
+            Command::TtlList => "ttl list",
+            Command::Embed(_, _) => "embed",
+            Command::SearchText(_, _, _) => "search_text",
+            Command::InsertText(_, _) => "insert_text",
+            Command::QueryText(_, _, _) => "query_text",
+            Command::Eval(_, _) => "eval",
+            Command::SlowlogGet(_) => "slowlog get",
+            Command::SlowlogLen => "slowlog len",
+            Command::SlowlogReset => "slowlog reset",
+            Command::Stats => "stats",
+            Command::StatsReset => "stats reset",
+            Command::StatsDims(_) => "stats dims",
+            Command::CollectionStats(_) => "collection stats",
+            Command::Warmup(_, _) => "warmup",
+            Command::BackupNow => "backup now",
+            Command::RestoreUntil(_) => "restore until",
+            Command::SnapshotsList => "snapshots list",
+            Command::SnapshotsInfo(_) => "snapshots info",
+            Command::SnapshotsDelete(_) => "snapshots delete",
+            Command::Shutdown(_) => "shutdown",
+        }
+    }
 }
 
 pub fn parse_command(input: &str) -> Result<Command, &str> {
-    let tokens: Vec<&str> = input.split_whitespace().collect();
+    if let Some(command) = parse_insert(input) {
+        return command;
+    }
+    let tokens = quote::split(input)?;
 
     if tokens.is_empty() {
         return Err("Empty command");
@@ -46,15 +1264,134 @@ pub fn parse_command(input: &str) -> Result<Command, &str> {
 
     match tokens[0].to_lowercase().as_str() {
         "ping" => Ok(Command::Ping),
+        "health" => Ok(Command::Health),
+        "hello" if tokens.len() == 1 => Ok(Command::Hello(None)),
+        "hello" if tokens.len() == 2 => tokens[1]
+            .parse()
+            .map(|version| Command::Hello(Some(version)))
+            .map_err(|_| "Invalid protocol version"),
+        "hello" => Err("Invalid HELLO command"),
+        "format" if tokens.len() == 1 => Ok(Command::Format(None, None)),
+        "format" if tokens.len() == 2 => Format::from_name(&tokens[1].to_lowercase())
+            .map(|format| Command::Format(Some(format), None))
+            .ok_or("Unknown format"),
+        "format" if tokens.len() == 3 && tokens[1].eq_ignore_ascii_case("text") => {
+            Notation::from_name(&tokens[2].to_lowercase())
+                .map(|notation| Command::Format(Some(Format::Text), Some(notation)))
+                .ok_or("Unknown notation")
+        }
+        "format" => Err("Invalid FORMAT command"),
+        "auth" if tokens.len() == 2 => Ok(Command::Auth(tokens[1].clone())),
+        "auth" => Err("Invalid AUTH command"),
+        "help" if tokens.len() == 1 => Ok(Command::Help(None)),
+        "help" => Ok(Command::Help(Some(tokens[1..].join(" ")))),
+        "monitor" => Ok(Command::Monitor),
+        "subscribe" => {
+            if tokens.len() < 2 {
+                return Err("Invalid SUBSCRIBE command");
+            }
+            Ok(Command::Subscribe(tokens[1..].to_vec()))
+        }
+        "select" if tokens.len() == 2 => tokens[1]
+            .parse()
+            .map(Command::Select)
+            .map_err(|_| "Invalid database number"),
+        "select" => Err("Invalid SELECT command"),
+        "slowlog" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("get") if tokens.len() == 2 => Ok(Command::SlowlogGet(DEFAULT_SLOWLOG_GET)),
+            Some("get") if tokens.len() == 3 => tokens[2]
+                .parse()
+                .map(Command::SlowlogGet)
+                .map_err(|_| "Invalid SLOWLOG command"),
+            Some("len") if tokens.len() == 2 => Ok(Command::SlowlogLen),
+            Some("reset") if tokens.len() == 2 => Ok(Command::SlowlogReset),
+            _ => Err("Invalid SLOWLOG command"),
+        },
+        "stats" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            None => Ok(Command::Stats),
+            Some("reset") if tokens.len() == 2 => Ok(Command::StatsReset),
+            Some("dims") if tokens.len() == 2 => Ok(Command::StatsDims(String::new())),
+            Some("dims") if tokens.len() == 3 => Ok(Command::StatsDims(parse_prefix(&tokens[2]))),
+            _ => Err("Invalid STATS command"),
+        },
+        "collection" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("stats") if tokens.len() == 3 => {
+                Ok(Command::CollectionStats(parse_prefix(&tokens[2])))
+            }
+            _ => Err("Invalid COLLECTION command"),
+        },
+        "randomkey" if tokens.len() == 1 => Ok(Command::RandomKey),
+        "randomkey" => Err("Invalid RANDOMKEY command"),
+        "sample" if tokens.len() == 2 => tokens[1]
+            .parse()
+            .map(Command::Sample)
+            .map_err(|_| "Invalid SAMPLE command"),
+        "sample" => Err("Invalid SAMPLE command"),
+        "count" if tokens.len() == 1 => Ok(Command::Count(String::new())),
+        "count" if tokens.len() == 2 => Ok(Command::Count(parse_prefix(&tokens[1]))),
+        "count" => Err("Invalid COUNT command"),
+        "aggregate" if tokens.len() == 3 => Aggregation::from_name(&tokens[1].to_lowercase())
+            .map(|aggregation| Command::Aggregate(aggregation, parse_prefix(&tokens[2])))
+            .ok_or("Invalid AGGREGATE command"),
+        "aggregate" => Err("Invalid AGGREGATE command"),
+        "compact" if tokens.len() == 1 => Ok(Command::Compact),
+        "compact" => Err("Invalid COMPACT command"),
+        "embed" if tokens.len() == 3 => Ok(Command::Embed(tokens[1].clone(), tokens[2].clone())),
+        "embed" => Err("Invalid EMBED command"),
+        "search_text" if (3..=4).contains(&tokens.len()) => {
+            let (text, k, prefix) = parse_text_query(&tokens[1..])?;
+            Ok(Command::SearchText(text, k, prefix))
+        }
+        "search_text" => Err("Invalid SEARCH_TEXT command"),
+        "insert_text" if tokens.len() == 3 => {
+            Ok(Command::InsertText(tokens[1].clone(), tokens[2].clone()))
+        }
+        "insert_text" => Err("Invalid INSERT_TEXT command"),
+        "query_text" if (3..=4).contains(&tokens.len()) => {
+            let (text, k, prefix) = parse_text_query(&tokens[1..])?;
+            Ok(Command::QueryText(text, k, prefix))
+        }
+        "query_text" => Err("Invalid QUERY_TEXT command"),
+        "eval" if tokens.len() >= 2 => Ok(Command::Eval(tokens[1].clone(), tokens[2..].to_vec())),
+        "eval" => Err("Invalid EVAL command"),
+        "warmup" if tokens.len() <= 3 => {
+            let indexes =
+                tokens.len() > 1 && tokens[tokens.len() - 1].eq_ignore_ascii_case("withindexes");
+            let prefix = match tokens.len() - indexes as usize {
+                1 => String::new(),
+                2 => parse_prefix(&tokens[1]),
+                _ => return Err("Invalid WARMUP command"),
+            };
+            Ok(Command::Warmup(prefix, indexes))
+        }
+        "warmup" => Err("Invalid WARMUP command"),
+        "backup" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("now") if tokens.len() == 2 => Ok(Command::BackupNow),
+            _ => Err("Invalid BACKUP command"),
+        },
+        "restore" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("until") if tokens.len() == 3 => RecoveryPoint::parse(&tokens[2])
+                .map(Command::RestoreUntil)
+                .ok_or("Invalid RESTORE command"),
+            _ => Err("Invalid RESTORE command"),
+        },
+        "snapshots" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("list") if tokens.len() == 2 => Ok(Command::SnapshotsList),
+            Some("info") if tokens.len() == 3 => Ok(Command::SnapshotsInfo(tokens[2].clone())),
+            Some("delete") if tokens.len() == 3 => Ok(Command::SnapshotsDelete(tokens[2].clone())),
+            _ => Err("Invalid SNAPSHOTS command"),
+        },
+        "shutdown" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            None => Ok(Command::Shutdown(true)),
+            Some("save") if tokens.len() == 2 => Ok(Command::Shutdown(true)),
+            Some("nosave") if tokens.len() == 2 => Ok(Command::Shutdown(false)),
+            _ => Err("Invalid SHUTDOWN command"),
+        },
         "insert" => {
             if tokens.len() < 2 {
                 return Err("Invalid INSERT command");
             }
-            let values = tokens[1..]
-                .iter()
-                .filter_map(|s| s.parse::<f32>().ok())
-                .collect();
-
+            let values = parse_components(&tokens[1..])?;
             Ok(Command::Insert(values))
         }
         "named_insert" => {
@@ -62,19 +1399,31 @@ pub fn parse_command(input: &str) -> Result<Command, &str> {
                 return Err("Invalid NAMED_INSERT command");
             }
             let key = tokens[1].to_string();
-            let values = tokens[2..]
-                .iter()
-                .filter_map(|s| s.parse::<f32>().ok())
-                .collect();
-
+            let values = parse_components(&tokens[2..])?;
             Ok(Command::NamedInsert(key, values))
         }
+        "binsert" if tokens.len() == 4 => parse_binary_insert(&tokens[1], &tokens[2], &tokens[3]),
+        "binsert" => Err("Invalid BINSERT command"),
+        "alias" if tokens.len() == 3 => {
+            Ok(Command::Alias(tokens[1].to_string(), tokens[2].to_string()))
+        }
+        "alias" => Err("Invalid ALIAS command"),
         "get" => {
+            let key = tokens.get(1).ok_or("Invalid GET command")?.to_string();
+            let options = parse_get_options(&tokens[2..]).ok_or("Invalid GET command")?;
+            Ok(Command::Get(key, options))
+        }
+        "dim" => {
             if tokens.len() != 2 {
-                return Err("Invalid GET command");
+                return Err("Invalid DIM command");
             }
-            let key = tokens[1].to_string();
-            Ok(Command::Get(key))
+            Ok(Command::Dim(tokens[1].to_string()))
+        }
+        "describe" => {
+            if tokens.len() != 2 {
+                return Err("Invalid DESCRIBE command");
+            }
+            Ok(Command::Describe(tokens[1].to_string()))
         }
         "remove" => {
             if tokens.len() != 2 {
@@ -83,15 +1432,35 @@ pub fn parse_command(input: &str) -> Result<Command, &str> {
             let key = tokens[1].to_string();
             Ok(Command::Remove(key))
         }
+        "del" if tokens.len() >= 2 => Ok(Command::Delete(tokens[1..].to_vec())),
+        "del" => Err("Invalid DEL command"),
+        "delprefix" if tokens.len() == 2 => Ok(Command::RemovePrefix(parse_prefix(&tokens[1]))),
+        "delprefix" => Err("Invalid DELPREFIX command"),
         "knn" => {
-            let key = tokens.get(1).ok_or("Missing key")?.to_string();
-            let k = tokens
-                .get(2)
-                .ok_or("Missing k")?
-                .parse::<usize>()
-                .map_err(|_| "Invalid k value")?;
-            Ok(Command::KNearestNeighbors(key, k))
+            let (key, k, options) = parse_knn(&tokens[1..])?;
+            Ok(Command::KNearestNeighbors(key, k, options))
         }
+        "knn_in" => parse_knn_in(&tokens[1..]),
+        "scan" => parse_scan(&tokens[1..]),
+        "explain" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("knn") => {
+                let (key, k, options) = parse_knn(&tokens[2..])?;
+                Ok(Command::ExplainKnn(key, k, options))
+            }
+            _ => Err("Invalid EXPLAIN command"),
+        },
+        "bench" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("recall") if tokens.len() == 4 => {
+                let sample_size = tokens[2]
+                    .parse()
+                    .ok()
+                    .filter(|&sample_size| sample_size >= 1)
+                    .ok_or("Invalid sample size")?;
+                let k = tokens[3].parse().map_err(|_| "Invalid k value")?;
+                Ok(Command::BenchRecall(sample_size, k))
+            }
+            _ => Err("Invalid BENCH command"),
+        },
         "vadd" => {
             let key1 = tokens.get(1).ok_or("Missing key1")?.to_string();
             let key2 = tokens.get(2).ok_or("Missing key2")?.to_string();
@@ -108,7 +1477,9 @@ pub fn parse_command(input: &str) -> Result<Command, &str> {
                 .get(2)
                 .ok_or("Missing scalar")?
                 .parse::<f32>()
-                .map_err(|_| "Invalid scalar value")?;
+                .ok()
+                .filter(|scalar| scalar.is_finite())
+                .ok_or("Invalid scalar value")?;
             Ok(Command::VectorScaling(key, scalar))
         }
         "vcosine" => {
@@ -117,12 +1488,436 @@ pub fn parse_command(input: &str) -> Result<Command, &str> {
             Ok(Command::CosineSimilarity(key1, key2))
         }
         "dump" => {
-            if tokens.len() != 2 {
-                return Err("Invalid DUMP command");
-            }
+            let prefix = match tokens.len() {
+                2 => None,
+                4 if tokens[2].eq_ignore_ascii_case("prefix") => Some(tokens[3].to_string()),
+                _ => return Err("Invalid DUMP command"),
+            };
             let file_path = tokens[1].to_string();
-            Ok(Command::Dump(file_path))
+            Ok(Command::Dump(file_path, prefix))
+        }
+        "changes" => {
+            let seq = tokens
+                .get(1)
+                .ok_or("Missing sequence number")?
+                .parse::<u64>()
+                .map_err(|_| "Invalid sequence number")?;
+            Ok(Command::Changes(seq))
+        }
+        "sync" => Ok(Command::Sync),
+        "replicaof" => {
+            let host = tokens.get(1).ok_or("Missing host")?;
+            let port = tokens.get(2).ok_or("Missing port")?;
+            if tokens.len() != 3 {
+                return Err("Invalid REPLICAOF command");
+            }
+            if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+                return Ok(Command::ReplicaOf(None));
+            }
+            port.parse::<u16>().map_err(|_| "Invalid port")?;
+            Ok(Command::ReplicaOf(Some(format!("{}:{}", host, port))))
+        }
+        "cluster" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("nodes") if tokens.len() == 2 => Ok(Command::ClusterNodes),
+            _ => Err("Invalid CLUSTER command"),
+        },
+        "raft" => parse_raft(&tokens[1..]),
+        "index" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("create") if (4..=5).contains(&tokens.len()) => {
+                let kind =
+                    IndexKind::from_name(&tokens[3].to_lowercase()).ok_or("Unknown index kind")?;
+                let metric = tokens.get(4).map(|metric| metric.to_lowercase());
+                Ok(Command::IndexCreate(parse_prefix(&tokens[2]), kind, metric))
+            }
+            Some("rebuild") if tokens.len() >= 3 && tokens.len() % 2 == 1 => {
+                let params = tokens[3..]
+                    .chunks(2)
+                    .map(|pair| {
+                        let value = pair[1].parse().map_err(|_| "Invalid index parameter")?;
+                        Ok((pair[0].to_lowercase(), value))
+                    })
+                    .collect::<Result<Vec<(String, usize)>, &str>>()?;
+                Ok(Command::IndexRebuild(parse_prefix(&tokens[2]), params))
+            }
+            Some("drop") if tokens.len() == 3 => Ok(Command::IndexDrop(parse_prefix(&tokens[2]))),
+            Some("list") if tokens.len() == 2 => Ok(Command::IndexList),
+            Some("status") if tokens.len() == 2 => Ok(Command::IndexStatus),
+            _ => Err("Invalid INDEX command"),
+        },
+        "reduce" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("drop") if tokens.len() == 3 => Ok(Command::ReduceDrop(parse_prefix(&tokens[2]))),
+            Some("list") if tokens.len() == 2 => Ok(Command::ReduceList),
+            Some(kind) if tokens.len() == 4 => {
+                let kind = ReductionKind::from_name(kind).ok_or("Unknown reduction kind")?;
+                let dim = tokens[3].parse().map_err(|_| "Invalid dimension")?;
+                Ok(Command::Reduce(parse_prefix(&tokens[2]), kind, dim))
+            }
+            _ => Err("Invalid REDUCE command"),
+        },
+        "pipeline" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("set") if tokens.len() >= 4 => Ok(Command::PipelineSet(
+                parse_prefix(&tokens[2]),
+                tokens[3..].to_vec(),
+            )),
+            Some("drop") if tokens.len() == 3 => {
+                Ok(Command::PipelineDrop(parse_prefix(&tokens[2])))
+            }
+            Some("list") if tokens.len() == 2 => Ok(Command::PipelineList),
+            _ => Err("Invalid PIPELINE command"),
+        },
+        "ttl" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("set") if tokens.len() == 4 => {
+                let ttl = tokens[3].parse().map_err(|_| "Invalid time to live")?;
+                Ok(Command::TtlSet(parse_prefix(&tokens[2]), ttl))
+            }
+            Some("drop") if tokens.len() == 3 => Ok(Command::TtlDrop(parse_prefix(&tokens[2]))),
+            Some("list") if tokens.len() == 2 => Ok(Command::TtlList),
+            _ => Err("Invalid TTL command"),
+        },
+        "config" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("get") if tokens.len() == 3 => Ok(Command::ConfigGet(tokens[2].to_lowercase())),
+            Some("set") if tokens.len() == 4 => Ok(Command::ConfigSet(
+                tokens[2].to_lowercase(),
+                tokens[3].to_string(),
+            )),
+            _ => Err("Invalid CONFIG command"),
+        },
+        "import" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("csv") => {
+                let path = tokens.get(2).ok_or("Missing path")?.to_string();
+                let mut options = CsvOptions::default();
+                let mut flags = tokens[3..].iter();
+                while let Some(flag) = flags.next() {
+                    match flag.to_lowercase().as_str() {
+                        "--key-column" => {
+                            options.key_column = flags
+                                .next()
+                                .ok_or("Missing key column")?
+                                .parse::<usize>()
+                                .map_err(|_| "Invalid key column")?;
+                        }
+                        "--header" => options.header = true,
+                        _ => return Err("Invalid IMPORT command"),
+                    }
+                }
+                Ok(Command::ImportCsv(path, options))
+            }
+            Some("npy") => {
+                let (path, keys_path) = parse_npy_paths(&tokens).ok_or("Invalid IMPORT command")?;
+                Ok(Command::ImportNpy(path, keys_path))
+            }
+            Some("jsonl") if tokens.len() == 3 => Ok(Command::ImportJsonl(tokens[2].to_string())),
+            _ => Err("Invalid IMPORT command"),
+        },
+        "export" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("npy") => {
+                let (path, keys_path) = parse_npy_paths(&tokens).ok_or("Invalid EXPORT command")?;
+                Ok(Command::ExportNpy(path, keys_path))
+            }
+            Some("jsonl") if tokens.len() == 3 => Ok(Command::ExportJsonl(tokens[2].to_string())),
+            Some("vec") if tokens.len() == 3 => Ok(Command::ExportVec(tokens[2].to_string())),
+            _ => Err("Invalid EXPORT command"),
+        },
+        "bulk" => {
+            if tokens.len() < 3 || tokens.len() > 4 {
+                return Err("Invalid BULK command");
+            }
+            let count = tokens[1].parse::<usize>().map_err(|_| "Invalid count")?;
+            let dim = tokens[2]
+                .parse::<usize>()
+                .map_err(|_| "Invalid dimension")?;
+            if dim == 0 {
+                return Err("Invalid dimension");
+            }
+            let keyed = match tokens.get(3) {
+                Some(flag) if flag.eq_ignore_ascii_case("keys") => true,
+                Some(_) => return Err("Invalid BULK command"),
+                None => false,
+            };
+            Ok(Command::Bulk(BulkHeader { count, dim, keyed }))
         }
+        "arrow" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("upload") if tokens.len() == 3 => {
+                let size = tokens[2].parse::<usize>().map_err(|_| "Invalid size")?;
+                Ok(Command::ArrowUpload(size))
+            }
+            Some("download") if tokens.len() == 2 => Ok(Command::ArrowDownload),
+            _ => Err("Invalid ARROW command"),
+        },
+        "metric" => match tokens.get(1).map(|s| s.to_lowercase()).as_deref() {
+            Some("upload") if tokens.len() == 4 => {
+                let size = tokens[3].parse::<usize>().map_err(|_| "Invalid size")?;
+                Ok(Command::MetricUpload(tokens[2].to_string(), size))
+            }
+            Some("list") if tokens.len() == 2 => Ok(Command::MetricList),
+            _ => Err("Invalid METRIC command"),
+        },
         _ => Err("Unknown command"),
     }
 }
+
+/// Parses the flags following the key of `get`: `withmeta`, `withnorm` and `raw`,
+/// optionally followed by `f16` or `bf16`, in any order.
+fn parse_get_options(flags: &[String]) -> Option<GetOptions> {
+    let mut options = GetOptions::default();
+    let mut flags = flags.iter().map(|flag| flag.to_lowercase()).peekable();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "withmeta" => options.meta = true,
+            "withnorm" => options.norm = true,
+            "raw" => {
+                options.raw = true;
+                options.precision = match flags.peek().map(String::as_str) {
+                    Some("f16") => Some(Precision::F16),
+                    Some("bf16") => Some(Precision::Bf16),
+                    _ => None,
+                };
+                if options.precision.is_some() {
+                    flags.next();
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+/// Parses the `<path> [keys <keys_path>]` arguments of `import npy` and `export npy`.
+fn parse_npy_paths(tokens: &[String]) -> Option<(String, Option<String>)> {
+    match tokens {
+        [_, _, path] => Some((path.to_string(), None)),
+        [_, _, path, keyword, keys_path] if keyword.eq_ignore_ascii_case("keys") => {
+            Some((path.to_string(), Some(keys_path.to_string())))
+        }
+        _ => None,
+    }
+}
+
+/// Parses `insert`, `named_insert` and `binsert` straight from the command line,
+/// without copying their arguments, since parsing the components is most of the
+/// work of ingesting vectors. Returns `None` for lines left to the general parser: other
+/// commands, and lines with quotes or non-ASCII characters, which may need to
+/// be unquoted or split on Unicode whitespace.
+fn parse_insert(input: &str) -> Option<Result<Command, &'static str>> {
+    let line = input.as_bytes();
+    if !line.is_ascii() || line.contains(&b'"') || line.contains(&b'\'') {
+        return None;
+    }
+    let mut arguments = line
+        .split(|&byte| (byte as char).is_whitespace())
+        .filter(|argument| !argument.is_empty())
+        .peekable();
+    let name = arguments.next()?;
+    if name.eq_ignore_ascii_case(b"binsert") {
+        // The line is ASCII, so its arguments are valid UTF-8
+        let text = |argument| String::from_utf8_lossy(argument);
+        return match (arguments.next(), arguments.next(), arguments.next()) {
+            (Some(key), Some(dim), Some(payload)) if arguments.next().is_none() => {
+                Some(parse_binary_insert(&text(key), &text(dim), payload))
+            }
+            _ => Some(Err("Invalid BINSERT command")),
+        };
+    }
+    let key = if name.eq_ignore_ascii_case(b"insert") {
+        None
+    } else if name.eq_ignore_ascii_case(b"named_insert") {
+        match arguments.next() {
+            Some(key) => Some(String::from_utf8_lossy(key).into_owned()),
+            None => return Some(Err("Invalid NAMED_INSERT command")),
+        }
+    } else {
+        return None;
+    };
+    if arguments.peek().is_none() {
+        return Some(Err(match key {
+            Some(_) => "Invalid NAMED_INSERT command",
+            None => "Invalid INSERT command",
+        }));
+    }
+    Some(parse_components(arguments).map(|values| match key {
+        Some(key) => Command::NamedInsert(key, values),
+        None => Command::Insert(values),
+    }))
+}
+
+/// Parses the components of a vector, skipping arguments that are not numbers.
+fn parse_components<I>(arguments: I) -> Result<Vec<f32>, &'static str>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let values = arguments
+        .into_iter()
+        .filter_map(|argument| fast_float2::parse::<f32, _>(argument).ok())
+        .collect::<Vec<f32>>();
+    Ok(values)
+}
+
+/// Parses the arguments of `binsert`: the key, the dimension, and the base64
+/// payload holding the components of the vector as little-endian `f32`s.
+fn parse_binary_insert(
+    key: &str,
+    dim: &str,
+    payload: impl AsRef<[u8]>,
+) -> Result<Command, &'static str> {
+    let dim = dim
+        .parse::<usize>()
+        .ok()
+        .filter(|&dim| dim > 0)
+        .ok_or("Invalid dimension")?;
+    let bytes = BASE64
+        .decode(payload)
+        .map_err(|_| "Invalid base64 payload")?;
+    if dim.checked_mul(4) != Some(bytes.len()) {
+        return Err("Payload length does not match the dimension");
+    }
+    let values = bytes
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<f32>>();
+    Ok(Command::BinaryInsert(key.to_string(), values))
+}
+
+/// Parses the arguments of `knn_in`: k, the key of the query vector or its
+/// components, and `keys` followed by the candidate keys.
+fn parse_knn_in(arguments: &[String]) -> Result<Command, &'static str> {
+    let k = arguments
+        .first()
+        .ok_or("Invalid KNN_IN command")?
+        .parse::<usize>()
+        .map_err(|_| "Invalid k value")?;
+    let separator = arguments
+        .iter()
+        .position(|argument| argument.eq_ignore_ascii_case("keys"))
+        .filter(|&separator| separator > 1 && separator + 1 < arguments.len())
+        .ok_or("Invalid KNN_IN command")?;
+    let query = match &arguments[1..separator] {
+        [key] => KnnQuery::Key(key.to_string()),
+        components => {
+            let vector = parse_components(components)?;
+            if vector.len() != components.len() {
+                return Err("Invalid query vector");
+            }
+            KnnQuery::Vector(vector)
+        }
+    };
+    Ok(Command::KnnIn(
+        k,
+        query,
+        arguments[separator + 1..].to_vec(),
+    ))
+}
+
+/// Parses the arguments of `knn`: a key and k, optionally followed by a metric, and
+/// by `offset` and a number of neighbors to skip and `timeout` and a number of
+/// milliseconds, in any order.
+fn parse_knn(arguments: &[String]) -> Result<(String, usize, KnnOptions), &'static str> {
+    let key = arguments.first().ok_or("Missing key")?.to_string();
+    let k = arguments
+        .get(1)
+        .ok_or("Missing k")?
+        .parse::<usize>()
+        .map_err(|_| "Invalid k value")?;
+    let mut options = arguments[2..].iter().peekable();
+    let mut knn_options = KnnOptions {
+        metric: options
+            .next_if(|option| !KNN_OPTIONS.contains(&option.to_lowercase().as_str()))
+            .map(|metric| metric.to_lowercase()),
+        ..KnnOptions::default()
+    };
+    while let Some(option) = options.next() {
+        match option.to_lowercase().as_str() {
+            "offset" => {
+                knn_options.offset = options
+                    .next()
+                    .ok_or("Missing offset")?
+                    .parse()
+                    .map_err(|_| "Invalid offset value")?;
+            }
+            "timeout" => {
+                let timeout = options.next().ok_or("Missing timeout")?;
+                knn_options.timeout = Some(timeout.parse().map_err(|_| "Invalid timeout value")?);
+            }
+            "rerank" => {
+                let factor = options
+                    .next()
+                    .ok_or("Missing rerank factor")?
+                    .parse()
+                    .ok()
+                    .filter(|&factor| factor >= 1)
+                    .ok_or("Invalid rerank factor")?;
+                knn_options.rerank = Some(factor);
+            }
+            _ => return Err("Invalid KNN command"),
+        }
+    }
+    Ok((key, k, knn_options))
+}
+
+/// Parses the arguments of `search_text` and `query_text`: a text and k, optionally
+/// followed by the prefix of the collection to search.
+fn parse_text_query(arguments: &[String]) -> Result<(String, usize, String), &'static str> {
+    let k = arguments[1].parse().map_err(|_| "Invalid k value")?;
+    let prefix = arguments
+        .get(2)
+        .map_or(String::new(), |prefix| parse_prefix(prefix));
+    Ok((arguments[0].clone(), k, prefix))
+}
+
+/// Parses the arguments of `scan`: optionally `after` and the key to resume after,
+/// and `count` and the number of keys to return, in any order.
+fn parse_scan(arguments: &[String]) -> Result<Command, &'static str> {
+    let mut after = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    let mut options = arguments.iter();
+    while let Some(option) = options.next() {
+        match option.to_lowercase().as_str() {
+            "after" => after = Some(options.next().ok_or("Missing key")?.to_string()),
+            "count" => {
+                count = options
+                    .next()
+                    .ok_or("Missing count")?
+                    .parse()
+                    .ok()
+                    .filter(|count| (1..=MAX_SCAN_COUNT).contains(count))
+                    .ok_or("Count must be between 1 and 10000")?;
+            }
+            _ => return Err("Invalid SCAN command"),
+        }
+    }
+    Ok(Command::Scan(after, count))
+}
+
+/// Parses the arguments of `raft`: the subcommand, followed by the terms, addresses
+/// and sequence numbers it takes.
+fn parse_raft(arguments: &[String]) -> Result<Command, &'static str> {
+    let number = |index: usize| {
+        arguments
+            .get(index)
+            .and_then(|number| number.parse::<u64>().ok())
+            .ok_or("Invalid RAFT command")
+    };
+    match arguments.first().map(|s| s.to_lowercase()).as_deref() {
+        Some("status") if arguments.len() == 1 => Ok(Command::RaftStatus),
+        Some("vote") if arguments.len() == 5 => Ok(Command::RaftVote(VoteRequest {
+            term: number(1)?,
+            candidate: arguments[2].clone(),
+            position: (number(3)?, number(4)?),
+        })),
+        Some("heartbeat") if arguments.len() == 3 => {
+            Ok(Command::RaftHeartbeat(number(1)?, arguments[2].clone()))
+        }
+        Some("changes") if arguments.len() == 4 => Ok(Command::RaftChanges(
+            number(1)?,
+            arguments[2].clone(),
+            number(3)?,
+        )),
+        _ => Err("Invalid RAFT command"),
+    }
+}
+
+/// Parses the key prefix of a collection, where `*` stands for the whole database.
+/// A trailing `*` is dropped, so `docs:*` stands for the `docs:` collection.
+fn parse_prefix(prefix: &str) -> String {
+    prefix.strip_suffix('*').unwrap_or(prefix).to_string()
+}
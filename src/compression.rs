@@ -0,0 +1,148 @@
+use crate::encryption;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Result as IoResult, Write};
+
+/// The compression applied to a database dump.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// Plain JSON.
+    None,
+    /// Gzip-compressed JSON, used for paths ending in `.gz`.
+    Gzip,
+    /// Zstandard-compressed JSON, used for paths ending in `.zst`.
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    /// Chooses the compression from the extension of a dump path.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Returns the name of the compression: `none`, `gzip` or `zstd`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Detects the compression of a dump from its first bytes, so compressed dumps
+    /// can be loaded whatever their name.
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Creates `path` and writes to it through `write`, compressing the output as
+/// chosen by the path's extension, then encrypting it if an encryption key is
+/// installed.
+pub fn write_file<F>(path: &str, write: F) -> IoResult<()>
+where
+    F: FnOnce(&mut dyn Write) -> IoResult<()>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    #[cfg(feature = "encryption")]
+    if let Some(key) = encryption::key() {
+        let mut encryptor = encryption::Encryptor::new(file, key)?;
+        compress(path, &mut encryptor, write)?;
+        return encryptor.finish()?.flush();
+    }
+    compress(path, &mut file, write)?;
+    file.flush()
+}
+
+/// Writes to `output` through `write`, compressing the output as chosen by the
+/// extension of `path`.
+fn compress<F>(path: &str, output: &mut dyn Write, write: F) -> IoResult<()>
+where
+    F: FnOnce(&mut dyn Write) -> IoResult<()>,
+{
+    match Compression::from_path(path) {
+        Compression::None => write(output),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish().map(|_| ())
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            write(&mut encoder)?;
+            encoder.finish().map(|_| ())
+        }
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => Err(zstd_unsupported()),
+    }
+}
+
+/// Opens `path` for reading, transparently decrypting it if it is an encrypted
+/// dump and decompressing it if it is a gzip or zstd-compressed dump.
+pub fn open_file(path: &str) -> IoResult<Box<dyn Read>> {
+    let (header, reader) = peek(BufReader::new(File::open(path)?), encryption::MAGIC.len())?;
+    if header.starts_with(encryption::MAGIC) {
+        #[cfg(feature = "encryption")]
+        {
+            let key = encryption::require_key("dump")?;
+            let decryptor = BufReader::new(encryption::Decryptor::new(reader, key)?);
+            let (header, reader) = peek(decryptor, ZSTD_MAGIC.len())?;
+            return decompress(&header, reader);
+        }
+        #[cfg(not(feature = "encryption"))]
+        return Err(encryption::unsupported());
+    }
+    decompress(&header, reader)
+}
+
+/// Reads up to `length` bytes from the start of `reader`, returning them along with
+/// a reader that reads them again.
+fn peek<R: Read>(mut reader: R, length: usize) -> IoResult<(Vec<u8>, impl Read)> {
+    let mut header = vec![0; length];
+    let mut read = 0;
+    while read < header.len() {
+        match reader.read(&mut header[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    header.truncate(read);
+    Ok((header.clone(), std::io::Cursor::new(header).chain(reader)))
+}
+
+/// Wraps `reader`, whose first bytes are `header`, into a reader decompressing it.
+fn decompress<R: Read + 'static>(header: &[u8], reader: R) -> IoResult<Box<dyn Read>> {
+    Ok(match Compression::detect(header) {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(GzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Zstandard compression is not enabled in this build",
+    )
+}
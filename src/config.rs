@@ -0,0 +1,748 @@
+use crate::acl;
+use crate::backup::DEFAULT_BACKUP_RETENTION;
+use crate::changelog::DEFAULT_CHANGELOG_CAPACITY;
+use crate::format::Notation;
+use crate::gpu;
+use crate::limits::DEFAULT_MAX_LINE_LENGTH;
+use crate::metric::{self, DEFAULT_METRIC};
+use crate::slowlog::{DEFAULT_SLOWLOG_SIZE, DEFAULT_SLOWLOG_THRESHOLD};
+use crate::storage::DEFAULT_CACHE_SIZE;
+use crate::vemcache::Dedup;
+use serde::Deserialize;
+
+/// The endpoint `embed` and `search_text` embed text with when none is configured.
+const DEFAULT_EMBED_URL: &str = "https://api.openai.com/v1/embeddings";
+
+/// The model text is embedded with when none is configured.
+const DEFAULT_EMBED_MODEL: &str = "text-embedding-3-small";
+
+/// The S3-compatible object storage backups are pushed to when none is configured.
+const DEFAULT_S3_ENDPOINT: &str = "https://s3.amazonaws.com";
+
+/// The region of the bucket backups are pushed to when none is configured.
+const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// The environment variables named like the settings of Redis that are accepted
+/// along with those of the parameters, with the parameter they set.
+/// `VEMCACHE_PASSWORD` grants the admin role, like the password of Redis grants
+/// every command.
+const ENV_ALIASES: &[(&str, &str)] = &[
+    ("VEMCACHE_MAXMEMORY", "max_memory"),
+    ("VEMCACHE_PASSWORD", "acl_tokens"),
+];
+
+/// The names of all configuration parameters, in the order they are listed.
+const PARAMETERS: &[&str] = &[
+    "host",
+    "port",
+    "admin_host",
+    "admin_port",
+    "rest_port",
+    "probe_port",
+    "read_only",
+    "acl_tokens",
+    "changelog_size",
+    "replicaof",
+    "primary_token",
+    "cluster_nodes",
+    "cluster_self",
+    "raft_nodes",
+    "raft_self",
+    "data_dir",
+    "allow_any_dump_path",
+    "backup_dir",
+    "backup_interval",
+    "backup_retention",
+    "backup_deltas",
+    "s3_endpoint",
+    "s3_region",
+    "s3_bucket",
+    "s3_prefix",
+    "s3_access_key",
+    "s3_secret_key",
+    "audit_file",
+    "encryption_key",
+    "encryption_key_file",
+    "encryption_key_command",
+    "metric",
+    "dedup",
+    "storage",
+    "storage_cache_size",
+    "storage_file",
+    "gpu",
+    "gpu_threshold",
+    "slowlog_threshold",
+    "slowlog_size",
+    "max_line_length",
+    "max_commands_per_second",
+    "max_memory",
+    "client_timeout",
+    "query_timeout",
+    "autotune_recall",
+    "autotune_interval",
+    "autotuned",
+    "float_format",
+    "tcp_keepalive",
+    "tcp_nodelay",
+    "databases",
+    "embed_url",
+    "embed_model",
+    "embed_api_key",
+    "text_model_dir",
+];
+
+/// The server configuration.
+///
+/// The configuration is read from an optional TOML file, then overridden by
+/// environment variables, and finally by command-line flags. Every parameter can be read at runtime with `config get`.
+/// The parameters that can safely change while the server is running can also be
+/// updated with `config set`, without requiring a restart.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address the server listens on.
+    pub host: String,
+    /// The port the server listens on.
+    pub port: u16,
+    /// The address the admin port listens on.
+    pub admin_host: String,
+    /// The port on which admin commands are accepted, or 0 to accept them on the
+    /// main port. Once set, admin commands are rejected on the main port.
+    pub admin_port: u16,
+    /// The port the Qdrant-compatible REST API listens on, on `host`, or 0 to disable it.
+    pub rest_port: u16,
+    /// The port the readiness, liveness and preStop probes are answered on, on
+    /// `host`, or 0 to disable them. See the `probe` module.
+    pub probe_port: u16,
+    /// Whether commands that modify the database are rejected.
+    pub read_only: bool,
+    /// The tokens clients authenticate with, as `role:token` entries, empty to let
+    /// every client run every command. See the `acl` module. They are hidden from
+    /// `config get`.
+    pub acl_tokens: Vec<String>,
+    /// The number of changes retained in the change log.
+    pub changelog_size: usize,
+    /// The `host:port` address of the primary this server replicates from.
+    pub replicaof: Option<String>,
+    /// The token this server authenticates with to its primary, or to the other
+    /// nodes of its Raft group, if they require one. It is hidden from `config get`.
+    pub primary_token: Option<String>,
+    /// The addresses of all nodes of the cluster, empty if cluster mode is disabled.
+    pub cluster_nodes: Vec<String>,
+    /// The address of this node, as it appears in `cluster_nodes`.
+    pub cluster_self: Option<String>,
+    /// The addresses of all nodes of the Raft group, empty if Raft mode is disabled.
+    /// See the `raft` module.
+    pub raft_nodes: Vec<String>,
+    /// The address of this node, as it appears in `raft_nodes`.
+    pub raft_self: Option<String>,
+    /// The directory holding snapshots and the write-ahead log, if persistence is enabled.
+    pub data_dir: Option<String>,
+    /// Whether `dump` may write outside of the data directory.
+    pub allow_any_dump_path: bool,
+    /// The directory backups are written to, if backups are enabled. See the
+    /// `backup` module.
+    pub backup_dir: Option<String>,
+    /// The interval between scheduled backups, in seconds, or 0 to only back up
+    /// with `backup now`.
+    pub backup_interval: u64,
+    /// The number of full backups of every database kept along with their delta
+    /// backups, or 0 to keep them all.
+    pub backup_retention: usize,
+    /// The number of delta backups, holding only the changes since the last full
+    /// backup, taken between full backups.
+    pub backup_deltas: usize,
+    /// The URL of the S3-compatible object storage backups are pushed to.
+    pub s3_endpoint: String,
+    /// The region of the bucket, used to sign requests.
+    pub s3_region: String,
+    /// The bucket backups are pushed to and restored from, if any. See the `s3` module.
+    pub s3_bucket: Option<String>,
+    /// The prefix of the keys of the backups in the bucket, e.g. `vemcache/`.
+    pub s3_prefix: String,
+    /// The access key ID requests to the storage are signed with.
+    pub s3_access_key: Option<String>,
+    /// The secret access key requests to the storage are signed with. It is hidden
+    /// from `config get`.
+    pub s3_secret_key: Option<String>,
+    /// The file every command changing the database or the server is appended to,
+    /// if auditing is enabled. See the `audit` module.
+    pub audit_file: Option<String>,
+    /// The key dumps and the write-ahead log are encrypted with, as 64 hexadecimal
+    /// digits, if encryption is enabled. See the `encryption` module. It is hidden
+    /// from `config get`.
+    pub encryption_key: Option<String>,
+    /// The file the encryption key is read from, instead of `encryption_key`.
+    pub encryption_key_file: Option<String>,
+    /// The shell command printing the encryption key, such as a call to a key
+    /// management service, instead of `encryption_key`.
+    pub encryption_key_command: Option<String>,
+    /// The metric `knn` ranks vectors by when none is given.
+    pub metric: String,
+    /// What happens when a vector identical to one already stored is inserted under
+    /// another key: `off`, `reject` or `share`. See `Dedup`.
+    pub dedup: String,
+    /// Where vectors are kept: `memory`, `disk` to keep them in the data directory,
+    /// or `mmap` to serve them read-only from `storage_file`.
+    pub storage: String,
+    /// The number of vectors the disk storage keeps in memory.
+    pub storage_cache_size: usize,
+    /// The vector file served by the memory-mapped storage.
+    pub storage_file: Option<String>,
+    /// Whether exhaustive searches are offloaded to the GPU, if there is one.
+    pub gpu: bool,
+    /// The number of vectors the database has to hold for searches to run on the GPU.
+    pub gpu_threshold: usize,
+    /// The latency, in microseconds, above which commands are recorded in the slow log.
+    pub slowlog_threshold: u64,
+    /// The number of commands retained in the slow log.
+    pub slowlog_size: usize,
+    /// The maximum length of a command line in bytes, or 0 for no limit.
+    pub max_line_length: usize,
+    /// The maximum number of commands per second of every connection, or 0 for no limit.
+    pub max_commands_per_second: u64,
+    /// The number of bytes of memory the server may use before commands adding data
+    /// are rejected, while the others are still served, or 0 for no limit. See the
+    /// `memory` module.
+    pub max_memory: usize,
+    /// The number of seconds after which idle connections are closed, or 0 to keep them open.
+    pub client_timeout: u64,
+    /// The number of milliseconds after which `knn` searches are abandoned, or 0 for
+    /// no limit.
+    pub query_timeout: u64,
+    /// The recall@10 the search parameters of the approximate indexes are tuned
+    /// towards, between 0 and 1, or 0 to leave them as they were created. See
+    /// `Vemcache::tune_indexes`.
+    pub autotune_recall: f64,
+    /// The number of seconds between two rounds of tuning, greater than 0.
+    pub autotune_interval: u64,
+    /// The search parameters chosen by the last round of tuning, as
+    /// `database/prefix/param=value` entries. It is read-only.
+    #[serde(skip)]
+    pub autotuned: Vec<String>,
+    /// How the components of vectors are written in text responses: `shortest`,
+    /// `hex`, or a number of decimal places. See `Notation`. Connections opened
+    /// afterwards use it, unless they pick another one with `format text`.
+    pub float_format: String,
+    /// The number of seconds a connection is idle before TCP keepalive probes are sent
+    /// to detect dead peers, or 0 to disable keepalive.
+    pub tcp_keepalive: u64,
+    /// Whether Nagle's algorithm is disabled, so responses are sent without delay.
+    pub tcp_nodelay: bool,
+    /// The number of logical databases, numbered from 0, that clients choose from with `select`.
+    pub databases: usize,
+    /// The OpenAI-compatible endpoint `embed` and `search_text` embed text with.
+    pub embed_url: String,
+    /// The model the endpoint embeds text with.
+    pub embed_model: String,
+    /// The key sent to the endpoint as a bearer token, if it requires one. It is
+    /// hidden from `config get`.
+    pub embed_api_key: Option<String>,
+    /// The directory holding the model `insert_text` and `query_text` embed text with,
+    /// if one is loaded. See the `model` module.
+    pub text_model_dir: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 7070,
+            admin_host: "127.0.0.1".to_string(),
+            admin_port: 0,
+            rest_port: 0,
+            probe_port: 0,
+            read_only: false,
+            acl_tokens: Vec::new(),
+            changelog_size: DEFAULT_CHANGELOG_CAPACITY,
+            replicaof: None,
+            primary_token: None,
+            cluster_nodes: Vec::new(),
+            cluster_self: None,
+            raft_nodes: Vec::new(),
+            raft_self: None,
+            data_dir: None,
+            allow_any_dump_path: false,
+            backup_dir: None,
+            backup_interval: 0,
+            backup_retention: DEFAULT_BACKUP_RETENTION,
+            backup_deltas: 0,
+            s3_endpoint: DEFAULT_S3_ENDPOINT.to_string(),
+            s3_region: DEFAULT_S3_REGION.to_string(),
+            s3_bucket: None,
+            s3_prefix: String::new(),
+            s3_access_key: None,
+            s3_secret_key: None,
+            audit_file: None,
+            encryption_key: None,
+            encryption_key_file: None,
+            encryption_key_command: None,
+            metric: DEFAULT_METRIC.to_string(),
+            dedup: "off".to_string(),
+            storage: "memory".to_string(),
+            storage_cache_size: DEFAULT_CACHE_SIZE,
+            storage_file: None,
+            gpu: false,
+            gpu_threshold: gpu::DEFAULT_THRESHOLD,
+            slowlog_threshold: DEFAULT_SLOWLOG_THRESHOLD,
+            slowlog_size: DEFAULT_SLOWLOG_SIZE,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            max_commands_per_second: 0,
+            max_memory: 0,
+            client_timeout: 0,
+            query_timeout: 0,
+            autotune_recall: 0.0,
+            autotune_interval: 300,
+            autotuned: Vec::new(),
+            float_format: "shortest".to_string(),
+            tcp_keepalive: 300,
+            tcp_nodelay: true,
+            databases: 16,
+            embed_url: DEFAULT_EMBED_URL.to_string(),
+            embed_model: DEFAULT_EMBED_MODEL.to_string(),
+            embed_api_key: None,
+            text_model_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from a TOML file. Parameters missing from the file
+    /// keep their default value. The values are not checked; see `validate`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the file cannot be read or is not a valid configuration.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path, err))?;
+        toml::from_str(&contents).map_err(|err| format!("Invalid configuration {}: {}", path, err))
+    }
+
+    /// Overrides the configuration with environment variables.
+    ///
+    /// Every parameter can be set with a variable named after it in upper case and
+    /// prefixed with `VEMCACHE_` (e.g. `VEMCACHE_PORT`, `VEMCACHE_CHANGELOG_SIZE`).
+    /// `VEMCACHE_MAXMEMORY` is accepted for `max_memory`, and `VEMCACHE_PASSWORD`
+    /// for an `acl_tokens` entry granting the admin role; the variables of the
+    /// parameters take precedence over them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if a variable has an invalid value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::config::Config;
+    ///
+    /// std::env::set_var("VEMCACHE_PASSWORD", "s3cret");
+    /// let mut config = Config::default();
+    /// config.apply_env().unwrap();
+    /// assert_eq!(config.acl_tokens, ["admin:s3cret"]);
+    /// ```
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        for (var, name) in ENV_ALIASES {
+            if let Ok(value) = dotenv::var(var) {
+                let assigned = match *name {
+                    "acl_tokens" => format!("admin:{}", value),
+                    _ => value.clone(),
+                };
+                self.assign(name, &assigned)
+                    .map_err(|_| format!("Invalid value for {}: {}", var, value))?;
+            }
+        }
+        for name in PARAMETERS {
+            let var = format!("VEMCACHE_{}", name.to_uppercase());
+            if let Ok(value) = dotenv::var(&var) {
+                self.assign(name, &value)
+                    .map_err(|_| format!("Invalid value for {}: {}", var, value))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Overrides the configuration with command-line flags.
+    ///
+    /// Every parameter can be set with a flag named after it, with dashes instead of
+    /// underscores, followed by its value (e.g. `--changelog-size 50000`). `--read-only`
+    /// takes no value. `--config` is skipped, since the file is read beforehand.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if a flag is unknown, is missing its value, or has an invalid value.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if flag == "--read-only" {
+                self.read_only = true;
+                continue;
+            }
+            let name = flag
+                .strip_prefix("--")
+                .ok_or_else(|| format!("Unexpected argument: {}", flag))?
+                .replace('-', "_");
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", flag))?;
+            if name == "config" {
+                continue;
+            }
+            if !PARAMETERS.contains(&name.as_str()) {
+                return Err(format!("Unknown flag: {}", flag));
+            }
+            self.assign(&name, value)
+                .map_err(|_| format!("Invalid value for {}: {}", flag, value))?;
+        }
+        Ok(())
+    }
+
+    /// Checks every parameter against the rules applied when it is assigned, which
+    /// the values read from a configuration file bypass.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message naming the first parameter with an invalid value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::config::Config;
+    ///
+    /// let mut config = Config::default();
+    /// assert!(config.validate().is_ok());
+    ///
+    /// config.databases = 0;
+    /// assert_eq!(config.validate().unwrap_err(), "Invalid value for databases: 0");
+    ///
+    /// let mut config = Config::default();
+    /// config.raft_nodes = vec!["10.0.0.1:7070".to_string()];
+    /// assert!(config.validate().is_err());
+    ///
+    /// config.acl_tokens = vec!["admin:s3cret".to_string()];
+    /// config.primary_token = Some("s3cret".to_string());
+    /// assert!(config.validate().is_ok());
+    ///
+    /// config.replicaof = Some("10.0.0.2:7070".to_string());
+    /// assert!(config.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .acl_tokens
+            .iter()
+            .any(|entry| acl::parse_entry(entry).is_none())
+        {
+            return Err(
+                "Invalid acl_tokens entry, expected role:token with a role of reader, writer or admin"
+                    .to_string(),
+            );
+        }
+        let mut checked = Config::default();
+        for name in PARAMETERS {
+            // Secrets are masked by `get`, and accept any value
+            if *name == "acl_tokens" || self.get(name).is_some_and(|value| value == "********") {
+                continue;
+            }
+            let value = self.get(name).unwrap_or_default();
+            checked
+                .assign(name, &value)
+                .map_err(|_| format!("Invalid value for {}: {}", name, value))?;
+        }
+        if !self.raft_nodes.is_empty()
+            && (!self.cluster_nodes.is_empty() || self.replicaof.is_some())
+        {
+            return Err(
+                "raft_nodes cannot be combined with cluster_nodes or replicaof, the Raft group elects the node to replicate from"
+                    .to_string(),
+            );
+        }
+        // Any client could otherwise hold elections or name itself the leader
+        let peer_role = self
+            .primary_token
+            .as_deref()
+            .and_then(|token| acl::authenticate(&self.acl_tokens, token));
+        if !self.raft_nodes.is_empty() && peer_role != Some(acl::Role::Admin) {
+            return Err(
+                "raft_nodes requires acl_tokens, and a primary_token granting the admin role the nodes authenticate with"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the current value of a parameter, or `None` if there is no such parameter.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "host" => self.host.clone(),
+            "port" => self.port.to_string(),
+            "admin_host" => self.admin_host.clone(),
+            "admin_port" => self.admin_port.to_string(),
+            "rest_port" => self.rest_port.to_string(),
+            "probe_port" => self.probe_port.to_string(),
+            "read_only" => self.read_only.to_string(),
+            "acl_tokens" => self
+                .acl_tokens
+                .iter()
+                .filter_map(|entry| acl::parse_entry(entry))
+                .map(|(role, _)| format!("{}:********", role.name()))
+                .collect::<Vec<String>>()
+                .join(","),
+            "changelog_size" => self.changelog_size.to_string(),
+            "replicaof" => self.replicaof.clone().unwrap_or_default(),
+            "primary_token" => match self.primary_token {
+                Some(_) => "********".to_string(),
+                None => String::new(),
+            },
+            "cluster_nodes" => self.cluster_nodes.join(","),
+            "cluster_self" => self.cluster_self.clone().unwrap_or_default(),
+            "raft_nodes" => self.raft_nodes.join(","),
+            "raft_self" => self.raft_self.clone().unwrap_or_default(),
+            "data_dir" => self.data_dir.clone().unwrap_or_default(),
+            "allow_any_dump_path" => self.allow_any_dump_path.to_string(),
+            "backup_dir" => self.backup_dir.clone().unwrap_or_default(),
+            "backup_interval" => self.backup_interval.to_string(),
+            "backup_retention" => self.backup_retention.to_string(),
+            "backup_deltas" => self.backup_deltas.to_string(),
+            "s3_endpoint" => self.s3_endpoint.clone(),
+            "s3_region" => self.s3_region.clone(),
+            "s3_bucket" => self.s3_bucket.clone().unwrap_or_default(),
+            "s3_prefix" => self.s3_prefix.clone(),
+            "s3_access_key" => self.s3_access_key.clone().unwrap_or_default(),
+            "s3_secret_key" => match self.s3_secret_key {
+                Some(_) => "********".to_string(),
+                None => String::new(),
+            },
+            "audit_file" => self.audit_file.clone().unwrap_or_default(),
+            "encryption_key" => match self.encryption_key {
+                Some(_) => "********".to_string(),
+                None => String::new(),
+            },
+            "encryption_key_file" => self.encryption_key_file.clone().unwrap_or_default(),
+            "encryption_key_command" => self.encryption_key_command.clone().unwrap_or_default(),
+            "metric" => self.metric.clone(),
+            "dedup" => self.dedup.clone(),
+            "storage" => self.storage.clone(),
+            "storage_cache_size" => self.storage_cache_size.to_string(),
+            "storage_file" => self.storage_file.clone().unwrap_or_default(),
+            "gpu" => self.gpu.to_string(),
+            "gpu_threshold" => self.gpu_threshold.to_string(),
+            "slowlog_threshold" => self.slowlog_threshold.to_string(),
+            "slowlog_size" => self.slowlog_size.to_string(),
+            "max_line_length" => self.max_line_length.to_string(),
+            "max_commands_per_second" => self.max_commands_per_second.to_string(),
+            "max_memory" => self.max_memory.to_string(),
+            "client_timeout" => self.client_timeout.to_string(),
+            "query_timeout" => self.query_timeout.to_string(),
+            "autotune_recall" => self.autotune_recall.to_string(),
+            "autotune_interval" => self.autotune_interval.to_string(),
+            "autotuned" => self.autotuned.join(","),
+            "float_format" => self.float_format.clone(),
+            "tcp_keepalive" => self.tcp_keepalive.to_string(),
+            "tcp_nodelay" => self.tcp_nodelay.to_string(),
+            "databases" => self.databases.to_string(),
+            "embed_url" => self.embed_url.clone(),
+            "embed_model" => self.embed_model.clone(),
+            "embed_api_key" => match self.embed_api_key {
+                Some(_) => "********".to_string(),
+                None => String::new(),
+            },
+            "text_model_dir" => self.text_model_dir.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Returns the name and value of every parameter whose name matches `pattern`,
+    /// where `*` in the pattern matches any sequence of characters.
+    pub fn matching(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        PARAMETERS
+            .iter()
+            .filter(|name| glob_match(pattern, name))
+            .filter_map(|name| Some((*name, self.get(name)?)))
+            .collect()
+    }
+
+    /// Updates a parameter at runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the parameter does not exist, cannot be changed
+    /// while the server is running, or if the value is invalid.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "read_only" if self.storage == "mmap" && value != "true" => {
+                Err("The memory-mapped storage is always read-only".to_string())
+            }
+            "read_only"
+            | "changelog_size"
+            | "backup_retention"
+            | "backup_deltas"
+            | "metric"
+            | "dedup"
+            | "slowlog_threshold"
+            | "slowlog_size"
+            | "max_line_length"
+            | "max_commands_per_second"
+            | "max_memory"
+            | "client_timeout"
+            | "query_timeout"
+            | "autotune_recall"
+            | "float_format"
+            | "tcp_keepalive"
+            | "tcp_nodelay"
+            | "embed_url"
+            | "embed_model"
+            | "embed_api_key" => self
+                .assign(name, value)
+                .map_err(|_| format!("Invalid value for {}: {}", name, value)),
+            "replicaof" => Err("Use the REPLICAOF command to change replicaof".to_string()),
+            _ if PARAMETERS.contains(&name) => {
+                Err(format!("{} cannot be changed at runtime", name))
+            }
+            _ => Err(format!("Unknown configuration parameter: {}", name)),
+        }
+    }
+
+    /// Parses `value` and assigns it to the parameter `name`, whether or not it can
+    /// change at runtime. Unknown names are ignored.
+    fn assign(&mut self, name: &str, value: &str) -> Result<(), ()> {
+        match name {
+            "host" => self.host = value.to_string(),
+            "port" => self.port = value.parse().map_err(|_| ())?,
+            "admin_host" => self.admin_host = value.to_string(),
+            "admin_port" => self.admin_port = value.parse().map_err(|_| ())?,
+            "rest_port" => self.rest_port = value.parse().map_err(|_| ())?,
+            "probe_port" => self.probe_port = value.parse().map_err(|_| ())?,
+            "read_only" => self.read_only = value.parse().map_err(|_| ())?,
+            "acl_tokens" => {
+                let entries = parse_list(value);
+                if !entries
+                    .iter()
+                    .all(|entry| acl::parse_entry(entry).is_some())
+                {
+                    return Err(());
+                }
+                self.acl_tokens = entries;
+            }
+            "changelog_size" => self.changelog_size = value.parse().map_err(|_| ())?,
+            "replicaof" => self.replicaof = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "primary_token" => {
+                self.primary_token = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "cluster_nodes" => self.cluster_nodes = parse_list(value),
+            "cluster_self" => self.cluster_self = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "raft_nodes" => self.raft_nodes = parse_list(value),
+            "raft_self" => self.raft_self = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "data_dir" => self.data_dir = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "allow_any_dump_path" => self.allow_any_dump_path = value.parse().map_err(|_| ())?,
+            "backup_dir" => self.backup_dir = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "backup_interval" => self.backup_interval = value.parse().map_err(|_| ())?,
+            "backup_retention" => self.backup_retention = value.parse().map_err(|_| ())?,
+            "backup_deltas" => self.backup_deltas = value.parse().map_err(|_| ())?,
+            "s3_endpoint" => self.s3_endpoint = value.to_string(),
+            "s3_region" => self.s3_region = value.to_string(),
+            "s3_bucket" => self.s3_bucket = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "s3_prefix" => self.s3_prefix = value.to_string(),
+            "s3_access_key" => {
+                self.s3_access_key = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "s3_secret_key" => {
+                self.s3_secret_key = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "audit_file" => self.audit_file = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "encryption_key" => {
+                self.encryption_key = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "encryption_key_file" => {
+                self.encryption_key_file = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "encryption_key_command" => {
+                self.encryption_key_command = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "metric" => {
+                metric::builtin_metric(value).ok_or(())?;
+                self.metric = value.to_string();
+            }
+            "dedup" => {
+                Dedup::from_name(value).ok_or(())?;
+                self.dedup = value.to_string();
+            }
+            "storage" => match value {
+                "memory" | "disk" | "mmap" => self.storage = value.to_string(),
+                _ => return Err(()),
+            },
+            "storage_cache_size" => self.storage_cache_size = value.parse().map_err(|_| ())?,
+            "storage_file" => self.storage_file = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "gpu" => self.gpu = value.parse().map_err(|_| ())?,
+            "gpu_threshold" => self.gpu_threshold = value.parse().map_err(|_| ())?,
+            "slowlog_threshold" => self.slowlog_threshold = value.parse().map_err(|_| ())?,
+            "slowlog_size" => self.slowlog_size = value.parse().map_err(|_| ())?,
+            "max_line_length" => self.max_line_length = value.parse().map_err(|_| ())?,
+            "max_commands_per_second" => {
+                self.max_commands_per_second = value.parse().map_err(|_| ())?
+            }
+            "max_memory" => self.max_memory = value.parse().map_err(|_| ())?,
+            "client_timeout" => self.client_timeout = value.parse().map_err(|_| ())?,
+            "query_timeout" => self.query_timeout = value.parse().map_err(|_| ())?,
+            "autotune_recall" => match value.parse() {
+                Ok(recall) if (0.0..=1.0).contains(&recall) => self.autotune_recall = recall,
+                _ => return Err(()),
+            },
+            "autotune_interval" => match value.parse() {
+                Ok(interval) if interval > 0 => self.autotune_interval = interval,
+                _ => return Err(()),
+            },
+            "float_format" => {
+                Notation::from_name(value).ok_or(())?;
+                self.float_format = value.to_string();
+            }
+            "tcp_keepalive" => self.tcp_keepalive = value.parse().map_err(|_| ())?,
+            "tcp_nodelay" => self.tcp_nodelay = value.parse().map_err(|_| ())?,
+            "databases" => match value.parse() {
+                Ok(databases) if databases > 0 => self.databases = databases,
+                _ => return Err(()),
+            },
+            "embed_url" => self.embed_url = value.to_string(),
+            "embed_model" => self.embed_model = value.to_string(),
+            "embed_api_key" => {
+                self.embed_api_key = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "text_model_dir" => {
+                self.text_model_dir = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path given with `--config`, if any.
+pub fn config_path(args: &[String]) -> Option<&str> {
+    args.iter()
+        .position(|arg| arg == "--config")
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+/// Splits a comma-separated list, ignoring empty entries.
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Matches `text` against a pattern in which `*` matches any sequence of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Text embeddings computed by an external API.
+//!
+//! Text is embedded by an endpoint compatible with the OpenAI embeddings API: the
+//! client posts `{"model": ..., "input": ...}` and reads the vector from
+//! `data[0].embedding` in the response.
+
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The time after which a request to the endpoint is abandoned.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A client of an embeddings endpoint.
+pub struct EmbeddingClient {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingClient {
+    /// Creates a client of the endpoint at `url`, embedding text with `model` and
+    /// authenticating with `api_key` as a bearer token, if there is one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use vemcache::embed::EmbeddingClient;
+    ///
+    /// let api_key = std::env::var("OPENAI_API_KEY").ok();
+    /// let client = EmbeddingClient::new(
+    ///     "https://api.openai.com/v1/embeddings",
+    ///     "text-embedding-3-small",
+    ///     api_key,
+    /// );
+    /// let vector = client.embed("a sentence to embed").unwrap();
+    /// ```
+    pub fn new(url: &str, model: &str, api_key: Option<String>) -> Self {
+        Self {
+            url: url.to_string(),
+            model: model.to_string(),
+            api_key,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        }
+    }
+
+    /// Returns the embedding of `text`. The request blocks until the endpoint
+    /// answers, so it must not be made while holding the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the endpoint cannot be reached, answers with an
+    /// error, or does not answer with a vector.
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let mut request = self.agent.post(&self.url);
+        if let Some(api_key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", api_key));
+        }
+        let body = serde_json::json!({ "model": self.model, "input": text });
+        let response = request.send_json(body).map_err(|err| match err {
+            ureq::Error::Status(status, response) => {
+                let reason = response.into_string().unwrap_or_default();
+                format!("Embedding endpoint answered {}: {}", status, reason.trim())
+            }
+            ureq::Error::Transport(err) => {
+                format!("Could not reach the embedding endpoint: {}", err)
+            }
+        })?;
+        let response = response
+            .into_json::<EmbeddingResponse>()
+            .map_err(|err| format!("Invalid response from the embedding endpoint: {}", err))?;
+        let vector = response
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .filter(|vector| !vector.is_empty())
+            .ok_or("The embedding endpoint returned no embedding")?;
+        Ok(vector)
+    }
+}
@@ -0,0 +1,330 @@
+//! Encryption at rest of dumps and the write-ahead log, with AES-256-GCM.
+//!
+//! Once a key is installed with `set_key`, every dump, along with the indexes,
+//! projections and pipelines saved with it, is encrypted as it is written, after
+//! being compressed, and every entry appended to the write-ahead log is encrypted
+//! on its own. Encrypted and plain files can both be read back, so encryption can
+//! be enabled on an existing data directory.
+//!
+//! An encrypted dump starts with `MAGIC`, followed by chunks of at most 64 KiB,
+//! each made of its length as a 32-bit little-endian integer, a random nonce and
+//! the ciphertext. The index of every chunk and whether it is the last one are
+//! authenticated along with it, so a dump whose chunks were reordered or that was
+//! truncated is rejected rather than partially loaded. An encrypted entry of the
+//! write-ahead log is a line holding `!` followed by the nonce and the ciphertext
+//! of the plain line, in base64.
+
+/// The bytes an encrypted dump starts with.
+pub const MAGIC: &[u8] = b"VEMCRYPT";
+
+/// The character an encrypted line of the write-ahead log starts with.
+pub const WAL_PREFIX: char = '!';
+
+#[cfg(feature = "encryption")]
+pub use self::aes::*;
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Encryption is not enabled in this build",
+    )
+}
+
+#[cfg(feature = "encryption")]
+mod aes {
+    use super::MAGIC;
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+    use aes_gcm::{Aes256Gcm, Nonce};
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use std::io::{Error, ErrorKind, Read, Result as IoResult, Write};
+    use std::sync::OnceLock;
+
+    /// The length of a key, in bytes.
+    pub const KEY_LENGTH: usize = 32;
+
+    /// The maximum length of the plaintext of a chunk of an encrypted dump.
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    /// The length of a nonce, in bytes.
+    const NONCE_LENGTH: usize = 12;
+
+    /// The length of the authentication tag appended to every ciphertext, in bytes.
+    const TAG_LENGTH: usize = 16;
+
+    /// The key dumps and the write-ahead log are encrypted with, if any.
+    static KEY: OnceLock<Key> = OnceLock::new();
+
+    /// A key to encrypt and decrypt with.
+    pub struct Key {
+        cipher: Aes256Gcm,
+    }
+
+    impl Key {
+        /// Parses a key written as 64 hexadecimal digits, as generated by
+        /// `openssl rand -hex 32`. Surrounding whitespace is ignored.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error message if `hex` is not a key.
+        ///
+        /// # Example
+        ///
+        /// ```
+        /// use vemcache::encryption::Key;
+        ///
+        /// assert!(Key::from_hex(&"0f".repeat(32)).is_ok());
+        /// assert!(Key::from_hex("0f0f").is_err());
+        /// ```
+        pub fn from_hex(hex: &str) -> Result<Self, String> {
+            let hex = hex.trim();
+            let invalid = || {
+                format!(
+                    "An encryption key must be {} hexadecimal digits",
+                    KEY_LENGTH * 2
+                )
+            };
+            if hex.len() != KEY_LENGTH * 2 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            let mut key = [0; KEY_LENGTH];
+            for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+                *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+            }
+            Ok(Self {
+                cipher: Aes256Gcm::new(&key.into()),
+            })
+        }
+
+        /// Encrypts `plaintext` under a new random nonce, which is prepended to the
+        /// ciphertext.
+        fn seal(&self, plaintext: &[u8], aad: &[u8]) -> IoResult<Vec<u8>> {
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let ciphertext = self
+                .cipher
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: plaintext,
+                        aad,
+                    },
+                )
+                .map_err(|_| Error::other("Could not encrypt"))?;
+            let mut sealed = nonce.to_vec();
+            sealed.extend(ciphertext);
+            Ok(sealed)
+        }
+
+        /// Decrypts what `seal` returned, checking that it was not altered.
+        fn open(&self, sealed: &[u8], aad: &[u8]) -> IoResult<Vec<u8>> {
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "Could not decrypt, the key is wrong or the data is corrupted",
+                )
+            };
+            if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+                return Err(invalid());
+            }
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+            self.cipher
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad,
+                    },
+                )
+                .map_err(|_| invalid())
+        }
+
+        /// Encrypts a line of the write-ahead log, without its line break.
+        pub fn seal_line(&self, line: &str) -> IoResult<String> {
+            let sealed = self.seal(line.as_bytes(), &[])?;
+            Ok(format!("{}{}", super::WAL_PREFIX, BASE64.encode(sealed)))
+        }
+
+        /// Decrypts a line of the write-ahead log sealed by `seal_line`.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the line was not sealed with this key or was altered.
+        pub fn open_line(&self, line: &str) -> IoResult<String> {
+            let encoded = line.strip_prefix(super::WAL_PREFIX).unwrap_or(line);
+            let sealed = BASE64
+                .decode(encoded)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            String::from_utf8(self.open(&sealed, &[])?)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+        }
+    }
+
+    /// Installs the key dumps and the write-ahead log are encrypted with, for the
+    /// rest of the process. Only the first key installed is kept.
+    pub fn set_key(key: Key) {
+        let _ = KEY.set(key);
+    }
+
+    /// Returns the key installed with `set_key`, if any.
+    pub fn key() -> Option<&'static Key> {
+        KEY.get()
+    }
+
+    /// Returns the key installed with `set_key`, or an error saying that `what`
+    /// cannot be read without it.
+    pub(crate) fn require_key(what: &str) -> IoResult<&'static Key> {
+        key().ok_or_else(|| {
+            Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "The {} is encrypted but no encryption key is configured",
+                    what
+                ),
+            )
+        })
+    }
+
+    /// Returns the data authenticated along with the chunk at `index`.
+    fn chunk_aad(index: u64, last: bool) -> [u8; 9] {
+        let mut aad = [0; 9];
+        aad[..8].copy_from_slice(&index.to_le_bytes());
+        aad[8] = last as u8;
+        aad
+    }
+
+    /// A writer encrypting what is written to it into an encrypted dump.
+    ///
+    /// `finish` must be called once everything is written, otherwise the dump is
+    /// incomplete and cannot be read back.
+    pub struct Encryptor<W: Write> {
+        inner: W,
+        key: &'static Key,
+        buffer: Vec<u8>,
+        index: u64,
+    }
+
+    impl<W: Write> Encryptor<W> {
+        /// Starts an encrypted dump written to `inner`.
+        pub fn new(mut inner: W, key: &'static Key) -> IoResult<Self> {
+            inner.write_all(MAGIC)?;
+            Ok(Self {
+                inner,
+                key,
+                buffer: Vec::with_capacity(CHUNK_SIZE),
+                index: 0,
+            })
+        }
+
+        /// Encrypts the buffered plaintext into a chunk.
+        fn write_chunk(&mut self, last: bool) -> IoResult<()> {
+            let sealed = self.key.seal(&self.buffer, &chunk_aad(self.index, last))?;
+            self.inner
+                .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&sealed)?;
+            self.buffer.clear();
+            self.index += 1;
+            Ok(())
+        }
+
+        /// Writes the last chunk and returns the underlying writer.
+        pub fn finish(mut self) -> IoResult<W> {
+            self.write_chunk(true)?;
+            Ok(self.inner)
+        }
+    }
+
+    impl<W: Write> Write for Encryptor<W> {
+        fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+            if self.buffer.len() == CHUNK_SIZE {
+                self.write_chunk(false)?;
+            }
+            let length = buf.len().min(CHUNK_SIZE - self.buffer.len());
+            self.buffer.extend_from_slice(&buf[..length]);
+            Ok(length)
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            self.inner.flush()
+        }
+    }
+
+    /// A reader decrypting an encrypted dump.
+    pub struct Decryptor<R: Read> {
+        inner: R,
+        key: &'static Key,
+        plaintext: Vec<u8>,
+        position: usize,
+        index: u64,
+        finished: bool,
+    }
+
+    impl<R: Read> Decryptor<R> {
+        /// Starts reading the encrypted dump read from `inner`, which must start
+        /// with `MAGIC`.
+        pub fn new(mut inner: R, key: &'static Key) -> IoResult<Self> {
+            let mut magic = [0; MAGIC.len()];
+            inner.read_exact(&mut magic)?;
+            if magic != MAGIC {
+                return Err(Error::new(ErrorKind::InvalidData, "Not an encrypted dump"));
+            }
+            Ok(Self {
+                inner,
+                key,
+                plaintext: Vec::new(),
+                position: 0,
+                index: 0,
+                finished: false,
+            })
+        }
+
+        /// Decrypts the next chunk.
+        fn read_chunk(&mut self) -> IoResult<()> {
+            let truncated = |err: Error| match err.kind() {
+                ErrorKind::UnexpectedEof => {
+                    Error::new(ErrorKind::InvalidData, "The encrypted dump is truncated")
+                }
+                _ => err,
+            };
+            let mut length = [0; 4];
+            self.inner.read_exact(&mut length).map_err(truncated)?;
+            let length = u32::from_le_bytes(length) as usize;
+            if length > CHUNK_SIZE {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "The encrypted dump is corrupted",
+                ));
+            }
+            let mut sealed = vec![0; NONCE_LENGTH + length + TAG_LENGTH];
+            self.inner.read_exact(&mut sealed).map_err(truncated)?;
+            // Whether this is the last chunk is only known once it is authenticated
+            self.plaintext = match self.key.open(&sealed, &chunk_aad(self.index, false)) {
+                Ok(plaintext) => plaintext,
+                Err(_) => {
+                    let plaintext = self.key.open(&sealed, &chunk_aad(self.index, true))?;
+                    self.finished = true;
+                    plaintext
+                }
+            };
+            self.position = 0;
+            self.index += 1;
+            Ok(())
+        }
+    }
+
+    impl<R: Read> Read for Decryptor<R> {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            while self.position == self.plaintext.len() {
+                if self.finished {
+                    return Ok(0);
+                }
+                self.read_chunk()?;
+            }
+            let length = buf.len().min(self.plaintext.len() - self.position);
+            buf[..length].copy_from_slice(&self.plaintext[self.position..][..length]);
+            self.position += length;
+            Ok(length)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{BufWriter, Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+
+use crate::compression;
+use crate::npy;
+use crate::storage::{HEADER_SIZE, MAGIC, VERSION};
+use crate::vemcache::Snapshot;
+
+/// Exports a snapshot to a NumPy file and returns the number of exported vectors.
+///
+/// If `path` ends in `.npz`, a single archive holding the vectors as a `float32`
+/// matrix named `vectors` and their keys as a string array named `keys` is written.
+/// Otherwise, the matrix is written to a `.npy` file and the keys, one per line,
+/// to `keys_path`, which defaults to the path of the matrix followed by `.keys`.
+/// Vectors are ordered by key.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be written, or if the vectors do not all have
+/// the same dimension.
+pub fn write_npy_file(
+    snapshot: &Snapshot,
+    path: &Path,
+    keys_path: Option<&Path>,
+) -> IoResult<usize> {
+    let vectors = snapshot.vectors();
+    let columns = vectors.first().map_or(0, |(_, vector)| vector.len());
+    if vectors.iter().any(|(_, vector)| vector.len() != columns) {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "Vectors must all have the same dimension to be exported as a matrix",
+        ));
+    }
+    let keys = vectors.iter().map(|(key, _)| *key).collect::<Vec<&str>>();
+    let data = vectors
+        .iter()
+        .flat_map(|(_, vector)| vector.iter().copied())
+        .collect::<Vec<f32>>();
+
+    if path.extension().and_then(|ext| ext.to_str()) == Some("npz") {
+        let mut archive = zip::ZipWriter::new(BufWriter::new(File::create(path)?));
+        let options = SimpleFileOptions::default();
+        archive.start_file("vectors.npy", options)?;
+        npy::write_f32(&mut archive, keys.len(), columns, &data)?;
+        archive.start_file("keys.npy", options)?;
+        npy::write_unicode(&mut archive, &keys)?;
+        archive.finish()?.flush()?;
+    } else {
+        let mut file = BufWriter::new(File::create(path)?);
+        npy::write_f32(&mut file, keys.len(), columns, &data)?;
+        file.flush()?;
+
+        let keys_path = keys_path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(format!("{}.keys", path.display())));
+        let mut file = BufWriter::new(File::create(keys_path)?);
+        for key in &keys {
+            writeln!(file, "{}", key)?;
+        }
+        file.flush()?;
+    }
+    Ok(keys.len())
+}
+
+/// Exports a snapshot to a JSON Lines file and returns the number of exported vectors.
+///
+/// Each line holds a vector as `{"id": "vector1", "vector": [1.0, 2.0]}`, in key
+/// order. The file is compressed with gzip or zstd if `path` ends in `.gz` or `.zst`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+pub fn write_jsonl_file(snapshot: &Snapshot, path: &Path) -> IoResult<usize> {
+    let vectors = snapshot.vectors();
+    compression::write_file(&path.to_string_lossy(), |writer| {
+        for (id, vector) in &vectors {
+            serde_json::to_writer(
+                &mut *writer,
+                &serde_json::json!({ "id": id, "vector": vector }),
+            )?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    })?;
+    Ok(vectors.len())
+}
+
+/// Exports a snapshot to a vector file, which can be served read-only by the
+/// memory-mapped storage, and returns the number of exported vectors.
+///
+/// Vectors are written in key order. See `storage::MmapStorage` for the format.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written, if the vectors do not all have
+/// the same dimension, or if a key is longer than 65535 bytes.
+pub fn write_vector_file(snapshot: &Snapshot, path: &Path) -> IoResult<usize> {
+    let vectors = snapshot.vectors();
+    let dim = vectors.first().map_or(0, |(_, vector)| vector.len());
+    if vectors.iter().any(|(_, vector)| vector.len() != dim) {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "Vectors must all have the same dimension to be exported as a vector file",
+        ));
+    }
+    let dim = u32::try_from(dim)
+        .map_err(|_| IoError::new(ErrorKind::InvalidData, "Vectors are too long"))?;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(vectors.len() as u64).to_le_bytes());
+    header.extend_from_slice(&dim.to_le_bytes());
+    header.resize(HEADER_SIZE, 0);
+    file.write_all(&header)?;
+    for (_, vector) in &vectors {
+        for value in vector.iter() {
+            file.write_all(&value.to_le_bytes())?;
+        }
+    }
+    for (key, _) in &vectors {
+        let len = u16::try_from(key.len()).map_err(|_| {
+            IoError::new(ErrorKind::InvalidData, format!("Key is too long: {}", key))
+        })?;
+        file.write_all(&len.to_le_bytes())?;
+        file.write_all(key.as_bytes())?;
+    }
+    file.flush()?;
+    Ok(vectors.len())
+}
@@ -0,0 +1,199 @@
+//! Binary encodings of the responses holding vectors, chosen per connection with
+//! the `format` command.
+//!
+//! Connections start in the text format, in which vectors are written as lists of
+//! numbers within lines of text. In the `msgpack` and `cbor` formats, the responses
+//! holding vectors are encoded as MessagePack or CBOR instead, so clients decode
+//! them with a library rather than parsing text:
+//!
+//! - `get` and `aggregate` return an array of floats, or nil, and `get` with flags
+//!   a map of the fields it asked for, `raw` components being a byte string;
+//! - the vector operations `vadd`, `vsub` and `vscale` return an array of floats;
+//! - `sample` and the searches (`knn`, `knn_in`, `search_text` and `query_text`)
+//!   return an array of maps with an `id` string and a `vector` array of floats.
+//!
+//! In the text protocol, an encoded response is the line `<format>: <size>`
+//! followed by `size` bytes, like the response to `arrow download`. Over RESP, it
+//! is a bulk string. Other responses, errors included, are lines of text in every
+//! format.
+//!
+//! In the text format, the components of vectors are written in the `Notation` of
+//! the connection, `float_format` unless the connection picks another one with
+//! `format text <notation>`.
+
+use serde::Serialize;
+use std::fmt::Write;
+
+/// The largest number of decimal places of the fixed notation.
+const MAX_DECIMAL_PLACES: usize = 40;
+
+/// The format of the responses holding vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    MessagePack,
+    Cbor,
+}
+
+/// A vector and its key, as encoded in the responses to `sample` and searches.
+#[derive(Serialize)]
+pub struct KeyedVector<'a> {
+    pub id: &'a str,
+    pub vector: &'a [f32],
+}
+
+/// How the components of vectors are written in the text format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    /// The shortest decimal that parses back to the same `f32`, in scientific
+    /// notation below 1e-5 and from 1e16 on, such as `0.1` or `1e-7`.
+    #[default]
+    Shortest,
+    /// A fixed number of decimal places, rounding the components.
+    Fixed(usize),
+    /// Hexadecimal floats as written by C's `%a`, such as `0x1.99999ap-4`, which
+    /// hold the exact bits of the components.
+    Hex,
+}
+
+/// Bytes, encoded as a byte string rather than as an array of numbers.
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Notation {
+    /// Returns the notation named `name`: `shortest`, `hex`, or a number of decimal
+    /// places.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shortest" => Some(Notation::Shortest),
+            "hex" => Some(Notation::Hex),
+            places => places
+                .parse()
+                .ok()
+                .filter(|&places| places <= MAX_DECIMAL_PLACES)
+                .map(Notation::Fixed),
+        }
+    }
+
+    /// Returns the name of the notation, as accepted by `from_name`.
+    pub fn name(&self) -> String {
+        match self {
+            Notation::Shortest => "shortest".to_string(),
+            Notation::Fixed(places) => places.to_string(),
+            Notation::Hex => "hex".to_string(),
+        }
+    }
+
+    /// Writes `vector` as a list of components, such as `[0.1, 0.2]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::format::Notation;
+    ///
+    /// let vector = [0.1, -2.0, 1e-7];
+    /// assert_eq!(Notation::Shortest.vector(&vector), "[0.1, -2.0, 1e-7]");
+    /// assert_eq!(Notation::Fixed(3).vector(&vector), "[0.100, -2.000, 0.000]");
+    /// assert_eq!(Notation::Hex.vector(&vector), "[0x1.99999ap-4, -0x1p+1, 0x1.ad7f2ap-24]");
+    /// ```
+    pub fn vector(&self, vector: &[f32]) -> String {
+        let mut text = String::with_capacity(vector.len() * 12 + 2);
+        text.push('[');
+        for (i, value) in vector.iter().enumerate() {
+            if i > 0 {
+                text.push_str(", ");
+            }
+            // Writing to a String does not fail
+            let _ = match self {
+                Notation::Shortest => write!(text, "{:?}", value),
+                Notation::Fixed(places) => write!(text, "{:.*}", places, value),
+                Notation::Hex => write_hex(&mut text, *value),
+            };
+        }
+        text.push(']');
+        text
+    }
+}
+
+/// Writes `value` as a hexadecimal float: `0x1.<fraction>p<exponent>` for normal
+/// numbers, `0x0.<fraction>p-126` for subnormal ones, with the trailing zeros of
+/// the fraction left out.
+fn write_hex(text: &mut String, value: f32) -> std::fmt::Result {
+    if !value.is_finite() {
+        return write!(text, "{}", value);
+    }
+    let bits = value.to_bits();
+    if bits >> 31 == 1 {
+        text.push('-');
+    }
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let fraction = bits & 0x7f_ffff;
+    let (lead, exponent) = match (exponent, fraction) {
+        (0, 0) => return write!(text, "0x0p+0"),
+        (0, _) => (0, -126),
+        (exponent, _) => (1, exponent - 127),
+    };
+    // The 23 bits of the fraction, as 6 hexadecimal digits
+    let digits = format!("{:06x}", fraction << 1);
+    let digits = digits.trim_end_matches('0');
+    match digits.is_empty() {
+        true => write!(text, "0x{}p{:+}", lead, exponent),
+        false => write!(text, "0x{}.{}p{:+}", lead, digits, exponent),
+    }
+}
+
+impl Format {
+    /// Returns the format named `name`: `text`, `msgpack` or `cbor`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "msgpack" => Some(Format::MessagePack),
+            "cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the format, as given to `format`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::MessagePack => "msgpack",
+            Format::Cbor => "cbor",
+        }
+    }
+
+    /// Encodes `value`, or returns `None` in the text format, where responses are
+    /// written as text.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::format::Format;
+    ///
+    /// // A fixarray of two float 32
+    /// let encoded = Format::MessagePack.encode(&[1.0f32, 2.0]).unwrap();
+    /// assert_eq!(encoded, [0x92, 0xca, 0x3f, 0x80, 0, 0, 0xca, 0x40, 0, 0, 0]);
+    /// assert_eq!(Format::Text.encode(&[1.0f32, 2.0]), None);
+    /// ```
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Option<Vec<u8>> {
+        match self {
+            Format::Text => None,
+            // Neither encoder fails writing to memory values that serialize as
+            // numbers, strings, sequences and maps with string keys
+            Format::MessagePack => {
+                Some(rmp_serde::to_vec_named(value).expect("MessagePack encoding failed"))
+            }
+            Format::Cbor => {
+                let mut encoded = Vec::new();
+                ciborium::into_writer(value, &mut encoded).expect("CBOR encoding failed");
+                Some(encoded)
+            }
+        }
+    }
+}
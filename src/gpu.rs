@@ -0,0 +1,23 @@
+//! Offloads exhaustive searches to the GPU.
+//!
+//! The vectors searched are uploaded to the GPU once and kept there until the
+//! database changes, so each query only uploads the query vector and reads back
+//! one score per vector. The GPU is only used for searches scanning at least
+//! `DEFAULT_THRESHOLD` vectors (see `Vemcache::attach_gpu`), with the built-in
+//! `dot`, `cosine` and `euclidean` metrics; every other search, and every search
+//! the GPU fails to run, runs on the CPU.
+//!
+//! The GPU itself is behind the `gpu` feature, and is driven through `wgpu`, so
+//! it works with any Vulkan, Metal, DirectX 12 or OpenGL device.
+
+#[cfg(feature = "gpu")]
+mod device;
+
+#[cfg(feature = "gpu")]
+pub use device::Gpu;
+
+/// The default number of vectors a search has to scan before it runs on the GPU.
+///
+/// Below this size, uploading the vectors and synchronizing with the GPU costs
+/// more than scoring them on the CPU.
+pub const DEFAULT_THRESHOLD: usize = 100_000;
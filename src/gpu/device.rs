@@ -0,0 +1,351 @@
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+use wgpu::util::DeviceExt;
+
+use crate::metric;
+use crate::storage::Storage;
+
+/// The number of rows scored by each workgroup, as declared by the shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// A GPU that scores vectors against queries.
+pub struct Gpu {
+    name: String,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    // The largest buffer a single dispatch can bind, in bytes
+    max_binding: u64,
+    max_workgroups: u32,
+    // The vectors of the last search, kept on the GPU until the database changes
+    matrix: Mutex<Option<Matrix>>,
+}
+
+/// Vectors uploaded to the GPU, as a matrix split into chunks that fit in a buffer.
+struct Matrix {
+    generation: u64,
+    prefix: String,
+    // `None` if the vectors do not all have the same dimension, in which case
+    // nothing is uploaded
+    dim: Option<usize>,
+    keys: Vec<String>,
+    chunks: Vec<(wgpu::Buffer, u32)>,
+}
+
+impl Gpu {
+    /// Opens the most powerful GPU of the machine, or returns `None` if there is
+    /// no GPU. Software adapters are ignored, since they are slower than searching
+    /// on the CPU directly.
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))?;
+        let info = adapter.get_info();
+        if info.device_type == wgpu::DeviceType::Cpu {
+            return None;
+        }
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("vemcache"),
+                required_features: wgpu::Features::empty(),
+                required_limits: limits.clone(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .ok()?;
+        // Errors are caught with error scopes, anything else is only reported
+        device.on_uncaptured_error(Box::new(|err| println!("Error on the GPU: {}", err)));
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("distances"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("distances.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("distances"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Some(Self {
+            name: info.name,
+            device,
+            queue,
+            pipeline,
+            max_binding: u64::from(limits.max_storage_buffer_binding_size)
+                .min(limits.max_buffer_size),
+            max_workgroups: limits.max_compute_workgroups_per_dimension,
+            matrix: Mutex::new(None),
+        })
+    }
+
+    /// The name of the GPU.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the distance between `query` and every vector of `storage` whose key
+    /// starts with `prefix`, according to the built-in metric named `metric`.
+    ///
+    /// The vectors are uploaded the first time, and again whenever `generation` or
+    /// `prefix` differs from the previous call. Returns `None` if the search has to
+    /// run on the CPU instead: if the metric is not supported, if the vectors and
+    /// the query do not all have the same dimension, or if the GPU failed.
+    pub(crate) fn distances(
+        &self,
+        storage: &dyn Storage,
+        generation: u64,
+        prefix: &str,
+        query: &[f32],
+        metric: &str,
+    ) -> Option<Vec<(String, f32)>> {
+        if !matches!(metric, "dot" | "cosine" | "euclidean") {
+            return None;
+        }
+        let mut matrix = self
+            .matrix
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let current = matrix
+            .as_ref()
+            .is_some_and(|matrix| matrix.generation == generation && matrix.prefix == prefix);
+        if !current {
+            // Free the previous vectors before uploading the new ones
+            *matrix = None;
+            match self.upload(storage, generation, prefix) {
+                Ok(uploaded) => *matrix = Some(uploaded),
+                Err(err) => {
+                    println!("Error uploading vectors to the GPU: {}", err);
+                    return None;
+                }
+            }
+        }
+        let matrix = matrix.as_ref()?;
+        if matrix.dim != Some(query.len()) {
+            return None;
+        }
+
+        let scores = match self.score(matrix, query) {
+            Ok(scores) => scores,
+            Err(err) => {
+                println!("Error searching on the GPU: {}", err);
+                return None;
+            }
+        };
+        let query_norm = metric::dot_product(query, query);
+        let distances = matrix
+            .keys
+            .iter()
+            .cloned()
+            .zip(scores)
+            .map(|(key, (product, norm))| {
+                let distance = match metric {
+                    "dot" => -product,
+                    "cosine" => {
+                        metric::cosine_distance(product / (query_norm.sqrt() * norm.sqrt()))
+                    }
+                    _ => (query_norm + norm - 2.0 * product).max(0.0).sqrt(),
+                };
+                (key, distance)
+            })
+            .collect();
+        Some(distances)
+    }
+
+    /// Returns the number of rows of `dim` components that fit in a chunk.
+    fn max_rows(&self, dim: usize) -> usize {
+        let row_size = (dim as u64 * 4).max(8);
+        let max_rows = (self.max_binding / row_size)
+            .min(u64::from(self.max_workgroups) * u64::from(WORKGROUP_SIZE));
+        (max_rows as usize).max(1)
+    }
+
+    /// Uploads the vectors of `storage` whose key starts with `prefix`, a chunk at a time.
+    fn upload(
+        &self,
+        storage: &dyn Storage,
+        generation: u64,
+        prefix: &str,
+    ) -> Result<Matrix, String> {
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        let mut keys = Vec::new();
+        let mut dim = None;
+        let mut uniform = true;
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::new();
+        let mut rows = 0;
+        storage.scan(&mut |block| {
+            for (key, vector) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                if !uniform {
+                    return;
+                }
+                match dim {
+                    None if !vector.is_empty() => dim = Some(vector.len()),
+                    Some(dim) if dim == vector.len() => {}
+                    _ => {
+                        uniform = false;
+                        return;
+                    }
+                }
+                keys.push(key.to_string());
+                for value in vector.iter() {
+                    chunk.extend_from_slice(&value.to_le_bytes());
+                }
+                rows += 1;
+                if rows == self.max_rows(vector.len()) {
+                    chunks.push((self.create_chunk(&chunk), rows as u32));
+                    chunk.clear();
+                    rows = 0;
+                }
+            }
+        });
+        if rows > 0 && uniform {
+            chunks.push((self.create_chunk(&chunk), rows as u32));
+        }
+        if let Some(err) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(err.to_string());
+        }
+        if !uniform {
+            keys.clear();
+            chunks.clear();
+            dim = None;
+        }
+        Ok(Matrix {
+            generation,
+            prefix: prefix.to_string(),
+            dim,
+            keys,
+            chunks,
+        })
+    }
+
+    fn create_chunk(&self, contents: &[u8]) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("vectors"),
+                contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    /// Returns the dot product of `query` with every row of `matrix`, along with the
+    /// squared magnitude of the row.
+    fn score(&self, matrix: &Matrix, query: &[f32]) -> Result<Vec<(f32, f32)>, String> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        let query = query
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let query = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("query"),
+                contents: &query,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let layout = self.pipeline.get_bind_group_layout(0);
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        let mut readbacks = Vec::new();
+        for (vectors, rows) in &matrix.chunks {
+            let size = u64::from(*rows) * 8;
+            let results = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("results"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            // Padded to the 16 bytes uniform buffers are aligned to
+            let params = [*rows, matrix.dim.unwrap_or(0) as u32, 0, 0]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<u8>>();
+            let params = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("params"),
+                    contents: &params,
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("distances"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: vectors.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: query.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: results.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            });
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(rows.div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+            encoder.copy_buffer_to_buffer(&results, 0, &readback, 0, size);
+            readbacks.push(readback);
+        }
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        for readback in &readbacks {
+            let sender = sender.clone();
+            readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+        }
+        self.device.poll(wgpu::Maintain::Wait);
+        let errors = [
+            pollster::block_on(self.device.pop_error_scope()),
+            pollster::block_on(self.device.pop_error_scope()),
+        ];
+        if let Some(err) = errors.into_iter().flatten().next() {
+            return Err(err.to_string());
+        }
+        for _ in &readbacks {
+            receiver
+                .recv()
+                .map_err(|err| err.to_string())?
+                .map_err(|err| err.to_string())?;
+        }
+
+        let mut scores = Vec::with_capacity(matrix.keys.len());
+        for readback in &readbacks {
+            let bytes = readback.slice(..).get_mapped_range();
+            scores.extend(bytes.chunks_exact(8).map(|result| {
+                let product = f32::from_le_bytes([result[0], result[1], result[2], result[3]]);
+                let norm = f32::from_le_bytes([result[4], result[5], result[6], result[7]]);
+                (product, norm)
+            }));
+        }
+        Ok(scores)
+    }
+}
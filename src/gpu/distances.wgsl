@@ -0,0 +1,30 @@
+// Scores every row of a matrix of vectors against a query, one row per invocation.
+// Each result holds the dot product of the row with the query and the squared
+// magnitude of the row, from which every supported metric is derived.
+
+struct Params {
+    rows: u32,
+    dim: u32,
+}
+
+@group(0) @binding(0) var<storage, read> matrix: array<f32>;
+@group(0) @binding(1) var<storage, read> query: array<f32>;
+@group(0) @binding(2) var<storage, read_write> results: array<vec2<f32>>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let row = id.x;
+    if row >= params.rows {
+        return;
+    }
+    let start = row * params.dim;
+    var product = 0.0;
+    var norm = 0.0;
+    for (var i = 0u; i < params.dim; i++) {
+        let value = matrix[start + i];
+        product += value * query[i];
+        norm += value * value;
+    }
+    results[row] = vec2<f32>(product, norm);
+}
@@ -1,16 +1,419 @@
+use crate::acl::{self, Role};
+use crate::backup;
+use crate::cluster::Cluster;
+use crate::commands::{self, GetOptions, KnnOptions, KnnQuery};
+use crate::config::Config;
+#[cfg(feature = "embeddings")]
+use crate::embed::EmbeddingClient;
+use crate::export;
+use crate::format::{Bytes, Format, KeyedVector, Notation};
+use crate::health::Health;
+use crate::import::{self, CsvOptions, Import};
+use crate::index::IndexKind;
+use crate::ipc;
+use crate::limits::{self, Line};
+use crate::metric;
+use crate::model::TextModel;
+use crate::monitor::Monitor;
+use crate::persistence::{self, PathError, RecoveryPoint};
+use crate::pipeline::{Pipeline, Precision};
+use crate::pubsub::PubSub;
+use crate::quote::quote;
+use crate::raft::{self, Raft, VoteRequest};
+use crate::reduce::ReductionKind;
+use crate::replication::Replication;
+use crate::resp;
+use crate::script;
+use crate::slowlog::SlowLog;
+use crate::stats::Stats;
+use crate::vemcache::{Aggregation, Dedup, IndexBuilder, Snapshot};
+use crate::wasm_metric::WasmMetric;
 use crate::Vemcache;
-use tokio::io::AsyncWriteExt;
-use tokio::net::tcp::WriteHalf;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::tcp::{ReadHalf, WriteHalf};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
 
-pub async fn handle_ping(writer: &mut WriteHalf<'_>) {
-    if let Err(_) = writer.write_all(b"pong\n").await {
+/// The number of bytes of responses gathered before they are sent to the client.
+const RESPONSE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The write half of a client connection, which remembers whether the response to
+/// the current command is an error.
+///
+/// Responses are written in the text protocol. When the connection speaks RESP,
+/// they are kept until `end_response` frames them instead. Responses holding
+/// vectors are encoded in the format chosen with `format`. Responses are gathered
+/// in a buffer, sent once it is full or on `flush`, so the responses to pipelined
+/// commands can be sent together.
+pub struct ResponseWriter<'a> {
+    writer: BufWriter<WriteHalf<'a>>,
+    responded: bool,
+    failed: bool,
+    protocol: u8,
+    format: Format,
+    notation: Notation,
+    // The response to the current command, when the connection speaks RESP
+    buffer: Vec<u8>,
+    // Whether the response in `buffer` is encoded rather than text
+    encoded: bool,
+}
+
+impl<'a> ResponseWriter<'a> {
+    pub fn new(writer: WriteHalf<'a>) -> Self {
+        Self {
+            writer: BufWriter::with_capacity(RESPONSE_BUFFER_SIZE, writer),
+            responded: false,
+            failed: false,
+            protocol: resp::PROTOCOL_TEXT,
+            format: Format::Text,
+            notation: Notation::Shortest,
+            buffer: Vec::new(),
+            encoded: false,
+        }
+    }
+
+    /// Writes a response, or part of it, to the client.
+    pub async fn write_all(&mut self, response: &[u8]) -> std::io::Result<()> {
+        if !self.responded {
+            self.responded = true;
+            self.failed = response.starts_with(b"Error");
+        }
+        match self.protocol {
+            resp::PROTOCOL_RESP => {
+                self.buffer.extend_from_slice(response);
+                Ok(())
+            }
+            _ => self.writer.write_all(response).await,
+        }
+    }
+
+    /// Writes a response encoded in the format of the connection: the line
+    /// `<format>: <size>` followed by the bytes in the text protocol, or a bulk
+    /// string over RESP.
+    pub async fn write_encoded(&mut self, encoded: &[u8]) -> std::io::Result<()> {
+        match self.protocol {
+            resp::PROTOCOL_RESP => {
+                self.responded = true;
+                self.encoded = true;
+                self.buffer.extend_from_slice(encoded);
+                Ok(())
+            }
+            _ => {
+                let header = format!("{}: {}\n", self.format.name(), encoded.len());
+                self.write_all(header.as_bytes()).await?;
+                self.writer.write_all(encoded).await
+            }
+        }
+    }
+
+    /// Completes the response written so far, as a single RESP frame if the
+    /// connection speaks RESP. The response is only sent once the buffer is full
+    /// or on `flush`.
+    pub async fn end_response(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let response = std::mem::take(&mut self.buffer);
+        let frame = match std::mem::take(&mut self.encoded) {
+            true => resp::bulk_string(&response),
+            false => resp::encode(&response),
+        };
+        self.writer.write_all(&frame).await
+    }
+
+    /// Completes the response written so far, and sends every response not sent yet.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        self.end_response().await?;
+        self.writer.flush().await
+    }
+
+    /// Returns the version of the protocol the connection speaks.
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Switches the connection to the protocol `version`, from the next response on.
+    pub fn set_protocol(&mut self, version: u8) {
+        self.protocol = version;
+    }
+
+    /// Returns the format of the responses holding vectors.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Switches the responses holding vectors to `format`, from the next response on.
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Returns how the components of vectors are written in the text format.
+    pub fn notation(&self) -> Notation {
+        self.notation
+    }
+
+    /// Writes the components of vectors in `notation`, from the next response on.
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.notation = notation;
+    }
+
+    /// Returns whether the response to the current command was an error, and starts
+    /// the response to the next one.
+    pub fn finish(&mut self) -> bool {
+        self.responded = false;
+        std::mem::take(&mut self.failed)
+    }
+}
+
+/// Switches the connection to the protocol `version`, if given, and responds with
+/// the name and version of the server and the protocol now spoken, one `name value`
+/// per line.
+pub async fn handle_hello(version: Option<u8>, writer: &mut ResponseWriter<'_>) {
+    match version {
+        None => {}
+        Some(version @ (resp::PROTOCOL_TEXT | resp::PROTOCOL_RESP)) => writer.set_protocol(version),
+        Some(_) => {
+            handle_error("NOPROTO unsupported protocol version", writer).await;
+            return;
+        }
+    }
+    let response = format!(
+        "server vemcache\nversion {}\nproto {}\n",
+        env!("CARGO_PKG_VERSION"),
+        writer.protocol()
+    );
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Switches the responses holding vectors to `format`, and the text format to
+/// `notation`, if given, and responds with `OK`. Otherwise responds with the name of
+/// the format of the connection, followed by its notation in the text format.
+pub async fn handle_format(
+    format: Option<Format>,
+    notation: Option<Notation>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    if let Some(notation) = notation {
+        writer.set_notation(notation);
+    }
+    let response = match (format, writer.format()) {
+        (Some(format), _) => {
+            writer.set_format(format);
+            "OK\n".to_string()
+        }
+        (None, Format::Text) => format!("text {}\n", writer.notation().name()),
+        (None, format) => format!("{}\n", format.name()),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Sends a response holding vectors: `value` encoded in the format of the
+/// connection, or `text` written in the notation of the connection in the text
+/// format.
+async fn write_vectors<T: serde::Serialize + ?Sized>(
+    value: &T,
+    text: impl FnOnce(Notation) -> String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let sent = match writer.format().encode(value) {
+        Some(encoded) => writer.write_encoded(&encoded).await,
+        None => {
+            let text = text(writer.notation());
+            writer.write_all(text.as_bytes()).await
+        }
+    };
+    if sent.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Sends keyed vectors, as `<title>: <count>` followed by an `ID: <key>, Vector:
+/// [...]` line per vector in the text format.
+async fn write_keyed_vectors(
+    title: &str,
+    vectors: &[(String, Arc<Vec<f32>>)],
+    writer: &mut ResponseWriter<'_>,
+) {
+    let keyed = vectors
+        .iter()
+        .map(|(id, vector)| KeyedVector { id, vector })
+        .collect::<Vec<_>>();
+    let text = |notation: Notation| {
+        let mut response = format!("{}: {}\n", title, vectors.len());
+        for (id, vector) in vectors {
+            let vector = notation.vector(vector);
+            response.push_str(&format!("ID: {}, Vector: {}\n", quote(id), vector));
+        }
+        response
+    };
+    write_vectors(&keyed, text, writer).await;
+}
+
+/// Lists every command with its summary, or describes the commands named `name`.
+pub async fn handle_help(name: Option<&str>, writer: &mut ResponseWriter<'_>) {
+    let response = match name {
+        None => {
+            let mut response = format!("Commands: {}\n", commands::COMMAND_DOCS.len());
+            for doc in commands::COMMAND_DOCS {
+                response.push_str(&format!("{} - {}\n", doc.name, doc.summary));
+            }
+            response
+        }
+        Some(name) => {
+            let docs = commands::command_docs(name);
+            if docs.is_empty() {
+                handle_error("Unknown command", writer).await;
+                return;
+            }
+            let mut response = format!("Commands: {}\n", docs.len());
+            for doc in docs {
+                response.push_str(&format!(
+                    "syntax {}\narity {}\nsummary {}\n",
+                    doc.syntax, doc.arity, doc.summary
+                ));
+            }
+            response
+        }
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Grants the connection the role bound to `token`. A wrong token leaves the role of
+/// the connection unchanged.
+pub async fn handle_auth(
+    config: &RwLock<Config>,
+    token: &str,
+    role: &mut Option<Role>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let (configured, granted) = {
+        let config = config.read().unwrap();
+        let granted = acl::authenticate(&config.acl_tokens, token);
+        (!config.acl_tokens.is_empty(), granted)
+    };
+    match granted {
+        _ if !configured => {
+            handle_error("AUTH called without any token configured", writer).await;
+        }
+        Some(granted) => {
+            *role = Some(granted);
+            if writer.write_all(b"OK\n").await.is_err() {
+                println!("Error sending response to client");
+            }
+        }
+        None => handle_error("WRONGPASS invalid token", writer).await,
+    }
+}
+
+pub async fn handle_ping(writer: &mut ResponseWriter<'_>) {
+    if writer.write_all(b"pong\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_health(health: &Health, writer: &mut ResponseWriter<'_>) {
+    if writer.write_all(health.report().as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Streams the commands processed by the server until the client disconnects.
+/// Lines sent by the client in the meantime are ignored, and those longer than
+/// `max_line_length` are not kept in memory.
+pub async fn handle_monitor(
+    monitor: &Monitor,
+    reader: &mut BufReader<ReadHalf<'_>>,
+    max_line_length: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut commands = monitor.subscribe();
+    if writer.write_all(b"OK\n").await.is_err() || writer.flush().await.is_err() {
+        println!("Error sending response to client");
+        return;
+    }
+    let mut line = String::new();
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Ok(command) => {
+                    let written = writer.write_all(command.as_bytes()).await;
+                    if written.and(writer.flush().await).is_err() {
+                        return;
+                    }
+                }
+                // Commands missed by a slow client are skipped
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            read = limits::read_line(reader, &mut line, max_line_length) => match read {
+                Ok(Line::Closed) | Err(_) => return,
+                Ok(_) => line.clear(),
+            },
+        }
+    }
+}
+
+/// Streams the messages published on `channels` as `message <channel> <payload>`
+/// lines until the client disconnects. Lines sent by the client in the meantime
+/// are ignored like in `handle_monitor`.
+pub async fn handle_subscribe(
+    pubsub: &PubSub,
+    channels: &[String],
+    reader: &mut BufReader<ReadHalf<'_>>,
+    max_line_length: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut messages = pubsub.subscribe();
+    if writer.write_all(b"OK\n").await.is_err() || writer.flush().await.is_err() {
         println!("Error sending response to client");
+        return;
+    }
+    let mut line = String::new();
+    loop {
+        tokio::select! {
+            message = messages.recv() => match message {
+                Ok(message) if channels.contains(&message.channel) => {
+                    let message = format!(
+                        "message {} {}\n",
+                        quote(&message.channel),
+                        quote(&message.payload)
+                    );
+                    let written = writer.write_all(message.as_bytes()).await;
+                    if written.and(writer.flush().await).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // Messages missed by a slow client are skipped
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            read = limits::read_line(reader, &mut line, max_line_length) => match read {
+                Ok(Line::Closed) | Err(_) => return,
+                Ok(_) => line.clear(),
+            },
+        }
     }
 }
 
-pub async fn handle_insert(db: &mut Vemcache, values: Vec<f32>, writer: &mut WriteHalf<'_>) {
-    db.insert_with_uuid(values);
-    if let Err(_) = writer.write_all(b"OK\n").await {
+pub async fn handle_insert(db: &mut Vemcache, values: Vec<f32>, writer: &mut ResponseWriter<'_>) {
+    if let Err(err) = db.try_insert_with_uuid(values) {
+        handle_error(&format!("Vector rejected: {}", err), writer).await;
+        return;
+    }
+    if writer.write_all(b"OK\n").await.is_err() {
         println!("Error sending response to client");
     }
 }
@@ -19,30 +422,275 @@ pub async fn handle_named_insert(
     db: &mut Vemcache,
     key: String,
     values: Vec<f32>,
-    writer: &mut WriteHalf<'_>,
+    writer: &mut ResponseWriter<'_>,
 ) {
-    db.insert_with_key(key, values);
-    if let Err(_) = writer.write_all(b"OK\n").await {
+    if let Err(err) = db.try_insert_with_key(key, values) {
+        handle_error(&format!("Vector rejected: {}", err), writer).await;
+        return;
+    }
+    if writer.write_all(b"OK\n").await.is_err() {
         println!("Error sending response to client");
     }
 }
 
-pub async fn handle_get(db: &mut Vemcache, key: String, writer: &mut WriteHalf<'_>) {
-    if let Some(values) = db.get(key) {
-        let response = format!("{:?}\n", values);
-        if let Err(_) = writer.write_all(response.as_bytes()).await {
-            println!("Error sending response to client");
-        }
+pub async fn handle_alias(
+    db: &mut Vemcache,
+    key: String,
+    existing: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = if db.alias(key, &existing) {
+        "OK\n"
     } else {
-        if let Err(_) = writer.write_all(b"null\n").await {
-            println!("Error sending response to client");
+        "Key not found\n"
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_bulk_insert(
+    db: &mut Vemcache,
+    vectors: Vec<(String, Vec<f32>)>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let count = vectors.len();
+    let mut rejected = 0;
+    for (key, vector) in vectors {
+        if db.try_insert_with_key(key, vector).is_err() {
+            rejected += 1;
+        }
+    }
+    let response = match rejected {
+        0 => format!("Inserted {} vectors\n", count),
+        rejected => format!(
+            "Inserted {} vectors, {} rejected\n",
+            count - rejected,
+            rejected
+        ),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// The response to `get` with flags, holding the fields they ask for.
+#[derive(Default, Serialize)]
+struct VectorDetails<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vector: Option<&'a [f32]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw: Option<Bytes<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dtype: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    norm: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dim: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accessed_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+impl VectorDetails<'_> {
+    /// Writes the fields as `name value` lines, the vector in `notation` and the raw
+    /// components in base64.
+    fn to_text(&self, notation: Notation) -> String {
+        let mut text = String::new();
+        if let Some(vector) = self.vector {
+            text.push_str(&format!("vector {}\n", notation.vector(vector)));
+        }
+        if let (Some(raw), Some(dtype)) = (&self.raw, self.dtype) {
+            text.push_str(&format!("raw {}\ndtype {}\n", BASE64.encode(raw.0), dtype));
+        }
+        let numbers = [
+            ("norm", self.norm.map(|norm| norm.to_string())),
+            ("dim", self.dim.map(|dim| dim.to_string())),
+            ("created_at", self.created_at.map(|at| at.to_string())),
+            ("accessed_at", self.accessed_at.map(|at| at.to_string())),
+            ("expires_at", self.expires_at.map(|at| at.to_string())),
+        ];
+        for (name, value) in numbers {
+            if let Some(value) = value {
+                text.push_str(&format!("{} {}\n", name, value));
+            }
+        }
+        text
+    }
+}
+
+/// Returns the components of `vector` as little-endian bytes, rounded to `precision`
+/// if any, along with the name of their type.
+fn raw_components(vector: &[f32], precision: Option<Precision>) -> (Vec<u8>, &'static str) {
+    match precision {
+        None => (
+            vector
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+            "f32",
+        ),
+        Some(Precision::F16) => (
+            vector
+                .iter()
+                .flat_map(|value| half::f16::from_f32(*value).to_le_bytes())
+                .collect(),
+            "f16",
+        ),
+        Some(Precision::Bf16) => (
+            vector
+                .iter()
+                .flat_map(|value| half::bf16::from_f32(*value).to_le_bytes())
+                .collect(),
+            "bf16",
+        ),
+    }
+}
+
+pub async fn handle_get(
+    db: &mut Vemcache,
+    key: String,
+    options: GetOptions,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let values = db.get(key.clone());
+    if !options.is_extended() {
+        let text = |notation: Notation| match &values {
+            Some(values) => format!("{}\n", notation.vector(values)),
+            None => "null\n".to_string(),
+        };
+        write_vectors(&values, text, writer).await;
+        return;
+    }
+    let Some(values) = values else {
+        write_vectors(&None::<()>, |_| "null\n".to_string(), writer).await;
+        return;
+    };
+    let raw = options
+        .raw
+        .then(|| raw_components(&values, options.precision));
+    let mut details = VectorDetails {
+        vector: (!options.raw).then_some(&values[..]),
+        raw: raw.as_ref().map(|(bytes, _)| Bytes(bytes)),
+        dtype: raw.as_ref().map(|(_, dtype)| *dtype),
+        norm: options
+            .norm
+            .then(|| metric::dot_product(&values, &values).sqrt()),
+        ..Default::default()
+    };
+    if options.meta {
+        details.dim = Some(values.len());
+        if let Some(timestamps) = db.timestamps(&key) {
+            details.created_at = Some(timestamps.created_at);
+            details.accessed_at = Some(timestamps.accessed_at);
+        }
+        details.expires_at = db.expires_at(&key);
+    }
+    write_vectors(&details, |notation| details.to_text(notation), writer).await;
+}
+
+pub async fn handle_dim(db: &mut Vemcache, key: String, writer: &mut ResponseWriter<'_>) {
+    let response = match db.peek(&key) {
+        Some(values) => format!("{}\n", values.len()),
+        None => "null\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Sends the properties of a vector as `name value` lines: its number of
+/// components (`dim`), its Euclidean norm (`norm`) and, if known, when it was
+/// inserted (`created_at`) and last retrieved (`accessed_at`) and when it expires
+/// (`expires_at`), in milliseconds since the Unix epoch. Describing a vector does
+/// not count as retrieving it.
+pub async fn handle_describe(db: &mut Vemcache, key: String, writer: &mut ResponseWriter<'_>) {
+    let response = match db.peek(&key) {
+        Some(values) => {
+            let mut response = format!(
+                "dim {}\nnorm {}\n",
+                values.len(),
+                metric::dot_product(&values, &values).sqrt()
+            );
+            if let Some(timestamps) = db.timestamps(&key) {
+                response.push_str(&format!(
+                    "created_at {}\naccessed_at {}\n",
+                    timestamps.created_at, timestamps.accessed_at
+                ));
+            }
+            if let Some(expires_at) = db.expires_at(&key) {
+                response.push_str(&format!("expires_at {}\n", expires_at));
+            }
+            response
         }
+        None => "null\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_random_key(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let response = match db.random_key() {
+        Some(key) => format!("{}\n", quote(&key)),
+        None => "null\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_sample(db: &mut Vemcache, n: usize, writer: &mut ResponseWriter<'_>) {
+    write_keyed_vectors("Sample", &db.sample(n), writer).await;
+}
+
+pub async fn handle_count(db: &mut Vemcache, prefix: String, writer: &mut ResponseWriter<'_>) {
+    let response = format!("{}\n", db.count(&prefix));
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
     }
 }
 
-pub async fn handle_remove(db: &mut Vemcache, key: String, writer: &mut WriteHalf<'_>) {
+pub async fn handle_aggregate(
+    db: &mut Vemcache,
+    aggregation: Aggregation,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let values = db.aggregate(&prefix, aggregation);
+    let text = |notation: Notation| match &values {
+        Some(values) => format!("{}\n", notation.vector(values)),
+        None => "null\n".to_string(),
+    };
+    write_vectors(&values, text, writer).await;
+}
+
+pub async fn handle_remove(db: &mut Vemcache, key: String, writer: &mut ResponseWriter<'_>) {
     db.remove(key);
-    if let Err(_) = writer.write_all(b"OK\n").await {
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Removes every vector of `keys` and sends the number of vectors removed.
+pub async fn handle_delete(db: &mut Vemcache, keys: Vec<String>, writer: &mut ResponseWriter<'_>) {
+    let removed = keys.into_iter().filter_map(|key| db.remove(key)).count();
+    let response = format!("{}\n", removed);
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_remove_prefix(
+    db: &mut Vemcache,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = format!("{}\n", db.remove_prefix(&prefix));
+    if writer.write_all(response.as_bytes()).await.is_err() {
         println!("Error sending response to client");
     }
 }
@@ -51,53 +699,200 @@ pub async fn handle_k_nearest_neighbors(
     db: &mut Vemcache,
     key: String,
     k: usize,
-    writer: &mut WriteHalf<'_>,
+    options: KnnOptions,
+    query_timeout: u64,
+    writer: &mut ResponseWriter<'_>,
 ) {
-    match db.get(key) {
+    let timeout = options.timeout.unwrap_or(query_timeout);
+    let metric = match options.metric {
+        Some(name) => match db.metric(&name) {
+            Some(metric) => Some(metric),
+            None => {
+                handle_error(&format!("Unknown metric: {}", name), writer).await;
+                return;
+            }
+        },
+        None => None,
+    };
+    let deadline = search_deadline(timeout);
+    match db.get(key.clone()) {
         Some(query_vector) => {
-            let neighbors = db.k_nearest_neighbors(query_vector, k);
-            let response = neighbors
+            // The neighbors before the page are searched for, then skipped
+            let nearest = k.saturating_add(options.offset);
+            let oversample = options.rerank.unwrap_or(1);
+            let metric = metric.as_deref();
+            let Ok(neighbors) =
+                db.search_with_deadline(&key, &query_vector, nearest, metric, oversample, deadline)
+            else {
+                handle_search_timeout(timeout, writer).await;
+                return;
+            };
+            let page = neighbors
                 .into_iter()
-                .map(|(id, vector)| format!("ID: {}, Vector: {:?}", id, vector))
-                .collect::<Vec<String>>()
-                .join("\n");
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+                .skip(options.offset)
+                .collect::<Vec<_>>();
+            write_keyed_vectors("Neighbors", &page, writer).await;
+        }
+        None => {
+            let response = "Key not found\n";
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
+    }
+}
+
+/// Sends the k nearest neighbors of the query among the vectors of `candidates`.
+pub async fn handle_knn_in(
+    db: &mut Vemcache,
+    k: usize,
+    query: KnnQuery,
+    candidates: Vec<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let query_vector = match query {
+        KnnQuery::Key(key) => db.get(key),
+        KnnQuery::Vector(vector) => Some(Arc::new(vector)),
+    };
+    match query_vector {
+        Some(query_vector) => {
+            let neighbors = db.nearest_among(&query_vector, k, &candidates, None);
+            write_keyed_vectors("Neighbors", &neighbors, writer).await;
+        }
         None => {
-            let response = "Key not found\n";
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(b"Key not found\n").await.is_err() {
                 println!("Error sending response to client");
             }
         }
     }
 }
 
+pub async fn handle_explain_knn(
+    db: &mut Vemcache,
+    key: String,
+    k: usize,
+    options: KnnOptions,
+    query_timeout: u64,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let timeout = options.timeout.unwrap_or(query_timeout);
+    let metric = match options.metric {
+        Some(name) => match db.metric(&name) {
+            Some(metric) => Some(metric),
+            None => {
+                handle_error(&format!("Unknown metric: {}", name), writer).await;
+                return;
+            }
+        },
+        None => None,
+    };
+    let started = Instant::now();
+    let deadline = search_deadline(timeout);
+    let Some(query_vector) = db.peek(&key) else {
+        if writer.write_all(b"Key not found\n").await.is_err() {
+            println!("Error sending response to client");
+        }
+        return;
+    };
+    let looked_up = Instant::now();
+    let nearest = k.saturating_add(options.offset);
+    let metric = metric.as_deref();
+    let oversample = options.rerank.unwrap_or(1);
+    let Ok(explanation) =
+        db.explain_search(&key, &query_vector, nearest, metric, oversample, deadline)
+    else {
+        handle_search_timeout(timeout, writer).await;
+        return;
+    };
+    let searched = Instant::now();
+    let found = explanation
+        .neighbors
+        .iter()
+        .skip(options.offset)
+        .filter(|(id, _)| db.peek(id).is_some())
+        .count();
+    let fetched = Instant::now();
+
+    let selectivity = match db.len() {
+        0 => 0.0,
+        len => explanation.collection_size as f64 / len as f64,
+    };
+    let response = format!(
+        "collection {}\nindex {}\nplan {}\nmetric {}\ncollection_size {}\n\
+         selectivity {:.4}\ncandidates {}\nreranked {}\nresults {}\nlookup_us {}\nsearch_us {}\n\
+         fetch_us {}\ntotal_us {}\n",
+        quote(&explanation.collection),
+        explanation.index.as_deref().unwrap_or("none"),
+        if explanation.used_index {
+            "index"
+        } else {
+            "scan"
+        },
+        explanation.metric,
+        explanation.collection_size,
+        selectivity,
+        explanation.candidates,
+        explanation.reranked,
+        found,
+        looked_up.duration_since(started).as_micros(),
+        searched.duration_since(looked_up).as_micros(),
+        fetched.duration_since(searched).as_micros(),
+        fetched.duration_since(started).as_micros(),
+    );
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_scan(
+    db: &mut Vemcache,
+    after: Option<String>,
+    count: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let keys = db.scan_keys(after.as_deref(), count);
+    let mut response = format!("Keys: {}\n", keys.len());
+    for key in keys {
+        response.push_str(&format!("{}\n", quote(&key)));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Returns the deadline of a search allowed to run for `timeout` milliseconds from
+/// now, or `None` if `timeout` is 0.
+fn search_deadline(timeout: u64) -> Option<Instant> {
+    (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout))
+}
+
+async fn handle_search_timeout(timeout: u64, writer: &mut ResponseWriter<'_>) {
+    let message = format!("TIMEOUT The search did not complete within {} ms", timeout);
+    handle_error(&message, writer).await;
+}
+
 pub async fn handle_vector_addition(
     db: &mut Vemcache,
     key1: String,
     key2: String,
-    writer: &mut WriteHalf<'_>,
+    writer: &mut ResponseWriter<'_>,
 ) {
     match (db.get(key1.clone()), db.get(key2.clone())) {
         (Some(_vector1), Some(_vector2)) => match db.vector_addition(&key1, &key2) {
             Some(result) => {
-                let response = format!("Result: {:?}\n", result);
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
-                    println!("Error sending response to client");
-                }
+                let text = |notation: Notation| format!("Result: {}\n", notation.vector(&result));
+                write_vectors(&result, text, writer).await;
             }
             None => {
                 let response = "Vectors are not compatible for addition\n";
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
+                if writer.write_all(response.as_bytes()).await.is_err() {
                     println!("Error sending response to client");
                 }
             }
         },
         _ => {
             let response = "One or both keys not found\n";
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
@@ -108,26 +903,24 @@ pub async fn handle_vector_subtraction(
     db: &mut Vemcache,
     key1: String,
     key2: String,
-    writer: &mut WriteHalf<'_>,
+    writer: &mut ResponseWriter<'_>,
 ) {
     match (db.get(key1.clone()), db.get(key2.clone())) {
         (Some(_vector1), Some(_vector2)) => match db.vector_subtraction(&key1, &key2) {
             Some(result) => {
-                let response = format!("Result: {:?}\n", result);
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
-                    println!("Error sending response to client");
-                }
+                let text = |notation: Notation| format!("Result: {}\n", notation.vector(&result));
+                write_vectors(&result, text, writer).await;
             }
             None => {
                 let response = "Vectors are not compatible for subtraction\n";
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
+                if writer.write_all(response.as_bytes()).await.is_err() {
                     println!("Error sending response to client");
                 }
             }
         },
         _ => {
             let response = "One or both keys not found\n";
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
@@ -138,7 +931,7 @@ pub async fn handle_vector_scaling(
     db: &mut Vemcache,
     key: String,
     scalar: f32,
-    writer: &mut WriteHalf<'_>,
+    writer: &mut ResponseWriter<'_>,
 ) {
     match db.get(key.clone()) {
         Some(_vector) => {
@@ -146,15 +939,14 @@ pub async fn handle_vector_scaling(
             match db.vector_scaling(&key, scalar) {
                 Some(result) => {
                     // Format and send the result to the client
-                    let response = format!("Result: {:?}\n", result);
-                    if let Err(_) = writer.write_all(response.as_bytes()).await {
-                        println!("Error sending response to client");
-                    }
+                    let text =
+                        |notation: Notation| format!("Result: {}\n", notation.vector(&result));
+                    write_vectors(&result, text, writer).await;
                 }
                 None => {
                     // Handle the case where vector scaling failed (e.g., due to invalid scalar)
-                    let response = format!("Error: Vector scaling failed\n");
-                    if let Err(_) = writer.write_all(response.as_bytes()).await {
+                    let response = "Error: Vector scaling failed\n";
+                    if writer.write_all(response.as_bytes()).await.is_err() {
                         println!("Error sending response to client");
                     }
                 }
@@ -162,7 +954,7 @@ pub async fn handle_vector_scaling(
         }
         None => {
             let response = "Key not found\n";
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
@@ -173,52 +965,1551 @@ pub async fn handle_cosine_similarity(
     db: &mut Vemcache,
     key1: String,
     key2: String,
-    writer: &mut WriteHalf<'_>,
+    writer: &mut ResponseWriter<'_>,
 ) {
     match (db.get(key1.clone()), db.get(key2.clone())) {
         (Some(vector1), Some(vector2)) => match db.cosine_similarity(&vector1, &vector2) {
             Some(similarity) => {
                 let response = format!("Cosine Similarity: {:.4}\n", similarity);
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
+                if writer.write_all(response.as_bytes()).await.is_err() {
                     println!("Error sending response to client");
                 }
             }
             None => {
                 let response = "Vectors are not compatible for cosine similarity\n";
-                if let Err(_) = writer.write_all(response.as_bytes()).await {
+                if writer.write_all(response.as_bytes()).await.is_err() {
                     println!("Error sending response to client");
                 }
             }
         },
         _ => {
             let response = "One or both keys not found\n";
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
     }
 }
 
-pub async fn handle_error(error_msg: &str, writer: &mut WriteHalf<'_>) {
+pub async fn handle_error(error_msg: &str, writer: &mut ResponseWriter<'_>) {
     let response = format!("Error: {}\n", error_msg);
-    if let Err(_) = writer.write_all(response.as_bytes()).await {
+    if writer.write_all(response.as_bytes()).await.is_err() {
         println!("Error sending response to client");
     }
 }
 
-pub async fn handle_dump(db: &mut Vemcache, file_path: String, writer: &mut WriteHalf<'_>) {
-    match db.dump(&file_path) {
-        Ok(_) => {
+/// Writes `snapshot` of the logical database numbered `database` to `file_path`.
+/// Once a full snapshot, `full` is set, is written to the directory it is restored
+/// from, the write-ahead log of `db` is compacted up to it.
+pub async fn handle_dump(
+    db: &Mutex<Vemcache>,
+    snapshot: serde_json::Result<Snapshot>,
+    full: bool,
+    config: &RwLock<Config>,
+    database: usize,
+    file_path: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let data_dir = config.read().unwrap().data_dir.clone();
+    let path = persistence::resolve_dump_path(&config.read().unwrap(), &file_path, database);
+    let path = match path {
+        Ok(path) => path,
+        Err(err) => {
+            let response = format!("Error creating database dump: {}\n", err);
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                println!("Error sending response to client");
+            }
+            return;
+        }
+    };
+    let file_path = path.to_string_lossy().to_string();
+    let compact = data_dir.filter(|data_dir| {
+        full && persistence::is_restored_from(Path::new(data_dir), database, &path)
+    });
+
+    // Serialize on a blocking thread so other clients are served during the dump
+    let result = match snapshot {
+        Ok(snapshot) => {
+            let seq = snapshot.seq();
+            tokio::task::spawn_blocking(move || snapshot.dump(&path.to_string_lossy()))
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+                .map(|_| seq)
+        }
+        Err(err) => Err(err.into()),
+    };
+    match result {
+        Ok(seq) => {
+            if compact.is_some() {
+                if let Err(err) = db.lock().await.compact_wal(seq) {
+                    println!("Could not compact the write-ahead log: {}", err);
+                }
+            }
             let response = format!("Database dump successful: {}\n", file_path);
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
         }
         Err(err) => {
             let response = format!("Error creating database dump: {}\n", err);
-            if let Err(_) = writer.write_all(response.as_bytes()).await {
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                println!("Error sending response to client");
+            }
+        }
+    }
+}
+
+/// Responds with the changes applied after `seq`, and returns the sequence number of
+/// the last one sent, if any.
+pub async fn handle_changes(
+    db: &mut Vemcache,
+    seq: u64,
+    writer: &mut ResponseWriter<'_>,
+) -> Option<u64> {
+    match db.changes_since(seq) {
+        Ok(changes) => {
+            let last = changes.last().map(|change| change.seq);
+            let mut response = format!("Changes: {}\n", changes.len());
+            for change in changes {
+                response.push_str(&format!("{} {}\n", change.seq, change.command));
+            }
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                println!("Error sending response to client");
+            }
+            last
+        }
+        Err(oldest) => {
+            let response = format!(
+                "Error: Changes after {} are no longer available, oldest is {}\n",
+                seq, oldest
+            );
+            if writer.write_all(response.as_bytes()).await.is_err() {
                 println!("Error sending response to client");
             }
+            None
+        }
+    }
+}
+
+pub async fn handle_sync(seq: u64, snapshot: Snapshot, writer: &mut ResponseWriter<'_>) {
+    // Serialize on a blocking thread so other clients are served during the sync
+    let json = tokio::task::spawn_blocking(move || snapshot.to_sync_json())
+        .await
+        .unwrap_or_else(|err| Err(serde_json::Error::io(std::io::Error::other(err))));
+    match json {
+        Ok(snapshot) => {
+            let response = format!("Sync: {}\n{}\n", seq, snapshot);
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                println!("Error sending response to client");
+            }
+        }
+        Err(err) => {
+            let response = format!("Error creating snapshot: {}\n", err);
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                println!("Error sending response to client");
+            }
+        }
+    }
+}
+
+pub async fn handle_replicaof(
+    db: &Arc<Mutex<Vemcache>>,
+    replication: &std::sync::Mutex<Replication>,
+    raft: Option<&Raft>,
+    config: &RwLock<Config>,
+    primary: Option<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    if raft.is_some() {
+        let response =
+            "Error: REPLICAOF is not allowed in Raft mode, nodes follow the leader they elect\n";
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            println!("Error sending response to client");
         }
+        return;
+    }
+    {
+        let mut replication = replication.lock().unwrap();
+        let mut config = config.write().unwrap();
+        config.replicaof = primary.clone();
+        match primary {
+            Some(primary) => {
+                let token = config.primary_token.clone();
+                replication.replicate_from(primary, token, Arc::clone(db))
+            }
+            None => replication.stop(),
+        }
+    }
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Checks that the connection can switch to database `index`, out of `databases`.
+/// Returns whether it can.
+pub async fn handle_select(
+    index: usize,
+    databases: usize,
+    cluster: Option<&Cluster>,
+    raft: Option<&Raft>,
+    writer: &mut ResponseWriter<'_>,
+) -> bool {
+    let (selected, response) = if cluster.is_some() && index != 0 {
+        (
+            false,
+            "Error: SELECT is not allowed in cluster mode\n".to_string(),
+        )
+    } else if raft.is_some() && index != 0 {
+        (
+            false,
+            "Error: SELECT is not allowed in Raft mode\n".to_string(),
+        )
+    } else if index >= databases {
+        (
+            false,
+            format!(
+                "Error: Database {} does not exist, there are {}\n",
+                index, databases
+            ),
+        )
+    } else {
+        (true, "OK\n".to_string())
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+    selected
+}
+
+pub async fn handle_cluster_nodes(cluster: Option<&Cluster>, writer: &mut ResponseWriter<'_>) {
+    let response = match cluster {
+        Some(cluster) => format!("{}\n", cluster.nodes().join("\n")),
+        None => "Error: Cluster mode is not enabled\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Responds with the role of the server in its Raft group, its term and the leader
+/// of the term, one `name value` per line.
+pub async fn handle_raft_status(raft: Option<&Raft>, writer: &mut ResponseWriter<'_>) {
+    let response = match raft {
+        Some(raft) => {
+            let (role, term, leader) = raft.status();
+            format!(
+                "role {}\nterm {}\nleader {}\n",
+                role.name(),
+                term,
+                leader.as_deref().unwrap_or("none")
+            )
+        }
+        None => "Error: Raft mode is not enabled\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Answers the vote request of a candidate with the term of the server, followed by
+/// `granted` or `denied`. `db` is database 0, the one the Raft group replicates.
+pub async fn handle_raft_vote(
+    raft: Option<&Arc<Raft>>,
+    db: &Vemcache,
+    request: VoteRequest,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = match raft {
+        Some(raft) => match raft.vote(&request, db.last_seq()) {
+            (term, true) => format!("Term: {} granted\n", term),
+            (term, false) => format!("Term: {} denied\n", term),
+        },
+        None => "Error: Raft mode is not enabled\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Answers the heartbeat of the leader of `term` with the term of the server.
+/// `db` is database 0, the one the Raft group replicates.
+pub async fn handle_raft_heartbeat(
+    raft: Option<&Arc<Raft>>,
+    db: &Vemcache,
+    term: u64,
+    leader: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = match raft {
+        Some(raft) => format!("Term: {}\n", raft.heartbeat(term, &leader, db.last_seq())),
+        None => "Error: Raft mode is not enabled\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Records that the follower `node` of the leader of `term` holds the changes up to
+/// `seq`, and responds with the changes after it, like `changes`. `stream` is what
+/// the connection of the follower was sent so far.
+pub async fn handle_raft_changes(
+    raft: Option<&Raft>,
+    db: &mut Vemcache,
+    stream: &mut raft::Stream,
+    term: u64,
+    node: String,
+    seq: u64,
+    writer: &mut ResponseWriter<'_>,
+) {
+    match raft {
+        Some(raft) => {
+            raft.acknowledge(stream, term, &node, seq);
+            if let Some(last) = handle_changes(db, seq, writer).await {
+                stream.sent(last);
+            }
+        }
+        None => {
+            if writer
+                .write_all(b"Error: Raft mode is not enabled\n")
+                .await
+                .is_err()
+            {
+                println!("Error sending response to client");
+            }
+        }
+    }
+}
+
+/// Starts building an index in the background and responds right away. The
+/// database is only locked to copy the collection and to install the index.
+pub async fn handle_index_create(
+    db: &Arc<Mutex<Vemcache>>,
+    health: &Arc<Health>,
+    prefix: String,
+    kind: IndexKind,
+    metric: Option<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut guard = db.lock().await;
+    let metric = match metric {
+        Some(name) => guard.metric(&name),
+        None => Some(guard.default_metric()),
+    };
+    let response = match metric {
+        Some(metric) => {
+            let builder = guard.start_index_build(prefix, kind.build(metric));
+            drop(guard);
+            spawn_index_build(db, health, builder);
+            "OK\n".to_string()
+        }
+        None => "Error: Unknown metric\n".to_string(),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Starts building a fresh index of a collection in the background, which replaces
+/// its index once built.
+pub async fn handle_index_rebuild(
+    db: &Arc<Mutex<Vemcache>>,
+    health: &Arc<Health>,
+    prefix: String,
+    params: Vec<(String, usize)>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut guard = db.lock().await;
+    let response = match guard.start_index_rebuild(&prefix, &params) {
+        Ok(builder) => {
+            drop(guard);
+            spawn_index_build(db, health, builder);
+            "OK\n".to_string()
+        }
+        Err(err) => format!("Error: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Runs an index build started with `Vemcache::start_index_build` on a blocking
+/// thread, so other commands are served in the meantime, then installs the index.
+fn spawn_index_build(db: &Arc<Mutex<Vemcache>>, health: &Arc<Health>, mut builder: IndexBuilder) {
+    let db = Arc::clone(db);
+    let health = Arc::clone(health);
+    tokio::spawn(async move {
+        let _build = health.start_index_build();
+        let id = builder.id();
+        let built = tokio::task::spawn_blocking(move || {
+            builder.run();
+            builder
+        })
+        .await;
+        let mut db = db.lock().await;
+        match built {
+            Ok(builder) => {
+                db.finish_index_build(builder);
+            }
+            Err(err) => {
+                println!("Error building an index: {}", err);
+                db.abort_index_build(id);
+            }
+        }
+    });
+}
+
+/// Rebuilds the indexes of the database that hold removed vectors, and responds
+/// with the number of indexes rebuilt.
+pub async fn handle_compact(
+    db: &Arc<Mutex<Vemcache>>,
+    health: &Health,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut db = Arc::clone(db).lock_owned().await;
+    let _build = health.start_index_build();
+    // Rebuild on a blocking thread so health checks are answered in the meantime
+    let response = tokio::task::spawn_blocking(move || db.compact_indexes())
+        .await
+        .map_or_else(
+            |err| format!("Error: Could not compact the indexes: {}\n", err),
+            |compacted| format!("Compacted: {}\n", compacted),
+        );
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Reads the vectors of a collection, and searches its indexes if asked to, on a
+/// blocking thread, since paging in a large dataset can take a while.
+pub async fn handle_warmup(
+    db: &Arc<Mutex<Vemcache>>,
+    prefix: String,
+    indexes: bool,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let db = Arc::clone(db).lock_owned().await;
+    let response = tokio::task::spawn_blocking(move || db.warm_up(&prefix, indexes))
+        .await
+        .map_or_else(
+            |err| format!("Error: Could not warm up: {}\n", err),
+            |(vectors, indexes)| format!("Warmed: {} vectors, {} indexes\n", vectors, indexes),
+        );
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Reduces the dimension of the vectors of a collection, and responds with the
+/// number of vectors projected.
+pub async fn handle_reduce(
+    db: &Arc<Mutex<Vemcache>>,
+    health: &Health,
+    prefix: String,
+    kind: ReductionKind,
+    dim: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let mut db = Arc::clone(db).lock_owned().await;
+    let _build = health.start_index_build();
+    // Fitting the projection and rebuilding the indexes take a while, so run them on a
+    // blocking thread so health checks are answered in the meantime
+    let response = tokio::task::spawn_blocking(move || db.reduce(&prefix, kind, dim))
+        .await
+        .map_or_else(
+            |err| format!("Error: Could not reduce the collection: {}\n", err),
+            |reduced| match reduced {
+                Ok(count) => format!("Reduced: {}\n", count),
+                Err(err) => format!("Error: {}\n", err),
+            },
+        );
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Runs a script with the database locked, and responds with its result encoded
+/// as JSON.
+pub async fn handle_eval(
+    db: &Arc<Mutex<Vemcache>>,
+    source: String,
+    args: Vec<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let db = Arc::clone(db).lock_owned().await;
+    // Scripts may run for a while, so run them on a blocking thread
+    let result = tokio::task::spawn_blocking(move || script::eval(db, &source, args))
+        .await
+        .unwrap_or_else(|err| Err(format!("Could not run the script: {}", err)));
+    match result {
+        Ok(result) => {
+            if writer
+                .write_all(format!("{}\n", result).as_bytes())
+                .await
+                .is_err()
+            {
+                println!("Error sending response to client");
+            }
+        }
+        // Errors point at the line of the script, but must fit on one
+        Err(err) => handle_error(&err.replace('\n', " "), writer).await,
+    }
+}
+
+/// Inserts the embedding of `text` under `key`. The database is only locked once
+/// the embedding endpoint has answered, so other clients are not held up meanwhile.
+pub async fn handle_embed(
+    db: &Arc<Mutex<Vemcache>>,
+    config: &RwLock<Config>,
+    key: String,
+    text: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let vector = embed_text(config, text).await;
+    insert_embedding(db, key, vector, writer).await;
+}
+
+/// Finds the `k` nearest neighbors of the embedding of `text` in the collection of
+/// `prefix`, replying like `knn`.
+pub async fn handle_search_text(
+    db: &Arc<Mutex<Vemcache>>,
+    config: &RwLock<Config>,
+    text: String,
+    k: usize,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let query_vector = embed_text(config, text).await;
+    search_embedding(db, config, query_vector, k, prefix, writer).await;
+}
+
+/// Inserts the embedding of `text` computed by the model loaded by the server under
+/// `key`.
+pub async fn handle_insert_text(
+    db: &Arc<Mutex<Vemcache>>,
+    model: Option<&Arc<dyn TextModel>>,
+    key: String,
+    text: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let vector = run_text_model(model, text).await;
+    insert_embedding(db, key, vector, writer).await;
+}
+
+/// Finds the `k` nearest neighbors of the embedding of `text` computed by the model
+/// loaded by the server in the collection of `prefix`, replying like `knn`.
+pub async fn handle_query_text(
+    db: &Arc<Mutex<Vemcache>>,
+    config: &RwLock<Config>,
+    model: Option<&Arc<dyn TextModel>>,
+    text: String,
+    k: usize,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let query_vector = run_text_model(model, text).await;
+    search_embedding(db, config, query_vector, k, prefix, writer).await;
+}
+
+/// Inserts an embedding under `key`, or reports why it could not be computed.
+async fn insert_embedding(
+    db: &Arc<Mutex<Vemcache>>,
+    key: String,
+    vector: Result<Vec<f32>, String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let vector = match vector {
+        Ok(vector) => vector,
+        Err(err) => {
+            handle_error(&err, writer).await;
+            return;
+        }
+    };
+    if let Err(err) = db.lock().await.try_insert_with_key(key, vector) {
+        handle_error(&format!("Vector rejected: {}", err), writer).await;
+        return;
+    }
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Finds the `k` nearest neighbors of an embedding in the collection of `prefix`, or
+/// reports why it could not be computed.
+async fn search_embedding(
+    db: &Arc<Mutex<Vemcache>>,
+    config: &RwLock<Config>,
+    query_vector: Result<Vec<f32>, String>,
+    k: usize,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let query_vector = match query_vector {
+        Ok(vector) => vector,
+        Err(err) => {
+            handle_error(&err, writer).await;
+            return;
+        }
+    };
+    let timeout = config.read().unwrap().query_timeout;
+    let deadline = search_deadline(timeout);
+    let db = db.lock().await;
+    let Ok(neighbors) = db.search_with_deadline(&prefix, &query_vector, k, None, 1, deadline)
+    else {
+        drop(db);
+        handle_search_timeout(timeout, writer).await;
+        return;
+    };
+    drop(db);
+    write_keyed_vectors("Neighbors", &neighbors, writer).await;
+}
+
+/// Embeds `text` with the model loaded by the server, on a blocking thread.
+async fn run_text_model(
+    model: Option<&Arc<dyn TextModel>>,
+    text: String,
+) -> Result<Vec<f32>, String> {
+    let model = Arc::clone(model.ok_or("No text model is loaded, set text_model_dir")?);
+    tokio::task::spawn_blocking(move || model.embed(&text))
+        .await
+        .unwrap_or_else(|err| Err(format!("Could not embed the text: {}", err)))
+}
+
+/// Embeds `text` with the configured endpoint, on a blocking thread.
+#[cfg(feature = "embeddings")]
+async fn embed_text(config: &RwLock<Config>, text: String) -> Result<Vec<f32>, String> {
+    let client = {
+        let config = config.read().unwrap();
+        EmbeddingClient::new(
+            &config.embed_url,
+            &config.embed_model,
+            config.embed_api_key.clone(),
+        )
+    };
+    tokio::task::spawn_blocking(move || client.embed(&text))
+        .await
+        .unwrap_or_else(|err| Err(format!("Could not embed the text: {}", err)))
+        // Endpoints may answer with errors spanning several lines
+        .map_err(|err| err.replace('\n', " "))
+}
+
+#[cfg(not(feature = "embeddings"))]
+async fn embed_text(_config: &RwLock<Config>, _text: String) -> Result<Vec<f32>, String> {
+    Err(
+        "This build does not support embeddings, rebuild it with the embeddings feature"
+            .to_string(),
+    )
+}
+
+/// Registers the metric implemented by a WebAssembly module, and saves the module
+/// in the directory of the database, if there is a data directory.
+pub async fn handle_metric_register(
+    db: &mut Vemcache,
+    config: &RwLock<Config>,
+    database: usize,
+    name: String,
+    wasm: Vec<u8>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    if metric::builtin_metric(&name).is_some() {
+        handle_error(&format!("Cannot replace the {} metric", name), writer).await;
+        return;
+    }
+    let metric = match WasmMetric::new(&name, &wasm) {
+        Ok(metric) => metric,
+        Err(err) => {
+            handle_error(&format!("Invalid module: {}", err), writer).await;
+            return;
+        }
+    };
+    let data_dir = config.read().unwrap().data_dir.clone();
+    if let Some(data_dir) = data_dir {
+        let dir = persistence::database_dir(Path::new(&data_dir), database);
+        if let Err(err) = persistence::save_metric(&dir, &name, &wasm) {
+            handle_error(&format!("Could not save the module: {}", err), writer).await;
+            return;
+        }
+    }
+    db.register_metric(Arc::new(metric));
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_metric_list(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let names = db.metric_names();
+    let mut response = format!("Metrics: {}\n", names.len());
+    for name in names {
+        response.push_str(&format!("{}\n", name));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_pipeline_set(
+    db: &mut Vemcache,
+    prefix: String,
+    words: Vec<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    match Pipeline::parse(&words) {
+        Ok(pipeline) => {
+            db.set_pipeline(&prefix, pipeline);
+            if writer.write_all(b"OK\n").await.is_err() {
+                println!("Error sending response to client");
+            }
+        }
+        Err(err) => handle_error(&err, writer).await,
+    }
+}
+
+pub async fn handle_pipeline_drop(
+    db: &mut Vemcache,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = if db.drop_pipeline(&prefix) {
+        "OK\n"
+    } else {
+        "Error: Collection has no pipeline\n"
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_pipeline_list(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let pipelines = db.pipelines().collect::<Vec<_>>();
+    let mut response = format!("Pipelines: {}\n", pipelines.len());
+    for (prefix, pipeline) in pipelines {
+        let prefix = if prefix.is_empty() {
+            "*".into()
+        } else {
+            quote(prefix)
+        };
+        response.push_str(&format!("{} {}\n", prefix, pipeline));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_ttl_set(
+    db: &mut Vemcache,
+    prefix: String,
+    ttl: u64,
+    writer: &mut ResponseWriter<'_>,
+) {
+    db.set_ttl(&prefix, ttl);
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_ttl_drop(db: &mut Vemcache, prefix: String, writer: &mut ResponseWriter<'_>) {
+    let response = if db.drop_ttl(&prefix) {
+        "OK\n"
+    } else {
+        "Error: Collection has no TTL\n"
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_ttl_list(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let ttls = db.ttls().collect::<Vec<_>>();
+    let mut response = format!("TTLs: {}\n", ttls.len());
+    for (prefix, ttl) in ttls {
+        let prefix = if prefix.is_empty() {
+            "*".into()
+        } else {
+            quote(prefix)
+        };
+        response.push_str(&format!("{} {}\n", prefix, ttl));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_reduce_drop(
+    db: &mut Vemcache,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = if db.drop_projection(&prefix) {
+        "OK\n"
+    } else {
+        "Error: Collection is not reduced\n"
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_reduce_list(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let projections = db.projections().collect::<Vec<_>>();
+    let mut response = format!("Reductions: {}\n", projections.len());
+    for (prefix, projection) in projections {
+        let prefix = if prefix.is_empty() {
+            "*".into()
+        } else {
+            quote(prefix)
+        };
+        response.push_str(&format!(
+            "{} {} {} {}\n",
+            prefix,
+            projection.kind().name(),
+            projection.input_dim(),
+            projection.output_dim()
+        ));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_index_drop(db: &mut Vemcache, prefix: String, writer: &mut ResponseWriter<'_>) {
+    let response = if db.drop_index(&prefix) {
+        "OK\n"
+    } else {
+        "Error: Collection is not indexed\n"
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_index_list(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let indexes = db.indexes();
+    let mut response = format!("Indexes: {}\n", indexes.len());
+    for (prefix, index) in indexes {
+        let prefix = if prefix.is_empty() {
+            "*".into()
+        } else {
+            quote(prefix)
+        };
+        response.push_str(&format!(
+            "{} {} {} {} {}\n",
+            prefix,
+            index.kind(),
+            index.metric().name(),
+            index.len(),
+            index.removed()
+        ));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_bench_recall(
+    db: &mut Vemcache,
+    sample_size: usize,
+    k: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let recalls = db.recall(sample_size, k);
+    let mut response = format!("Indexes: {}\n", recalls.len());
+    for recall in recalls {
+        let prefix = if recall.collection.is_empty() {
+            "*".into()
+        } else {
+            quote(&recall.collection)
+        };
+        response.push_str(&format!(
+            "{} {} {} {:.4}\n",
+            prefix, recall.index, recall.queries, recall.recall
+        ));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_index_status(db: &mut Vemcache, writer: &mut ResponseWriter<'_>) {
+    let builds = db.index_builds();
+    let mut response = format!("Builds: {}\n", builds.len());
+    for build in builds {
+        let prefix = if build.prefix.is_empty() {
+            "*".into()
+        } else {
+            quote(&build.prefix)
+        };
+        response.push_str(&format!(
+            "{} {} {} {}\n",
+            prefix, build.kind, build.indexed, build.total
+        ));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_config_get(
+    config: &RwLock<Config>,
+    pattern: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let parameters = config.read().unwrap().matching(&pattern);
+    let response = if parameters.is_empty() {
+        format!("Error: Unknown configuration parameter: {}\n", pattern)
+    } else {
+        parameters
+            .into_iter()
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect::<String>()
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_config_set(
+    databases: &[Arc<Mutex<Vemcache>>],
+    config: &RwLock<Config>,
+    slowlog: &std::sync::Mutex<SlowLog>,
+    name: String,
+    value: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let result = {
+        let mut config = config.write().unwrap();
+        config.set(&name, &value).map(|_| {
+            slowlog.lock().unwrap().set_capacity(config.slowlog_size);
+            (
+                config.changelog_size,
+                config.metric.clone(),
+                Dedup::from_name(&config.dedup).unwrap_or_default(),
+            )
+        })
+    };
+    let response = match result {
+        Ok((changelog_size, metric, dedup)) => {
+            // Apply the parameters that live outside of the configuration
+            for db in databases {
+                let mut db = db.lock().await;
+                db.set_changelog_capacity(changelog_size);
+                db.set_default_metric(&metric);
+                db.set_dedup(dedup);
+            }
+            "OK\n".to_string()
+        }
+        Err(err) => format!("Error: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_slowlog_get(
+    slowlog: &std::sync::Mutex<SlowLog>,
+    count: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = {
+        let slowlog = slowlog.lock().unwrap();
+        let entries = slowlog.get(count);
+        let mut response = format!("Slowlog: {}\n", entries.len());
+        for entry in entries {
+            response.push_str(&format!(
+                "{} {} {} {}\n",
+                entry.id,
+                entry.timestamp,
+                entry.duration.as_micros(),
+                entry.command
+            ));
+        }
+        response
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_slowlog_len(
+    slowlog: &std::sync::Mutex<SlowLog>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = format!("{}\n", slowlog.lock().unwrap().len());
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_slowlog_reset(
+    slowlog: &std::sync::Mutex<SlowLog>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    slowlog.lock().unwrap().reset();
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_stats(stats: &std::sync::Mutex<Stats>, writer: &mut ResponseWriter<'_>) {
+    let response = {
+        let stats = stats.lock().unwrap();
+        let mut lines = Vec::new();
+        for (name, command) in stats.commands() {
+            lines.push(format!(
+                "{} {} {} {} {} {}",
+                name.replace(' ', "_"),
+                command.calls(),
+                command.errors(),
+                command.percentile(0.5).as_micros(),
+                command.percentile(0.95).as_micros(),
+                command.percentile(0.99).as_micros()
+            ));
+        }
+        let mut response = format!("Stats: {}\n", lines.len());
+        for line in lines {
+            response.push_str(&line);
+            response.push('\n');
+        }
+        response
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_stats_reset(stats: &std::sync::Mutex<Stats>, writer: &mut ResponseWriter<'_>) {
+    stats.lock().unwrap().reset();
+    if writer.write_all(b"OK\n").await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_stats_dims(db: &mut Vemcache, prefix: String, writer: &mut ResponseWriter<'_>) {
+    let stats = db.dimension_stats(&prefix);
+    let mut response = format!("Dims: {}\n", stats.len());
+    for (dim, stats) in stats.iter().enumerate() {
+        response.push_str(&format!(
+            "{} {} {} {} {} {}\n",
+            dim, stats.count, stats.mean, stats.std, stats.min, stats.max
+        ));
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Sends the state of a collection and of its index, one `name value` pair per
+/// line.
+pub async fn handle_collection_stats(
+    db: &mut Vemcache,
+    prefix: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let stats = db.collection_stats(&prefix);
+    let dim = match stats.dim {
+        Some(dim) => dim.to_string(),
+        None if stats.count == 0 => "null".to_string(),
+        None => "mixed".to_string(),
+    };
+    let mut response = format!(
+        "count {}\ndim {}\nmetric {}\nmemory {}\n",
+        stats.count, dim, stats.metric, stats.memory
+    );
+    match stats.index {
+        Some(index) => {
+            let prefix = if index.prefix.is_empty() {
+                "*".into()
+            } else {
+                quote(&index.prefix)
+            };
+            response.push_str(&format!("index {}\nindex_prefix {}\n", index.kind, prefix));
+            for (name, value) in index.params {
+                response.push_str(&format!("index_{} {}\n", name, value));
+            }
+            response.push_str(&format!(
+                "index_vectors {}\nindex_removed {}\nindex_memory {}\nneeds_compaction {}\n",
+                index.len, index.removed, index.memory, index.needs_compaction
+            ));
+        }
+        None => response.push_str("index null\n"),
+    }
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_backup(
+    databases: &[Arc<Mutex<Vemcache>>],
+    config: &RwLock<Config>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let response = match backup::back_up(databases, config).await {
+        Ok(backups) => {
+            let mut response = format!("Backups: {}\n", backups.len());
+            for backup in backups {
+                response.push_str(&format!("{}\n", backup.display()));
+            }
+            response
+        }
+        Err(err) => format!("Error backing up: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_restore_until(
+    db: &mut Vemcache,
+    config: &RwLock<Config>,
+    database: usize,
+    point: RecoveryPoint,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let Some(data_dir) = config.read().unwrap().data_dir.clone() else {
+        handle_error("Point-in-time recovery requires a data directory", writer).await;
+        return;
+    };
+    let dir = persistence::database_dir(std::path::Path::new(&data_dir), database);
+    // The database stays locked, so no change slips in between reading the
+    // write-ahead log and rolling back, but the files are read on a blocking thread
+    let recovered = tokio::task::spawn_blocking(move || persistence::recover(&dir, point))
+        .await
+        .unwrap_or_else(|err| Err(std::io::Error::other(err)));
+    let response = match recovered {
+        Ok((recovered, seq)) => {
+            let (removed, inserted) = db.revert_to(recovered.snapshot());
+            format!(
+                "Restored to change {}: {} removed, {} inserted\n",
+                seq, removed, inserted
+            )
+        }
+        Err(err) => format!("Error restoring: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Returns the directory of the database numbered `database`, or `None` if no data
+/// directory is configured.
+fn database_dir(config: &RwLock<Config>, database: usize) -> Option<std::path::PathBuf> {
+    let data_dir = config.read().unwrap().data_dir.clone()?;
+    Some(persistence::database_dir(
+        std::path::Path::new(&data_dir),
+        database,
+    ))
+}
+
+pub async fn handle_snapshots_list(
+    config: &RwLock<Config>,
+    database: usize,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let Some(dir) = database_dir(config, database) else {
+        handle_error("Snapshots require a data directory", writer).await;
+        return;
+    };
+    let response = match persistence::list_snapshots(&dir) {
+        Ok(snapshots) => {
+            let mut response = format!("Snapshots: {}\n", snapshots.len());
+            for snapshot in snapshots {
+                response.push_str(&format!(
+                    "{} {} {} {}\n",
+                    quote(&snapshot.name),
+                    snapshot.size,
+                    backup::utc_timestamp(snapshot.modified),
+                    snapshot_version(&snapshot)
+                ));
+            }
+            response
+        }
+        Err(err) => format!("Error listing snapshots: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_snapshots_info(
+    config: &RwLock<Config>,
+    database: usize,
+    name: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let Some(dir) = database_dir(config, database) else {
+        handle_error("Snapshots require a data directory", writer).await;
+        return;
+    };
+    let response = match persistence::snapshot_info(&dir, &name) {
+        Ok(snapshot) => {
+            let sidecars = match snapshot.sidecars.is_empty() {
+                true => "none".to_string(),
+                false => snapshot.sidecars.join(","),
+            };
+            format!(
+                "name {}\nsize {}\nmodified {}\nversion {}\ncompression {}\nencrypted {}\nbase {}\nsidecars {}\n",
+                quote(&snapshot.name),
+                snapshot.size,
+                backup::utc_timestamp(snapshot.modified),
+                snapshot_version(&snapshot),
+                snapshot.compression.name(),
+                snapshot.encrypted,
+                snapshot.base.as_deref().map(quote).unwrap_or("null".into()),
+                sidecars
+            )
+        }
+        Err(err) => format!("Error: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_snapshots_delete(
+    config: &RwLock<Config>,
+    database: usize,
+    name: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let Some(dir) = database_dir(config, database) else {
+        handle_error("Snapshots require a data directory", writer).await;
+        return;
+    };
+    let response = match persistence::delete_snapshot(&dir, &name) {
+        Ok(()) => "OK\n".to_string(),
+        Err(err) => format!("Error: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Returns the format version of `snapshot`, or `unknown` if it cannot be read.
+fn snapshot_version(snapshot: &persistence::Snapshot) -> String {
+    snapshot
+        .version
+        .map_or("unknown".to_string(), |version| version.to_string())
+}
+
+/// Stops the server once the write-ahead logs and storages of every database are on
+/// disk, after writing a snapshot of every non-empty database to its directory if
+/// `save` is set and a data directory is configured. The databases stay locked until the
+/// process exits, so no change is accepted in the meantime. If anything cannot be
+/// written, the error is sent to the client and the server keeps running.
+pub async fn handle_shutdown(
+    databases: &[Arc<Mutex<Vemcache>>],
+    config: &RwLock<Config>,
+    save: bool,
+    writer: &mut ResponseWriter<'_>,
+) {
+    if let Err(err) = prepare_shutdown(databases, config, save).await {
+        let response = format!("Error shutting down: {}\n", err);
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            println!("Error sending response to client");
+        }
+        return;
+    }
+    if writer.write_all(b"OK\n").await.is_err() || writer.flush().await.is_err() {
+        println!("Error sending response to client");
+    }
+    println!("Shutting down at the request of a client");
+    std::process::exit(0);
+}
+
+/// Gets the databases ready for the process to exit: if `save` is set and a data
+/// directory is configured, every database that is not empty is saved to a
+/// snapshot the next start restores, then every database is flushed to disk.
+/// On success, the databases stay locked, so no change is made that the snapshot
+/// would miss.
+///
+/// # Errors
+///
+/// Returns an error if a database cannot be saved or flushed.
+pub async fn prepare_shutdown(
+    databases: &[Arc<Mutex<Vemcache>>],
+    config: &RwLock<Config>,
+    save: bool,
+) -> std::io::Result<()> {
+    let data_dir = config.read().unwrap().data_dir.clone();
+    let mut guards = Vec::new();
+    for db in databases {
+        guards.push(db.lock().await);
+    }
+
+    let mut result = Ok(());
+    for (index, db) in guards.iter_mut().enumerate() {
+        // Empty databases are fully restored by replaying their write-ahead log
+        if let (true, Some(data_dir), false) = (save, &data_dir, db.is_empty()) {
+            let path = persistence::database_dir(std::path::Path::new(data_dir), index)
+                .join(persistence::SHUTDOWN_SNAPSHOT);
+            result = match db.snapshot_with_indexes() {
+                // Serialize on a blocking thread, as the runtime keeps serving health checks
+                Ok(snapshot) => {
+                    tokio::task::spawn_blocking(move || snapshot.dump(&path.to_string_lossy()))
+                        .await
+                        .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+                }
+                Err(err) => Err(err.into()),
+            };
+            if result.is_ok() {
+                let seq = db.last_seq();
+                if let Err(err) = db.compact_wal(seq) {
+                    println!("Could not compact the write-ahead log: {}", err);
+                }
+            }
+        }
+        result = result.and_then(|_| db.flush());
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_ok() {
+        // Changes made after the databases are saved would be lost, so they stay
+        // locked until the process exits
+        std::mem::forget(guards);
+    }
+    result
+}
+
+pub async fn handle_import_csv(
+    db: &Mutex<Vemcache>,
+    config: &RwLock<Config>,
+    file_path: String,
+    options: CsvOptions,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let path = persistence::resolve_path(&config.read().unwrap(), &file_path);
+    let result = match path {
+        // Parse on a blocking thread so other clients are served during the import
+        Ok(path) => tokio::task::spawn_blocking(move || import::read_csv_file(&path, &options))
+            .await
+            .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    let response = match result {
+        Ok(import) => import_response(import, &mut *db.lock().await),
+        Err(err) => format!("Error importing {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_import_npy(
+    db: &Mutex<Vemcache>,
+    config: &RwLock<Config>,
+    file_path: String,
+    keys_path: Option<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let paths = resolve_npy_paths(
+        config,
+        &file_path,
+        keys_path.as_deref(),
+        persistence::resolve_path,
+    );
+    let result = match paths {
+        // Parse on a blocking thread so other clients are served during the import
+        Ok((path, keys_path)) => {
+            tokio::task::spawn_blocking(move || import::read_npy_file(&path, keys_path.as_deref()))
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+                .map_err(|err| err.to_string())
+        }
+        Err(err) => Err(err),
+    };
+    let response = match result {
+        Ok(import) => import_response(import, &mut *db.lock().await),
+        Err(err) => format!("Error importing {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_export_npy(
+    snapshot: Snapshot,
+    config: &RwLock<Config>,
+    file_path: String,
+    keys_path: Option<String>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let paths = resolve_npy_paths(
+        config,
+        &file_path,
+        keys_path.as_deref(),
+        persistence::resolve_export_path,
+    );
+    let result = match paths {
+        // Write on a blocking thread so other clients are served during the export
+        Ok((path, keys_path)) => tokio::task::spawn_blocking(move || {
+            export::write_npy_file(&snapshot, &path, keys_path.as_deref())
+        })
+        .await
+        .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+        .map_err(|err| err.to_string()),
+        Err(err) => Err(err),
+    };
+    let response = match result {
+        Ok(count) => format!("Exported {} vectors\n", count),
+        Err(err) => format!("Error exporting {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_import_jsonl(
+    db: &Mutex<Vemcache>,
+    config: &RwLock<Config>,
+    file_path: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let path = persistence::resolve_path(&config.read().unwrap(), &file_path);
+    let result = match path {
+        // Parse on a blocking thread so other clients are served during the import
+        Ok(path) => tokio::task::spawn_blocking(move || import::read_jsonl_file(&path))
+            .await
+            .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    let response = match result {
+        Ok(import) => import_response(import, &mut *db.lock().await),
+        Err(err) => format!("Error importing {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_export_jsonl(
+    snapshot: Snapshot,
+    config: &RwLock<Config>,
+    file_path: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let path = persistence::resolve_export_path(&config.read().unwrap(), &file_path);
+    let result = match path {
+        // Write on a blocking thread so other clients are served during the export
+        Ok(path) => tokio::task::spawn_blocking(move || export::write_jsonl_file(&snapshot, &path))
+            .await
+            .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    let response = match result {
+        Ok(count) => format!("Exported {} vectors\n", count),
+        Err(err) => format!("Error exporting {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_export_vec(
+    snapshot: Snapshot,
+    config: &RwLock<Config>,
+    file_path: String,
+    writer: &mut ResponseWriter<'_>,
+) {
+    let path = persistence::resolve_export_path(&config.read().unwrap(), &file_path);
+    let result = match path {
+        // Write on a blocking thread so other clients are served during the export
+        Ok(path) => {
+            tokio::task::spawn_blocking(move || export::write_vector_file(&snapshot, &path))
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+                .map_err(|err| err.to_string())
+        }
+        Err(err) => Err(err.to_string()),
+    };
+    let response = match result {
+        Ok(count) => format!("Exported {} vectors\n", count),
+        Err(err) => format!("Error exporting {}: {}\n", file_path, err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_arrow_import(
+    db: &Mutex<Vemcache>,
+    stream: Vec<u8>,
+    writer: &mut ResponseWriter<'_>,
+) {
+    // Decode on a blocking thread so other clients are served during the import
+    let result = tokio::task::spawn_blocking(move || ipc::read_stream(&stream))
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|result| result.map_err(|err| err.to_string()));
+    let response = match result {
+        Ok(import) => import_response(import, &mut *db.lock().await),
+        Err(err) => format!("Error importing Arrow stream: {}\n", err),
+    };
+    if writer.write_all(response.as_bytes()).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+pub async fn handle_arrow_download(snapshot: Snapshot, writer: &mut ResponseWriter<'_>) {
+    // Encode on a blocking thread so other clients are served during the download
+    let result = tokio::task::spawn_blocking(move || ipc::write_stream(&snapshot))
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|result| result.map_err(|err| err.to_string()));
+    let response = match result {
+        Ok(stream) => {
+            let mut response = format!("Arrow: {}\n", stream.len()).into_bytes();
+            response.extend_from_slice(&stream);
+            response
+        }
+        Err(err) => format!("Error encoding Arrow stream: {}\n", err).into_bytes(),
+    };
+    if writer.write_all(&response).await.is_err() {
+        println!("Error sending response to client");
+    }
+}
+
+/// Resolves the path of a NumPy file and of its optional keys file against the data directory.
+fn resolve_npy_paths(
+    config: &RwLock<Config>,
+    file_path: &str,
+    keys_path: Option<&str>,
+    resolve: fn(&Config, &str) -> Result<PathBuf, PathError>,
+) -> Result<(PathBuf, Option<PathBuf>), String> {
+    let config = config.read().unwrap();
+    let path = resolve(&config, file_path).map_err(|err| err.to_string())?;
+    let keys_path = keys_path
+        .map(|keys_path| resolve(&config, keys_path))
+        .transpose()
+        .map_err(|err| err.to_string())?;
+    Ok((path, keys_path))
+}
+
+fn import_response(mut import: Import, db: &mut Vemcache) -> String {
+    let errors = std::mem::take(&mut import.errors);
+    let mut count = 0;
+    let mut rejected = Vec::new();
+    for (key, vector) in import.vectors {
+        match db.try_insert_with_key(key.clone(), vector) {
+            Ok(()) => count += 1,
+            Err(err) => rejected.push((key, err)),
+        }
+    }
+    if errors.is_empty() && rejected.is_empty() {
+        return format!("Imported {} vectors\n", count);
+    }
+    let mut response = format!(
+        "Imported {} vectors, {} errors\n",
+        count,
+        errors.len() + rejected.len()
+    );
+    for error in errors.iter().take(import::MAX_REPORTED_ERRORS) {
+        response.push_str(&format!("Row {}: {}\n", error.row, error.message));
+    }
+    let remaining = import::MAX_REPORTED_ERRORS.saturating_sub(errors.len());
+    for (key, err) in rejected.iter().take(remaining) {
+        response.push_str(&format!("Key {}: Vector rejected: {}\n", quote(key), err));
     }
+    response
 }
@@ -0,0 +1,132 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::OnceCell;
+
+/// The state of the server reported by the `health` command and the probes.
+///
+/// The state is kept in atomics rather than behind the database lock, so health
+/// checks are answered right away even while a long operation holds the database.
+#[derive(Default)]
+pub struct Health {
+    loading: AtomicBool,
+    index_builds: AtomicUsize,
+    // Set once the server has been ready, so indexes built afterwards, while
+    // searches are served, do not make it unready again
+    started: AtomicBool,
+    draining: AtomicBool,
+    drained: OnceCell<()>,
+    wal_failed: AtomicBool,
+}
+
+/// Marks an index build as in progress until it is dropped.
+pub struct IndexBuild<'a> {
+    health: &'a Health,
+}
+
+impl Health {
+    /// Creates the state of a server that is ready to serve commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records whether the dataset is being loaded from disk.
+    pub fn set_loading(&self, loading: bool) {
+        self.loading.store(loading, Ordering::SeqCst);
+    }
+
+    /// Returns whether the dataset is being loaded from disk, in which case commands
+    /// other than `health` are rejected.
+    pub fn is_loading(&self) -> bool {
+        self.loading.load(Ordering::SeqCst)
+    }
+
+    /// Marks an index build as in progress for the lifetime of the returned guard.
+    pub fn start_index_build(&self) -> IndexBuild<'_> {
+        self.index_builds.fetch_add(1, Ordering::SeqCst);
+        IndexBuild { health: self }
+    }
+
+    /// Returns the number of indexes being built.
+    pub fn index_builds(&self) -> usize {
+        self.index_builds.load(Ordering::SeqCst)
+    }
+
+    /// Records that a change could not be appended to the write-ahead log, after
+    /// which the server is no longer ready until it is restarted.
+    pub fn set_wal_failed(&self) {
+        self.wal_failed.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether a change could not be appended to the write-ahead log.
+    pub fn is_wal_failed(&self) -> bool {
+        self.wal_failed.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the server is shutting down, after `drain` was called.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the server should receive traffic: the snapshot of the data
+    /// directory is loaded, the indexes being built at that point are built, the
+    /// write-ahead log has not failed, and the server is not shutting down.
+    pub fn is_ready(&self) -> bool {
+        if self.is_draining() || self.is_wal_failed() {
+            return false;
+        }
+        if self.started.load(Ordering::SeqCst) {
+            return true;
+        }
+        let started = !self.is_loading() && self.index_builds() == 0;
+        if started {
+            self.started.store(true, Ordering::SeqCst);
+        }
+        started
+    }
+
+    /// Stops reporting the server as ready, then runs `save`, which prepares the
+    /// databases for the shutdown, unless a previous call succeeded. Calls made
+    /// while `save` runs wait for it, so the databases are saved once whether the
+    /// shutdown is announced by a preStop hook, a signal, or both.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `save`, in which case the next call runs it again.
+    pub async fn drain<F>(&self, save: F) -> std::io::Result<()>
+    where
+        F: Future<Output = std::io::Result<()>>,
+    {
+        self.draining.store(true, Ordering::SeqCst);
+        self.drained.get_or_try_init(|| save).await.map(|_| ())
+    }
+
+    /// Returns the report sent in response to `health`: the status of the server,
+    /// `loading`, `building`, `ready`, `failing` or `draining`, followed by the
+    /// details it is derived from, one `name value` pair per line.
+    pub fn report(&self) -> String {
+        let loading = self.is_loading();
+        let draining = self.is_draining();
+        let wal_failed = self.is_wal_failed();
+        let status = match (self.is_ready(), loading) {
+            _ if draining => "draining",
+            _ if wal_failed => "failing",
+            (true, _) => "ready",
+            (false, true) => "loading",
+            (false, false) => "building",
+        };
+        format!(
+            "status {}\nloading {}\nindex_builds {}\ndraining {}\nwal_failed {}\n",
+            status,
+            loading,
+            self.index_builds(),
+            draining,
+            wal_failed
+        )
+    }
+}
+
+impl Drop for IndexBuild<'_> {
+    fn drop(&mut self) {
+        self.health.index_builds.fetch_sub(1, Ordering::SeqCst);
+    }
+}
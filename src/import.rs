@@ -0,0 +1,301 @@
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Error as IoError, ErrorKind, Read, Result as IoResult};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::compression;
+
+use crate::npy::{self, Array};
+use crate::Vemcache;
+
+/// The maximum number of row errors included in a response to a client.
+pub const MAX_REPORTED_ERRORS: usize = 10;
+
+/// The options of a CSV import.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CsvOptions {
+    /// The index of the column holding the key of each vector. Every other column
+    /// holds a component of the vector.
+    pub key_column: usize,
+    /// Whether the first row is a header to be skipped.
+    pub header: bool,
+}
+
+/// A row of an import file that could not be imported.
+#[derive(Debug, PartialEq)]
+pub struct RowError {
+    /// The 1-based line number of the row.
+    pub row: u64,
+    /// Why the row was rejected.
+    pub message: String,
+}
+
+/// The vectors read from an import file, along with the rows that were rejected.
+///
+/// Reading a file and inserting its vectors are separate steps, so large files can
+/// be parsed without holding on to the database.
+#[derive(Debug, Default)]
+pub struct Import {
+    /// The keys and vectors read from the file, in order.
+    pub vectors: Vec<(String, Vec<f32>)>,
+    /// The rows that were rejected.
+    pub errors: Vec<RowError>,
+}
+
+impl Import {
+    /// Inserts the imported vectors into the database and returns how many were
+    /// inserted, leaving out those rejected by the pipeline of their collection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::import::{self, CsvOptions};
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// let csv = "vector1,1.0,2.0\nvector2,3.0,4.0\n";
+    /// let import = import::read_csv(csv.as_bytes(), &CsvOptions::default());
+    /// assert_eq!(import.insert_into(&mut db), 2);
+    /// ```
+    pub fn insert_into(self, db: &mut Vemcache) -> usize {
+        let mut count = 0;
+        for (key, vector) in self.vectors {
+            if db.try_insert_with_key(key, vector).is_ok() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Rejects a vector whose dimension differs from the vectors imported before it.
+    pub(crate) fn push(&mut self, row: u64, key: String, vector: Vec<f32>) {
+        if let Some((_, first)) = self.vectors.first() {
+            if first.len() != vector.len() {
+                let message = format!(
+                    "Expected {} dimensions, found {}",
+                    first.len(),
+                    vector.len()
+                );
+                self.errors.push(RowError { row, message });
+                return;
+            }
+        }
+        self.vectors.push((key, vector));
+    }
+}
+
+/// Reads a CSV file of vectors, where each row holds a key and the components of a vector.
+///
+/// Every row must have the same number of components as the first imported row.
+/// Rows that are malformed are reported rather than aborting the import.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened.
+pub fn read_csv_file(path: &Path, options: &CsvOptions) -> IoResult<Import> {
+    Ok(read_csv(File::open(path)?, options))
+}
+
+/// Reads CSV vectors from `reader`. See `read_csv_file`.
+pub fn read_csv<R: Read>(reader: R, options: &CsvOptions) -> Import {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(options.header)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut import = Import::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let row = err.position().map_or(0, |position| position.line());
+                import.errors.push(RowError {
+                    row,
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let row = record.position().map_or(0, |position| position.line());
+
+        let key = match record.get(options.key_column) {
+            Some(key) if !key.trim().is_empty() => key.trim().to_string(),
+            _ => {
+                import.errors.push(RowError {
+                    row,
+                    message: format!("Missing key in column {}", options.key_column),
+                });
+                continue;
+            }
+        };
+        let vector = record
+            .iter()
+            .enumerate()
+            .filter(|(column, _)| *column != options.key_column)
+            .map(|(column, value)| {
+                fast_float2::parse::<f32, _>(value.trim())
+                    .map_err(|_| format!("Invalid value in column {}: {}", column, value))
+            })
+            .collect::<Result<Vec<f32>, String>>();
+        match vector {
+            Ok(vector) if vector.is_empty() => import.errors.push(RowError {
+                row,
+                message: "Empty vector".to_string(),
+            }),
+            Ok(vector) => import.push(row, key, vector),
+            Err(message) => import.errors.push(RowError { row, message }),
+        }
+    }
+    import
+}
+
+/// A line of a JSON Lines import file.
+///
+/// Other fields, such as `metadata`, are accepted but ignored, since vectors do not
+/// carry metadata.
+#[derive(Deserialize)]
+struct JsonlRecord {
+    id: String,
+    vector: Vec<f32>,
+}
+
+/// Reads a JSON Lines file of vectors, where each line is an object such as
+/// `{"id": "vector1", "vector": [1.0, 2.0], "metadata": {...}}`.
+///
+/// The file may be compressed with gzip or zstd. Every vector must have the same
+/// number of components as the first imported vector. Lines that are malformed are
+/// reported rather than aborting the import, and blank lines are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read.
+pub fn read_jsonl_file(path: &Path) -> IoResult<Import> {
+    read_jsonl(BufReader::new(compression::open_file(
+        &path.to_string_lossy(),
+    )?))
+}
+
+/// Reads JSON Lines vectors from `reader`. See `read_jsonl_file`.
+///
+/// # Errors
+///
+/// Returns an error if `reader` cannot be read.
+pub fn read_jsonl<R: BufRead>(reader: R) -> IoResult<Import> {
+    let mut import = Import::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let row = index as u64 + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JsonlRecord>(&line) {
+            Ok(record) if record.id.is_empty() => import.errors.push(RowError {
+                row,
+                message: "Missing id".to_string(),
+            }),
+            Ok(record) if record.vector.is_empty() => import.errors.push(RowError {
+                row,
+                message: "Empty vector".to_string(),
+            }),
+            Ok(record) => import.push(row, record.id, record.vector),
+            Err(err) => import.errors.push(RowError {
+                row,
+                message: err.to_string(),
+            }),
+        }
+    }
+    Ok(import)
+}
+
+/// Reads vectors from a NumPy file.
+///
+/// The file is either a `.npy` file holding an N×D `float32` or `float64` matrix,
+/// or a `.npz` archive holding such a matrix named `vectors` and, optionally, the
+/// keys of the vectors as a string array named `keys`. For `.npy` files, the keys
+/// are read from `keys_path`, a text file with one key per line, which defaults
+/// to the path of the matrix followed by `.keys`. Vectors without keys are given
+/// a UUID, like `insert`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read, is not a supported NumPy file, or if
+/// the number of keys does not match the number of vectors.
+pub fn read_npy_file(path: &Path, keys_path: Option<&Path>) -> IoResult<Import> {
+    let (matrix, keys) = if path.extension().and_then(|ext| ext.to_str()) == Some("npz") {
+        read_npz_arrays(path)?
+    } else {
+        let matrix = npy::parse(&fs::read(path)?).map_err(invalid_data)?;
+        let keys_path = keys_path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(format!("{}.keys", path.display())));
+        let keys = match fs::read_to_string(&keys_path) {
+            Ok(keys) => Some(keys.lines().map(str::to_string).collect::<Vec<String>>()),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        (matrix, keys)
+    };
+
+    let (rows, columns, data) = match matrix {
+        Array::Float { shape, data } if shape.len() == 2 => (shape[0], shape[1], data),
+        Array::Float { shape, .. } => {
+            return Err(invalid_data(format!(
+                "Expected a two-dimensional matrix, found {} dimensions",
+                shape.len()
+            )))
+        }
+        Array::Unicode { .. } => return Err(invalid_data("Expected a matrix of floats")),
+    };
+    if let Some(keys) = &keys {
+        if keys.len() != rows {
+            return Err(invalid_data(format!(
+                "Found {} keys for {} vectors",
+                keys.len(),
+                rows
+            )));
+        }
+    }
+
+    let mut import = Import::default();
+    let mut keys = keys.map(Vec::into_iter);
+    for vector in data.chunks_exact(columns.max(1)).take(rows) {
+        let key = match keys.as_mut().and_then(Iterator::next) {
+            Some(key) => key,
+            None => Uuid::new_v4().to_string(),
+        };
+        import.vectors.push((key, vector.to_vec()));
+    }
+    Ok(import)
+}
+
+/// Reads the `vectors` matrix and the optional `keys` array of a `.npz` archive.
+fn read_npz_arrays(path: &Path) -> IoResult<(Array, Option<Vec<String>>)> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(invalid_data)?;
+    let mut read_array = |name: &str| -> IoResult<Option<Array>> {
+        let mut entry = match archive.by_name(&format!("{}.npy", name)) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(invalid_data(err)),
+        };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        npy::parse(&bytes).map(Some).map_err(invalid_data)
+    };
+
+    let matrix = read_array("vectors")?
+        .ok_or_else(|| invalid_data("Missing vectors array in .npz archive"))?;
+    let keys = match read_array("keys")? {
+        Some(Array::Unicode { data, .. }) => Some(data),
+        Some(Array::Float { .. }) => return Err(invalid_data("Expected keys to be strings")),
+        None => None,
+    };
+    Ok((matrix, keys))
+}
+
+fn invalid_data<E>(err: E) -> IoError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    IoError::new(ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,266 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::metric::Metric;
+use crate::storage::Storage;
+
+mod flat;
+mod hnsw;
+mod ivf;
+
+pub use flat::Flat;
+pub use hnsw::Hnsw;
+pub use ivf::Ivf;
+
+/// A structure answering nearest-neighbor queries over the vectors of a collection.
+///
+/// The database keeps every index up to date as vectors are inserted and removed,
+/// so indexes only need to implement the search structure itself. Vectors are
+/// shared with the database rather than copied.
+///
+/// Indexes rank vectors by the metric they were created with. Approximate indexes
+/// may miss some of the nearest neighbors in exchange for faster searches.
+pub trait VectorIndex: Send + Sync {
+    /// The name of the kind of index, e.g. `"hnsw"`.
+    fn kind(&self) -> &str;
+
+    /// The metric the index ranks vectors by.
+    fn metric(&self) -> &dyn Metric;
+
+    /// Returns the number of indexed vectors.
+    fn len(&self) -> usize;
+
+    /// Returns whether the index holds no vectors.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a vector under `key`, replacing any vector indexed under it.
+    fn insert(&mut self, key: &str, vector: Arc<Vec<f32>>);
+
+    /// Removes the vector indexed under `key`, if any.
+    ///
+    /// Indexes whose structure is costly to repair may only mark the vector as
+    /// removed, and leave it out of search results until `compact` is called.
+    fn remove(&mut self, key: &str);
+
+    /// Returns the number of removed vectors still held by the index.
+    fn removed(&self) -> usize {
+        0
+    }
+
+    /// Rebuilds the index without the removed vectors it still holds, reclaiming
+    /// their memory.
+    fn compact(&mut self) {}
+
+    /// Returns the parameters the index was built with, as `(name, value)` pairs,
+    /// e.g. `("m", 16)` for HNSW. Indexes without parameters return none.
+    fn params(&self) -> Vec<(&'static str, usize)> {
+        Vec::new()
+    }
+
+    /// Returns the name and value of the parameter trading the speed of searches for
+    /// their recall, along with the range of values worth trying, e.g.
+    /// `("ef_search", 64, 16..=512)` for HNSW. Exact indexes have none.
+    fn search_param(&self) -> Option<(&'static str, usize, RangeInclusive<usize>)> {
+        None
+    }
+
+    /// Sets the parameter returned by `search_param`, at least 1.
+    fn set_search_param(&mut self, _value: usize) {}
+
+    /// Returns an estimate of the memory held by the index, in bytes. The vectors
+    /// are shared with the database, so only the pointers to them are counted.
+    fn memory_usage(&self) -> usize;
+
+    /// Returns the keys of the `k` vectors nearest to `query` along with their
+    /// distance, nearest first.
+    fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)>;
+
+    /// Searches like `search`, also returning the number of vectors, or centroids,
+    /// the query was compared with. Defaults to the number of indexed vectors, as
+    /// for an exhaustive search.
+    fn search_counted(&self, query: &[f32], k: usize) -> (Vec<(String, f32)>, usize) {
+        (self.search(query, k), self.len())
+    }
+
+    /// Serializes the structure of the index that is costly to rebuild, which
+    /// `IndexKind::load` restores. The vectors are not included, since they are
+    /// saved in the snapshot. Indexes that are cheap to rebuild from their vectors
+    /// return `null`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be serialized.
+    fn save(&self) -> serde_json::Result<Box<RawValue>> {
+        RawValue::from_string("null".to_string())
+    }
+}
+
+/// An index saved alongside a snapshot, so it can be loaded back faster than it
+/// is built.
+#[derive(Serialize, Deserialize)]
+pub struct SavedIndex {
+    /// The key prefix of the indexed collection.
+    pub prefix: String,
+    /// The kind of index, as named by `VectorIndex::kind`.
+    pub kind: String,
+    /// The name of the metric of the index.
+    pub metric: String,
+    /// The structure of the index, as returned by `VectorIndex::save`.
+    pub state: Box<RawValue>,
+}
+
+/// The kinds of index built into Vemcache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexKind {
+    /// An exact search over every vector.
+    Flat,
+    /// A hierarchical navigable small world graph, for fast approximate searches.
+    Hnsw,
+    /// An inverted file index, which only searches the clusters nearest to the query.
+    Ivf,
+}
+
+impl IndexKind {
+    /// Returns the kind of index named `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(IndexKind::Flat),
+            "hnsw" => Some(IndexKind::Hnsw),
+            "ivf" => Some(IndexKind::Ivf),
+            _ => None,
+        }
+    }
+
+    /// Creates an empty index of this kind with the default parameters.
+    pub fn build(self, metric: Arc<dyn Metric>) -> Box<dyn VectorIndex> {
+        match self {
+            IndexKind::Flat => Box::new(Flat::new(metric)),
+            IndexKind::Hnsw => Box::new(Hnsw::new(metric)),
+            IndexKind::Ivf => Box::new(Ivf::new(metric)),
+        }
+    }
+
+    /// Creates an empty index of this kind with the given parameters, named as by
+    /// `VectorIndex::params`, and the default ones for the others.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if this kind of index has no parameter of one of
+    /// the names given.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::metric::Cosine;
+    /// use std::sync::Arc;
+    ///
+    /// let params = [("ef_search".to_string(), 128)];
+    /// let index = IndexKind::Hnsw.build_with_params(Arc::new(Cosine), &params).unwrap();
+    /// assert_eq!(index.params(), [("m", 16), ("ef_construction", 200), ("ef_search", 128)]);
+    /// assert!(IndexKind::Flat.build_with_params(Arc::new(Cosine), &params).is_err());
+    /// ```
+    pub fn build_with_params(
+        self,
+        metric: Arc<dyn Metric>,
+        params: &[(String, usize)],
+    ) -> Result<Box<dyn VectorIndex>, String> {
+        let default = self.build(Arc::clone(&metric));
+        let mut values = default.params();
+        for (name, value) in params {
+            match values.iter_mut().find(|(other, _)| other == name) {
+                Some((_, default)) => *default = *value,
+                None => {
+                    return Err(format!(
+                        "{} indexes have no parameter {}",
+                        default.kind(),
+                        name
+                    ))
+                }
+            }
+        }
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(other, _)| *other == name)
+                .map_or(0, |(_, value)| *value)
+        };
+        Ok(match self {
+            IndexKind::Flat => default,
+            IndexKind::Hnsw => Box::new(Hnsw::with_params(
+                metric,
+                value("m"),
+                value("ef_construction"),
+                value("ef_search"),
+            )),
+            IndexKind::Ivf => Box::new(Ivf::with_params(metric, value("lists"), value("probes"))),
+        })
+    }
+
+    /// Restores an index of this kind over the vectors of `storage` whose key starts
+    /// with `prefix`, from the `state` returned by `VectorIndex::save`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `state` is not a valid state for this kind of index, or
+    /// does not match the vectors of `storage`.
+    pub fn load(
+        self,
+        metric: Arc<dyn Metric>,
+        state: &RawValue,
+        prefix: &str,
+        storage: &dyn Storage,
+    ) -> serde_json::Result<Box<dyn VectorIndex>> {
+        let vectors = storage.iter().filter(|(key, _)| key.starts_with(prefix));
+        Ok(match self {
+            IndexKind::Flat => {
+                let mut index = Flat::new(metric);
+                for (key, vector) in vectors {
+                    index.insert(&key, vector);
+                }
+                Box::new(index)
+            }
+            IndexKind::Hnsw => {
+                let index = Hnsw::load(metric, state, |key| storage.get(key))?;
+                if index.len() != vectors.count() {
+                    return Err(serde::de::Error::custom(
+                        "the index does not cover the same vectors as the snapshot",
+                    ));
+                }
+                Box::new(index)
+            }
+            IndexKind::Ivf => Box::new(Ivf::load(metric, state, vectors)?),
+        })
+    }
+}
+
+/// Orders scored keys by distance, breaking ties by key so that vectors at the same
+/// distance are always returned in the same order.
+pub(crate) fn by_distance(
+    (key1, dist1): &(String, f32),
+    (key2, dist2): &(String, f32),
+) -> Ordering {
+    dist1.total_cmp(dist2).then_with(|| key1.cmp(key2))
+}
+
+/// Returns an estimate of the memory held by `map`, its keys included, in bytes.
+fn map_memory_usage<V>(map: &HashMap<String, V>) -> usize {
+    // A control byte per bucket, on top of the entry
+    map.capacity() * (size_of::<(String, V)>() + 1)
+        + map.keys().map(String::capacity).sum::<usize>()
+}
+
+/// Sorts scored keys by distance and keeps the `k` nearest.
+fn nearest(mut scored: Vec<(String, f32)>, k: usize) -> Vec<(String, f32)> {
+    scored.sort_by(by_distance);
+    scored.truncate(k);
+    scored
+}
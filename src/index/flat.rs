@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use super::{nearest, VectorIndex};
+use crate::keys::KeyMap;
+use crate::metric::Metric;
+
+/// An exact index that compares the query with every vector.
+///
+/// Searches are linear in the size of the collection, but always return the true
+/// nearest neighbors, for any metric.
+pub struct Flat {
+    metric: Arc<dyn Metric>,
+    vectors: KeyMap<Arc<Vec<f32>>>,
+}
+
+impl Flat {
+    /// Creates an empty index ranking vectors by `metric`.
+    pub fn new(metric: Arc<dyn Metric>) -> Self {
+        Self {
+            metric,
+            vectors: KeyMap::new(),
+        }
+    }
+}
+
+impl VectorIndex for Flat {
+    fn kind(&self) -> &str {
+        "flat"
+    }
+
+    fn metric(&self) -> &dyn Metric {
+        self.metric.as_ref()
+    }
+
+    fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    fn insert(&mut self, key: &str, vector: Arc<Vec<f32>>) {
+        self.vectors.insert(key, vector);
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.vectors.remove(key);
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.vectors.memory_usage()
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        let (keys, vectors): (Vec<&str>, Vec<&[f32]>) = self
+            .vectors
+            .iter()
+            .map(|(key, vector)| (key, vector.as_slice()))
+            .unzip();
+        let mut distances = Vec::with_capacity(vectors.len());
+        self.metric.distances(query, &vectors, &mut distances);
+        let scored = keys
+            .into_iter()
+            .map(str::to_string)
+            .zip(distances)
+            .collect();
+        nearest(scored, k)
+    }
+}
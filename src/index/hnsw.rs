@@ -0,0 +1,468 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use super::{by_distance, map_memory_usage, VectorIndex};
+use crate::metric::Metric;
+
+/// The default number of neighbors linked to each node above the bottom layer.
+pub const DEFAULT_M: usize = 16;
+
+/// The default number of candidates considered while inserting a vector.
+pub const DEFAULT_EF_CONSTRUCTION: usize = 200;
+
+/// The default number of candidates considered while searching.
+pub const DEFAULT_EF_SEARCH: usize = 64;
+
+/// The smallest number of candidates considered while searching that tuning tries.
+/// Searches consider at least as many candidates as the neighbors asked for, so
+/// below the usual number of neighbors, recall measured while tuning would not tell
+/// values apart.
+const MIN_EF_SEARCH: usize = 16;
+
+/// The largest number of candidates considered while searching that tuning tries.
+const MAX_EF_SEARCH: usize = 512;
+
+/// An approximate index based on a hierarchical navigable small world graph.
+///
+/// Every vector is a node linked to its nearest neighbors in a stack of layers,
+/// each sparser than the one below. Searches descend greedily from the sparsest
+/// layer and explore the bottom layer around the best entry point found, so they
+/// only compare the query with a small fraction of the collection.
+///
+/// Removed vectors are only marked as deleted: their nodes keep routing searches
+/// but are left out of the results, until `compact` rebuilds the graph without them.
+pub struct Hnsw {
+    metric: Arc<dyn Metric>,
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    nodes: Vec<Node>,
+    // The live nodes, by key
+    ids: HashMap<String, usize>,
+    entry: Option<usize>,
+    rng: u64,
+}
+
+struct Node {
+    key: String,
+    vector: Arc<Vec<f32>>,
+    // The neighbors of the node in each layer it belongs to, from the bottom up
+    links: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+/// The state of an index saved by `VectorIndex::save`. Nodes are identified by
+/// their position in `nodes`.
+#[derive(Serialize, Deserialize)]
+struct SavedHnsw<N> {
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    entry: Option<usize>,
+    rng: u64,
+    nodes: Vec<N>,
+}
+
+#[derive(Serialize)]
+struct SavedNodeRef<'a> {
+    key: &'a str,
+    links: &'a [Vec<usize>],
+    // Only deleted nodes save their vector, which is no longer in the snapshot
+    vector: Option<&'a [f32]>,
+}
+
+#[derive(Deserialize)]
+struct SavedNode {
+    key: String,
+    links: Vec<Vec<usize>>,
+    vector: Option<Vec<f32>>,
+}
+
+/// A node and its distance to the query, ordered by distance.
+#[derive(Clone, Copy)]
+struct Candidate {
+    distance: f32,
+    id: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl Hnsw {
+    /// Creates an empty index ranking vectors by `metric`, with the default parameters.
+    pub fn new(metric: Arc<dyn Metric>) -> Self {
+        Self::with_params(
+            metric,
+            DEFAULT_M,
+            DEFAULT_EF_CONSTRUCTION,
+            DEFAULT_EF_SEARCH,
+        )
+    }
+
+    /// Creates an empty index linking each node to `m` neighbors (`2 * m` in the
+    /// bottom layer), and considering `ef_construction` and `ef_search` candidates
+    /// while inserting and searching respectively. Larger values improve recall at
+    /// the expense of speed.
+    pub fn with_params(
+        metric: Arc<dyn Metric>,
+        m: usize,
+        ef_construction: usize,
+        ef_search: usize,
+    ) -> Self {
+        Self {
+            metric,
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            ef_search: ef_search.max(1),
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            // Levels only need to be spread out, not unpredictable
+            rng: 0x853c_49e6_748f_ea9b,
+        }
+    }
+
+    /// Restores an index saved by `VectorIndex::save`, looking up the vectors of its
+    /// live nodes with `vector`.
+    pub(super) fn load(
+        metric: Arc<dyn Metric>,
+        state: &RawValue,
+        vector: impl Fn(&str) -> Option<Arc<Vec<f32>>>,
+    ) -> serde_json::Result<Self> {
+        let saved = serde_json::from_str::<SavedHnsw<SavedNode>>(state.get())?;
+        let count = saved.nodes.len();
+        let mut index = Self::with_params(metric, saved.m, saved.ef_construction, saved.ef_search);
+        for (id, node) in saved.nodes.into_iter().enumerate() {
+            let valid =
+                !node.links.is_empty() && node.links.iter().flatten().all(|&link| link < count);
+            let deleted = node.vector.is_some();
+            let vector = match node.vector {
+                Some(vector) => Some(Arc::new(vector)),
+                None => vector(&node.key),
+            };
+            let Some(vector) = vector.filter(|_| valid) else {
+                return Err(serde::de::Error::custom(format!(
+                    "invalid node {} for key {}",
+                    id, node.key
+                )));
+            };
+            if !deleted {
+                index.ids.insert(node.key.clone(), id);
+            }
+            index.nodes.push(Node {
+                key: node.key,
+                vector,
+                links: node.links,
+                deleted,
+            });
+        }
+        if saved.entry.is_some_and(|entry| entry >= count) {
+            return Err(serde::de::Error::custom("invalid entry point"));
+        }
+        index.entry = saved.entry;
+        index.rng = saved.rng;
+        Ok(index)
+    }
+
+    fn top_level(&self, id: usize) -> usize {
+        self.nodes[id].links.len() - 1
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    /// Draws the top layer of a new node from an exponential distribution, so each
+    /// layer holds about `1 / m` of the nodes of the layer below.
+    fn random_level(&mut self) -> usize {
+        // splitmix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        (-uniform.ln() / (self.m as f64).ln()) as usize
+    }
+
+    /// Returns the `ef` nodes nearest to `query` in `level` reachable from `entries`,
+    /// nearest first. Deleted nodes are explored, but only returned if `deleted` is set.
+    /// The number of nodes compared with `query` is added to `evaluated`.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entries: &[usize],
+        ef: usize,
+        level: usize,
+        deleted: bool,
+        evaluated: &mut usize,
+    ) -> Vec<Candidate> {
+        let mut visited = entries.iter().copied().collect::<HashSet<usize>>();
+        let mut candidates = BinaryHeap::new();
+        let mut nearest = BinaryHeap::new();
+        for &id in entries {
+            let candidate = Candidate {
+                distance: self.metric.distance(query, &self.nodes[id].vector),
+                id,
+            };
+            candidates.push(Reverse(candidate));
+            if deleted || !self.nodes[id].deleted {
+                nearest.push(candidate);
+            }
+        }
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let furthest = nearest
+                .peek()
+                .map_or(f32::INFINITY, |c: &Candidate| c.distance);
+            if nearest.len() >= ef && candidate.distance > furthest {
+                break;
+            }
+            for &neighbor in &self.nodes[candidate.id].links[level] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.metric.distance(query, &self.nodes[neighbor].vector);
+                let furthest = nearest
+                    .peek()
+                    .map_or(f32::INFINITY, |c: &Candidate| c.distance);
+                if nearest.len() < ef || distance < furthest {
+                    let candidate = Candidate {
+                        distance,
+                        id: neighbor,
+                    };
+                    candidates.push(Reverse(candidate));
+                    if deleted || !self.nodes[neighbor].deleted {
+                        nearest.push(candidate);
+                        if nearest.len() > ef {
+                            nearest.pop();
+                        }
+                    }
+                }
+            }
+        }
+        *evaluated += visited.len();
+        nearest.into_sorted_vec()
+    }
+
+    /// Descends from the entry point to `level`, keeping the nearest node of each layer.
+    fn descend(&self, query: &[f32], level: usize, evaluated: &mut usize) -> Vec<usize> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut entries = vec![entry];
+        for layer in (level + 1..=self.top_level(entry)).rev() {
+            entries = vec![self.search_layer(query, &entries, 1, layer, true, evaluated)[0].id];
+        }
+        entries
+    }
+
+    /// Keeps the `max` links of `id` in `level` nearest to it.
+    fn prune(&mut self, id: usize, level: usize, max: usize) {
+        let vector = Arc::clone(&self.nodes[id].vector);
+        let mut links = self.nodes[id].links[level]
+            .iter()
+            .map(|&link| Candidate {
+                distance: self.metric.distance(&vector, &self.nodes[link].vector),
+                id: link,
+            })
+            .collect::<Vec<Candidate>>();
+        links.sort();
+        links.dedup_by_key(|candidate| candidate.id);
+        links.truncate(max);
+        self.nodes[id].links[level] = links.into_iter().map(|c| c.id).collect();
+    }
+}
+
+impl VectorIndex for Hnsw {
+    fn kind(&self) -> &str {
+        "hnsw"
+    }
+
+    fn metric(&self) -> &dyn Metric {
+        self.metric.as_ref()
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn insert(&mut self, key: &str, vector: Arc<Vec<f32>>) {
+        // Replaying the write-ahead log over a snapshot inserts the same vectors again
+        if let Some(&id) = self.ids.get(key) {
+            if self.nodes[id].vector == vector {
+                self.nodes[id].vector = vector;
+                return;
+            }
+        }
+        self.remove(key);
+
+        let level = self.random_level();
+        let top = self.entry.map(|entry| self.top_level(entry));
+        self.nodes.push(Node {
+            key: key.to_string(),
+            vector: Arc::clone(&vector),
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        let id = self.nodes.len() - 1;
+        self.ids.insert(key.to_string(), id);
+
+        let Some(top) = top else {
+            self.entry = Some(id);
+            return;
+        };
+        let mut evaluated = 0;
+        let mut entries = self.descend(&vector, level, &mut evaluated);
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(
+                &vector,
+                &entries,
+                self.ef_construction,
+                layer,
+                true,
+                &mut evaluated,
+            );
+            let neighbors = found
+                .iter()
+                .take(self.m)
+                .map(|candidate| candidate.id)
+                .collect::<Vec<usize>>();
+            let max_links = self.max_links(layer);
+            for &neighbor in &neighbors {
+                self.nodes[neighbor].links[layer].push(id);
+                if self.nodes[neighbor].links[layer].len() > max_links {
+                    self.prune(neighbor, layer, max_links);
+                }
+            }
+            self.nodes[id].links[layer] = neighbors;
+            entries = found.into_iter().map(|candidate| candidate.id).collect();
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(id) = self.ids.remove(key) {
+            self.nodes[id].deleted = true;
+        }
+    }
+
+    fn removed(&self) -> usize {
+        self.nodes.len() - self.ids.len()
+    }
+
+    fn params(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("m", self.m),
+            ("ef_construction", self.ef_construction),
+            ("ef_search", self.ef_search),
+        ]
+    }
+
+    fn search_param(&self) -> Option<(&'static str, usize, RangeInclusive<usize>)> {
+        Some(("ef_search", self.ef_search, MIN_EF_SEARCH..=MAX_EF_SEARCH))
+    }
+
+    fn set_search_param(&mut self, value: usize) {
+        self.ef_search = value.max(1);
+    }
+
+    fn memory_usage(&self) -> usize {
+        let nodes = self.nodes.iter().map(|node| {
+            let links = node.links.iter().map(|layer| {
+                std::mem::size_of::<Vec<usize>>() + layer.capacity() * std::mem::size_of::<usize>()
+            });
+            node.key.capacity() + links.sum::<usize>()
+        });
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + nodes.sum::<usize>()
+            + map_memory_usage(&self.ids)
+    }
+
+    fn compact(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.ids.clear();
+        self.entry = None;
+        for node in nodes.into_iter().filter(|node| !node.deleted) {
+            self.insert(&node.key, node.vector);
+        }
+    }
+
+    fn save(&self) -> serde_json::Result<Box<RawValue>> {
+        serde_json::value::to_raw_value(&SavedHnsw {
+            m: self.m,
+            ef_construction: self.ef_construction,
+            ef_search: self.ef_search,
+            entry: self.entry,
+            rng: self.rng,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| SavedNodeRef {
+                    key: &node.key,
+                    links: &node.links,
+                    vector: node.deleted.then_some(node.vector.as_slice()),
+                })
+                .collect(),
+        })
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        self.search_counted(query, k).0
+    }
+
+    fn search_counted(&self, query: &[f32], k: usize) -> (Vec<(String, f32)>, usize) {
+        let mut evaluated = 0;
+        let entries = self.descend(query, 0, &mut evaluated);
+        if entries.is_empty() {
+            return (Vec::new(), evaluated);
+        }
+        let mut nearest = self
+            .search_layer(
+                query,
+                &entries,
+                self.ef_search.max(k),
+                0,
+                false,
+                &mut evaluated,
+            )
+            .into_iter()
+            .map(|candidate| (self.nodes[candidate.id].key.clone(), candidate.distance))
+            .collect::<Vec<(String, f32)>>();
+        // Candidates at the same distance are ordered by node, which depends on the
+        // order of insertion, rather than by key
+        nearest.sort_by(by_distance);
+        nearest.truncate(k);
+        (nearest, evaluated)
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use super::{map_memory_usage, nearest, VectorIndex};
+use crate::metric::Metric;
+
+/// The default number of clusters the vectors are partitioned into.
+pub const DEFAULT_LISTS: usize = 64;
+
+/// The default number of clusters searched per query.
+pub const DEFAULT_PROBES: usize = 8;
+
+/// The number of vectors per cluster needed before the clusters are trained.
+const TRAINING_FACTOR: usize = 16;
+
+/// The number of k-means iterations run while training the clusters.
+const TRAINING_ITERATIONS: usize = 10;
+
+/// An approximate index partitioning the vectors into clusters, or inverted lists.
+///
+/// Each vector is stored in the list of its nearest centroid, and searches only
+/// scan the lists of the centroids nearest to the query. The centroids are
+/// trained with k-means once the collection holds enough vectors; until then,
+/// searches are exact.
+pub struct Ivf {
+    metric: Arc<dyn Metric>,
+    lists: usize,
+    probes: usize,
+    // Empty until the index is trained, in which case `buckets` has a single list
+    centroids: Vec<Vec<f32>>,
+    buckets: Vec<HashMap<String, Arc<Vec<f32>>>>,
+    assignments: HashMap<String, usize>,
+}
+
+/// The state of an index saved by `VectorIndex::save`. The lists are not saved,
+/// since assigning the vectors to the trained centroids is fast.
+#[derive(Serialize, Deserialize)]
+struct SavedIvf {
+    lists: usize,
+    probes: usize,
+    centroids: Vec<Vec<f32>>,
+}
+
+impl Ivf {
+    /// Creates an empty index ranking vectors by `metric`, with the default parameters.
+    pub fn new(metric: Arc<dyn Metric>) -> Self {
+        Self::with_params(metric, DEFAULT_LISTS, DEFAULT_PROBES)
+    }
+
+    /// Creates an empty index partitioning the vectors into `lists` clusters and
+    /// searching the `probes` clusters nearest to each query. More probes improve
+    /// recall at the expense of speed.
+    pub fn with_params(metric: Arc<dyn Metric>, lists: usize, probes: usize) -> Self {
+        Self {
+            metric,
+            lists: lists.max(1),
+            probes: probes.max(1),
+            centroids: Vec::new(),
+            buckets: vec![HashMap::new()],
+            assignments: HashMap::new(),
+        }
+    }
+
+    /// Restores an index saved by `VectorIndex::save` over `vectors`, without
+    /// training the centroids again.
+    pub(super) fn load(
+        metric: Arc<dyn Metric>,
+        state: &RawValue,
+        vectors: impl Iterator<Item = (String, Arc<Vec<f32>>)>,
+    ) -> serde_json::Result<Self> {
+        let saved = serde_json::from_str::<SavedIvf>(state.get())?;
+        let mut index = Self::with_params(metric, saved.lists, saved.probes);
+        if !saved.centroids.is_empty() {
+            index.buckets = vec![HashMap::new(); saved.centroids.len()];
+            index.centroids = saved.centroids;
+        }
+        for (key, vector) in vectors {
+            index.insert(&key, vector);
+        }
+        Ok(index)
+    }
+
+    /// Returns the index of the list whose centroid is nearest to `vector`.
+    fn nearest_list(&self, vector: &[f32]) -> usize {
+        self.centroids
+            .iter()
+            .map(|centroid| self.metric.distance(vector, centroid))
+            .enumerate()
+            .min_by(|(_, dist1), (_, dist2)| dist1.total_cmp(dist2))
+            .map_or(0, |(list, _)| list)
+    }
+
+    /// Clusters the indexed vectors with k-means and redistributes them into the lists.
+    fn train(&mut self) {
+        let mut vectors = self
+            .buckets
+            .drain(..)
+            .flatten()
+            .collect::<Vec<(String, Arc<Vec<f32>>)>>();
+        // Sort so the initial centroids do not depend on the order of the hash maps
+        vectors.sort_unstable_by(|(key1, _), (key2, _)| key1.cmp(key2));
+
+        let step = vectors.len() / self.lists;
+        self.centroids = (0..self.lists)
+            .map(|list| vectors[list * step].1.to_vec())
+            .collect();
+        for _ in 0..TRAINING_ITERATIONS {
+            let mut sums = self
+                .centroids
+                .iter()
+                .map(|centroid| vec![0.0; centroid.len()])
+                .collect::<Vec<Vec<f32>>>();
+            let mut counts = vec![0usize; self.lists];
+            for (_, vector) in &vectors {
+                let list = self.nearest_list(vector);
+                for (sum, value) in sums[list].iter_mut().zip(vector.iter()) {
+                    *sum += value;
+                }
+                counts[list] += 1;
+            }
+            // Empty clusters keep their previous centroid
+            for ((centroid, sum), count) in self.centroids.iter_mut().zip(sums).zip(counts) {
+                if count > 0 {
+                    *centroid = sum.into_iter().map(|sum| sum / count as f32).collect();
+                }
+            }
+        }
+
+        self.buckets = vec![HashMap::new(); self.lists];
+        self.assignments.clear();
+        for (key, vector) in vectors {
+            self.insert(&key, vector);
+        }
+    }
+}
+
+impl VectorIndex for Ivf {
+    fn kind(&self) -> &str {
+        "ivf"
+    }
+
+    fn metric(&self) -> &dyn Metric {
+        self.metric.as_ref()
+    }
+
+    fn len(&self) -> usize {
+        self.assignments.len()
+    }
+
+    fn insert(&mut self, key: &str, vector: Arc<Vec<f32>>) {
+        self.remove(key);
+        let list = self.nearest_list(&vector);
+        self.buckets[list].insert(key.to_string(), vector);
+        self.assignments.insert(key.to_string(), list);
+
+        if self.centroids.is_empty() && self.len() >= self.lists * TRAINING_FACTOR {
+            self.train();
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(list) = self.assignments.remove(key) {
+            self.buckets[list].remove(key);
+        }
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        self.search_counted(query, k).0
+    }
+
+    fn search_counted(&self, query: &[f32], k: usize) -> (Vec<(String, f32)>, usize) {
+        let mut lists = self
+            .centroids
+            .iter()
+            .map(|centroid| self.metric.distance(query, centroid))
+            .enumerate()
+            .collect::<Vec<(usize, f32)>>();
+        lists.sort_by(|(_, dist1), (_, dist2)| dist1.total_cmp(dist2));
+        let probed = if lists.is_empty() {
+            vec![0]
+        } else {
+            lists
+                .into_iter()
+                .take(self.probes)
+                .map(|(list, _)| list)
+                .collect()
+        };
+
+        let scored = probed
+            .into_iter()
+            .flat_map(|list| &self.buckets[list])
+            .map(|(key, vector)| (key.clone(), self.metric.distance(query, vector)))
+            .collect::<Vec<(String, f32)>>();
+        let evaluated = self.centroids.len() + scored.len();
+        (nearest(scored, k), evaluated)
+    }
+
+    fn params(&self) -> Vec<(&'static str, usize)> {
+        vec![("lists", self.lists), ("probes", self.probes)]
+    }
+
+    fn search_param(&self) -> Option<(&'static str, usize, RangeInclusive<usize>)> {
+        // Until the index is trained, every search is exact
+        Some(("probes", self.probes, 1..=self.centroids.len().max(1)))
+    }
+
+    fn set_search_param(&mut self, value: usize) {
+        self.probes = value.max(1);
+    }
+
+    fn memory_usage(&self) -> usize {
+        let centroids = self.centroids.iter().map(|centroid| {
+            std::mem::size_of::<Vec<f32>>() + centroid.capacity() * std::mem::size_of::<f32>()
+        });
+        centroids.sum::<usize>()
+            + self.buckets.iter().map(map_memory_usage).sum::<usize>()
+            + map_memory_usage(&self.assignments)
+    }
+
+    fn save(&self) -> serde_json::Result<Box<RawValue>> {
+        serde_json::value::to_raw_value(&SavedIvf {
+            lists: self.lists,
+            probes: self.probes,
+            centroids: self.centroids.clone(),
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::Float32Type;
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, ListArray, RecordBatch, StringArray};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::import::{Import, RowError};
+use crate::vemcache::Snapshot;
+
+/// The number of vectors written per record batch.
+const BATCH_SIZE: usize = 8192;
+
+/// Reads vectors from an Arrow IPC stream.
+///
+/// Every record batch must have a `id` column of strings and a `vector` column holding
+/// `float32` lists, either fixed-size or variable-size. Other columns are ignored.
+/// Every vector must have the same number of components as the first imported vector.
+/// Rows with a null id or vector are reported rather than aborting the import.
+///
+/// # Errors
+///
+/// Returns an error if the stream is not valid Arrow IPC or does not have the expected columns.
+pub fn read_stream(bytes: &[u8]) -> Result<Import, ArrowError> {
+    let mut import = Import::default();
+    let mut row = 0;
+    for batch in StreamReader::try_new(bytes, None)? {
+        let batch = batch?;
+        let ids = batch
+            .column_by_name("id")
+            .and_then(|ids| ids.as_string_opt::<i32>())
+            .ok_or_else(|| {
+                ArrowError::SchemaError("Expected an id column of strings".to_string())
+            })?;
+        let vectors = batch
+            .column_by_name("vector")
+            .ok_or_else(|| ArrowError::SchemaError("Missing vector column".to_string()))?;
+        let vector_at: Box<dyn Fn(usize) -> ArrayRef> = match vectors.data_type() {
+            DataType::FixedSizeList(item, _) if item.data_type() == &DataType::Float32 => {
+                let vectors = vectors.as_fixed_size_list();
+                Box::new(move |index| vectors.value(index))
+            }
+            DataType::List(item) if item.data_type() == &DataType::Float32 => {
+                let vectors = vectors.as_list::<i32>();
+                Box::new(move |index| vectors.value(index))
+            }
+            other => {
+                return Err(ArrowError::SchemaError(format!(
+                    "Expected a vector column of float32 lists, found {}",
+                    other
+                )))
+            }
+        };
+
+        for index in 0..batch.num_rows() {
+            row += 1;
+            if ids.is_null(index) || vectors.is_null(index) {
+                import.errors.push(RowError {
+                    row,
+                    message: "Null id or vector".to_string(),
+                });
+                continue;
+            }
+            let vector = vector_at(index);
+            let vector = vector.as_primitive::<Float32Type>().values().to_vec();
+            if vector.is_empty() {
+                import.errors.push(RowError {
+                    row,
+                    message: "Empty vector".to_string(),
+                });
+                continue;
+            }
+            import.push(row, ids.value(index).to_string(), vector);
+        }
+    }
+    Ok(import)
+}
+
+/// Writes a snapshot as an Arrow IPC stream, ordered by key.
+///
+/// The stream has an `id` column of strings and a `vector` column of `float32` lists,
+/// which are fixed-size if all vectors have the same dimension.
+///
+/// # Errors
+///
+/// Returns an error if the record batches cannot be encoded.
+pub fn write_stream(snapshot: &Snapshot) -> Result<Vec<u8>, ArrowError> {
+    let vectors = snapshot.vectors();
+    let dim = vectors.first().map_or(0, |(_, vector)| vector.len());
+    let fixed_size = vectors.iter().all(|(_, vector)| vector.len() == dim);
+
+    let item = Arc::new(Field::new_list_field(DataType::Float32, true));
+    let vector_type = if fixed_size {
+        DataType::FixedSizeList(item, dim as i32)
+    } else {
+        DataType::List(item)
+    };
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("vector", vector_type, false),
+    ]));
+
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+    for chunk in vectors.chunks(BATCH_SIZE) {
+        let ids = StringArray::from_iter_values(chunk.iter().map(|(id, _)| *id));
+        let values = chunk
+            .iter()
+            .map(|(_, vector)| Some(vector.iter().copied().map(Some)));
+        let vectors: ArrayRef = if fixed_size {
+            Arc::new(
+                FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(values, dim as i32),
+            )
+        } else {
+            Arc::new(ListArray::from_iter_primitive::<Float32Type, _, _>(values))
+        };
+        writer.write(&RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(ids), vectors],
+        )?)?;
+    }
+    writer.into_inner()
+}
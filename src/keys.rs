@@ -0,0 +1,219 @@
+//! Compact storage of keys.
+//!
+//! A database holds a key for every vector, often a UUID or a URL, and the maps
+//! holding them spend a heap allocation and a 24-byte header per key on top of its
+//! bytes when keys are `String`s. `KeyMap` interns its keys in a single arena
+//! instead and refers to them with integer handles internally, so a key costs its
+//! bytes and a span. Keys are only turned back into strings when they are handed
+//! out.
+
+use hashbrown::HashTable;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// The number of bytes of removed keys the arena may hold before it is compacted,
+/// as long as they are less than half of it.
+const MIN_GARBAGE: usize = 64 * 1024;
+
+/// A map from keys to values, like a `HashMap<String, V>`, which stores its keys
+/// in an arena.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::keys::KeyMap;
+///
+/// let mut map = KeyMap::new();
+/// map.insert("550e8400-e29b-41d4-a716-446655440000", 1);
+/// map.insert("https://example.com/docs/1", 2);
+///
+/// assert_eq!(map.get("https://example.com/docs/1"), Some(&2));
+/// assert_eq!(map.remove("550e8400-e29b-41d4-a716-446655440000"), Some(1));
+/// assert_eq!(map.iter().collect::<Vec<_>>(), vec![("https://example.com/docs/1", &2)]);
+/// ```
+pub struct KeyMap<V> {
+    // The bytes of every key back to back, along with those of the keys removed
+    // since the arena was last compacted
+    arena: String,
+    // The key and value of every handle; the slots of removed keys have no value
+    // and are reused
+    slots: Vec<Slot<V>>,
+    free: Vec<u32>,
+    // The handles of the keys, by hash of the key
+    table: HashTable<u32>,
+    hasher: RandomState,
+    garbage: usize,
+}
+
+/// Where the key of a handle lies in the arena, and its value.
+struct Slot<V> {
+    start: usize,
+    len: u32,
+    value: Option<V>,
+}
+
+impl<V> Default for KeyMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> KeyMap<V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self {
+            arena: String::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            table: HashTable::new(),
+            hasher: RandomState::new(),
+            garbage: 0,
+        }
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns whether the map holds no key.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Returns the value of `key`, if any.
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let handle = self.find(key)?;
+        self.slots[handle as usize].value.as_ref()
+    }
+
+    /// Returns whether the map holds `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Sets the value of `key`, and returns its previous value, if any.
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
+        if let Some(handle) = self.find(key) {
+            return self.slots[handle as usize].value.replace(value);
+        }
+        let slot = Slot {
+            start: self.arena.len(),
+            len: u32::try_from(key.len()).expect("Keys are shorter than 4 GiB"),
+            value: Some(value),
+        };
+        self.arena.push_str(key);
+        let handle = match self.free.pop() {
+            Some(handle) => {
+                self.slots[handle as usize] = slot;
+                handle
+            }
+            None => {
+                self.slots.push(slot);
+                u32::try_from(self.slots.len() - 1).expect("Fewer than 2^32 keys")
+            }
+        };
+        let Self {
+            arena,
+            slots,
+            table,
+            hasher,
+            ..
+        } = self;
+        table.insert_unique(hasher.hash_one(key), handle, |&handle| {
+            hasher.hash_one(resolve(arena, slots, handle))
+        });
+        None
+    }
+
+    /// Removes `key`, and returns its value, if any.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let hash = self.hasher.hash_one(key);
+        let Self {
+            arena,
+            slots,
+            table,
+            ..
+        } = self;
+        let (handle, _) = table
+            .find_entry(hash, |&handle| resolve(arena, slots, handle) == key)
+            .ok()?
+            .remove();
+        let slot = &mut self.slots[handle as usize];
+        self.garbage += slot.len as usize;
+        let value = slot.value.take();
+        self.free.push(handle);
+        if self.garbage > MIN_GARBAGE && self.garbage > self.arena.len() / 2 {
+            self.compact();
+        }
+        value
+    }
+
+    /// Removes every key.
+    pub fn clear(&mut self) {
+        self.arena = String::new();
+        self.slots.clear();
+        self.free.clear();
+        self.table.clear();
+        self.garbage = 0;
+    }
+
+    /// Returns every key and its value, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.slots.iter().filter_map(|slot| {
+            let value = slot.value.as_ref()?;
+            Some((
+                &self.arena[slot.start..slot.start + slot.len as usize],
+                value,
+            ))
+        })
+    }
+
+    /// Returns every key, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an estimate of the memory held by the map, its keys included, in
+    /// bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.arena.capacity()
+            + self.slots.capacity() * std::mem::size_of::<Slot<V>>()
+            + self.free.capacity() * std::mem::size_of::<u32>()
+            // A control byte per bucket, on top of the handle
+            + self.table.capacity() * (std::mem::size_of::<u32>() + 1)
+    }
+
+    /// Returns the handle of `key`, if the map holds it.
+    fn find(&self, key: &str) -> Option<u32> {
+        self.table
+            .find(self.hasher.hash_one(key), |&handle| {
+                resolve(&self.arena, &self.slots, handle) == key
+            })
+            .copied()
+    }
+
+    /// Copies the keys still in the map to a new arena, reclaiming the bytes of the
+    /// removed ones. Handles are left unchanged.
+    fn compact(&mut self) {
+        let mut arena = String::with_capacity(self.arena.len() - self.garbage);
+        for slot in &mut self.slots {
+            if slot.value.is_none() {
+                slot.start = 0;
+                slot.len = 0;
+                continue;
+            }
+            let start = arena.len();
+            arena.push_str(&self.arena[slot.start..slot.start + slot.len as usize]);
+            slot.start = start;
+        }
+        self.arena = arena;
+        self.garbage = 0;
+    }
+}
+
+/// Returns the key of `handle`.
+fn resolve<'a, V>(arena: &'a str, slots: &[Slot<V>], handle: u32) -> &'a str {
+    let slot = &slots[handle as usize];
+    &arena[slot.start..slot.start + slot.len as usize]
+}
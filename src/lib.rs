@@ -0,0 +1,87 @@
+//! Vemcache is an in-memory vector database.
+//!
+//! The `vemcache` binary serves the database over TCP. The engine can also be
+//! embedded directly through `Vemcache`.
+//!
+//! The server and the file formats it imports and exports are behind the default
+//! `server` feature. Without it, the engine only depends on crates that compile to
+//! `wasm32-unknown-unknown`; enable the `wasm` feature there as well so UUIDs are
+//! generated with the browser's crypto API.
+
+#[cfg(feature = "server")]
+pub mod acl;
+#[cfg(feature = "server")]
+pub mod audit;
+#[cfg(feature = "server")]
+pub mod backup;
+#[cfg(feature = "server")]
+pub mod bulk;
+pub mod changelog;
+pub mod checksum;
+pub mod cluster;
+#[cfg(feature = "server")]
+pub mod commands;
+pub mod compression;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "embeddings")]
+pub mod embed;
+pub mod encryption;
+#[cfg(feature = "server")]
+pub mod export;
+#[cfg(feature = "server")]
+pub mod format;
+pub mod gpu;
+#[cfg(feature = "server")]
+pub mod handlers;
+#[cfg(feature = "server")]
+pub mod health;
+#[cfg(feature = "server")]
+pub mod import;
+pub mod index;
+#[cfg(feature = "server")]
+pub mod ipc;
+pub mod keys;
+#[cfg(feature = "server")]
+pub mod limits;
+#[cfg(feature = "server")]
+pub mod memory;
+pub mod metric;
+pub mod model;
+#[cfg(feature = "server")]
+pub mod monitor;
+pub mod npy;
+#[cfg(feature = "server")]
+pub mod persistence;
+pub mod pipeline;
+#[cfg(feature = "server")]
+pub mod probe;
+#[cfg(feature = "server")]
+pub mod pubsub;
+pub mod quote;
+#[cfg(feature = "server")]
+pub mod raft;
+pub mod reduce;
+#[cfg(feature = "server")]
+pub mod replication;
+#[cfg(feature = "server")]
+pub mod resp;
+#[cfg(feature = "rest")]
+pub mod rest;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod slowlog;
+#[cfg(feature = "server")]
+pub mod stats;
+pub mod storage;
+pub mod vemcache;
+pub mod wal;
+#[cfg(feature = "wasm-metrics")]
+pub mod wasm_metric;
+
+pub use crate::vemcache::Vemcache;
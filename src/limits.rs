@@ -0,0 +1,124 @@
+use std::io::Result as IoResult;
+use std::time::Instant;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// The default maximum length of a command line, in bytes.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The outcome of reading a command line.
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    /// A line was read.
+    Read,
+    /// The line was longer than allowed and was skipped.
+    TooLong,
+    /// The line was not UTF-8 and was skipped.
+    Invalid,
+    /// The client closed the connection.
+    Closed,
+}
+
+/// Reads a line of at most `max_length` bytes, not counting the line break, into
+/// `line`. Longer lines are skipped without being kept in memory, so a client
+/// cannot exhaust the memory of the server with a single gigantic line. A
+/// `max_length` of 0 allows lines of any length.
+///
+/// Lines that are too long or not UTF-8 are consumed entirely, so the next call
+/// reads the following line and the connection stays in sync with the client.
+///
+/// # Errors
+///
+/// Returns an error if the line cannot be read from `reader`.
+pub async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+    max_length: usize,
+) -> IoResult<Line> {
+    let limit = match max_length {
+        0 => u64::MAX,
+        max_length => max_length as u64 + 1,
+    };
+    let mut bytes = Vec::new();
+    let read = (&mut *reader)
+        .take(limit)
+        .read_until(b'\n', &mut bytes)
+        .await?;
+    if read == 0 {
+        return Ok(Line::Closed);
+    }
+    if read as u64 == limit && bytes.last() != Some(&b'\n') {
+        skip_line(reader).await?;
+        return Ok(Line::TooLong);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => {
+            line.push_str(&text);
+            Ok(Line::Read)
+        }
+        Err(_) => Ok(Line::Invalid),
+    }
+}
+
+/// Discards everything up to and including the next line break.
+async fn skip_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> IoResult<()> {
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        match buffer.iter().position(|byte| *byte == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Limits the rate of commands of a connection with a token bucket.
+///
+/// The bucket holds up to a second worth of commands, so clients can send short
+/// bursts as long as their average rate stays within the limit.
+pub struct RateLimiter {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter with a full bucket.
+    pub fn new() -> Self {
+        Self {
+            tokens: f64::INFINITY,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Returns whether another command is allowed under a limit of `per_second`
+    /// commands per second, in which case it is counted. A limit of 0 allows any
+    /// number of commands. The limit can change between calls.
+    pub fn allow(&mut self, per_second: u64) -> bool {
+        if per_second == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.refilled = now;
+        let capacity = per_second as f64;
+        self.tokens = (self.tokens + elapsed * capacity).min(capacity);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
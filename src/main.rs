@@ -1,85 +1,897 @@
 use dotenv::dotenv;
 use std::net::SocketAddr;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, BufReader};
 use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use uuid::Uuid;
 
-mod commands;
-mod handlers;
-mod vemcache;
+use vemcache::acl::{self, Role};
+use vemcache::audit::AuditLog;
+use vemcache::cluster::Cluster;
+use vemcache::config::{self, Config};
+use vemcache::format::Notation;
+use vemcache::handlers::*;
+use vemcache::health::Health;
+use vemcache::limits::{self, Line, RateLimiter};
+use vemcache::model::TextModel;
+use vemcache::monitor::Monitor;
+use vemcache::pubsub::{self, PubSub};
+use vemcache::raft::{self, Raft};
+use vemcache::replication::Replication;
+use vemcache::resp;
+use vemcache::server::Server;
+use vemcache::slowlog::SlowLog;
+use vemcache::stats::Stats;
+use vemcache::storage::{DiskStorage, MemoryStorage, MmapStorage, Storage};
+use vemcache::vemcache::Dedup;
+use vemcache::{backup, bulk, commands, encryption, memory, persistence, wasm_metric, Vemcache};
 
-use handlers::*;
-use vemcache::Vemcache;
+// Counts the memory allocated, so writes can be rejected beyond `max_memory`
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
 
-async fn handle_client(mut stream: tokio::net::TcpStream, db: &mut Vemcache) {
-    let (reader, mut writer) = stream.split();
+/// How often the indexes are checked for removed vectors to compact.
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the vectors whose time to live has elapsed are removed.
+const EXPIRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of vectors of every indexed collection the recall is measured with
+/// while tuning its index.
+const AUTOTUNE_SAMPLE_SIZE: usize = 50;
+
+/// The number of neighbors the recall is measured for while tuning.
+const AUTOTUNE_K: usize = 10;
+
+/// Serves a client connection. Admin commands are only accepted from connections to
+/// the admin port, if there is one.
+async fn handle_client(
+    mut stream: tokio::net::TcpStream,
+    addr: SocketAddr,
+    admin: bool,
+    server: Arc<Server>,
+) {
+    let Server {
+        databases,
+        replication,
+        cluster,
+        raft,
+        config,
+        health,
+        slowlog,
+        stats,
+        monitor,
+        pubsub,
+        audit,
+        ..
+    } = &*server;
+    let (reader, writer) = stream.split();
+    let mut writer = ResponseWriter::new(writer);
+    let float_format = config.read().unwrap().float_format.clone();
+    writer.set_notation(Notation::from_name(&float_format).unwrap_or_default());
     let mut reader = BufReader::new(reader);
+    let mut rate_limiter = RateLimiter::new();
+    let mut selected = 0;
+    let mut raft_stream = raft::Stream::default();
+    // Every client has every right unless tokens are configured
+    let mut role = match config.read().unwrap().acl_tokens.is_empty() {
+        true => Some(Role::Admin),
+        false => None,
+    };
 
     loop {
-        let mut command = String::new();
-        if let Err(_) = reader.read_line(&mut command).await {
-            println!("Error reading from client");
+        // Responses are complete once the command is handled. They are only sent
+        // once no other command line the client pipelined is already received, so a
+        // batch of commands is answered with as few writes as possible
+        let sent = match reader.buffer().contains(&b'\n') {
+            true => writer.end_response().await,
+            false => writer.flush().await,
+        };
+        if sent.is_err() {
+            println!("Error sending response to client");
             return;
         }
-        command = command.trim().to_string();
+        let mut line = String::new();
+        let (max_line_length, client_timeout) = {
+            let config = config.read().unwrap();
+            (config.max_line_length, config.client_timeout)
+        };
+        let read = limits::read_line(&mut reader, &mut line, max_line_length);
+        let read = if client_timeout == 0 {
+            read.await
+        } else {
+            match tokio::time::timeout(Duration::from_secs(client_timeout), read).await {
+                Ok(read) => read,
+                // The client has been idle for too long
+                Err(_) => return,
+            }
+        };
+        match read {
+            Ok(Line::Read) => {}
+            Ok(Line::TooLong) => {
+                let error_msg = format!("Line too long, the limit is {} bytes", max_line_length);
+                handle_error(&error_msg, &mut writer).await;
+                continue;
+            }
+            Ok(Line::Invalid) => {
+                handle_error("Commands must be valid UTF-8", &mut writer).await;
+                continue;
+            }
+            // The client closed the connection
+            Ok(Line::Closed) => return,
+            Err(_) => {
+                println!("Error reading from client");
+                return;
+            }
+        }
+        if line.starts_with('*') {
+            match resp::read_request(&mut reader, &line, max_line_length).await {
+                Ok(Ok(request)) => {
+                    line = request;
+                    writer.set_protocol(resp::PROTOCOL_RESP);
+                }
+                Ok(Err(error_msg)) => {
+                    // The connection is out of sync with the client, so it is closed
+                    handle_error(error_msg, &mut writer).await;
+                    let _ = writer.flush().await;
+                    return;
+                }
+                Err(_) => {
+                    println!("Error reading from client");
+                    return;
+                }
+            }
+        }
+        let line = line.trim();
+        // Commands rejected before being dispatched are not part of the statistics
+        writer.finish();
 
-        match commands::parse_command(&command) {
-            Ok(commands::Command::Ping) => {
-                handle_ping(&mut writer).await;
+        let mut command = match commands::parse_command(line) {
+            // Tokens are kept out of the monitor, the logs and the statistics
+            Ok(commands::Command::Auth(token)) => {
+                handle_auth(config, &token, &mut role, &mut writer).await;
+                continue;
+            }
+            Ok(command) if command.is_immediate() => {
+                server
+                    .dispatch(&mut selected, &mut raft_stream, command, &mut writer)
+                    .await;
+                continue;
+            }
+            command => command,
+        };
+        if let Ok(cmd) = &command {
+            if let Err(error_msg) = acl::authorize(role, cmd.required_role()) {
+                handle_error(&error_msg, &mut writer).await;
+                // The payload is not read, so the connection is closed
+                if cmd.has_payload() {
+                    let _ = writer.flush().await;
+                    return;
+                }
+                continue;
+            }
+        }
+        if command.is_ok() && health.is_loading() {
+            // Payloads of bulk commands are not read, so the connection is closed
+            let closes = command.as_ref().is_ok_and(|cmd| cmd.has_payload());
+            handle_error("LOADING The dataset is being loaded", &mut writer).await;
+            if closes {
+                let _ = writer.flush().await;
+                return;
+            }
+            continue;
+        }
+        if let Ok(cmd) = &command {
+            if cmd.is_admin() && !admin && config.read().unwrap().admin_port != 0 {
+                handle_error(
+                    "NOPERM Admin commands are only accepted on the admin port",
+                    &mut writer,
+                )
+                .await;
+                // The module is not read, so the connection is closed
+                if let commands::Command::MetricUpload(_, _) = cmd {
+                    let _ = writer.flush().await;
+                    return;
+                }
+                continue;
+            }
+        }
+        if let Ok(commands::Command::Monitor) = command {
+            handle_monitor(monitor, &mut reader, max_line_length, &mut writer).await;
+            return;
+        }
+        monitor.publish(addr, selected, line);
+        if let Ok(commands::Command::Subscribe(channels)) = &command {
+            handle_subscribe(pubsub, channels, &mut reader, max_line_length, &mut writer).await;
+            return;
+        }
+        if let Ok(commands::Command::Bulk(header)) = &command {
+            if header
+                .vector_bytes()
+                .is_none_or(|size| size > bulk::MAX_PAYLOAD_SIZE)
+            {
+                // The payload cannot be skipped reliably, so the connection is closed
+                handle_error("Bulk payload too large", &mut writer).await;
+                let _ = writer.flush().await;
+                return;
             }
-            Ok(commands::Command::Insert(values)) => {
-                handle_insert(db, values, &mut writer).await;
+            let vectors = match bulk::read_payload(&mut reader, header).await {
+                Ok(Ok(vectors)) => vectors,
+                Ok(Err(error_msg)) => {
+                    handle_error(&error_msg, &mut writer).await;
+                    continue;
+                }
+                Err(_) => {
+                    println!("Error reading from client");
+                    return;
+                }
+            };
+            let vectors = vectors
+                .into_iter()
+                .map(|(key, vector)| {
+                    let key = key.unwrap_or_else(|| match &cluster {
+                        Some(cluster) => cluster.generate_local_key(),
+                        None => Uuid::new_v4().to_string(),
+                    });
+                    (key, vector)
+                })
+                .collect();
+            command = Ok(commands::Command::BulkInsert(vectors));
+        }
+        if let Ok(commands::Command::ArrowUpload(size)) = &command {
+            if *size > bulk::MAX_PAYLOAD_SIZE {
+                // The stream cannot be skipped reliably, so the connection is closed
+                handle_error("Arrow stream too large", &mut writer).await;
+                let _ = writer.flush().await;
+                return;
             }
-            Ok(commands::Command::NamedInsert(key, values)) => {
-                handle_named_insert(db, key, values, &mut writer).await;
+            let mut stream = vec![0; *size];
+            if reader.read_exact(&mut stream).await.is_err() {
+                println!("Error reading from client");
+                return;
             }
-            Ok(commands::Command::Get(key)) => {
-                handle_get(db, key, &mut writer).await;
+            command = Ok(commands::Command::ArrowImport(stream));
+        }
+        if let Ok(commands::Command::MetricUpload(name, size)) = &mut command {
+            if *size > wasm_metric::MAX_MODULE_SIZE {
+                // The module cannot be skipped reliably, so the connection is closed
+                handle_error("Module too large", &mut writer).await;
+                let _ = writer.flush().await;
+                return;
             }
-            Ok(commands::Command::Remove(key)) => {
-                handle_remove(db, key, &mut writer).await;
+            let mut wasm = vec![0; *size];
+            if reader.read_exact(&mut wasm).await.is_err() {
+                println!("Error reading from client");
+                return;
             }
-            Ok(commands::Command::KNearestNeighbors(key, k)) => {
-                handle_k_nearest_neighbors(db, key, k, &mut writer).await;
+            command = Ok(commands::Command::MetricRegister(
+                std::mem::take(name),
+                wasm,
+            ));
+        }
+        if let (Some(cluster), Ok(commands::Command::Insert(values))) = (&cluster, &mut command) {
+            // Generate the key up front so the vector stays on this node
+            let key = cluster.generate_local_key();
+            command = Ok(commands::Command::NamedInsert(key, std::mem::take(values)));
+        }
+        let max_commands_per_second = config.read().unwrap().max_commands_per_second;
+        if !rate_limiter.allow(max_commands_per_second) {
+            let error_msg = format!(
+                "THROTTLED Too many commands, the limit is {} per second",
+                max_commands_per_second
+            );
+            handle_error(&error_msg, &mut writer).await;
+            continue;
+        }
+        if let Ok(cmd) = &command {
+            if let Some(owner) = cluster
+                .as_ref()
+                .and_then(|cluster| cmd.keys().into_iter().find_map(|key| cluster.redirect(key)))
+            {
+                handle_error(&format!("MOVED {}", owner), &mut writer).await;
+                continue;
             }
-            Ok(commands::Command::VectorAddition(key1, key2)) => {
-                handle_vector_addition(db, key1, key2, &mut writer).await;
+            if cluster.is_some() && cmd.has_unknown_keys() {
+                let error_msg = format!(
+                    "CLUSTER {} is not supported in cluster mode, since it writes keys other nodes may own",
+                    cmd.name()
+                );
+                handle_error(&error_msg, &mut writer).await;
+                continue;
             }
-            Ok(commands::Command::VectorSubtraction(key1, key2)) => {
-                handle_vector_subtraction(db, key1, key2, &mut writer).await;
+
+            if cmd.is_write() && config.read().unwrap().read_only {
+                handle_error("READONLY Server is in read-only mode", &mut writer).await;
+                continue;
             }
-            Ok(commands::Command::VectorScaling(key, scalar)) => {
-                handle_vector_scaling(db, key, scalar, &mut writer).await;
+
+            if let (Some(raft), true) = (raft, cmd.is_write()) {
+                if let Err(error_msg) = raft.check_leader() {
+                    handle_error(&error_msg, &mut writer).await;
+                    continue;
+                }
             }
-            Ok(commands::Command::CosineSimilarity(key1, key2)) => {
-                handle_cosine_similarity(db, key1, key2, &mut writer).await;
+
+            if cmd.is_write() && replication.lock().unwrap().is_replica() {
+                handle_error(
+                    "READONLY You can't write against a read only replica",
+                    &mut writer,
+                )
+                .await;
+                continue;
             }
-            Ok(commands::Command::Dump(file_path)) => {
-                handle_dump(db, file_path, &mut writer).await;
+
+            if cmd.uses_memory() && memory::is_over_limit(config.read().unwrap().max_memory) {
+                handle_error(
+                    "out of memory, the memory used is above max_memory",
+                    &mut writer,
+                )
+                .await;
+                continue;
             }
-            Err(error_msg) => {
-                handle_error(error_msg, &mut writer).await;
+        }
+
+        if let (Some(audit), Ok(cmd)) = (audit, &command) {
+            if cmd.is_audited() {
+                audit.record(addr, selected, line);
+            }
+        }
+
+        let command_name = command.as_ref().map_or("invalid", |command| command.name());
+        let is_write = command.as_ref().is_ok_and(|command| command.is_write());
+        let started = Instant::now();
+        match command {
+            Ok(command) => {
+                server
+                    .dispatch(&mut selected, &mut raft_stream, command, &mut writer)
+                    .await
+            }
+            Err(error_msg) => handle_error(error_msg, &mut writer).await,
+        }
+        // A write the write-ahead log failed to keep is lost on restart
+        let mut seq = 0;
+        if is_write {
+            let db = databases[selected].lock().await;
+            if db.wal_error().is_some() {
+                health.set_wal_failed();
+            }
+            seq = db.last_seq();
+        }
+        // Writes are only acknowledged once a majority of the Raft group holds them.
+        // Otherwise whether they survive is unknown, so the connection is closed
+        // without a response
+        if let (Some(raft), true) = (raft, is_write) {
+            if !raft.wait_committed(seq).await {
+                println!(
+                    "Closing the connection of {}: a majority of the raft nodes did not acknowledge its write",
+                    addr
+                );
+                return;
+            }
+        }
+
+        // The duration includes waiting for the database, which is part of the
+        // latency seen by the client
+        let duration = started.elapsed();
+        if duration >= Duration::from_micros(config.read().unwrap().slowlog_threshold) {
+            slowlog.lock().unwrap().record(line, duration);
+        }
+        stats
+            .lock()
+            .unwrap()
+            .record(command_name, duration, writer.finish());
+    }
+}
+
+/// Compacts the indexes that hold more removed vectors than live ones, so searches
+/// do not slow down and memory is reclaimed without waiting for `compact`.
+async fn compact_indexes(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(COMPACTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        for db in &server.databases {
+            let mut db = Arc::clone(db).lock_owned().await;
+            if !db.needs_compaction() {
+                continue;
+            }
+            let _build = server.health.start_index_build();
+            // Rebuild on a blocking thread so health checks are answered in the meantime
+            if let Err(err) = tokio::task::spawn_blocking(move || db.compact_indexes()).await {
+                println!("Error compacting the indexes: {}", err);
+            }
+        }
+    }
+}
+
+/// Tunes the search parameters of the approximate indexes of every database
+/// towards `autotune_recall` every `interval`, unless it is 0, and records the
+/// values chosen in `autotuned`.
+async fn tune_indexes(server: Arc<Server>, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let target = server.config.read().unwrap().autotune_recall;
+        if target == 0.0 {
+            continue;
+        }
+        let mut autotuned = Vec::new();
+        for (number, db) in server.databases.iter().enumerate() {
+            let mut db = Arc::clone(db).lock_owned().await;
+            // Measuring recall runs exhaustive searches, so on a blocking thread
+            let tuned = tokio::task::spawn_blocking(move || {
+                db.tune_indexes(target, AUTOTUNE_SAMPLE_SIZE, AUTOTUNE_K)
+            })
+            .await;
+            let tunings = match tuned {
+                Ok(tunings) => tunings,
+                Err(err) => {
+                    println!("Error tuning the indexes: {}", err);
+                    continue;
+                }
+            };
+            for tuning in tunings {
+                if tuning.value != tuning.previous {
+                    println!(
+                        "Tuned {} of the index of {:?} in database {} from {} to {}: recall {:.3}, {} µs per search",
+                        tuning.param,
+                        tuning.collection,
+                        number,
+                        tuning.previous,
+                        tuning.value,
+                        tuning.recall,
+                        tuning.latency.as_micros()
+                    );
+                }
+                autotuned.push(format!(
+                    "{}/{}/{}={}",
+                    number, tuning.collection, tuning.param, tuning.value
+                ));
+            }
+        }
+        server.config.write().unwrap().autotuned = autotuned;
+    }
+}
+
+/// Removes the vectors whose time to live has elapsed from every database, and
+/// publishes their keys on the expiry channel of their database.
+async fn expire_vectors(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        for (index, db) in server.databases.iter().enumerate() {
+            let expired = db.lock().await.remove_expired();
+            for key in expired {
+                server.pubsub.publish(&pubsub::expired_channel(index), &key);
             }
         }
     }
 }
 
+/// Backs up every database to `backup_dir` every `interval`.
+async fn back_up_periodically(server: Arc<Server>, backup_dir: String, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        match backup::back_up(&server.databases, &server.config).await {
+            Ok(backups) => println!("Backed up {} databases to {}", backups.len(), backup_dir),
+            Err(err) => println!("Error backing up to {}: {}", backup_dir, err),
+        }
+    }
+}
+
+/// Saves the databases and exits once the process is asked to terminate, with
+/// SIGTERM as container runtimes and service managers do, or with Ctrl+C. If a
+/// preStop probe already saved them, the process exits right away.
+async fn shut_down_on_signal(server: Arc<Server>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())
+            .unwrap_or_else(|err| exit_with_error(&format!("Could not handle SIGTERM: {}", err)));
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    println!("Shutting down, saving the databases");
+    let save = prepare_shutdown(&server.databases, &server.config, true);
+    match server.health.drain(save).await {
+        Ok(()) => std::process::exit(0),
+        Err(err) => exit_with_error(&format!("Error shutting down: {}", err)),
+    }
+}
+
+/// Accepts connections on `listener` until the server stops. `admin` tells whether
+/// it is the admin port.
+async fn accept(listener: TcpListener, admin: bool, server: Arc<Server>) {
+    loop {
+        let (stream, addr) = listener.accept().await.unwrap();
+        if let Err(err) = configure_socket(&stream, &server.config.read().unwrap()) {
+            println!("Error configuring the connection of {}: {}", addr, err);
+        }
+        tokio::spawn(handle_client(stream, addr, admin, Arc::clone(&server)));
+    }
+}
+
+/// Applies the TCP options of the configuration to a new connection.
+fn configure_socket(stream: &tokio::net::TcpStream, config: &Config) -> std::io::Result<()> {
+    stream.set_nodelay(config.tcp_nodelay)?;
+    let socket = socket2::SockRef::from(stream);
+    if config.tcp_keepalive == 0 {
+        socket.set_keepalive(false)
+    } else {
+        let keepalive =
+            socket2::TcpKeepalive::new().with_time(Duration::from_secs(config.tcp_keepalive));
+        socket.set_tcp_keepalive(&keepalive)
+    }
+}
+
+/// Opens the storage of the logical database numbered `index`, as configured.
+/// Only the first database serves the vector file of the memory-mapped storage,
+/// the others are kept in memory.
+fn open_storage(config: &Config, index: usize) -> Box<dyn Storage> {
+    match (config.storage.as_str(), &config.data_dir) {
+        ("disk", Some(data_dir)) => {
+            let path =
+                persistence::database_dir(std::path::Path::new(data_dir), index).join("vectors");
+            let storage =
+                DiskStorage::open(&path, config.storage_cache_size).unwrap_or_else(|err| {
+                    exit_with_error(&format!("Could not open {}: {}", path.display(), err))
+                });
+            Box::new(storage)
+        }
+        ("disk", None) => exit_with_error("The disk storage requires a data directory"),
+        ("mmap", None) if index == 0 => {
+            let path = config.storage_file.as_deref().unwrap_or_else(|| {
+                exit_with_error("The memory-mapped storage requires a storage file")
+            });
+            let storage = MmapStorage::open(std::path::Path::new(path)).unwrap_or_else(|err| {
+                exit_with_error(&format!("Could not open {}: {}", path, err))
+            });
+            Box::new(storage)
+        }
+        ("mmap", Some(_)) => {
+            exit_with_error("The memory-mapped storage cannot be used with a data directory")
+        }
+        _ => Box::new(MemoryStorage::new()),
+    }
+}
+
+/// Loads the model stored in `dir` that embeds text for `insert_text` and `query_text`.
+fn load_text_model(dir: &str) -> Arc<dyn TextModel> {
+    #[cfg(feature = "onnx")]
+    match vemcache::model::OnnxModel::load(std::path::Path::new(dir)) {
+        Ok(model) => Arc::new(model),
+        Err(err) => exit_with_error(&err),
+    }
+    #[cfg(not(feature = "onnx"))]
+    exit_with_error(&format!(
+        "This build cannot load {}, rebuild it with the onnx feature",
+        dir
+    ))
+}
+
+/// Reads the key dumps and the write-ahead log are encrypted with from
+/// `encryption_key`, `encryption_key_file` or the output of `encryption_key_command`,
+/// whichever is set.
+fn load_encryption_key(config: &Config) -> Option<String> {
+    let sources = [
+        config.encryption_key.is_some(),
+        config.encryption_key_file.is_some(),
+        config.encryption_key_command.is_some(),
+    ];
+    if sources.iter().filter(|set| **set).count() > 1 {
+        exit_with_error(
+            "Only one of encryption_key, encryption_key_file and encryption_key_command may be set",
+        );
+    }
+    if let Some(key) = &config.encryption_key {
+        return Some(key.clone());
+    }
+    if let Some(path) = &config.encryption_key_file {
+        let key = std::fs::read_to_string(path).unwrap_or_else(|err| {
+            exit_with_error(&format!(
+                "Could not read the encryption key from {}: {}",
+                path, err
+            ))
+        });
+        return Some(key);
+    }
+    let command = config.encryption_key_command.as_ref()?;
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .unwrap_or_else(|err| {
+            exit_with_error(&format!(
+                "Could not run the encryption key command: {}",
+                err
+            ))
+        });
+    if !output.status.success() {
+        exit_with_error(&format!(
+            "The encryption key command failed with {}",
+            output.status
+        ));
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn exit_with_error(err: &str) -> ! {
+    eprintln!("Error: {}", err);
+    std::process::exit(1);
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-    let host = dotenv::var("VEMCACHE_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let port = dotenv::var("VEMCACHE_PORT").unwrap_or_else(|_| "7070".to_string());
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let mut config = match config::config_path(&args) {
+        Some(path) => Config::from_file(path).unwrap_or_else(|err| exit_with_error(&err)),
+        None => Config::default(),
+    };
+    config
+        .apply_env()
+        .unwrap_or_else(|err| exit_with_error(&err));
+    config
+        .apply_args(&args)
+        .unwrap_or_else(|err| exit_with_error(&err));
+    config
+        .validate()
+        .unwrap_or_else(|err| exit_with_error(&err));
+    // The key is installed before anything is restored from the data directory
+    if let Some(key) = load_encryption_key(&config) {
+        let key = encryption::Key::from_hex(&key).unwrap_or_else(|err| exit_with_error(&err));
+        encryption::set_key(key);
+    }
 
-    let addr = format!("{}:{}", host, port).parse::<SocketAddr>().unwrap();
+    let addr = format!("{}:{}", config.host, config.port)
+        .parse::<SocketAddr>()
+        .unwrap();
     let listener = TcpListener::bind(addr).await.unwrap();
+    let admin_listener = match config.admin_port {
+        0 => None,
+        port => {
+            let admin_addr = format!("{}:{}", config.admin_host, port)
+                .parse::<SocketAddr>()
+                .unwrap_or_else(|_| {
+                    exit_with_error(&format!("Invalid admin address: {}", config.admin_host))
+                });
+            let admin_listener = TcpListener::bind(admin_addr).await.unwrap_or_else(|err| {
+                exit_with_error(&format!("Could not listen on {}: {}", admin_addr, err))
+            });
+            Some(admin_listener)
+        }
+    };
 
-    let mut db = Vemcache::new();
+    let rest_listener = match config.rest_port {
+        0 => None,
+        _ if !cfg!(feature = "rest") => exit_with_error(
+            "This build does not support the REST API, rebuild it with the rest feature",
+        ),
+        port => {
+            let rest_addr = format!("{}:{}", config.host, port);
+            let rest_listener = TcpListener::bind(&rest_addr).await.unwrap_or_else(|err| {
+                exit_with_error(&format!("Could not listen on {}: {}", rest_addr, err))
+            });
+            Some(rest_listener)
+        }
+    };
+    let probe_listener = match config.probe_port {
+        0 => None,
+        port => {
+            let probe_addr = format!("{}:{}", config.host, port);
+            let probe_listener = TcpListener::bind(&probe_addr).await.unwrap_or_else(|err| {
+                exit_with_error(&format!("Could not listen on {}: {}", probe_addr, err))
+            });
+            Some(probe_listener)
+        }
+    };
 
-    println!("Vemcache v{} listening on {}", VERSION, addr);
+    if config.storage == "mmap" {
+        // The vectors cannot be modified
+        config.read_only = true;
+    }
+    let mut databases = Vec::new();
+    for index in 0..config.databases {
+        let mut vemcache = Vemcache::with_storage(open_storage(&config, index));
+        vemcache.set_changelog_capacity(config.changelog_size);
+        vemcache.set_default_metric(&config.metric);
+        vemcache.set_dedup(Dedup::from_name(&config.dedup).unwrap_or_default());
+        // Only the first database searches on the GPU
+        if config.gpu && index == 0 {
+            #[cfg(feature = "gpu")]
+            match vemcache::gpu::Gpu::new() {
+                Some(gpu) => {
+                    println!("Offloading exhaustive searches to {}", gpu.name());
+                    vemcache.attach_gpu(gpu, config.gpu_threshold);
+                }
+                None => println!("No GPU found, searching on the CPU"),
+            }
+            #[cfg(not(feature = "gpu"))]
+            exit_with_error("This build does not support the GPU, rebuild it with the gpu feature");
+        }
+        databases.push(Arc::new(Mutex::new(vemcache)));
+    }
+    let text_model = config.text_model_dir.as_deref().map(load_text_model);
+    #[cfg(feature = "s3")]
+    let bucket =
+        vemcache::s3::Bucket::from_config(&config).unwrap_or_else(|err| exit_with_error(&err));
+    #[cfg(not(feature = "s3"))]
+    if config.s3_bucket.is_some() {
+        exit_with_error("This build does not support S3 backups, rebuild it with the s3 feature");
+    }
+    let health = Arc::new(Health::new());
+    if let Some(data_dir) = config.data_dir.clone() {
+        // Restore in the background so health checks are answered in the meantime.
+        // The databases are locked first, so no command sees them half loaded.
+        health.set_loading(true);
+        let mut guards = Vec::new();
+        for db in &databases {
+            guards.push(Arc::clone(db).lock_owned().await);
+        }
+        let health = Arc::clone(&health);
+        tokio::task::spawn_blocking(move || {
+            for (index, db) in guards.iter_mut().enumerate() {
+                let dir = persistence::database_dir(std::path::Path::new(&data_dir), index);
+                // Only databases that have never run here, e.g. in a new container
+                #[cfg(feature = "s3")]
+                if let (Some(bucket), false) =
+                    (&bucket, dir.join(vemcache::wal::WAL_FILE_NAME).exists())
+                {
+                    match bucket.fetch_newest_backup(index, &dir) {
+                        Ok(Some(path)) => println!("Fetched backup {}", path.display()),
+                        Ok(None) => {}
+                        Err(err) => {
+                            exit_with_error(&format!("Could not fetch the newest backup: {}", err))
+                        }
+                    }
+                }
+                persistence::restore(db, &dir).unwrap_or_else(|err| {
+                    exit_with_error(&format!(
+                        "Could not restore from {}: {}",
+                        dir.display(),
+                        err
+                    ))
+                });
+            }
+            health.set_loading(false);
+        });
+    }
+    let replication = Arc::new(std::sync::Mutex::new(Replication::default()));
 
-    loop {
-        let (stream, _) = listener.accept().await.unwrap();
-        handle_client(stream, &mut db).await;
+    if let Some(primary) = &config.replicaof {
+        replication.lock().unwrap().replicate_from(
+            primary.clone(),
+            config.primary_token.clone(),
+            Arc::clone(&databases[0]),
+        );
+    }
+
+    let cluster = if config.cluster_nodes.is_empty() {
+        None
+    } else {
+        let this_node = config
+            .cluster_self
+            .clone()
+            .unwrap_or_else(|| addr.to_string());
+        let cluster = Cluster::new(config.cluster_nodes.clone(), this_node)
+            .unwrap_or_else(|err| exit_with_error(&err));
+        Some(Arc::new(cluster))
+    };
+
+    let raft = if config.raft_nodes.is_empty() {
+        None
+    } else {
+        let this_node = config.raft_self.clone().unwrap_or_else(|| addr.to_string());
+        let raft = Raft::new(
+            config.raft_nodes.clone(),
+            this_node,
+            config.primary_token.clone(),
+            config.data_dir.as_deref().map(std::path::Path::new),
+            Arc::clone(&databases[0]),
+            Arc::clone(&replication),
+            Arc::clone(&health),
+        )
+        .unwrap_or_else(|err| exit_with_error(&err));
+        Some(raft)
+    };
+
+    // The port actually bound, if the configured one is 0
+    let bound = listener.local_addr().unwrap_or(addr);
+    println!("Vemcache v{} listening on {}", VERSION, bound);
+    if let Some(admin_listener) = &admin_listener {
+        println!(
+            "Accepting admin commands on {}",
+            admin_listener.local_addr().unwrap()
+        );
+    }
+    if let Some(rest_listener) = &rest_listener {
+        println!(
+            "Serving the REST API on {}",
+            rest_listener.local_addr().unwrap()
+        );
+    }
+    if let Some(probe_listener) = &probe_listener {
+        println!(
+            "Answering probes on {}",
+            probe_listener.local_addr().unwrap()
+        );
+    }
+    if config.read_only {
+        println!("Running in read-only mode");
+    }
+
+    let audit = config.audit_file.as_ref().map(|path| {
+        let audit = AuditLog::open(path).unwrap_or_else(|err| {
+            exit_with_error(&format!("Could not open the audit log {}: {}", path, err))
+        });
+        Arc::new(audit)
+    });
+    let slowlog = std::sync::Mutex::new(SlowLog::new(config.slowlog_size));
+    let server = Arc::new(Server {
+        databases,
+        replication,
+        cluster,
+        raft,
+        config: Arc::new(std::sync::RwLock::new(config)),
+        health,
+        slowlog,
+        stats: std::sync::Mutex::new(Stats::new()),
+        monitor: Monitor::new(),
+        pubsub: PubSub::new(),
+        text_model,
+        audit,
+    });
+
+    if let Some(raft) = &server.raft {
+        raft.start();
+    }
+    tokio::spawn(shut_down_on_signal(Arc::clone(&server)));
+    tokio::spawn(compact_indexes(Arc::clone(&server)));
+    tokio::spawn(expire_vectors(Arc::clone(&server)));
+    let autotune_interval = Duration::from_secs(server.config.read().unwrap().autotune_interval);
+    tokio::spawn(tune_indexes(Arc::clone(&server), autotune_interval));
+    let backup_schedule = {
+        let config = server.config.read().unwrap();
+        config
+            .backup_dir
+            .clone()
+            .filter(|_| config.backup_interval > 0)
+            .map(|dir| (dir, Duration::from_secs(config.backup_interval)))
+    };
+    if let Some((backup_dir, interval)) = backup_schedule {
+        tokio::spawn(back_up_periodically(
+            Arc::clone(&server),
+            backup_dir,
+            interval,
+        ));
+    }
+    #[cfg(feature = "rest")]
+    if let Some(rest_listener) = rest_listener {
+        tokio::spawn(vemcache::rest::serve(
+            rest_listener,
+            Arc::clone(&server.databases[0]),
+            Arc::clone(&server.config),
+            server.audit.clone(),
+            Arc::clone(&server.replication),
+            server.cluster.clone(),
+            server.raft.clone(),
+        ));
+    }
+    if let Some(probe_listener) = probe_listener {
+        tokio::spawn(vemcache::probe::serve(probe_listener, Arc::clone(&server)));
+    }
+    if let Some(admin_listener) = admin_listener {
+        tokio::spawn(accept(admin_listener, true, Arc::clone(&server)));
     }
+    accept(listener, false, server).await;
 }
@@ -0,0 +1,74 @@
+//! Accounting of the memory used by the server, against which `max_memory` is
+//! enforced.
+//!
+//! The server installs `CountingAllocator` as its global allocator, which keeps
+//! the number of bytes allocated on the heap up to date as memory is allocated and
+//! freed. Unlike the resident set size of the process, the count goes down as
+//! soon as vectors are removed, so writes are accepted again right away.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of bytes allocated through `CountingAllocator` and not freed yet.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the bytes allocated.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::memory::CountingAllocator;
+///
+/// #[global_allocator]
+/// static ALLOCATOR: CountingAllocator = CountingAllocator;
+///
+/// let before = vemcache::memory::allocated();
+/// let vector = vec![0.0f32; 1024];
+/// assert!(vemcache::memory::allocated() >= before + 4096);
+/// drop(vector);
+/// ```
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Returns the number of bytes allocated on the heap and not freed yet, or 0 if
+/// `CountingAllocator` is not the global allocator.
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Returns whether the memory used reaches `max_memory` bytes, in which case
+/// commands adding data are rejected. A `max_memory` of 0 sets no limit.
+pub fn is_over_limit(max_memory: usize) -> bool {
+    max_memory != 0 && allocated() >= max_memory
+}
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+/// The name of the metric used when none is selected.
+pub const DEFAULT_METRIC: &str = "euclidean";
+
+/// A distance function between vectors, used to rank vectors by similarity.
+///
+/// Smaller distances mean more similar vectors. Metrics that are naturally
+/// similarities, such as the dot product, are negated or inverted so that the
+/// nearest neighbors always have the smallest distance.
+///
+/// Custom metrics can be registered with `Vemcache::register_metric`.
+pub trait Metric: Send + Sync {
+    /// The name the metric is registered and selected under, e.g. `"cosine"`.
+    fn name(&self) -> &str;
+
+    /// Returns the distance between two vectors. Components beyond the length of
+    /// the shorter vector are ignored.
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32;
+
+    /// Appends the distance between `query` and each of `vectors` to `out`, in order.
+    ///
+    /// Exact searches score vectors in blocks through this method. Metrics built on
+    /// the dot product override it to score a whole block in one pass with
+    /// `dot_products`, which is much faster than comparing vectors one at a time.
+    fn distances(&self, query: &[f32], vectors: &[&[f32]], out: &mut Vec<f32>) {
+        out.extend(vectors.iter().map(|vector| self.distance(query, vector)));
+    }
+}
+
+/// The straight-line distance between two vectors.
+pub struct Euclidean;
+
+impl Metric for Euclidean {
+    fn name(&self) -> &str {
+        "euclidean"
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        v1.iter()
+            .zip(v2.iter())
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// One minus the cosine similarity, from 0 for vectors pointing the same way to 2
+/// for opposite vectors. A zero vector is at distance 1 from every vector.
+pub struct Cosine;
+
+impl Metric for Cosine {
+    fn name(&self) -> &str {
+        "cosine"
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        cosine_distance(cosine_similarity(v1, v2))
+    }
+
+    fn distances(&self, query: &[f32], vectors: &[&[f32]], out: &mut Vec<f32>) {
+        let magnitude_query = dot_product(query, query).sqrt();
+        let mut products = Vec::with_capacity(vectors.len());
+        let mut norms = Vec::with_capacity(vectors.len());
+        dot_products_with_norms(query, vectors, &mut products, &mut norms);
+        out.extend(
+            products
+                .into_iter()
+                .zip(norms)
+                .map(|(product, norm)| cosine_distance(product / (magnitude_query * norm.sqrt()))),
+        );
+    }
+}
+
+/// Turns a cosine similarity into a distance, with zero vectors at distance 1.
+pub(crate) fn cosine_distance(similarity: f32) -> f32 {
+    if similarity.is_nan() {
+        1.0
+    } else {
+        1.0 - similarity
+    }
+}
+
+/// The negated dot product, for embeddings trained for maximum inner product search.
+pub struct Dot;
+
+impl Metric for Dot {
+    fn name(&self) -> &str {
+        "dot"
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        -dot_product(v1, v2)
+    }
+
+    fn distances(&self, query: &[f32], vectors: &[&[f32]], out: &mut Vec<f32>) {
+        let start = out.len();
+        dot_products(query, vectors, out);
+        for distance in &mut out[start..] {
+            *distance = -*distance;
+        }
+    }
+}
+
+/// The sum of the absolute differences of the components, or Manhattan distance.
+pub struct L1;
+
+impl Metric for L1 {
+    fn name(&self) -> &str {
+        "l1"
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        v1.iter().zip(v2.iter()).map(|(x, y)| (x - y).abs()).sum()
+    }
+}
+
+/// The number of components that differ, for binary vectors stored as zeros and ones.
+pub struct Hamming;
+
+impl Metric for Hamming {
+    fn name(&self) -> &str {
+        "hamming"
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        v1.iter().zip(v2.iter()).filter(|(x, y)| x != y).count() as f32
+    }
+}
+
+/// Returns the metrics available in every database.
+pub fn builtin_metrics() -> Vec<Arc<dyn Metric>> {
+    vec![
+        Arc::new(Euclidean),
+        Arc::new(Cosine),
+        Arc::new(Dot),
+        Arc::new(L1),
+        Arc::new(Hamming),
+    ]
+}
+
+/// Returns the built-in metric named `name`, if any.
+pub fn builtin_metric(name: &str) -> Option<Arc<dyn Metric>> {
+    builtin_metrics()
+        .into_iter()
+        .find(|metric| metric.name() == name)
+}
+
+/// Returns the dot product of two vectors.
+pub fn dot_product(v1: &[f32], v2: &[f32]) -> f32 {
+    v1.iter().zip(v2.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// Appends the dot product of `query` with each of `vectors` to `out`, in order.
+///
+/// This is the product of the matrix whose rows are `vectors` with `query`,
+/// computed a few rows at a time with independent accumulators, so the compiler
+/// can vectorize it and every loaded chunk of the query is reused across rows.
+/// Components beyond the length of the shorter vector are ignored, as with
+/// `dot_product`.
+pub fn dot_products(query: &[f32], vectors: &[&[f32]], out: &mut Vec<f32>) {
+    let mut norms = Vec::new();
+    block_products::<false>(query, vectors, out, &mut norms);
+}
+
+/// Like `dot_products`, but also appends the squared magnitude of each of `vectors`
+/// to `norms`, computed in the same pass.
+fn dot_products_with_norms(
+    query: &[f32],
+    vectors: &[&[f32]],
+    out: &mut Vec<f32>,
+    norms: &mut Vec<f32>,
+) {
+    block_products::<true>(query, vectors, out, norms);
+}
+
+/// The number of rows multiplied together by `block_products`.
+const ROWS: usize = 4;
+
+/// The number of components accumulated independently for each row, a multiple
+/// of the SIMD width of common targets.
+const LANES: usize = 8;
+
+fn block_products<const NORMS: bool>(
+    query: &[f32],
+    vectors: &[&[f32]],
+    out: &mut Vec<f32>,
+    norms: &mut Vec<f32>,
+) {
+    for rows in vectors.chunks(ROWS) {
+        // The kernel covers the components shared by the query and every row of
+        // the block, the remainder is added one row at a time
+        let shared = rows
+            .iter()
+            .map(|row| row.len())
+            .fold(query.len(), usize::min);
+        let blocked = shared - shared % LANES;
+        let mut products = [[0.0f32; LANES]; ROWS];
+        let mut squares = [[0.0f32; LANES]; ROWS];
+        for start in (0..blocked).step_by(LANES) {
+            let chunk = &query[start..start + LANES];
+            for (row, vector) in rows.iter().enumerate() {
+                let values = &vector[start..start + LANES];
+                for lane in 0..LANES {
+                    products[row][lane] += chunk[lane] * values[lane];
+                    if NORMS {
+                        squares[row][lane] += values[lane] * values[lane];
+                    }
+                }
+            }
+        }
+        for (row, vector) in rows.iter().enumerate() {
+            let tail = query.len().min(vector.len());
+            out.push(
+                products[row].iter().sum::<f32>()
+                    + dot_product(&query[blocked..tail], &vector[blocked..tail]),
+            );
+            if NORMS {
+                norms.push(
+                    squares[row].iter().sum::<f32>()
+                        + dot_product(&vector[blocked..], &vector[blocked..]),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the cosine of the angle between two vectors, or NaN if either is a zero vector.
+pub fn cosine_similarity(v1: &[f32], v2: &[f32]) -> f32 {
+    let magnitude_v1 = dot_product(v1, v1).sqrt();
+    let magnitude_v2 = dot_product(v2, v2).sqrt();
+    dot_product(v1, v2) / (magnitude_v1 * magnitude_v2)
+}
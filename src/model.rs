@@ -0,0 +1,31 @@
+//! Sentence embeddings computed by a model running inside the server.
+//!
+//! A model is loaded from a directory holding `model.onnx`, a transformer taking
+//! `input_ids` and optionally `attention_mask` and `token_type_ids`, and
+//! `tokenizer.json`, its Hugging Face tokenizer. Models exported for
+//! sentence-transformers, such as `all-MiniLM-L6-v2`, work as they are.
+//!
+//! The embedding of a text is the mean of the hidden states of its tokens, or the
+//! first output of the model if it is already pooled, scaled to unit length.
+//!
+//! The ONNX runtime is behind the `onnx` feature, and is implemented with `tract`,
+//! so it does not need any native library.
+
+#[cfg(feature = "onnx")]
+mod onnx;
+
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxModel;
+
+/// The maximum number of tokens of a text, beyond which it is truncated.
+pub const MAX_TOKENS: usize = 512;
+
+/// Computes the embeddings of texts.
+pub trait TextModel: Send + Sync {
+    /// Returns the embedding of `text`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the text cannot be tokenized or the model fails.
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String>;
+}
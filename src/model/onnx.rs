@@ -0,0 +1,130 @@
+use super::{TextModel, MAX_TOKENS};
+use std::path::Path;
+use tokenizers::Tokenizer;
+use tract_onnx::prelude::*;
+
+/// A sentence embedding model run with `tract`.
+pub struct OnnxModel {
+    tokenizer: Tokenizer,
+    plan: TypedSimplePlan<TypedModel>,
+    inputs: Vec<ModelInput>,
+}
+
+/// The tensors a model can take, one value per token.
+#[derive(Clone, Copy)]
+enum ModelInput {
+    InputIds,
+    AttentionMask,
+    TokenTypeIds,
+}
+
+impl OnnxModel {
+    /// Loads the model and tokenizer stored in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if either file cannot be read, or if the model takes
+    /// inputs other than `input_ids`, `attention_mask` and `token_type_ids`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use vemcache::model::{OnnxModel, TextModel};
+    ///
+    /// let model = OnnxModel::load("models/all-MiniLM-L6-v2".as_ref()).unwrap();
+    /// let vector = model.embed("a sentence to embed").unwrap();
+    /// ```
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let tokenizer_path = dir.join("tokenizer.json");
+        let tokenizer = Tokenizer::from_file(&tokenizer_path)
+            .map_err(|err| format!("Could not load {}: {}", tokenizer_path.display(), err))?;
+        let model_path = dir.join("model.onnx");
+        let model = tract_onnx::onnx()
+            .model_for_path(&model_path)
+            .map_err(|err| format!("Could not load {}: {}", model_path.display(), err))?;
+        let inputs = model
+            .input_outlets()
+            .map_err(|err| err.to_string())?
+            .iter()
+            .map(|outlet| match model.node(outlet.node).name.as_str() {
+                "input_ids" => Ok(ModelInput::InputIds),
+                "attention_mask" => Ok(ModelInput::AttentionMask),
+                "token_type_ids" => Ok(ModelInput::TokenTypeIds),
+                name => Err(format!("Unsupported model input: {}", name)),
+            })
+            .collect::<Result<Vec<ModelInput>, String>>()?;
+        let plan = model
+            .into_optimized()
+            .and_then(|model| model.into_runnable())
+            .map_err(|err| format!("Could not load {}: {}", model_path.display(), err))?;
+        Ok(Self {
+            tokenizer,
+            plan,
+            inputs,
+        })
+    }
+}
+
+impl TextModel for OnnxModel {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|err| format!("Could not tokenize the text: {}", err))?;
+        let len = encoding.get_ids().len().min(MAX_TOKENS);
+        if len == 0 {
+            return Err("The text has no tokens".to_string());
+        }
+        let tensor = |values: &[u32]| {
+            let values = values[..len]
+                .iter()
+                .map(|&value| value as i64)
+                .collect::<Vec<i64>>();
+            Tensor::from_shape(&[1, len], &values).map(TValue::from)
+        };
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| match input {
+                ModelInput::InputIds => tensor(encoding.get_ids()),
+                ModelInput::AttentionMask => tensor(encoding.get_attention_mask()),
+                ModelInput::TokenTypeIds => tensor(encoding.get_type_ids()),
+            })
+            .collect::<TractResult<TVec<TValue>>>()
+            .map_err(|err| err.to_string())?;
+        let outputs = self
+            .plan
+            .run(inputs)
+            .map_err(|err| format!("The model failed: {}", err))?;
+        let output = outputs
+            .first()
+            .ok_or("The model has no output")?
+            .to_array_view::<f32>()
+            .map_err(|err| format!("Unexpected model output: {}", err))?;
+
+        let mut vector = match *output.shape() {
+            // Already pooled: [1, dim]
+            [1, _] => output.iter().copied().collect::<Vec<f32>>(),
+            // The hidden state of every token: [1, tokens, dim]
+            [1, tokens, dim] if tokens > 0 => {
+                let mut sum = vec![0.0; dim];
+                for token in output
+                    .as_slice()
+                    .ok_or("Unexpected model output")?
+                    .chunks_exact(dim)
+                {
+                    for (sum, value) in sum.iter_mut().zip(token) {
+                        *sum += value;
+                    }
+                }
+                sum.into_iter().map(|sum| sum / tokens as f32).collect()
+            }
+            ref shape => return Err(format!("Unexpected model output shape: {:?}", shape)),
+        };
+        let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|value| *value /= norm);
+        }
+        Ok(vector)
+    }
+}
@@ -0,0 +1,55 @@
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// The number of commands buffered for each monitoring connection. Connections
+/// that fall further behind skip the commands they missed.
+const MONITOR_BUFFER: usize = 1024;
+
+/// Streams the commands processed by the server to the connections that ran `monitor`.
+pub struct Monitor {
+    sender: Sender<String>,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    /// Creates a monitor without any subscribed connection.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(MONITOR_BUFFER);
+        Self { sender }
+    }
+
+    /// Subscribes to the commands processed from now on, as lines holding the Unix
+    /// time with microseconds, the database selected by the client and its address,
+    /// and the command.
+    pub fn subscribe(&self) -> Receiver<String> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes a command received from `client` while `database` was selected.
+    /// Nothing is done while no connection is monitoring the server.
+    pub fn publish(&self, client: SocketAddr, database: usize, command: &str) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format!(
+            "{}.{:06} [{} {}] {}\n",
+            now.as_secs(),
+            now.subsec_micros(),
+            database,
+            client,
+            command
+        );
+        // The last receiver may have unsubscribed in the meantime
+        let _ = self.sender.send(line);
+    }
+}
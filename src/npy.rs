@@ -0,0 +1,177 @@
+use std::io::{Result as IoResult, Write};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// An array read from a NumPy `.npy` file.
+#[derive(Debug, PartialEq)]
+pub enum Array {
+    /// A floating-point array, converted to `f32`, in row-major order.
+    Float { shape: Vec<usize>, data: Vec<f32> },
+    /// An array of strings (NumPy's `<U` dtype).
+    Unicode {
+        shape: Vec<usize>,
+        data: Vec<String>,
+    },
+}
+
+/// Parses a NumPy `.npy` file.
+///
+/// Little-endian `float32`, `float64` and unicode string arrays stored in C order
+/// are supported, which covers arrays saved with `numpy.save` on every common platform.
+///
+/// # Errors
+///
+/// Returns an error message if the file is not a supported `.npy` file.
+pub fn parse(bytes: &[u8]) -> Result<Array, String> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or("Not a NumPy .npy file")?;
+    let (major, rest) = rest.split_first().ok_or("Truncated .npy header")?;
+    let rest = rest.get(1..).ok_or("Truncated .npy header")?;
+    let (header_len, rest) = match major {
+        1 => {
+            let len = rest.get(..2).ok_or("Truncated .npy header")?;
+            (u16::from_le_bytes([len[0], len[1]]) as usize, &rest[2..])
+        }
+        2 | 3 => {
+            let len = rest.get(..4).ok_or("Truncated .npy header")?;
+            (
+                u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize,
+                &rest[4..],
+            )
+        }
+        _ => return Err(format!("Unsupported .npy version {}", major)),
+    };
+    let header = rest.get(..header_len).ok_or("Truncated .npy header")?;
+    let header = std::str::from_utf8(header).map_err(|_| "Invalid .npy header")?;
+    let data = &rest[header_len..];
+
+    let descr = header_value(header, "descr")
+        .map(|descr| descr.trim_matches(|c| c == '\'' || c == '"'))
+        .ok_or("Missing dtype in .npy header")?;
+    if header_value(header, "fortran_order") != Some("False") {
+        return Err("Fortran-ordered arrays are not supported".to_string());
+    }
+    let shape = header_value(header, "shape")
+        .ok_or("Missing shape in .npy header")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| {
+            dim.parse::<usize>()
+                .map_err(|_| "Invalid shape in .npy header")
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    let count = shape.iter().product::<usize>();
+
+    match descr {
+        "<f4" => {
+            let data = data.get(..count * 4).ok_or("Truncated .npy data")?;
+            let data = data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            Ok(Array::Float { shape, data })
+        }
+        "<f8" => {
+            let data = data.get(..count * 8).ok_or("Truncated .npy data")?;
+            let data = data
+                .chunks_exact(8)
+                .map(|b| {
+                    f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+                })
+                .collect();
+            Ok(Array::Float { shape, data })
+        }
+        _ if descr.starts_with("<U") => {
+            let width = descr[2..]
+                .parse::<usize>()
+                .map_err(|_| "Invalid dtype in .npy header")?;
+            let data = data.get(..count * width * 4).ok_or("Truncated .npy data")?;
+            let data = data
+                .chunks_exact(width.max(1) * 4)
+                .take(count)
+                .map(|item| {
+                    item.chunks_exact(4)
+                        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .take_while(|c| *c != 0)
+                        .map(|c| char::from_u32(c).ok_or("Invalid character in .npy data"))
+                        .collect::<Result<String, _>>()
+                })
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(Array::Unicode { shape, data })
+        }
+        _ => Err(format!("Unsupported dtype {}", descr)),
+    }
+}
+
+/// Writes a `rows`×`columns` `float32` matrix stored in row-major order as a `.npy` file.
+pub fn write_f32<W: Write>(
+    writer: &mut W,
+    rows: usize,
+    columns: usize,
+    data: &[f32],
+) -> IoResult<()> {
+    write_header(writer, "<f4", &format!("({}, {})", rows, columns))?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a one-dimensional array of strings as a `.npy` file.
+pub fn write_unicode<W: Write>(writer: &mut W, strings: &[&str]) -> IoResult<()> {
+    let width = strings
+        .iter()
+        .map(|s| s.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    write_header(
+        writer,
+        &format!("<U{}", width),
+        &format!("({},)", strings.len()),
+    )?;
+    for s in strings {
+        let mut written = 0;
+        for c in s.chars() {
+            writer.write_all(&(c as u32).to_le_bytes())?;
+            written += 1;
+        }
+        for _ in written..width {
+            writer.write_all(&0u32.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_header<W: Write>(writer: &mut W, descr: &str, shape: &str) -> IoResult<()> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // The data must start on a 64-byte boundary, and the header ends with a newline
+    let unpadded = MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())
+}
+
+/// Extracts the raw value of `key` from the Python dictionary literal of a `.npy` header.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header
+        .find(&format!("'{}'", key))
+        .or_else(|| header.find(&format!("\"{}\"", key)))?;
+    let rest = header[start + key.len() + 2..]
+        .trim_start()
+        .strip_prefix(':')?
+        .trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find(',').or_else(|| rest.find('}'))?
+    };
+    Some(rest[..end].trim())
+}
@@ -0,0 +1,622 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Result as IoResult};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::backup::{self, SIDECAR_SUFFIXES};
+use crate::checksum::{self, Header};
+use crate::commands::{self, Command};
+use crate::compression::{self, Compression};
+use crate::config::Config;
+use crate::wal::{Wal, WalEntry, WAL_FILE_NAME};
+use crate::wasm_metric::WasmMetric;
+use crate::Vemcache;
+
+/// The name of the snapshot written to the directory of every database by `shutdown`.
+pub const SHUTDOWN_SNAPSHOT: &str = "shutdown.json";
+
+/// The name of the directory, within the directory of every database, holding the
+/// modules of the metrics uploaded with `metric upload`.
+pub const METRICS_DIR_NAME: &str = "metrics";
+
+/// Restores the database from the data directory and enables the write-ahead log.
+///
+/// The newest JSON snapshot in `data_dir` is loaded first, unless the database
+/// already holds vectors kept by the disk storage, then the changes of the
+/// write-ahead log that are newer than the snapshot are replayed on top of it.
+/// Snapshots that cannot be loaded, or that are older than the first change left
+/// in the write-ahead log once it was compacted, are skipped in favor of the next
+/// newest one. Every subsequent change is appended to the write-ahead log.
+pub fn restore(db: &mut Vemcache, data_dir: &Path) -> IoResult<()> {
+    fs::create_dir_all(data_dir)?;
+    // Metrics come first, since saved indexes may rank vectors by them
+    restore_metrics(db, data_dir)?;
+
+    let wal_path = data_dir.join(WAL_FILE_NAME);
+    let entries = Wal::read(&wal_path)?;
+    let first_seq = entries.first().map_or(1, |entry| entry.seq);
+
+    let mut snapshot_seq = 0;
+    if db.is_empty() {
+        match load_newest_snapshot(db, data_dir, None, first_seq - 1, true)? {
+            Some((snapshot, seq)) => {
+                println!("Loaded snapshot {}", snapshot.display());
+                snapshot_seq = seq;
+            }
+            None if first_seq > 1 => println!(
+                "No snapshot holds the changes before {}, the first one in {}",
+                first_seq,
+                wal_path.display()
+            ),
+            None => {}
+        }
+    }
+
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| entry.seq > snapshot_seq)
+        .collect();
+    let count = entries.len();
+    let last_seq = replay(db, entries).max(snapshot_seq);
+    if count > 0 {
+        println!("Replayed {} changes from {}", count, wal_path.display());
+    }
+
+    db.resume_changelog(last_seq);
+    db.attach_wal(Wal::open(&wal_path)?);
+    Ok(())
+}
+
+/// Returns whether `path`, the file a snapshot of the logical database numbered
+/// `database` was written to, is in the directory `restore` loads it from, in
+/// which case the write-ahead log can be compacted up to the snapshot.
+pub fn is_restored_from(data_dir: &Path, database: usize, path: &Path) -> bool {
+    path.parent() == Some(database_dir(data_dir, database).as_path())
+}
+
+/// Loads the snapshot at `path` into `db`, along with the indexes saved with it if
+/// `with_indexes` is set. A delta backup is loaded on top of the full backup it was
+/// taken against, which must be in the same directory, after the earlier deltas
+/// of it found there, in order.
+fn load_snapshot(db: &mut Vemcache, path: &Path, with_indexes: bool) -> IoResult<()> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let base = backup::delta_base(name);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let full = match &base {
+        Some(base) => dir.join(base),
+        None => path.to_path_buf(),
+    };
+    let full = full.to_string_lossy();
+    db.load(&full)?;
+    if with_indexes {
+        // Indexes that cannot be loaded are simply not restored, like before they were saved
+        match db.load_indexes(&full) {
+            Ok(0) => {}
+            Ok(count) => println!("Loaded {} indexes saved with {}", count, full),
+            Err(err) => println!("Could not load the indexes saved with {}: {}", full, err),
+        }
+    }
+    let Some(base) = base else {
+        return Ok(());
+    };
+    for delta in backup::list_backups(dir)? {
+        let delta_name = delta
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if backup::delta_base(delta_name).as_ref() == Some(&base)
+            && backup::backup_order(delta_name) <= backup::backup_order(name)
+        {
+            db.load_delta(&delta.to_string_lossy(), &base)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the changes read from the write-ahead log to `db`, in order, and returns
+/// the sequence number of the last one, or 0 if there is none.
+fn replay(db: &mut Vemcache, entries: impl IntoIterator<Item = WalEntry>) -> u64 {
+    let mut last_seq = 0;
+    for entry in entries {
+        match commands::parse_command(&entry.command) {
+            Ok(Command::NamedInsert(key, values)) => db.insert_raw(key, values),
+            Ok(Command::Remove(key)) => {
+                db.remove(key);
+            }
+            _ => println!(
+                "Skipping unexpected write-ahead log entry {}: {}",
+                entry.seq, entry.command
+            ),
+        }
+        last_seq = entry.seq;
+    }
+    last_seq
+}
+
+/// The point in time `recover` brings a database back to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecoveryPoint {
+    /// Right after the change with this sequence number.
+    Change(u64),
+    /// The given time, undoing the changes applied after it.
+    Time(SystemTime),
+}
+
+impl RecoveryPoint {
+    /// Parses the sequence number of a change, or a UTC timestamp such as
+    /// `2024-01-02T03:04:05Z`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::persistence::RecoveryPoint;
+    ///
+    /// assert_eq!(RecoveryPoint::parse("42"), Some(RecoveryPoint::Change(42)));
+    /// assert!(matches!(RecoveryPoint::parse("2024-01-02T03:04:05Z"), Some(RecoveryPoint::Time(_))));
+    /// assert_eq!(RecoveryPoint::parse("yesterday"), None);
+    /// ```
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.parse() {
+            Ok(seq) => Some(RecoveryPoint::Change(seq)),
+            Err(_) => backup::parse_utc_timestamp(text).map(RecoveryPoint::Time),
+        }
+    }
+}
+
+/// Computes the contents of the database whose directory is `data_dir` as they
+/// were at `point`: the newest snapshot taken before it is loaded, then the
+/// write-ahead log is replayed up to it. Returns the contents along with the
+/// sequence number of the last change they include.
+///
+/// # Errors
+///
+/// Returns an error if `point` is a change that is not in the write-ahead log, if
+/// the write-ahead log cannot be read, or if it was compacted and no snapshot taken
+/// before `point` holds the changes dropped from it. Snapshots that cannot be
+/// loaded are skipped like in `restore`.
+pub fn recover(data_dir: &Path, point: RecoveryPoint) -> IoResult<(Vemcache, u64)> {
+    let entries = Wal::read(&data_dir.join(WAL_FILE_NAME))?;
+    let first_seq = entries.first().map_or(1, |entry| entry.seq);
+    // In milliseconds since the Unix epoch, like the entries of the write-ahead log
+    let until = match point {
+        RecoveryPoint::Change(seq) => entries
+            .iter()
+            .find(|entry| entry.seq == seq)
+            .map(|entry| entry.timestamp)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("Change {} is not in the write-ahead log", seq),
+                )
+            })?,
+        RecoveryPoint::Time(time) => time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+    };
+
+    let mut db = Vemcache::new();
+    let before = UNIX_EPOCH + Duration::from_millis(until);
+    let snapshot_seq = match load_newest_snapshot(&mut db, data_dir, Some(before), first_seq - 1, false)? {
+        Some((_, seq)) => seq,
+        None if first_seq > 1 => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No snapshot taken before the recovery point holds the changes before {}, the first one in the write-ahead log",
+                    first_seq
+                ),
+            ))
+        }
+        None => 0,
+    };
+    let entries = entries
+        .into_iter()
+        .take_while(|entry| match point {
+            RecoveryPoint::Change(seq) => entry.seq <= seq,
+            RecoveryPoint::Time(_) => entry.timestamp <= until,
+        })
+        .filter(|entry| entry.seq > snapshot_seq);
+    let last_seq = replay(&mut db, entries).max(snapshot_seq);
+    Ok((db, last_seq))
+}
+
+/// Resolves the path a client asked to dump the logical database numbered `database`
+/// to, like `resolve_path`. Dumps of databases other than the first one that land
+/// in the data directory are moved to the directory of the database, so they are
+/// restored into it rather than into the first database.
+pub fn resolve_dump_path(
+    config: &Config,
+    path: &str,
+    database: usize,
+) -> Result<PathBuf, PathError> {
+    let resolved = resolve_path(config, path)?;
+    let Some(data_dir) = config.data_dir.as_deref().map(Path::new) else {
+        return Ok(resolved);
+    };
+    Ok(match resolved.strip_prefix(data_dir) {
+        Ok(relative) if database != 0 => database_dir(data_dir, database).join(relative),
+        _ => resolved,
+    })
+}
+
+/// Returns the directory holding the snapshots, write-ahead log and disk storage of
+/// the logical database numbered `index`: the data directory itself for database 0,
+/// and its `db<index>` subdirectory for the others.
+pub fn database_dir(data_dir: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => data_dir.to_path_buf(),
+        index => data_dir.join(format!("db{}", index)),
+    }
+}
+
+/// Saves the module of the metric `name` in `data_dir`, the directory of a database,
+/// so `restore` registers the metric again after a restart.
+pub fn save_metric(data_dir: &Path, name: &str, wasm: &[u8]) -> IoResult<()> {
+    let dir = data_dir.join(METRICS_DIR_NAME);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{}.wasm", name)), wasm)
+}
+
+/// Registers the metrics whose modules were saved in `data_dir` by `save_metric`.
+/// Modules that cannot be loaded are skipped.
+fn restore_metrics(db: &mut Vemcache, data_dir: &Path) -> IoResult<()> {
+    let dir = match fs::read_dir(data_dir.join(METRICS_DIR_NAME)) {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in dir {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_stem()
+            .filter(|_| {
+                path.extension()
+                    .is_some_and(|extension| extension == "wasm")
+            })
+            .and_then(|name| name.to_str())
+        else {
+            continue;
+        };
+        match WasmMetric::new(name, &fs::read(&path)?) {
+            Ok(metric) => {
+                db.register_metric(Arc::new(metric));
+                println!("Loaded metric {}", name);
+            }
+            Err(err) => println!("Could not load metric {}: {}", path.display(), err),
+        }
+    }
+    Ok(())
+}
+
+/// Loads the most recently modified snapshot in `data_dir` that can be loaded into
+/// `db`, like `load_snapshot`, and returns its path along with the sequence number
+/// of the last change it holds, if any. Only snapshots modified strictly before
+/// `before` are considered if given, and only those holding every change up to
+/// `min_seq`. Snapshots that cannot be loaded are reported and skipped, so a
+/// corrupted one never keeps the server from starting.
+fn load_newest_snapshot(
+    db: &mut Vemcache,
+    data_dir: &Path,
+    before: Option<SystemTime>,
+    min_seq: u64,
+    with_indexes: bool,
+) -> IoResult<Option<(PathBuf, u64)>> {
+    for snapshot in snapshots_newest_first(data_dir, before)? {
+        let loaded = snapshot_header(&snapshot).and_then(|Header { seq, .. }| {
+            if seq < min_seq {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "It holds the changes up to {}, and the write-ahead log was compacted up to {}",
+                        seq, min_seq
+                    ),
+                ));
+            }
+            load_snapshot(db, &snapshot, with_indexes).map(|_| seq)
+        });
+        match loaded {
+            Ok(seq) => return Ok(Some((snapshot, seq))),
+            Err(err) => println!("Skipping snapshot {}: {}", snapshot.display(), err),
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the header of the snapshot at `path`.
+fn snapshot_header(path: &Path) -> IoResult<Header> {
+    checksum::read_header(compression::open_file(&path.to_string_lossy())?)
+}
+
+/// Returns whether the dump at `path` only holds part of a database, like the
+/// vectors under a prefix, in which case it is not a snapshot.
+fn is_partial(path: &Path) -> bool {
+    snapshot_header(path).is_ok_and(|header| header.partial)
+}
+
+/// Returns the snapshots in `data_dir`, most recently modified first, only
+/// considering those modified strictly before `before` if given. Snapshots are
+/// the files written by `dump`, optionally compressed with gzip or zstd; other
+/// JSON files, like exports and dumps of the vectors under a prefix, are left out.
+fn snapshots_newest_first(data_dir: &Path, before: Option<SystemTime>) -> IoResult<Vec<PathBuf>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_snapshot(&path) || !is_dump(&path) || is_partial(&path) {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if before.is_some_and(|before| modified >= before) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        snapshots.push((modified, name, path));
+    }
+    // Files written in quick succession, like a backup and its deltas, can share
+    // a modification time, in which case the delta sorting last comes first
+    snapshots.sort_by(|(time, name, _), (other_time, other, _)| {
+        (other_time, backup::backup_order(other)).cmp(&(time, backup::backup_order(name)))
+    });
+    Ok(snapshots.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Returns whether the file at `path` starts with the header of a dump. Encrypted
+/// files that cannot be decrypted are assumed to be dumps, so loading them reports
+/// the missing key.
+fn is_dump(path: &Path) -> bool {
+    let Ok(file) = compression::open_file(&path.to_string_lossy()) else {
+        return true;
+    };
+    let mut header = Vec::new();
+    file.take(checksum::MAGIC.len() as u64)
+        .read_to_end(&mut header)
+        .is_ok_and(|_| header == checksum::MAGIC)
+}
+
+fn is_snapshot(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    [".json", ".json.gz", ".json.zst"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// A snapshot in the directory of a database, as reported by `snapshots list` and
+/// `snapshots info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The file name of the snapshot.
+    pub name: String,
+    /// The size of the file, in bytes.
+    pub size: u64,
+    /// The time the file was last modified.
+    pub modified: SystemTime,
+    /// The compression of the snapshot, chosen by its extension.
+    pub compression: Compression,
+    /// Whether the snapshot is encrypted.
+    pub encrypted: bool,
+    /// The version of the format of the dump, 1 for dumps written before the format
+    /// was versioned, or `None` if it cannot be read, like when the snapshot is
+    /// encrypted and no key is installed.
+    pub version: Option<u32>,
+    /// The full backup a delta backup was taken against.
+    pub base: Option<String>,
+    /// The suffixes of the files saved along with the snapshot.
+    pub sidecars: Vec<&'static str>,
+}
+
+/// Returns the snapshots in `dir`, in the order `restore` considers them, so the
+/// last one is the snapshot loaded when the server starts.
+pub fn list_snapshots(dir: &Path) -> IoResult<Vec<Snapshot>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if is_snapshot(&path) && path.is_file() && is_dump(&path) && !is_partial(&path) {
+            snapshots.push(read_snapshot(&path)?);
+        }
+    }
+    snapshots.sort_by(|a, b| {
+        (a.modified, backup::backup_order(&a.name))
+            .cmp(&(b.modified, backup::backup_order(&b.name)))
+    });
+    Ok(snapshots)
+}
+
+/// Returns the snapshot named `name` in `dir`.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `name` is not the file name of a
+/// snapshot, and of kind `NotFound` if there is no such snapshot.
+pub fn snapshot_info(dir: &Path, name: &str) -> IoResult<Snapshot> {
+    let path = snapshot_path(dir, name)?;
+    if !path.is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No snapshot named {}", name),
+        ));
+    }
+    read_snapshot(&path)
+}
+
+/// Removes the snapshot named `name` from `dir`, along with the files saved with
+/// it. A full backup is kept while delta backups taken against it remain, since
+/// they cannot be loaded without it.
+///
+/// # Errors
+///
+/// Returns the same errors as `snapshot_info`, and an error of kind
+/// `InvalidInput` if delta backups were taken against the snapshot.
+pub fn delete_snapshot(dir: &Path, name: &str) -> IoResult<()> {
+    let path = snapshot_info(dir, name).map(|_| dir.join(name))?;
+    let deltas = backup::list_backups(dir)?
+        .iter()
+        .filter(|delta| {
+            let delta_name = delta.file_name().and_then(|name| name.to_str());
+            delta_name.and_then(backup::delta_base).as_deref() == Some(name)
+        })
+        .count();
+    if deltas > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} delta backups were taken against {}", deltas, name),
+        ));
+    }
+    fs::remove_file(&path)?;
+    for suffix in SIDECAR_SUFFIXES {
+        let mut sidecar = path.clone().into_os_string();
+        sidecar.push(suffix);
+        match fs::remove_file(sidecar) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of the snapshot named `name` in `dir`, rejecting names that
+/// are not those of a snapshot or could reach outside `dir`.
+fn snapshot_path(dir: &Path, name: &str) -> IoResult<PathBuf> {
+    let mut components = Path::new(name).components();
+    let plain = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    if !plain || !is_snapshot(Path::new(name)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Not the name of a snapshot: {}", name),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+/// Reads the metadata and the header of the snapshot at `path`.
+fn read_snapshot(path: &Path) -> IoResult<Snapshot> {
+    let metadata = fs::metadata(path)?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut magic = Vec::new();
+    fs::File::open(path)?
+        .take(crate::encryption::MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let path_text = path.to_string_lossy();
+    let sidecars = SIDECAR_SUFFIXES
+        .into_iter()
+        .filter(|suffix| Path::new(&format!("{}{}", path_text, suffix)).is_file())
+        .collect();
+    Ok(Snapshot {
+        base: backup::delta_base(&name),
+        name,
+        size: metadata.len(),
+        modified: metadata.modified()?,
+        compression: Compression::from_path(&path_text),
+        encrypted: magic.starts_with(crate::encryption::MAGIC),
+        version: format_version(&path_text),
+        sidecars,
+    })
+}
+
+/// Reads the version of the format of the dump at `path` from its header, or
+/// returns `None` if the dump cannot be opened or is empty.
+fn format_version(path: &str) -> Option<u32> {
+    let mut header = Vec::new();
+    compression::open_file(path)
+        .ok()?
+        .take((checksum::MAGIC.len() + 4) as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    match header.strip_prefix(checksum::MAGIC) {
+        Some(version) => Some(u32::from_le_bytes(version.try_into().ok()?)),
+        None if header.is_empty() => None,
+        // A dump written before the format was versioned
+        None => Some(1),
+    }
+}
+
+/// The reasons a path given by a client, to `dump` or `import`, can be rejected.
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    /// No path was given.
+    Empty,
+    /// The path is absolute, while files are restricted to the data directory.
+    Absolute(String),
+    /// The path contains `..`, which could escape the data directory.
+    ParentDirectory(String),
+    /// The path has the name of a snapshot, which only dumps may have.
+    Reserved(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::Empty => write!(f, "Empty path"),
+            PathError::Absolute(path) => write!(f, "Absolute paths are not allowed: {}", path),
+            PathError::ParentDirectory(path) => {
+                write!(f, "Paths may not contain '..': {}", path)
+            }
+            PathError::Reserved(path) => write!(
+                f,
+                "Names ending in .json, .json.gz or .json.zst are reserved for dumps: {}",
+                path
+            ),
+        }
+    }
+}
+
+/// Resolves a path a client asked to export to, like `resolve_path`. Exports may
+/// not have the name of a snapshot, since they would be taken for one.
+pub fn resolve_export_path(config: &Config, path: &str) -> Result<PathBuf, PathError> {
+    let resolved = resolve_path(config, path)?;
+    if is_snapshot(&resolved) {
+        return Err(PathError::Reserved(path.to_string()));
+    }
+    Ok(resolved)
+}
+
+/// Resolves a path a client asked to dump to or import from.
+///
+/// Files are restricted to the data directory (or the working directory if no
+/// data directory is configured): the path is resolved relative to it, and
+/// absolute paths or paths containing `..` are rejected. Otherwise a client could
+/// overwrite or read any file the server process can reach. Setting
+/// `allow_any_dump_path` lifts the restriction.
+pub fn resolve_path(config: &Config, path: &str) -> Result<PathBuf, PathError> {
+    if path.is_empty() {
+        return Err(PathError::Empty);
+    }
+    let requested = Path::new(path);
+    if config.allow_any_dump_path {
+        return Ok(requested.to_path_buf());
+    }
+
+    for component in requested.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(PathError::Absolute(path.to_string()))
+            }
+            Component::ParentDir => return Err(PathError::ParentDirectory(path.to_string())),
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+
+    Ok(match &config.data_dir {
+        Some(data_dir) => Path::new(data_dir).join(requested),
+        None => requested.to_path_buf(),
+    })
+}
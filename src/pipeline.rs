@@ -0,0 +1,160 @@
+//! Transforms applied to the vectors inserted into a collection.
+//!
+//! A pipeline is a sequence of transforms, written as a list of words such as
+//! `normalize clip -1 1 cast f16`, applied in order to every vector inserted into
+//! its collection before it is stored. A transform can also reject the vector, in
+//! which case it is not inserted.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The precisions vector components can be rounded to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    /// IEEE 754 half precision, with 11 significant bits.
+    F16,
+    /// Brain floating point, with the range of `f32` and 8 significant bits.
+    Bf16,
+}
+
+/// A step of a pipeline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// Scales the vector to unit length. Zero vectors are left as they are.
+    Normalize,
+    /// Clamps every component to `[min, max]`.
+    Clip { min: f32, max: f32 },
+    /// Rounds every component to the nearest value representable with `precision`,
+    /// rejecting the vector if a component overflows it.
+    Cast(Precision),
+    /// Rejects the vector if a component lies outside of `[min, max]`.
+    Reject { min: f32, max: f32 },
+}
+
+/// The transforms applied to the vectors inserted into a collection, in order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline(Vec<Transform>);
+
+impl Pipeline {
+    /// Parses a pipeline from its words, e.g. `["normalize", "cast", "f16"]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if a transform is unknown or misses its arguments.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::pipeline::Pipeline;
+    ///
+    /// let pipeline = Pipeline::parse(&["normalize", "reject", "-0.7", "0.7"]).unwrap();
+    /// assert_eq!(pipeline.to_string(), "normalize reject -0.7 0.7");
+    ///
+    /// assert_eq!(pipeline.apply(vec![3.0, 0.0, -4.0]), Err("Component 2 is out of range".to_string()));
+    /// assert_eq!(pipeline.apply(vec![1.0, 1.0, 1.0]).map(|v| v.len()), Ok(3));
+    /// ```
+    pub fn parse<S: AsRef<str>>(words: &[S]) -> Result<Self, String> {
+        let mut words = words.iter().map(AsRef::as_ref);
+        let mut transforms = Vec::new();
+        while let Some(word) = words.next() {
+            let mut bound = |what: &str| -> Result<f32, String> {
+                words
+                    .next()
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .filter(|value| !value.is_nan())
+                    .ok_or_else(|| format!("Missing {} of {}", what, word))
+            };
+            let transform = match word.to_lowercase().as_str() {
+                "normalize" => Transform::Normalize,
+                "clip" | "reject" => {
+                    let (min, max) = (bound("minimum")?, bound("maximum")?);
+                    if min > max {
+                        return Err(format!("Empty range for {}", word));
+                    }
+                    match word.eq_ignore_ascii_case("clip") {
+                        true => Transform::Clip { min, max },
+                        false => Transform::Reject { min, max },
+                    }
+                }
+                "cast" => match words.next().map(str::to_lowercase).as_deref() {
+                    Some("f16") => Transform::Cast(Precision::F16),
+                    Some("bf16") => Transform::Cast(Precision::Bf16),
+                    _ => return Err("Expected f16 or bf16 after cast".to_string()),
+                },
+                _ => return Err(format!("Unknown transform: {}", word)),
+            };
+            transforms.push(transform);
+        }
+        if transforms.is_empty() {
+            return Err("Empty pipeline".to_string());
+        }
+        Ok(Self(transforms))
+    }
+
+    /// Returns the transforms of the pipeline, in order.
+    pub fn transforms(&self) -> &[Transform] {
+        &self.0
+    }
+
+    /// Applies the transforms of the pipeline to `vector`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if a transform rejects the vector.
+    pub fn apply(&self, mut vector: Vec<f32>) -> Result<Vec<f32>, String> {
+        for transform in &self.0 {
+            match *transform {
+                Transform::Normalize => {
+                    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+                    if norm > 0.0 {
+                        vector.iter_mut().for_each(|value| *value /= norm);
+                    }
+                }
+                Transform::Clip { min, max } => {
+                    vector
+                        .iter_mut()
+                        .for_each(|value| *value = value.clamp(min, max));
+                }
+                Transform::Cast(Precision::F16) => {
+                    vector
+                        .iter_mut()
+                        .for_each(|value| *value = half::f16::from_f32(*value).to_f32());
+                    if let Some(index) = vector.iter().position(|value| value.is_infinite()) {
+                        return Err(format!("Component {} overflows f16", index));
+                    }
+                }
+                Transform::Cast(Precision::Bf16) => vector
+                    .iter_mut()
+                    .for_each(|value| *value = half::bf16::from_f32(*value).to_f32()),
+                Transform::Reject { min, max } => {
+                    if let Some(index) =
+                        vector.iter().position(|value| !(min..=max).contains(value))
+                    {
+                        return Err(format!("Component {} is out of range", index));
+                    }
+                }
+            }
+        }
+        Ok(vector)
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, transform) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match transform {
+                Transform::Normalize => write!(f, "normalize")?,
+                Transform::Clip { min, max } => write!(f, "clip {} {}", min, max)?,
+                Transform::Cast(Precision::F16) => write!(f, "cast f16")?,
+                Transform::Cast(Precision::Bf16) => write!(f, "cast bf16")?,
+                Transform::Reject { min, max } => write!(f, "reject {} {}", min, max)?,
+            }
+        }
+        Ok(())
+    }
+}
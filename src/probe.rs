@@ -0,0 +1,105 @@
+//! Probes for orchestrators such as Kubernetes, answered over HTTP on `probe_port`.
+//!
+//! | Method | Path       | Description                                                   |
+//! |--------|------------|---------------------------------------------------------------|
+//! | `GET`  | `/livez`   | `200` as long as the server answers                           |
+//! | `GET`  | `/readyz`  | `200` once the server is ready to receive traffic, else `503` |
+//! | `GET`  | `/prestop` | Saves the databases for the shutdown, `200` once saved        |
+//!
+//! The server is ready once the snapshot of the data directory is loaded and the
+//! indexes being built at that point are built, until it starts shutting down.
+//! `/prestop`, meant for a preStop hook, stops the server from reporting ready and
+//! saves the databases as on SIGTERM: they stay locked until the process exits, and
+//! the signal that follows stops the server without saving them again. `POST` is
+//! accepted as well.
+//!
+//! The probes are answered without waiting for the database, and are not
+//! authenticated, so the port should only be reachable by the orchestrator.
+
+use crate::handlers::prepare_shutdown;
+use crate::server::Server;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The longest request head read, probes send a few hundred bytes.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// How long a prober has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Answers the probes sent to `listener` until the server stops.
+pub async fn serve(listener: TcpListener, server: Arc<Server>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(answer(stream, Arc::clone(&server)));
+            }
+            Err(err) => println!("Error accepting a probe: {}", err),
+        }
+    }
+}
+
+/// Reads the request sent on `stream` and answers it, then closes the connection.
+async fn answer(mut stream: TcpStream, server: Arc<Server>) {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(Ok(Some(head))) => head,
+        _ => return,
+    };
+    let mut words = head.split(' ');
+    let method = words.next().unwrap_or_default();
+    let path = words.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/livez") => ("200 OK", "ok\n".to_string()),
+        ("GET", "/readyz") if server.health.is_ready() => ("200 OK", server.health.report()),
+        ("GET", "/readyz") => ("503 Service Unavailable", server.health.report()),
+        ("GET" | "POST", "/prestop") => {
+            let save = prepare_shutdown(&server.databases, &server.config, true);
+            match server.health.drain(save).await {
+                Ok(()) => ("200 OK", "saved\n".to_string()),
+                Err(err) => (
+                    "500 Internal Server Error",
+                    format!("Error: Could not save the databases: {}\n", err),
+                ),
+            }
+        }
+        (_, "/livez" | "/readyz" | "/prestop") => {
+            ("405 Method Not Allowed", "Method not allowed\n".to_string())
+        }
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    // The prober may have given up in the meantime
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Reads the head of the request, up to the blank line ending it, and returns its
+/// request line, or `None` if the connection closes first or the head is too long.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|end| end == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let line = head.split(|&byte| byte == b'\n').next().unwrap_or_default();
+    Ok(Some(
+        String::from_utf8_lossy(line)
+            .trim_end_matches('\r')
+            .to_string(),
+    ))
+}
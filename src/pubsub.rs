@@ -0,0 +1,63 @@
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// The number of messages buffered for each subscribed connection. Connections
+/// that fall further behind skip the messages they missed.
+const PUBSUB_BUFFER: usize = 1024;
+
+/// A message published on a channel.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub channel: String,
+    pub payload: String,
+}
+
+/// Delivers the events of the server to the connections that ran `subscribe`.
+pub struct PubSub {
+    sender: Sender<Message>,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PubSub {
+    /// Creates a pub/sub without any subscribed connection.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(PUBSUB_BUFFER);
+        Self { sender }
+    }
+
+    /// Subscribes to the messages published from now on, on every channel.
+    pub fn subscribe(&self) -> Receiver<Message> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes `payload` on `channel`. Nothing is done while no connection is
+    /// subscribed.
+    pub fn publish(&self, channel: &str, payload: &str) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        // The last receiver may have unsubscribed in the meantime
+        let _ = self.sender.send(Message {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        });
+    }
+}
+
+/// Returns the channel the keys of the vectors of the database numbered
+/// `database` are published on when they expire.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::pubsub;
+///
+/// assert_eq!(pubsub::expired_channel(0), "__keyevent@0__:expired");
+/// ```
+pub fn expired_channel(database: usize) -> String {
+    format!("__keyevent@{}__:expired", database)
+}
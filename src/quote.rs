@@ -0,0 +1,114 @@
+//! Quoting of the arguments of text commands.
+//!
+//! Arguments are separated by whitespace. An argument can be wrapped in double
+//! quotes to hold whitespace or special characters, with the escapes `\"`, `\\`,
+//! `\n`, `\r`, `\t` and `\xHH` for any byte, or in single quotes, where only `\'`
+//! is an escape. Quoted arguments must still be valid UTF-8 once unescaped.
+
+use std::borrow::Cow;
+
+/// Splits a command line into its arguments, unquoting the quoted ones.
+///
+/// # Errors
+///
+/// Returns an error message if a quote is not closed, is directly followed by
+/// something other than whitespace, or if a quoted argument is not valid UTF-8.
+pub fn split(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut arguments = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c != '"' && c != '\'' {
+            let mut end = line.len();
+            while let Some(&(index, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    end = index;
+                    break;
+                }
+                chars.next();
+            }
+            arguments.push(line[start..end].to_string());
+            continue;
+        }
+
+        chars.next();
+        let mut bytes = Vec::new();
+        loop {
+            let (_, next) = chars.next().ok_or("Unbalanced quotes")?;
+            match next {
+                '"' | '\'' if next == c => break,
+                '\\' if c == '\'' => match chars.peek() {
+                    Some(&(_, '\'')) => {
+                        chars.next();
+                        bytes.push(b'\'');
+                    }
+                    _ => bytes.push(b'\\'),
+                },
+                '\\' => {
+                    let (_, escaped) = chars.next().ok_or("Unbalanced quotes")?;
+                    match escaped {
+                        'n' => bytes.push(b'\n'),
+                        'r' => bytes.push(b'\r'),
+                        't' => bytes.push(b'\t'),
+                        'x' => {
+                            let high = chars.next().and_then(|(_, c)| c.to_digit(16));
+                            let low = chars.next().and_then(|(_, c)| c.to_digit(16));
+                            match (high, low) {
+                                (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+                                _ => return Err("Invalid escape sequence"),
+                            }
+                        }
+                        escaped => push_char(&mut bytes, escaped),
+                    }
+                }
+                next => push_char(&mut bytes, next),
+            }
+        }
+        if chars.peek().is_some_and(|(_, c)| !c.is_whitespace()) {
+            return Err("Closing quote must be followed by a space");
+        }
+        arguments.push(String::from_utf8(bytes).map_err(|_| "Quoted argument is not UTF-8")?);
+    }
+    Ok(arguments)
+}
+
+/// Returns `argument` as it must be written in a command line to be read back
+/// as is by `split`, in double quotes if it is empty or holds whitespace, quotes,
+/// backslashes or control characters.
+pub fn quote(argument: &str) -> Cow<'_, str> {
+    let plain = !argument.is_empty()
+        && !argument
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '\\'));
+    if plain {
+        return Cow::Borrowed(argument);
+    }
+    let mut quoted = String::with_capacity(argument.len() + 2);
+    quoted.push('"');
+    for c in argument.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    quoted.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buffer = [0; 4];
+    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+}
@@ -0,0 +1,678 @@
+//! Leader election among nodes holding copies of the same database, so a group of
+//! three nodes keeps accepting writes when one of them fails.
+//!
+//! Every node is started with the same list of nodes in `raft_nodes`, and they
+//! elect a leader as in the Raft consensus algorithm. Time is divided into terms,
+//! each with at most one leader. A node that hears nothing from a leader for an
+//! election timeout starts a new term and asks the others for their vote; the
+//! first node to collect the votes of a majority leads the term. A node votes for
+//! at most one candidate per term, and only for a candidate holding every change
+//! it holds itself, so a change held by a majority of the nodes survives the
+//! elections between running nodes. The term and the vote are saved in the data
+//! directory, if there is one, so a restart cannot make a node vote twice in a term.
+//!
+//! The leader accepts writes and sends heartbeats to the other nodes, which follow
+//! it with the replication of the `replication` module: they load a snapshot of its
+//! database, then stream its change log and tell it how far they got. A write is
+//! only acknowledged once a majority of the nodes hold it. Followers serve reads
+//! and answer writes with a `MOVED` error naming the leader. A leader that loses
+//! contact with a majority of the nodes steps down, so a partitioned minority stops
+//! accepting writes.
+//!
+//! The changes a node holds are positioned by the term of the leader they come
+//! from and their sequence number in its change log. A follower only reloads a
+//! snapshot when it starts following a leader, so positions are only compared
+//! within a term.
+//!
+//! This is leader election on top of the asynchronous replication, not a replicated
+//! log: the leader applies a write before any follower receives it, and only waits
+//! for a majority before answering. A write a majority does not acknowledge in time
+//! stays applied on the leader, readable by every client, while the connection of
+//! its writer is closed without a response; the next election may keep or drop it.
+//! Acknowledged writes can be lost as well once a node restarts: it does not know
+//! which changes it held, so it votes for any candidate.
+//!
+//! Only database 0 is replicated.
+
+use std::collections::HashMap;
+use std::io::{Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+use crate::health::Health;
+use crate::quote::quote;
+use crate::replication::Replication;
+use crate::Vemcache;
+
+/// The name of the file holding the term and the vote of the node, inside the data
+/// directory.
+pub const STATE_FILE_NAME: &str = "raft.state";
+
+/// How often the leader sends heartbeats to the other nodes.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The shortest time a node waits to hear from a leader before starting an
+/// election. A random delay of up to the same time is added to every wait, so the
+/// nodes rarely start elections at once.
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How often a node checks whether its election timeout has elapsed.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a node waits for another to answer a vote request or a heartbeat.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long the leader waits for a majority of the nodes to hold a write.
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The part a node plays in its term.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+impl Role {
+    /// Returns the name of the role, as listed by `raft status`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Follower => "follower",
+            Role::Candidate => "candidate",
+            Role::Leader => "leader",
+        }
+    }
+}
+
+/// A request for the vote of a node, sent by a candidate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoteRequest {
+    /// The term the candidate wants to lead.
+    pub term: u64,
+    /// The address of the candidate.
+    pub candidate: String,
+    /// The position of the last change the candidate holds: the term of the
+    /// leader it comes from and its sequence number in that leader's change log.
+    pub position: (u64, u64),
+}
+
+/// What a connection to this node streamed to a follower: the node it acknowledges
+/// changes for, and the sequence number of the last change it was sent. A follower
+/// can only acknowledge the changes its own connection was sent, so a connection
+/// cannot vouch for changes no follower received.
+#[derive(Debug, Default)]
+pub struct Stream {
+    node: Option<String>,
+    sent: u64,
+}
+
+impl Stream {
+    /// Records that the connection was sent every change up to the sequence number
+    /// `seq`, by a snapshot or a list of changes.
+    pub fn sent(&mut self, seq: u64) {
+        self.sent = self.sent.max(seq);
+    }
+}
+
+/// What the leader knows of another node.
+struct Peer {
+    /// The sequence number of the last change the node holds.
+    seq: u64,
+    /// When the node last answered.
+    contact: Instant,
+}
+
+struct State {
+    term: u64,
+    voted_for: Option<String>,
+    role: Role,
+    leader: Option<String>,
+    election_deadline: Instant,
+    // The position of the last change held, while not leading
+    position: (u64, u64),
+    // The leader followed by the replication, and its term
+    following: Option<(String, u64)>,
+    // The other nodes, while leading
+    peers: HashMap<String, Peer>,
+}
+
+/// The membership of this node in a group electing a leader. See the module
+/// documentation.
+pub struct Raft {
+    nodes: Vec<String>,
+    this_node: String,
+    token: Option<String>,
+    state_file: Option<PathBuf>,
+    state: Mutex<State>,
+    db: Arc<tokio::sync::Mutex<Vemcache>>,
+    replication: Arc<Mutex<Replication>>,
+    health: Arc<Health>,
+    // Notified when followers catch up and when the leader steps down
+    progress: Notify,
+}
+
+impl Raft {
+    /// Creates the membership of `this_node`, which must be one of `nodes`, in the
+    /// group of `nodes`. The term and the vote are read from, and saved to,
+    /// `data_dir`, if given. The other nodes are sent `token` to authenticate, if
+    /// given. The node replicates `db` through `replication` while following, and
+    /// takes no part in elections while `health` reports the database loading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if `this_node` is not one of `nodes`, or if the
+    /// saved term and vote cannot be read.
+    pub fn new(
+        nodes: Vec<String>,
+        this_node: String,
+        token: Option<String>,
+        data_dir: Option<&Path>,
+        db: Arc<tokio::sync::Mutex<Vemcache>>,
+        replication: Arc<Mutex<Replication>>,
+        health: Arc<Health>,
+    ) -> Result<Arc<Self>, String> {
+        if !nodes.contains(&this_node) {
+            return Err(format!("{} is not one of the raft nodes", this_node));
+        }
+        let state_file = data_dir.map(|dir| dir.join(STATE_FILE_NAME));
+        let (term, voted_for) = match &state_file {
+            Some(path) => read_state(path)
+                .map_err(|err| format!("Could not read {}: {}", path.display(), err))?,
+            None => (0, None),
+        };
+        Ok(Arc::new(Self {
+            nodes,
+            this_node,
+            token,
+            state_file,
+            state: Mutex::new(State {
+                term,
+                voted_for,
+                role: Role::Follower,
+                leader: None,
+                election_deadline: next_election_deadline(),
+                position: (0, 0),
+                following: None,
+                peers: HashMap::new(),
+            }),
+            db,
+            replication,
+            health,
+            progress: Notify::new(),
+        }))
+    }
+
+    /// Returns the address of this node.
+    pub fn this_node(&self) -> &str {
+        &self.this_node
+    }
+
+    /// Returns the role of this node, its term and the address of the leader of
+    /// the term, if known.
+    pub fn status(&self) -> (Role, u64, Option<String>) {
+        let state = self.state.lock().unwrap();
+        (state.role, state.term, state.leader.clone())
+    }
+
+    /// Checks that this node leads its term, so it may accept writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error message to answer writes with otherwise: a `MOVED` error
+    /// naming the leader, or a `TRYAGAIN` error while none is known.
+    pub fn check_leader(&self) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        match (&state.role, &state.leader) {
+            (Role::Leader, _) => Ok(()),
+            (_, Some(leader)) => Err(format!("MOVED {}", leader)),
+            (_, None) => Err("TRYAGAIN No leader has been elected yet".to_string()),
+        }
+    }
+
+    /// Starts electing a leader and, while leading, sending heartbeats to the other
+    /// nodes, in the background.
+    pub fn start(self: &Arc<Self>) {
+        tokio::spawn(Arc::clone(self).hold_elections());
+        for node in self.peers() {
+            tokio::spawn(Arc::clone(self).send_heartbeats(node.clone()));
+        }
+    }
+
+    /// Answers the vote `request` of a candidate, returning the term of this node
+    /// and whether it votes for the candidate. `last_seq` is the sequence number of
+    /// the last change in the change log of the database.
+    pub fn vote(self: &Arc<Self>, request: &VoteRequest, last_seq: u64) -> (u64, bool) {
+        let mut state = self.state.lock().unwrap();
+        self.observe_term(&mut state, request.term, last_seq);
+        let position = match state.role {
+            Role::Leader => (state.term, last_seq),
+            _ => state.position,
+        };
+        let granted = request.term == state.term
+            && self.nodes.contains(&request.candidate)
+            && match &state.voted_for {
+                Some(voted_for) => *voted_for == request.candidate,
+                None => true,
+            }
+            && request.position >= position;
+        if !granted {
+            return (state.term, false);
+        }
+        if state.voted_for.is_none() {
+            state.voted_for = Some(request.candidate.clone());
+            // A vote that is not saved could be cast again after a restart
+            if let Err(err) = self.save(&state) {
+                println!("Error saving the raft state: {}", err);
+                state.voted_for = None;
+                return (state.term, false);
+            }
+        }
+        state.election_deadline = next_election_deadline();
+        (state.term, true)
+    }
+
+    /// Handles a heartbeat from `leader`, the leader of `term`, and returns the
+    /// term of this node. `last_seq` is the sequence number of the last change in
+    /// the change log of the database.
+    ///
+    /// A node hearing from the leader of its term, or a later one, follows it.
+    /// Heartbeats naming a node outside of the group are ignored.
+    pub fn heartbeat(self: &Arc<Self>, term: u64, leader: &str, last_seq: u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        if term < state.term
+            || leader == self.this_node
+            || !self.nodes.iter().any(|node| node == leader)
+        {
+            return state.term;
+        }
+        self.observe_term(&mut state, term, last_seq);
+        if state.role != Role::Follower {
+            self.step_down(&mut state, last_seq);
+        }
+        state.leader = Some(leader.to_string());
+        state.election_deadline = next_election_deadline();
+        let following = (leader.to_string(), term);
+        if state.following.as_ref() != Some(&following) {
+            println!("Following leader {} of term {}", leader, term);
+            self.replication.lock().unwrap().follow_leader(
+                leader.to_string(),
+                term,
+                Arc::clone(self),
+                self.token.clone(),
+                Arc::clone(&self.db),
+            );
+            state.following = Some(following);
+        }
+        state.term
+    }
+
+    /// Records that `node`, following the leader of `term`, holds every change up
+    /// to the sequence number `seq`, if this node leads `term`. Only the changes
+    /// sent over `stream`, the connection of the follower, are acknowledged, and a
+    /// connection only acknowledges changes for the first node it names.
+    pub fn acknowledge(&self, stream: &mut Stream, term: u64, node: &str, seq: u64) {
+        if *stream.node.get_or_insert_with(|| node.to_string()) != node {
+            return;
+        }
+        let seq = seq.min(stream.sent);
+        let mut state = self.state.lock().unwrap();
+        if state.role != Role::Leader || state.term != term {
+            return;
+        }
+        if let Some(peer) = state.peers.get_mut(node) {
+            peer.seq = peer.seq.max(seq);
+            peer.contact = Instant::now();
+            self.progress.notify_waiters();
+        }
+    }
+
+    /// Records that this node holds the changes of the leader of `term` up to the
+    /// sequence number `seq`. Called by the replication following the leader.
+    pub fn synced(&self, term: u64, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .following
+            .as_ref()
+            .is_some_and(|(_, following)| *following == term)
+        {
+            state.position = (term, seq);
+        }
+    }
+
+    /// Waits until a majority of the nodes hold the changes up to the sequence
+    /// number `seq`, and returns `true` once they do. Returns `false` if this node
+    /// stops leading, or if they do not within a few seconds; the changes may then
+    /// be lost in the next election.
+    pub async fn wait_committed(&self, seq: u64) -> bool {
+        let deadline = tokio::time::Instant::now() + COMMIT_TIMEOUT;
+        loop {
+            let notified = self.progress.notified();
+            tokio::pin!(notified);
+            // Register before checking, so no progress is missed in between
+            notified.as_mut().enable();
+            {
+                let state = self.state.lock().unwrap();
+                if state.role != Role::Leader {
+                    return false;
+                }
+                let holders = 1 + state.peers.values().filter(|peer| peer.seq >= seq).count();
+                if holders >= self.majority() {
+                    return true;
+                }
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    /// Returns the addresses of the other nodes.
+    fn peers(&self) -> impl Iterator<Item = &String> {
+        self.nodes.iter().filter(|node| **node != self.this_node)
+    }
+
+    /// Returns the number of nodes making a majority.
+    fn majority(&self) -> usize {
+        self.nodes.len() / 2 + 1
+    }
+
+    /// Moves this node to `term` as a follower, if `term` is later than its own.
+    fn observe_term(&self, state: &mut State, term: u64, last_seq: u64) {
+        if term <= state.term {
+            return;
+        }
+        self.step_down(state, last_seq);
+        state.term = term;
+        state.voted_for = None;
+        state.leader = None;
+        // The leader of the new term is not known yet, so the position is kept
+        // until it is
+        if state.following.take().is_some() {
+            self.replication.lock().unwrap().stop();
+        }
+        if let Err(err) = self.save(state) {
+            println!("Error saving the raft state: {}", err);
+        }
+    }
+
+    /// Turns this node into a follower in its term.
+    fn step_down(&self, state: &mut State, last_seq: u64) {
+        if state.role == Role::Leader {
+            println!("Stepping down as leader of term {}", state.term);
+            state.position = (state.term, last_seq);
+            state.leader = None;
+            state.peers.clear();
+            self.progress.notify_waiters();
+        }
+        state.role = Role::Follower;
+    }
+
+    /// Saves the term and the vote of this node in the data directory, if there is one.
+    fn save(&self, state: &State) -> IoResult<()> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        // Written aside and renamed, so a crash leaves either state whole
+        let written = path.with_extension("state.new");
+        let mut file = std::fs::File::create(&written)?;
+        match &state.voted_for {
+            Some(voted_for) => writeln!(file, "{} {}", state.term, voted_for)?,
+            None => writeln!(file, "{}", state.term)?,
+        }
+        file.sync_all()?;
+        std::fs::rename(written, path)
+    }
+
+    /// Starts an election whenever the election timeout elapses without hearing
+    /// from a leader, and steps down while leading without a majority.
+    async fn hold_elections(self: Arc<Self>) {
+        loop {
+            tokio::time::sleep(TICK_INTERVAL).await;
+            if self.health.is_loading() {
+                self.state.lock().unwrap().election_deadline = next_election_deadline();
+                continue;
+            }
+            let (role, term, deadline, in_contact) = {
+                let state = self.state.lock().unwrap();
+                let in_contact = state
+                    .peers
+                    .values()
+                    .filter(|peer| peer.contact.elapsed() < ELECTION_TIMEOUT)
+                    .count();
+                (state.role, state.term, state.election_deadline, in_contact)
+            };
+            match role {
+                Role::Leader if 1 + in_contact < self.majority() => {
+                    let last_seq = self.db.lock().await.last_seq();
+                    let mut state = self.state.lock().unwrap();
+                    if state.role == Role::Leader && state.term == term {
+                        println!("Lost contact with a majority of the raft nodes");
+                        self.step_down(&mut state, last_seq);
+                        state.election_deadline = next_election_deadline();
+                    }
+                }
+                Role::Leader => {}
+                _ if Instant::now() >= deadline => self.run_election().await,
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts a new term as a candidate and asks the other nodes for their vote,
+    /// leading the term once a majority votes for this node.
+    async fn run_election(self: &Arc<Self>) {
+        let request = {
+            let mut state = self.state.lock().unwrap();
+            state.term += 1;
+            state.role = Role::Candidate;
+            state.voted_for = Some(self.this_node.clone());
+            state.leader = None;
+            state.election_deadline = next_election_deadline();
+            if state.following.take().is_some() {
+                self.replication.lock().unwrap().stop();
+            }
+            if let Err(err) = self.save(&state) {
+                println!("Error saving the raft state: {}", err);
+                state.role = Role::Follower;
+                return;
+            }
+            println!("Starting an election for term {}", state.term);
+            VoteRequest {
+                term: state.term,
+                candidate: self.this_node.clone(),
+                position: state.position,
+            }
+        };
+
+        let mut votes = 1;
+        if votes >= self.majority() {
+            self.lead(&mut self.state.lock().unwrap());
+            return;
+        }
+        let line = format!(
+            "raft vote {} {} {} {}\n",
+            request.term,
+            quote(&request.candidate),
+            request.position.0,
+            request.position.1
+        );
+        let mut requests = JoinSet::new();
+        for node in self.peers() {
+            let raft = Arc::clone(self);
+            let (node, line) = (node.clone(), line.clone());
+            requests.spawn(async move {
+                let mut connection = None;
+                let request = raft.request(&mut connection, &node, &line);
+                tokio::time::timeout(REQUEST_TIMEOUT, request).await
+            });
+        }
+        while let Some(response) = requests.join_next().await {
+            let Some((term, granted)) = response
+                .ok()
+                .and_then(Result::ok)
+                .and_then(Result::ok)
+                .as_deref()
+                .and_then(parse_vote_response)
+            else {
+                continue;
+            };
+            let mut state = self.state.lock().unwrap();
+            // Candidates are not leading, so their own sequence number is not needed
+            self.observe_term(&mut state, term, 0);
+            if state.role != Role::Candidate || state.term != request.term {
+                return;
+            }
+            if granted {
+                votes += 1;
+                if votes >= self.majority() {
+                    self.lead(&mut state);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Makes this node the leader of its term.
+    fn lead(&self, state: &mut State) {
+        println!("Elected leader of term {}", state.term);
+        state.role = Role::Leader;
+        state.leader = Some(self.this_node.clone());
+        // Every node gets an election timeout to answer before the leader steps down
+        let now = Instant::now();
+        state.peers = self
+            .peers()
+            .map(|node| {
+                (
+                    node.clone(),
+                    Peer {
+                        seq: 0,
+                        contact: now,
+                    },
+                )
+            })
+            .collect();
+    }
+
+    /// Sends heartbeats to `node` while this node leads, keeping a connection open.
+    async fn send_heartbeats(self: Arc<Self>, node: String) {
+        let mut connection = None;
+        loop {
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+            let term = {
+                let state = self.state.lock().unwrap();
+                if state.role != Role::Leader {
+                    continue;
+                }
+                state.term
+            };
+            let line = format!("raft heartbeat {} {}\n", term, quote(&self.this_node));
+            let request = self.request(&mut connection, &node, &line);
+            let response = match tokio::time::timeout(REQUEST_TIMEOUT, request).await {
+                Ok(Ok(response)) => response,
+                // The connection may be halfway through a response, so it is not reused
+                _ => {
+                    connection = None;
+                    continue;
+                }
+            };
+            let Some(node_term) = response
+                .strip_prefix("Term: ")
+                .and_then(|term| term.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if node_term > term {
+                let last_seq = self.db.lock().await.last_seq();
+                self.observe_term(&mut self.state.lock().unwrap(), node_term, last_seq);
+                continue;
+            }
+            let mut state = self.state.lock().unwrap();
+            if state.role == Role::Leader && state.term == term {
+                if let Some(peer) = state.peers.get_mut(&node) {
+                    peer.contact = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// Sends the command `line` to `node` over `connection`, connecting first if
+    /// it is not open, and returns the first line of the response.
+    async fn request(
+        &self,
+        connection: &mut Option<BufReader<TcpStream>>,
+        node: &str,
+        line: &str,
+    ) -> IoResult<String> {
+        if connection.is_none() {
+            let mut stream = BufReader::new(TcpStream::connect(node).await?);
+            if let Some(token) = &self.token {
+                let auth = format!("auth {}\n", quote(token));
+                stream.get_mut().write_all(auth.as_bytes()).await?;
+                let response = read_line(&mut stream).await?;
+                if response != "OK" {
+                    return Err(std::io::Error::other(format!(
+                        "authentication failed: {}",
+                        response
+                    )));
+                }
+            }
+            *connection = Some(stream);
+        }
+        let stream = connection.as_mut().unwrap();
+        stream.get_mut().write_all(line.as_bytes()).await?;
+        read_line(stream).await
+    }
+}
+
+/// Reads the term and the vote saved at `path`, or term 0 without a vote if
+/// nothing was saved yet.
+fn read_state(path: &Path) -> IoResult<(u64, Option<String>)> {
+    let state = match std::fs::read_to_string(path) {
+        Ok(state) => state,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((0, None)),
+        Err(err) => return Err(err),
+    };
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid raft state");
+    let mut words = state.split_whitespace();
+    let term = words
+        .next()
+        .and_then(|term| term.parse::<u64>().ok())
+        .ok_or_else(invalid)?;
+    Ok((term, words.next().map(str::to_string)))
+}
+
+/// Parses the answer to a vote request, `Term: <term> granted` or
+/// `Term: <term> denied`, into the term of the node and whether it voted.
+fn parse_vote_response(response: &str) -> Option<(u64, bool)> {
+    let (term, vote) = response.strip_prefix("Term: ")?.split_once(' ')?;
+    let granted = match vote {
+        "granted" => true,
+        "denied" => false,
+        _ => return None,
+    };
+    Some((term.parse().ok()?, granted))
+}
+
+/// Returns when the next election timeout of this node elapses.
+fn next_election_deadline() -> Instant {
+    let jitter = Uuid::new_v4().as_u128() % ELECTION_TIMEOUT.as_millis();
+    Instant::now() + ELECTION_TIMEOUT + Duration::from_millis(jitter as u64)
+}
+
+async fn read_line<R>(reader: &mut R) -> IoResult<String>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
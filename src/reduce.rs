@@ -0,0 +1,249 @@
+//! Dimensionality reduction of the vectors of a collection.
+//!
+//! A projection maps vectors to vectors with fewer components, cutting the memory
+//! they take and the time spent comparing them, at the cost of some accuracy.
+
+use serde::{Deserialize, Serialize};
+
+/// The number of vectors of a collection, drawn at random, that PCA is fitted on.
+pub const PCA_SAMPLE_SIZE: usize = 10_000;
+
+/// The number of rounds of subspace iteration run to find the principal components.
+const PCA_ITERATIONS: usize = 30;
+
+/// The ways a projection can be computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReductionKind {
+    /// Principal component analysis: projects onto the directions along which the
+    /// vectors of the collection vary the most.
+    Pca,
+    /// Random projection: projects onto random directions, which approximately
+    /// preserves distances without looking at the vectors.
+    Random,
+}
+
+impl ReductionKind {
+    /// Returns the kind of reduction named `name`, e.g. `"pca"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pca" => Some(ReductionKind::Pca),
+            "random" => Some(ReductionKind::Random),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the kind of reduction.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReductionKind::Pca => "pca",
+            ReductionKind::Random => "random",
+        }
+    }
+}
+
+/// A linear map from vectors of `input_dim` components to vectors of `output_dim`
+/// components.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Projection {
+    kind: ReductionKind,
+    // Subtracted from vectors before they are projected, empty if nothing is
+    mean: Vec<f32>,
+    // One row of `input_dim` components per output component
+    components: Vec<Vec<f32>>,
+}
+
+impl Projection {
+    /// Fits a projection onto the `target_dim` principal components of `vectors`.
+    /// Vectors whose dimension differs from the first one are ignored.
+    ///
+    /// The principal components are found by subspace iteration over the covariance
+    /// matrix, which takes time proportional to the number of vectors times the
+    /// square of their dimension.
+    ///
+    /// Returns `None` if there are no vectors, or if `target_dim` is 0 or not smaller
+    /// than their dimension.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::reduce::Projection;
+    ///
+    /// // Points along a line in 3 dimensions
+    /// let vectors = (0..10)
+    ///     .map(|i| vec![i as f32, 2.0 * i as f32, 1.0])
+    ///     .collect::<Vec<_>>();
+    /// let vectors = vectors.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    ///
+    /// let projection = Projection::pca(&vectors, 1).unwrap();
+    /// let (a, b) = (projection.project(vectors[0]), projection.project(vectors[9]));
+    /// // Distances along the line are preserved
+    /// assert!(((a[0] - b[0]).abs() - 9.0 * 5f32.sqrt()).abs() < 1e-3);
+    /// ```
+    pub fn pca(vectors: &[&[f32]], target_dim: usize) -> Option<Self> {
+        let dim = vectors.first()?.len();
+        if target_dim == 0 || target_dim >= dim {
+            return None;
+        }
+        let vectors = vectors.iter().filter(|vector| vector.len() == dim);
+
+        let mut mean = vec![0f64; dim];
+        let mut count = 0;
+        for vector in vectors.clone() {
+            count += 1;
+            for (mean, &value) in mean.iter_mut().zip(vector.iter()) {
+                *mean += value as f64;
+            }
+        }
+        for mean in &mut mean {
+            *mean /= count as f64;
+        }
+
+        // Only the upper triangle is accumulated, then mirrored
+        let mut covariance = vec![0f64; dim * dim];
+        let mut centered = vec![0f64; dim];
+        for vector in vectors {
+            for ((centered, &value), mean) in centered.iter_mut().zip(vector.iter()).zip(&mean) {
+                *centered = value as f64 - mean;
+            }
+            for i in 0..dim {
+                let row = &mut covariance[i * dim..(i + 1) * dim];
+                for j in i..dim {
+                    row[j] += centered[i] * centered[j];
+                }
+            }
+        }
+        for i in 0..dim {
+            for j in 0..i {
+                covariance[i * dim + j] = covariance[j * dim + i];
+            }
+        }
+
+        // Subspace iteration: repeatedly multiplying a basis by the covariance matrix
+        // and orthonormalizing it converges to its leading eigenvectors, in order
+        let mut state = 0x5eed;
+        let mut basis = (0..target_dim)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| uniform(&mut state) - 0.5)
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<Vec<f64>>>();
+        orthonormalize(&mut basis);
+        for _ in 0..PCA_ITERATIONS {
+            for vector in &mut basis {
+                let product = covariance
+                    .chunks_exact(dim)
+                    .map(|row| row.iter().zip(vector.iter()).map(|(a, b)| a * b).sum())
+                    .collect::<Vec<f64>>();
+                *vector = product;
+            }
+            orthonormalize(&mut basis);
+        }
+
+        Some(Self {
+            kind: ReductionKind::Pca,
+            mean: mean.into_iter().map(|mean| mean as f32).collect(),
+            components: basis
+                .into_iter()
+                .map(|vector| vector.into_iter().map(|value| value as f32).collect())
+                .collect(),
+        })
+    }
+
+    /// Creates a projection of vectors of `input_dim` components onto `target_dim`
+    /// random directions drawn from `seed`, with components of `±1/sqrt(target_dim)`
+    /// so distances are preserved on average.
+    ///
+    /// Returns `None` if `target_dim` is 0 or not smaller than `input_dim`.
+    pub fn random(input_dim: usize, target_dim: usize, seed: u64) -> Option<Self> {
+        if target_dim == 0 || target_dim >= input_dim {
+            return None;
+        }
+        let scale = 1.0 / (target_dim as f32).sqrt();
+        let mut state = seed;
+        let components = (0..target_dim)
+            .map(|_| {
+                (0..input_dim)
+                    .map(|_| match splitmix64(&mut state) & 1 {
+                        0 => scale,
+                        _ => -scale,
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(Self {
+            kind: ReductionKind::Random,
+            mean: Vec::new(),
+            components,
+        })
+    }
+
+    /// Returns how the projection was computed.
+    pub fn kind(&self) -> ReductionKind {
+        self.kind
+    }
+
+    /// Returns the number of components of the vectors the projection applies to.
+    pub fn input_dim(&self) -> usize {
+        self.components.first().map_or(0, Vec::len)
+    }
+
+    /// Returns the number of components of projected vectors.
+    pub fn output_dim(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Projects `vector`, which must have `input_dim` components.
+    pub fn project(&self, vector: &[f32]) -> Vec<f32> {
+        let centered = match self.mean.is_empty() {
+            true => vector.to_vec(),
+            false => vector
+                .iter()
+                .zip(&self.mean)
+                .map(|(value, mean)| value - mean)
+                .collect(),
+        };
+        self.components
+            .iter()
+            .map(|component| crate::metric::dot_product(component, &centered))
+            .collect()
+    }
+}
+
+/// Makes `basis` orthonormal with the modified Gram-Schmidt process. Vectors that
+/// are linearly dependent on the previous ones are replaced by zeros.
+fn orthonormalize(basis: &mut [Vec<f64>]) {
+    for i in 0..basis.len() {
+        let (previous, rest) = basis.split_at_mut(i);
+        let vector = &mut rest[0];
+        for other in previous.iter() {
+            let dot = vector.iter().zip(other).map(|(a, b)| a * b).sum::<f64>();
+            for (value, other) in vector.iter_mut().zip(other) {
+                *value -= dot * other;
+            }
+        }
+        let norm = vector.iter().map(|value| value * value).sum::<f64>().sqrt();
+        for value in vector.iter_mut() {
+            *value = if norm > f64::EPSILON {
+                *value / norm
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/// Returns a number drawn uniformly from `[0, 1)`.
+fn uniform(state: &mut u64) -> f64 {
+    (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Advances the splitmix64 generator whose state is `state` and returns its next value.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
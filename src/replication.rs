@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::commands::{self, Command};
+use crate::quote::quote;
+use crate::raft::Raft;
+use crate::Vemcache;
+
+/// How long a replica waits between polls of the primary's change log when it is caught up.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a follower of a Raft leader waits between polls when it is caught up,
+/// shorter since the leader waits for it to acknowledge writes.
+const FOLLOWER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a replica waits before reconnecting after losing its primary.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks whether this server is replicating from a primary.
+///
+/// While a primary is configured, a background task keeps the local database in
+/// sync with it and the server rejects write commands from clients.
+#[derive(Default)]
+pub struct Replication {
+    primary: Option<String>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Replication {
+    /// Returns `true` if this server is currently a replica.
+    pub fn is_replica(&self) -> bool {
+        self.primary.is_some()
+    }
+
+    /// Starts replicating from `primary` (a `host:port` address), authenticating
+    /// with `token` if given, replacing any previous replication task.
+    pub fn replicate_from(
+        &mut self,
+        primary: String,
+        token: Option<String>,
+        db: Arc<Mutex<Vemcache>>,
+    ) {
+        self.stop();
+        println!("Replicating from primary {}", primary);
+        self.task = Some(tokio::spawn(follow(primary.clone(), token, db, None)));
+        self.primary = Some(primary);
+    }
+
+    /// Starts following `leader`, the leader of `term` in the group of `raft`,
+    /// authenticating with `token` if given, replacing any previous replication
+    /// task. Unlike `replicate_from`, the leader is told how far this node has
+    /// caught up, and `raft` the position of the changes it holds.
+    pub fn follow_leader(
+        &mut self,
+        leader: String,
+        term: u64,
+        raft: Arc<Raft>,
+        token: Option<String>,
+        db: Arc<Mutex<Vemcache>>,
+    ) {
+        self.stop();
+        let follower = Follower { raft, term };
+        self.task = Some(tokio::spawn(follow(
+            leader.clone(),
+            token,
+            db,
+            Some(follower),
+        )));
+        self.primary = Some(leader);
+    }
+
+    /// Stops replicating and turns this server back into a primary.
+    /// The data received so far is kept.
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        if let Some(primary) = self.primary.take() {
+            println!("Stopped replicating from primary {}", primary);
+        }
+    }
+}
+
+/// A replica following the leader of a Raft group. See the `raft` module.
+struct Follower {
+    raft: Arc<Raft>,
+    /// The term of the leader.
+    term: u64,
+}
+
+/// Keeps the local database in sync with `primary`, reconnecting whenever the
+/// connection is lost.
+async fn follow(
+    primary: String,
+    token: Option<String>,
+    db: Arc<Mutex<Vemcache>>,
+    follower: Option<Follower>,
+) {
+    loop {
+        if let Err(err) = sync_with(&primary, token.as_deref(), &db, follower.as_ref()).await {
+            println!("Lost connection to primary {}: {}", primary, err);
+        }
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+/// Loads a full snapshot from the primary, then streams its change log into the
+/// local database until the connection fails. A `follower` reports the sequence
+/// number it reached to the leader with every poll, and to its `raft`.
+async fn sync_with(
+    primary: &str,
+    token: Option<&str>,
+    db: &Mutex<Vemcache>,
+    follower: Option<&Follower>,
+) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(primary).await?;
+    let (reader, mut writer) = stream.split();
+    let mut reader = BufReader::new(reader);
+
+    if let Some(token) = token {
+        writer
+            .write_all(format!("auth {}\n", quote(token)).as_bytes())
+            .await?;
+        let response = read_response_line(&mut reader).await?;
+        if response != "OK" {
+            return Err(invalid_data(format!("authentication failed: {}", response)));
+        }
+    }
+
+    let mut seq = full_sync(&mut reader, &mut writer, db).await?;
+    if let Some(follower) = follower {
+        follower.raft.synced(follower.term, seq);
+    }
+    loop {
+        let poll = match follower {
+            Some(follower) => format!(
+                "raft changes {} {} {}\n",
+                follower.term,
+                quote(follower.raft.this_node()),
+                seq
+            ),
+            None => format!("changes {}\n", seq),
+        };
+        writer.write_all(poll.as_bytes()).await?;
+        let header = read_response_line(&mut reader).await?;
+        let count = match header.strip_prefix("Changes: ") {
+            Some(count) => count.parse::<usize>().map_err(invalid_data)?,
+            None => {
+                // The primary no longer has the changes we need, start over from a snapshot.
+                println!("Resynchronizing with primary {}: {}", primary, header);
+                seq = full_sync(&mut reader, &mut writer, db).await?;
+                if let Some(follower) = follower {
+                    follower.raft.synced(follower.term, seq);
+                }
+                continue;
+            }
+        };
+
+        let mut changes = Vec::with_capacity(count);
+        for _ in 0..count {
+            changes.push(read_response_line(&mut reader).await?);
+        }
+        if changes.is_empty() {
+            let interval = match follower {
+                Some(_) => FOLLOWER_POLL_INTERVAL,
+                None => POLL_INTERVAL,
+            };
+            tokio::time::sleep(interval).await;
+            continue;
+        }
+
+        let mut db = db.lock().await;
+        for change in changes {
+            let (change_seq, command) = change
+                .split_once(' ')
+                .ok_or_else(|| invalid_data("malformed change"))?;
+            seq = change_seq.parse::<u64>().map_err(invalid_data)?;
+            match commands::parse_command(command) {
+                Ok(Command::NamedInsert(key, values)) => db.insert_raw(key, values),
+                Ok(Command::Remove(key)) => {
+                    db.remove(key);
+                }
+                _ => return Err(invalid_data(format!("unexpected change: {}", command))),
+            }
+        }
+        if let Some(follower) = follower {
+            follower.raft.synced(follower.term, seq);
+        }
+    }
+}
+
+/// Replaces the local database with a snapshot from the primary and returns the
+/// sequence number the snapshot corresponds to.
+async fn full_sync<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    db: &Mutex<Vemcache>,
+) -> std::io::Result<u64>
+where
+    R: AsyncBufReadExt + Unpin,
+    W: AsyncWriteExt + Unpin,
+{
+    writer.write_all(b"sync\n").await?;
+    let header = read_response_line(reader).await?;
+    let seq = header
+        .strip_prefix("Sync: ")
+        .ok_or_else(|| invalid_data(format!("unexpected sync response: {}", header)))?
+        .parse::<u64>()
+        .map_err(invalid_data)?;
+    let snapshot = read_response_line(reader).await?;
+    db.lock()
+        .await
+        .restore_snapshot(&snapshot)
+        .map_err(invalid_data)?;
+    println!("Synchronized with primary at sequence {}", seq);
+    Ok(seq)
+}
+
+async fn read_response_line<R>(reader: &mut R) -> std::io::Result<String>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn invalid_data<E>(err: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
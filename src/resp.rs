@@ -0,0 +1,153 @@
+//! The Redis serialization protocol (RESP2), spoken by `redis-cli` and Redis client
+//! libraries.
+//!
+//! Connections start with the text protocol, in which responses are lines of text.
+//! A connection switches to RESP when it sends a request framed as a RESP array of
+//! bulk strings, as `redis-cli` does, or when it asks for it with `hello 2`. Every
+//! response is then a single RESP frame, whose first byte tells its type:
+//!
+//! - a response of one line is a simple string (`+OK`), or an error (`-ERR ...`)
+//!   if it is an error;
+//! - a response of several lines is an array holding one bulk string per line;
+//! - a response that is not text, such as an Arrow stream, is a bulk string.
+//!
+//! The commands themselves are the same in both protocols.
+
+use crate::limits::{self, Line};
+use crate::quote::quote;
+use std::io::{Error, ErrorKind, Result as IoResult};
+use tokio::io::{AsyncBufRead, AsyncReadExt};
+
+/// The version of the text protocol, as negotiated with `hello`.
+pub const PROTOCOL_TEXT: u8 = 1;
+
+/// The version of RESP2, as negotiated with `hello`.
+pub const PROTOCOL_RESP: u8 = 2;
+
+/// The maximum number of arguments of a request.
+const MAX_ARGUMENTS: usize = 1024 * 1024;
+
+/// The maximum length of the header of a bulk string, e.g. `$5`.
+const MAX_HEADER_LENGTH: usize = 32;
+
+/// Reads the arguments of a request framed as a RESP array, whose header `*<count>`
+/// is in `header`, and returns them as a command line.
+///
+/// Returns an error message if the request is malformed or an argument is longer
+/// than `max_length` bytes, in which case the connection is out of sync and must
+/// be closed.
+///
+/// # Errors
+///
+/// Returns an error if the request cannot be read from `reader`.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::resp;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let mut request = &b"$3\r\nget\r\n$6\r\nmy key\r\n"[..];
+/// let line = resp::read_request(&mut request, "*2\r\n", 0).await.unwrap();
+/// assert_eq!(line, Ok("get \"my key\"".to_string()));
+/// # })
+/// ```
+pub async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    header: &str,
+    max_length: usize,
+) -> IoResult<Result<String, &'static str>> {
+    let Some(count) = header
+        .strip_prefix('*')
+        .and_then(|count| count.trim_end().parse::<usize>().ok())
+        .filter(|count| *count <= MAX_ARGUMENTS)
+    else {
+        return Ok(Err("Protocol error: invalid multibulk length"));
+    };
+    let mut arguments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut header = String::new();
+        match limits::read_line(reader, &mut header, MAX_HEADER_LENGTH).await? {
+            Line::Read => {}
+            Line::Closed => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            Line::TooLong | Line::Invalid => {
+                return Ok(Err("Protocol error: invalid bulk length"));
+            }
+        }
+        let Some(length) = header
+            .strip_prefix('$')
+            .and_then(|length| length.trim_end().parse::<usize>().ok())
+        else {
+            return Ok(Err("Protocol error: invalid bulk length"));
+        };
+        if max_length > 0 && length > max_length {
+            return Ok(Err("Protocol error: argument too long"));
+        }
+        let mut bytes = vec![0; length + 2];
+        reader.read_exact(&mut bytes).await?;
+        if !bytes.ends_with(b"\r\n") {
+            return Ok(Err("Protocol error: bulk string not terminated"));
+        }
+        bytes.truncate(length);
+        let Ok(argument) = String::from_utf8(bytes) else {
+            return Ok(Err("Protocol error: arguments must be valid UTF-8"));
+        };
+        arguments.push(quote(&argument).into_owned());
+    }
+    Ok(Ok(arguments.join(" ")))
+}
+
+/// Frames a complete response of the text protocol as a RESP frame.
+///
+/// Error responses (`Error: <message>`) whose message starts with an upper-case
+/// code, such as `MOVED` or `READONLY`, keep it as the code of the RESP error.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::resp;
+///
+/// assert_eq!(resp::encode(b"OK\n"), b"+OK\r\n");
+/// assert_eq!(resp::encode(b"Error: Key not found\n"), b"-ERR Key not found\r\n");
+/// assert_eq!(resp::encode(b"Error: MOVED 10.0.0.2:7070\n"), b"-MOVED 10.0.0.2:7070\r\n");
+/// assert_eq!(
+///     resp::encode(b"Keys: 1\nkey\n"),
+///     b"*2\r\n$7\r\nKeys: 1\r\n$3\r\nkey\r\n",
+/// );
+/// ```
+pub fn encode(response: &[u8]) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(response) else {
+        return bulk_string(response);
+    };
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    if text.contains('\n') {
+        let lines = text.split('\n').collect::<Vec<&str>>();
+        let mut frame = format!("*{}\r\n", lines.len()).into_bytes();
+        for line in lines {
+            frame.extend(bulk_string(line.as_bytes()));
+        }
+        return frame;
+    }
+    if text.contains('\r') {
+        return bulk_string(text.as_bytes());
+    }
+    match text.strip_prefix("Error: ") {
+        Some(message) => {
+            let code = message.split(' ').next().unwrap_or_default();
+            let coded = code.len() > 1 && code.chars().all(|c| c.is_ascii_uppercase());
+            match coded {
+                true => format!("-{}\r\n", message).into_bytes(),
+                false => format!("-ERR {}\r\n", message).into_bytes(),
+            }
+        }
+        None => format!("+{}\r\n", text).into_bytes(),
+    }
+}
+
+/// Frames `bytes` as a RESP bulk string.
+pub fn bulk_string(bytes: &[u8]) -> Vec<u8> {
+    let mut frame = format!("${}\r\n", bytes.len()).into_bytes();
+    frame.extend_from_slice(bytes);
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
@@ -0,0 +1,663 @@
+//! A subset of the Qdrant HTTP API, so clients written for Qdrant, such as the
+//! LangChain and LlamaIndex integrations, can use Vemcache without a connector of
+//! their own.
+//!
+//! A Qdrant collection `name` is the collection of database 0 whose keys start with
+//! `name:`, indexed with HNSW and the distance of the collection, and its point `id`
+//! is the vector stored under `name:id`. Indexed collections created with
+//! `index create` are listed as well. Payloads are kept in memory, so unlike the
+//! vectors, they are neither persisted nor replicated.
+//!
+//! Once `acl_tokens` is configured, requests must carry a token in the `api-key`
+//! header, as Qdrant clients send it, or as a bearer token. Requests changing the
+//! database require the writer role, others the reader role.
+//!
+//! Requests changing the database are rejected on a replica, and in a cluster, on
+//! points owned by another node, with `421 Misdirected Request` and the address of
+//! the owner in a `MOVED <address>` error. In Raft mode, they are rejected the same
+//! way on the followers, naming the leader, and the leader only answers them once a
+//! majority of the nodes hold the changes, or with `503 Service Unavailable` if
+//! they do not in time.
+//!
+//! | Method   | Path                                  | Description                     |
+//! |----------|---------------------------------------|---------------------------------|
+//! | `GET`    | `/collections`                        | Lists the collections           |
+//! | `GET`    | `/collections/{name}`                 | Describes a collection          |
+//! | `GET`    | `/collections/{name}/exists`          | Tells whether a collection exists |
+//! | `PUT`    | `/collections/{name}`                 | Creates a collection            |
+//! | `DELETE` | `/collections/{name}`                 | Deletes a collection and its points |
+//! | `PUT`    | `/collections/{name}/points`          | Inserts or replaces points      |
+//! | `POST`   | `/collections/{name}/points`          | Retrieves points by id          |
+//! | `POST`   | `/collections/{name}/points/delete`   | Deletes points by id            |
+//! | `POST`   | `/collections/{name}/points/search`   | Finds the nearest points        |
+//! | `POST`   | `/collections/{name}/points/query`    | Finds the nearest points        |
+
+use crate::acl::{self, Role};
+use crate::audit::AuditLog;
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::index::IndexKind;
+use crate::memory;
+use crate::metric::{self, Metric};
+use crate::raft::Raft;
+use crate::replication::Replication;
+use crate::Vemcache;
+use axum::extract::{ConnectInfo, Path, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+/// The number of keys read at a time when looking for the keys of a collection.
+const SCAN_BATCH_SIZE: usize = 1000;
+
+/// The state shared by the requests.
+struct RestState {
+    db: Arc<Mutex<Vemcache>>,
+    config: Arc<RwLock<Config>>,
+    audit: Option<Arc<AuditLog>>,
+    replication: Arc<std::sync::Mutex<Replication>>,
+    cluster: Option<Arc<Cluster>>,
+    raft: Option<Arc<Raft>>,
+    // The payloads of the points, by key
+    payloads: std::sync::Mutex<HashMap<String, Map<String, Value>>>,
+}
+
+type RestResult = Result<Response, RestError>;
+
+/// An error answered with `status` and a Qdrant error body.
+struct RestError(StatusCode, String);
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        let body = json!({ "status": { "error": self.1 }, "time": 0.0 });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> RestError {
+    RestError(StatusCode::BAD_REQUEST, message.into())
+}
+
+fn not_found(name: &str) -> RestError {
+    RestError(
+        StatusCode::NOT_FOUND,
+        format!("Not found: Collection `{}` doesn't exist!", name),
+    )
+}
+
+/// Wraps `result` in a Qdrant response body.
+fn ok(result: Value, started: Instant) -> RestResult {
+    let time = started.elapsed().as_secs_f64();
+    Ok(Json(json!({ "result": result, "status": "ok", "time": time })).into_response())
+}
+
+/// Serves the API on `listener`, operating on `db`, until the server stops.
+/// The requests changing the database are recorded in `audit`, if given, and
+/// rejected while `replication` follows a primary or for points `cluster` places
+/// on another node. In the Raft group of `raft`, they are only accepted by the leader.
+pub async fn serve(
+    listener: TcpListener,
+    db: Arc<Mutex<Vemcache>>,
+    config: Arc<RwLock<Config>>,
+    audit: Option<Arc<AuditLog>>,
+    replication: Arc<std::sync::Mutex<Replication>>,
+    cluster: Option<Arc<Cluster>>,
+    raft: Option<Arc<Raft>>,
+) {
+    let state = Arc::new(RestState {
+        db,
+        config,
+        audit,
+        replication,
+        cluster,
+        raft,
+        payloads: std::sync::Mutex::new(HashMap::new()),
+    });
+    let app = Router::new()
+        .route("/collections", get(list_collections))
+        .route(
+            "/collections/{name}",
+            get(get_collection)
+                .put(create_collection)
+                .delete(delete_collection),
+        )
+        .route("/collections/{name}/exists", get(collection_exists))
+        .route(
+            "/collections/{name}/points",
+            post(retrieve_points).put(upsert_points),
+        )
+        .route("/collections/{name}/points/delete", post(delete_points))
+        .route("/collections/{name}/points/search", post(search_points))
+        .route("/collections/{name}/points/query", post(query_points))
+        .layer(middleware::from_fn_with_state(Arc::clone(&state), guard))
+        .with_state(state);
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    if let Err(err) = axum::serve(listener, app).await {
+        println!("Error serving the REST API: {}", err);
+    }
+}
+
+/// Checks that the request carries a token granting the role it requires, if tokens
+/// are configured, and records the requests changing the database in the audit log,
+/// if there is one, before running them.
+async fn guard(
+    State(state): State<Arc<RestState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let changes = request.method() == Method::PUT
+        || request.method() == Method::DELETE
+        || path.ends_with("/points/delete");
+    let role = {
+        let config = state.config.read().unwrap();
+        match config.acl_tokens.is_empty() {
+            true => Some(Role::Admin),
+            false => request_token(&request)
+                .and_then(|token| acl::authenticate(&config.acl_tokens, token)),
+        }
+    };
+    let required = match changes {
+        true => Role::Writer,
+        false => Role::Reader,
+    };
+    if let Err(message) = acl::authorize(role, Some(required)) {
+        let status = match role {
+            None => StatusCode::UNAUTHORIZED,
+            Some(_) => StatusCode::FORBIDDEN,
+        };
+        return RestError(status, message).into_response();
+    }
+    if let (Some(audit), true) = (&state.audit, changes) {
+        audit.record(client, 0, &format!("{} {}", request.method(), path));
+    }
+    next.run(request).await
+}
+
+/// Returns the token sent with `request`, if any.
+fn request_token(request: &Request) -> Option<&str> {
+    let headers = request.headers();
+    if let Some(token) = headers.get("api-key") {
+        return token.to_str().ok();
+    }
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Returns the key prefix of the collection `name`.
+fn prefix(name: &str) -> String {
+    format!("{}:", name)
+}
+
+/// Returns up to `count` keys of the collection `name`, in order.
+fn collection_keys(db: &Vemcache, name: &str, count: usize) -> Vec<String> {
+    let prefix = prefix(name);
+    // Keys are scanned in order from the name, so the keys of the collection come
+    // after those of the collections whose name starts with it, such as `name-2:`
+    let mut keys = Vec::new();
+    let mut after = name.to_string();
+    while keys.len() < count {
+        let batch = db.scan_keys(Some(&after), SCAN_BATCH_SIZE);
+        let Some(last) = batch.last().cloned() else {
+            break;
+        };
+        let complete = batch.len() < SCAN_BATCH_SIZE;
+        keys.extend(batch.into_iter().filter(|key| key.starts_with(&prefix)));
+        if complete || (last > prefix && !last.starts_with(&prefix)) {
+            break;
+        }
+        after = last;
+    }
+    keys.truncate(count);
+    keys
+}
+
+/// Returns the dimension of the vectors of the collection `name`, that of its first
+/// point, or `None` if it is empty.
+fn collection_dimension(db: &Vemcache, name: &str) -> Option<usize> {
+    collection_keys(db, name, 1)
+        .first()
+        .and_then(|key| db.peek(key))
+        .map(|vector| vector.len())
+}
+
+/// Returns the metric of the collection `name`, if it exists.
+fn collection_metric(db: &Vemcache, name: &str) -> Option<Arc<dyn Metric>> {
+    let prefix = prefix(name);
+    let (_, index) = db.indexes().into_iter().find(|(p, _)| *p == prefix)?;
+    db.metric(index.metric().name())
+}
+
+/// Returns the Qdrant name of a metric.
+fn distance_name(metric: &str) -> &str {
+    match metric {
+        "cosine" => "Cosine",
+        "dot" => "Dot",
+        "euclidean" => "Euclid",
+        "l1" => "Manhattan",
+        other => other,
+    }
+}
+
+/// Returns the score Qdrant gives to a vector at `distance` from the query, which
+/// is the similarity for the metrics ranking by similarity.
+fn score(metric: &str, distance: f32) -> f32 {
+    match metric {
+        "cosine" => 1.0 - distance,
+        "dot" => -distance,
+        _ => distance,
+    }
+}
+
+/// Returns the key of the point `id` of the collection `name`.
+fn point_key(name: &str, id: &Value) -> Result<String, RestError> {
+    match id {
+        Value::Number(id) if id.is_u64() => Ok(format!("{}:{}", name, id)),
+        Value::String(id) if !id.is_empty() => Ok(format!("{}:{}", name, id)),
+        _ => Err(bad_request(format!("Invalid point id: {}", id))),
+    }
+}
+
+/// Returns the id of the point stored under `key` in the collection `name`.
+fn point_id(name: &str, key: &str) -> Value {
+    let id = &key[name.len() + 1..];
+    match id.parse::<u64>() {
+        Ok(id) => json!(id),
+        Err(_) => json!(id),
+    }
+}
+
+fn check_writable(state: &RestState) -> Result<(), RestError> {
+    if state.config.read().unwrap().read_only {
+        return Err(RestError(
+            StatusCode::FORBIDDEN,
+            "The server is read-only".to_string(),
+        ));
+    }
+    if let Some(raft) = &state.raft {
+        raft.check_leader()
+            .map_err(|message| RestError(StatusCode::MISDIRECTED_REQUEST, message))?;
+    }
+    if state.replication.lock().unwrap().is_replica() {
+        return Err(RestError(
+            StatusCode::FORBIDDEN,
+            "You can't write against a read only replica".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects requests changing the points `ids` of the collection `name` if one of
+/// them is owned by another node of the cluster.
+fn check_owned(state: &RestState, name: &str, ids: &[&Value]) -> Result<(), RestError> {
+    let Some(cluster) = &state.cluster else {
+        return Ok(());
+    };
+    for id in ids {
+        if let Some(owner) = cluster.redirect(&point_key(name, id)?) {
+            return Err(RestError(
+                StatusCode::MISDIRECTED_REQUEST,
+                format!("MOVED {}", owner),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Waits until a majority of the Raft group holds the changes made so far, in Raft
+/// mode, so they survive the failure of the leader.
+async fn check_committed(state: &RestState) -> Result<(), RestError> {
+    let Some(raft) = &state.raft else {
+        return Ok(());
+    };
+    let seq = state.db.lock().await.last_seq();
+    if !raft.wait_committed(seq).await {
+        return Err(RestError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "A majority of the raft nodes did not acknowledge the changes".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects requests adding data once the memory used reaches `max_memory`.
+fn check_memory(state: &RestState) -> Result<(), RestError> {
+    if memory::is_over_limit(state.config.read().unwrap().max_memory) {
+        return Err(RestError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Out of memory, the memory used is above max_memory".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn list_collections(State(state): State<Arc<RestState>>) -> RestResult {
+    let started = Instant::now();
+    let db = state.db.lock().await;
+    let collections = db
+        .indexes()
+        .into_iter()
+        .filter_map(|(prefix, _)| prefix.strip_suffix(':'))
+        .map(|name| json!({ "name": name }))
+        .collect::<Vec<Value>>();
+    ok(json!({ "collections": collections }), started)
+}
+
+async fn get_collection(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+) -> RestResult {
+    let started = Instant::now();
+    let db = state.db.lock().await;
+    let prefix = prefix(&name);
+    let (_, index) = db
+        .indexes()
+        .into_iter()
+        .find(|(p, _)| *p == prefix)
+        .ok_or_else(|| not_found(&name))?;
+    let size = collection_dimension(&db, &name).unwrap_or(0);
+    let result = json!({
+        "status": "green",
+        "points_count": index.len(),
+        "config": {
+            "params": {
+                "vectors": { "size": size, "distance": distance_name(index.metric().name()) }
+            }
+        }
+    });
+    ok(result, started)
+}
+
+async fn collection_exists(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+) -> RestResult {
+    let started = Instant::now();
+    let db = state.db.lock().await;
+    let exists = collection_metric(&db, &name).is_some();
+    ok(json!({ "exists": exists }), started)
+}
+
+#[derive(Deserialize)]
+struct CreateCollection {
+    vectors: VectorParams,
+}
+
+#[derive(Deserialize)]
+struct VectorParams {
+    distance: String,
+}
+
+async fn create_collection(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<CreateCollection>,
+) -> RestResult {
+    let started = Instant::now();
+    check_writable(&state)?;
+    check_memory(&state)?;
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(bad_request(format!("Invalid collection name: {}", name)));
+    }
+    let metric = match request.vectors.distance.as_str() {
+        "Cosine" => "cosine",
+        "Dot" => "dot",
+        "Euclid" => "euclidean",
+        "Manhattan" => "l1",
+        distance => return Err(bad_request(format!("Unsupported distance: {}", distance))),
+    };
+    let mut db = state.db.lock().await;
+    if collection_metric(&db, &name).is_some() {
+        return Err(bad_request(format!(
+            "Wrong input: Collection `{}` already exists!",
+            name
+        )));
+    }
+    let metric = metric::builtin_metric(metric).unwrap();
+    db.create_index(prefix(&name), IndexKind::Hnsw.build(metric));
+    ok(json!(true), started)
+}
+
+async fn delete_collection(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+) -> RestResult {
+    let started = Instant::now();
+    check_writable(&state)?;
+    // The points of the collection are spread over the nodes of the cluster
+    if state.cluster.is_some() {
+        return Err(bad_request(
+            "Deleting a collection is not supported in cluster mode, since other nodes may own its points",
+        ));
+    }
+    let prefix = prefix(&name);
+    let mut db = state.db.lock().await;
+    let existed = db.drop_index(&prefix);
+    loop {
+        let keys = collection_keys(&db, &name, SCAN_BATCH_SIZE);
+        if keys.is_empty() {
+            break;
+        }
+        for key in keys {
+            db.remove(key);
+        }
+    }
+    drop(db);
+    state
+        .payloads
+        .lock()
+        .unwrap()
+        .retain(|key, _| !key.starts_with(&prefix));
+    check_committed(&state).await?;
+    ok(json!(existed), started)
+}
+
+#[derive(Deserialize)]
+struct UpsertPoints {
+    points: Vec<Point>,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    id: Value,
+    vector: Vec<f32>,
+    #[serde(default)]
+    payload: Option<Map<String, Value>>,
+}
+
+async fn upsert_points(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<UpsertPoints>,
+) -> RestResult {
+    let started = Instant::now();
+    check_writable(&state)?;
+    check_memory(&state)?;
+    let ids = request
+        .points
+        .iter()
+        .map(|point| &point.id)
+        .collect::<Vec<&Value>>();
+    check_owned(&state, &name, &ids)?;
+    let mut db = state.db.lock().await;
+    collection_metric(&db, &name).ok_or_else(|| not_found(&name))?;
+    // Every point is checked first, so an invalid one leaves the collection untouched.
+    // Vectors are projected when inserted, so projected collections hold vectors of
+    // another dimension
+    let projected = db.projections().any(|(other, _)| other == prefix(&name));
+    let mut dimension = collection_dimension(&db, &name).filter(|_| !projected);
+    for point in &request.points {
+        point_key(&name, &point.id)?;
+        if point.vector.is_empty() || !point.vector.iter().all(|value| value.is_finite()) {
+            return Err(bad_request(format!("Invalid vector of point {}", point.id)));
+        }
+        let expected = *dimension.get_or_insert(point.vector.len());
+        if point.vector.len() != expected {
+            return Err(bad_request(format!(
+                "Wrong input: Vector dimension error: expected dim: {}, got {}",
+                expected,
+                point.vector.len()
+            )));
+        }
+    }
+    for point in request.points {
+        let key = point_key(&name, &point.id)?;
+        db.try_insert_with_key(key.clone(), point.vector)
+            .map_err(|err| bad_request(format!("Vector rejected: {}", err)))?;
+        let mut payloads = state.payloads.lock().unwrap();
+        match point.payload {
+            Some(payload) => payloads.insert(key, payload),
+            None => payloads.remove(&key),
+        };
+    }
+    drop(db);
+    check_committed(&state).await?;
+    ok(json!({ "operation_id": 0, "status": "completed" }), started)
+}
+
+#[derive(Deserialize)]
+struct PointIds {
+    #[serde(alias = "ids")]
+    points: Vec<Value>,
+    #[serde(default)]
+    with_payload: Option<bool>,
+    #[serde(default)]
+    with_vector: Option<bool>,
+}
+
+async fn retrieve_points(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<PointIds>,
+) -> RestResult {
+    let started = Instant::now();
+    let db = state.db.lock().await;
+    collection_metric(&db, &name).ok_or_else(|| not_found(&name))?;
+    let payloads = state.payloads.lock().unwrap();
+    let mut points = Vec::new();
+    for id in &request.points {
+        let key = point_key(&name, id)?;
+        let Some(vector) = db.peek(&key) else {
+            continue;
+        };
+        let mut point = json!({ "id": id });
+        if request.with_payload.unwrap_or(true) {
+            point["payload"] = json!(payloads.get(&key).cloned().unwrap_or_default());
+        }
+        if request.with_vector.unwrap_or(false) {
+            point["vector"] = json!(vector.as_slice());
+        }
+        points.push(point);
+    }
+    ok(json!(points), started)
+}
+
+async fn delete_points(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<PointIds>,
+) -> RestResult {
+    let started = Instant::now();
+    check_writable(&state)?;
+    check_owned(
+        &state,
+        &name,
+        &request.points.iter().collect::<Vec<&Value>>(),
+    )?;
+    let mut db = state.db.lock().await;
+    collection_metric(&db, &name).ok_or_else(|| not_found(&name))?;
+    for id in &request.points {
+        let key = point_key(&name, id)?;
+        db.remove(key.clone());
+        state.payloads.lock().unwrap().remove(&key);
+    }
+    drop(db);
+    check_committed(&state).await?;
+    ok(json!({ "operation_id": 0, "status": "completed" }), started)
+}
+
+#[derive(Deserialize)]
+struct SearchPoints {
+    #[serde(alias = "query")]
+    vector: Vec<f32>,
+    #[serde(alias = "top")]
+    limit: usize,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    with_payload: Option<bool>,
+    #[serde(default)]
+    with_vector: Option<bool>,
+    #[serde(default)]
+    score_threshold: Option<f32>,
+}
+
+async fn search_points(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<SearchPoints>,
+) -> RestResult {
+    let started = Instant::now();
+    let points = search(&state, &name, request).await?;
+    ok(json!(points), started)
+}
+
+async fn query_points(
+    State(state): State<Arc<RestState>>,
+    Path(name): Path<String>,
+    Json(request): Json<SearchPoints>,
+) -> RestResult {
+    let started = Instant::now();
+    let points = search(&state, &name, request).await?;
+    ok(json!({ "points": points }), started)
+}
+
+/// Returns the points of the collection `name` nearest to the query, best first.
+async fn search(
+    state: &RestState,
+    name: &str,
+    request: SearchPoints,
+) -> Result<Vec<Value>, RestError> {
+    let db = state.db.lock().await;
+    let metric = collection_metric(&db, name).ok_or_else(|| not_found(name))?;
+    let prefix = prefix(name);
+    let nearest = request.limit.saturating_add(request.offset);
+    let neighbors = db.search(&prefix, &request.vector, nearest, None);
+    let payloads = state.payloads.lock().unwrap();
+    let mut points = Vec::new();
+    for (key, vector) in neighbors.into_iter().skip(request.offset) {
+        let score = score(metric.name(), metric.distance(&request.vector, &vector));
+        if let Some(threshold) = request.score_threshold {
+            // Scores of similarities must be above the threshold, distances below it
+            let similarity = matches!(metric.name(), "cosine" | "dot");
+            if (similarity && score < threshold) || (!similarity && score > threshold) {
+                continue;
+            }
+        }
+        let mut point = json!({ "id": point_id(name, &key), "version": 0, "score": score });
+        if request.with_payload.unwrap_or(false) {
+            point["payload"] = json!(payloads.get(&key).cloned().unwrap_or_default());
+        }
+        if request.with_vector.unwrap_or(false) {
+            point["vector"] = json!(vector.as_slice());
+        }
+        points.push(point);
+    }
+    Ok(points)
+}
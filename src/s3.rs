@@ -0,0 +1,344 @@
+//! Backups pushed to a bucket of an S3-compatible object storage, such as AWS S3,
+//! MinIO or Cloudflare R2, and restored from it at startup.
+//!
+//! Once written to `backup_dir`, every backup is uploaded to `s3_bucket`, along
+//! with the indexes, projections and pipelines saved with it, under `s3_prefix`
+//! followed by the same relative path, and the backups of the database exceeding
+//! `backup_retention` are removed from the bucket. At startup, a database whose
+//! directory holds no write-ahead log yet, as in a new container, is restored from
+//! the newest backup in the bucket.
+//!
+//! Requests are signed with AWS Signature Version 4 and address the bucket in the
+//! path (`<s3_endpoint>/<s3_bucket>/<key>`), which every S3-compatible storage
+//! accepts.
+
+use crate::backup::{self, SIDECAR_SUFFIXES};
+use crate::config::Config;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The time after which a request to the storage is abandoned, if nothing is sent
+/// or received.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The payload hash of requests whose body is not signed.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// A bucket of an S3-compatible object storage.
+pub struct Bucket {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    agent: ureq::Agent,
+}
+
+impl Bucket {
+    /// Returns the bucket configured with `s3_bucket`, or `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if `s3_endpoint` is not an HTTP or HTTPS URL, or if
+    /// the credentials are missing.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        let Some(bucket) = &config.s3_bucket else {
+            return Ok(None);
+        };
+        let endpoint = config.s3_endpoint.trim_end_matches('/');
+        let host = endpoint
+            .strip_prefix("https://")
+            .or_else(|| endpoint.strip_prefix("http://"))
+            .filter(|host| !host.is_empty() && !host.contains('/'))
+            .ok_or_else(|| format!("Invalid S3 endpoint: {}", config.s3_endpoint))?;
+        let (Some(access_key), Some(secret_key)) = (&config.s3_access_key, &config.s3_secret_key)
+        else {
+            return Err("S3 backups require s3_access_key and s3_secret_key".to_string());
+        };
+        Ok(Some(Self {
+            endpoint: endpoint.to_string(),
+            host: host.to_string(),
+            bucket: bucket.clone(),
+            prefix: config.s3_prefix.clone(),
+            region: config.s3_region.clone(),
+            access_key: access_key.clone(),
+            secret_key: secret_key.clone(),
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(REQUEST_TIMEOUT)
+                .timeout_read(REQUEST_TIMEOUT)
+                .timeout_write(REQUEST_TIMEOUT)
+                .build(),
+        }))
+    }
+
+    /// Returns the prefix of the keys of the backups of the database numbered `index`.
+    fn database_prefix(&self, index: usize) -> String {
+        match index {
+            0 => self.prefix.clone(),
+            index => format!("{}db{}/", self.prefix, index),
+        }
+    }
+
+    /// Uploads the backup at `path` of the database numbered `index`, along with the
+    /// files saved with it, then removes the oldest backups of the database from the
+    /// bucket so that at most `retention` remain, unless `retention` is 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the storage cannot be reached or answers with an
+    /// error.
+    pub fn push_backup(&self, index: usize, path: &Path, retention: usize) -> Result<(), String> {
+        let prefix = self.database_prefix(index);
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("Invalid backup path: {}", path.display()))?;
+        // The backup goes last, so it is never listed without its files
+        for suffix in SIDECAR_SUFFIXES {
+            let sidecar = PathBuf::from(format!("{}{}", path.display(), suffix));
+            if sidecar.exists() {
+                self.upload(&format!("{}{}{}", prefix, name, suffix), &sidecar)?;
+            }
+        }
+        self.upload(&format!("{}{}", prefix, name), path)?;
+        if retention == 0 {
+            return Ok(());
+        }
+        let backups = self.list_backups(&prefix)?;
+        for backup in &backups[..backup::expired(&backups, retention)] {
+            for suffix in SIDECAR_SUFFIXES {
+                self.delete(&format!("{}{}", backup, suffix))?;
+            }
+            self.delete(backup)?;
+        }
+        Ok(())
+    }
+
+    /// Downloads the newest backup of the database numbered `index`, along with the
+    /// files saved with it, into `dir`, and returns its path, or `None` if the
+    /// bucket holds no backup of the database. If it is a delta backup, the full
+    /// backup it was taken against and the earlier deltas of it are downloaded
+    /// first, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the storage cannot be reached or answers with an
+    /// error, or if the backup cannot be written to `dir`.
+    pub fn fetch_newest_backup(&self, index: usize, dir: &Path) -> Result<Option<PathBuf>, String> {
+        let prefix = self.database_prefix(index);
+        let backups = self.list_backups(&prefix)?;
+        let Some(newest) = backups.last() else {
+            return Ok(None);
+        };
+        let base = backup::delta_base(newest).unwrap_or_else(|| newest.clone());
+        // Only the full backup and its deltas sort between it and the newest backup
+        let Some(start) = backups.iter().position(|key| *key == base) else {
+            return Err(format!("The full backup of {} is missing", newest));
+        };
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+        let mut path = None;
+        for key in &backups[start..] {
+            let file = dir.join(&key[prefix.len()..]);
+            for suffix in SIDECAR_SUFFIXES {
+                let sidecar = PathBuf::from(format!("{}{}", file.display(), suffix));
+                self.download(&format!("{}{}", key, suffix), &sidecar)?;
+            }
+            if !self.download(key, &file)? {
+                return Ok(None);
+            }
+            path = Some(file);
+        }
+        Ok(path)
+    }
+
+    /// Returns the keys of the backups directly under `prefix`, oldest first.
+    fn list_backups(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &continuation {
+                query.push(("continuation-token", token));
+            }
+            let response = self
+                .request("GET", "", &query)
+                .call()
+                .map_err(|err| request_error("list", &self.bucket, err))?
+                .into_string()
+                .map_err(|err| format!("Could not list {}: {}", self.bucket, err))?;
+            keys.extend(xml_elements(&response, "Key"));
+            continuation = xml_elements(&response, "NextContinuationToken").pop();
+            if continuation.is_none() {
+                break;
+            }
+        }
+        keys.retain(|key| backup::is_backup_name(&key[prefix.len().min(key.len())..]));
+        // The names hold the time the backups were taken, in an order that sorts
+        keys.sort_by(|a, b| backup::backup_order(a).cmp(&backup::backup_order(b)));
+        Ok(keys)
+    }
+
+    /// Uploads the file at `path` to `key`.
+    fn upload(&self, key: &str, path: &Path) -> Result<(), String> {
+        let file = File::open(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        let length = file
+            .metadata()
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?
+            .len();
+        self.request("PUT", key, &[])
+            .set("Content-Length", &length.to_string())
+            .send(file)
+            .map_err(|err| request_error("upload", key, err))?;
+        Ok(())
+    }
+
+    /// Downloads `key` to the file at `path`, returning `false` if there is no such key.
+    fn download(&self, key: &str, path: &Path) -> Result<bool, String> {
+        let response = match self.request("GET", key, &[]).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(false),
+            Err(err) => return Err(request_error("download", key, err)),
+        };
+        let mut file = File::create(path)
+            .map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+        std::io::copy(&mut response.into_reader(), &mut file)
+            .map_err(|err| format!("Could not download {}: {}", key, err))?;
+        Ok(true)
+    }
+
+    /// Removes `key`, if it exists.
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.request("DELETE", key, &[])
+            .call()
+            .map_err(|err| request_error("remove", key, err))?;
+        Ok(())
+    }
+
+    /// Returns a request of `key`, or of the bucket itself if `key` is empty, signed
+    /// with AWS Signature Version 4.
+    fn request(&self, method: &str, key: &str, query: &[(&str, &str)]) -> ureq::Request {
+        let path = match key {
+            "" => format!("/{}", uri_encode(&self.bucket, false)),
+            key => format!(
+                "/{}/{}",
+                uri_encode(&self.bucket, false),
+                uri_encode(key, true)
+            ),
+        };
+        let mut query = query
+            .iter()
+            .map(|(name, value)| {
+                format!("{}={}", uri_encode(name, false), uri_encode(value, false))
+            })
+            .collect::<Vec<String>>();
+        query.sort();
+        let query = query.join("&");
+
+        let timestamp = backup::utc_timestamp(SystemTime::now());
+        let date = &timestamp[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, query, self.host, UNSIGNED_PAYLOAD, timestamp, UNSIGNED_PAYLOAD
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part);
+        }
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key,
+            scope,
+            hex(&hmac(&signing_key, &string_to_sign))
+        );
+
+        let url = match query.is_empty() {
+            true => format!("{}{}", self.endpoint, path),
+            false => format!("{}{}?{}", self.endpoint, path, query),
+        };
+        self.agent
+            .request(method, &url)
+            .set("x-amz-date", &timestamp)
+            .set("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .set("Authorization", &authorization)
+    }
+}
+
+/// Describes the failure of a request to `action` `key`.
+fn request_error(action: &str, key: &str, err: ureq::Error) -> String {
+    match err {
+        ureq::Error::Status(status, response) => {
+            let reason = response.into_string().unwrap_or_default();
+            let code = xml_elements(&reason, "Code").pop().unwrap_or_default();
+            format!(
+                "Could not {} {}: the storage answered {} {}",
+                action, key, status, code
+            )
+        }
+        ureq::Error::Transport(err) => format!("Could not {} {}: {}", action, key, err),
+    }
+}
+
+/// Computes the HMAC-SHA256 of `message` with `key`.
+fn hmac(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Writes `bytes` in lower-case hexadecimal.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Percent-encodes `value` as required by AWS Signature Version 4, leaving slashes
+/// as they are if `keep_slashes` is set.
+fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slashes => encoded.push('/'),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Returns the text of every `<name>` element of `xml`, in order.
+fn xml_elements(xml: &str, name: &str) -> Vec<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        let text = rest[..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&");
+        elements.push(text);
+        rest = &rest[end + close.len()..];
+    }
+    elements
+}
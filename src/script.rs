@@ -0,0 +1,227 @@
+//! Server-side scripts, run with `eval`.
+//!
+//! Scripts are written in [Rhai](https://rhai.rs) and run against the database
+//! while it is locked, so the steps of a script are not interleaved with the
+//! commands of other clients. They only reach the database through the functions
+//! registered here, and cannot read files or load modules:
+//!
+//! | Function                    | Description                                            |
+//! |-----------------------------|--------------------------------------------------------|
+//! | `get(key)`                  | The vector stored under `key`, or `()` if there is none |
+//! | `insert(key, vector)`       | Inserts `vector` under `key`, failing if it is rejected |
+//! | `remove(key)`               | Removes the vector under `key`, returning whether it existed |
+//! | `knn(key, k)`               | The keys of the `k` nearest neighbors of the vector under `key` |
+//! | `knn(key, vector, k)`       | The keys of the `k` nearest neighbors of `vector` in the collection of `key` |
+//! | `vadd(a, b)`, `vsub(a, b)`  | The element-wise sum or difference of two vectors      |
+//! | `vscale(a, scalar)`         | The vector multiplied by `scalar`                      |
+//! | `vcosine(a, b)`             | The cosine similarity of two vectors                   |
+//! | `vmean(vectors)`            | The element-wise mean of an array of vectors           |
+//!
+//! Vectors are arrays of numbers. The arguments given after the script are
+//! available in the `ARGV` array.
+
+use crate::Vemcache;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+use std::cell::RefCell;
+use std::ops::DerefMut;
+use std::rc::Rc;
+
+/// The maximum number of operations a script may run before it is aborted.
+pub const MAX_SCRIPT_OPERATIONS: u64 = 1_000_000;
+
+/// The maximum number of elements of the arrays a script builds.
+const MAX_ARRAY_SIZE: usize = 1_000_000;
+
+/// The maximum depth of the function calls of a script.
+const MAX_CALL_LEVELS: usize = 32;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Runs `script` against `db` with `args` in its `ARGV` array, and returns the
+/// value of its last statement encoded as JSON.
+///
+/// Changes made by the script before it fails are kept.
+///
+/// # Errors
+///
+/// Returns an error message if the script does not compile, fails, or runs more
+/// than `MAX_SCRIPT_OPERATIONS` operations.
+///
+/// # Example
+///
+/// ```
+/// use vemcache::script;
+/// use vemcache::Vemcache;
+///
+/// let mut db = Vemcache::new();
+/// db.insert_with_key("a".to_string(), vec![1.0, 2.0]);
+/// db.insert_with_key("b".to_string(), vec![3.0, 4.0]);
+///
+/// let script = "let mean = vmean([get(ARGV[0]), get(ARGV[1])]); insert(\"mean\", mean); mean";
+/// let result = script::eval(Box::new(db), script, vec!["a".into(), "b".into()]);
+/// assert_eq!(result.unwrap(), "[2.0,3.0]");
+/// ```
+pub fn eval<D>(db: D, script: &str, args: Vec<String>) -> Result<String, String>
+where
+    D: DerefMut<Target = Vemcache> + 'static,
+{
+    let engine = engine(Rc::new(RefCell::new(db)));
+    let mut scope = Scope::new();
+    let args = args.into_iter().map(Dynamic::from).collect::<Array>();
+    scope.push_constant("ARGV", args);
+    let result = engine
+        .eval_with_scope::<Dynamic>(&mut scope, script)
+        .map_err(|err| err.to_string())?;
+    serde_json::to_string(&result).map_err(|err| err.to_string())
+}
+
+/// Creates a sandboxed engine whose functions operate on `db`.
+fn engine<D>(db: Rc<RefCell<D>>) -> Engine
+where
+    D: DerefMut<Target = Vemcache> + 'static,
+{
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(MAX_SCRIPT_OPERATIONS)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {});
+    engine.disable_symbol("eval");
+
+    let shared = Rc::clone(&db);
+    engine.register_fn("get", move |key: &str| -> Dynamic {
+        match shared.borrow().get(key.to_string()) {
+            Some(vector) => Dynamic::from_array(to_array(&vector)),
+            None => Dynamic::UNIT,
+        }
+    });
+    let shared = Rc::clone(&db);
+    engine.register_fn(
+        "insert",
+        move |key: &str, vector: Array| -> ScriptResult<()> {
+            let vector = to_vector(vector)?;
+            shared
+                .borrow_mut()
+                .try_insert_with_key(key.to_string(), vector)
+                .map_err(|err| format!("Vector rejected: {}", err).into())
+        },
+    );
+    let shared = Rc::clone(&db);
+    engine.register_fn("remove", move |key: &str| -> bool {
+        shared.borrow_mut().remove(key.to_string()).is_some()
+    });
+    let shared = Rc::clone(&db);
+    engine.register_fn("knn", move |key: &str, k: i64| -> ScriptResult<Array> {
+        let db = shared.borrow();
+        let query = db
+            .get(key.to_string())
+            .ok_or_else(|| format!("Key not found: {}", key))?;
+        search(&db, key, &query, k)
+    });
+    let shared = Rc::clone(&db);
+    engine.register_fn(
+        "knn",
+        move |key: &str, vector: Array, k: i64| -> ScriptResult<Array> {
+            search(&shared.borrow(), key, &to_vector(vector)?, k)
+        },
+    );
+
+    engine.register_fn("vadd", |a: Array, b: Array| -> ScriptResult<Array> {
+        combine(a, b, |a, b| a + b)
+    });
+    engine.register_fn("vsub", |a: Array, b: Array| -> ScriptResult<Array> {
+        combine(a, b, |a, b| a - b)
+    });
+    engine.register_fn("vscale", |a: Array, scalar: f32| -> ScriptResult<Array> {
+        scale(a, scalar)
+    });
+    engine.register_fn("vscale", |a: Array, scalar: i64| -> ScriptResult<Array> {
+        scale(a, scalar as f32)
+    });
+    engine.register_fn("vcosine", |a: Array, b: Array| -> ScriptResult<f32> {
+        let (a, b) = (to_vector(a)?, to_vector(b)?);
+        if a.len() != b.len() {
+            return Err("Vectors have different dimensions".into());
+        }
+        Ok(crate::metric::cosine_similarity(&a, &b))
+    });
+    engine.register_fn("vmean", |vectors: Array| -> ScriptResult<Array> {
+        let count = vectors.len();
+        let mut vectors = vectors.into_iter().map(|vector| {
+            vector
+                .try_cast::<Array>()
+                .ok_or_else(|| "Expected an array of vectors".into())
+                .and_then(to_vector)
+        });
+        let mut sum = vectors.next().ok_or("Cannot average no vectors")??;
+        for vector in vectors {
+            let vector = vector?;
+            if vector.len() != sum.len() {
+                return Err("Vectors have different dimensions".into());
+            }
+            for (sum, value) in sum.iter_mut().zip(vector) {
+                *sum += value;
+            }
+        }
+        Ok(sum
+            .into_iter()
+            .map(|sum| Dynamic::from_float(sum / count as f32))
+            .collect())
+    });
+    engine
+}
+
+/// Returns the keys of the `k` nearest neighbors of `query` in the collection `key`
+/// belongs to.
+fn search(db: &Vemcache, key: &str, query: &[f32], k: i64) -> ScriptResult<Array> {
+    let k = usize::try_from(k).map_err(|_| "k must not be negative")?;
+    Ok(db
+        .search(key, query, k, None)
+        .into_iter()
+        .map(|(id, _)| Dynamic::from(id))
+        .collect())
+}
+
+/// Applies `op` to the components of `a` and `b` pairwise.
+fn combine(a: Array, b: Array, op: impl Fn(f32, f32) -> f32) -> ScriptResult<Array> {
+    let (a, b) = (to_vector(a)?, to_vector(b)?);
+    if a.len() != b.len() {
+        return Err("Vectors have different dimensions".into());
+    }
+    Ok(a.iter()
+        .zip(&b)
+        .map(|(a, b)| Dynamic::from_float(op(*a, *b)))
+        .collect())
+}
+
+/// Multiplies the components of `a` by `scalar`.
+fn scale(a: Array, scalar: f32) -> ScriptResult<Array> {
+    Ok(to_array(
+        &to_vector(a)?
+            .into_iter()
+            .map(|value| value * scalar)
+            .collect::<Vec<f32>>(),
+    ))
+}
+
+/// Converts an array of numbers into a vector.
+fn to_vector(array: Array) -> ScriptResult<Vec<f32>> {
+    array
+        .into_iter()
+        .map(|value| {
+            let value = match value.as_float() {
+                Ok(value) => value,
+                Err(_) => value.as_int().map_err(|_| "Expected an array of numbers")? as f32,
+            };
+            Ok(value)
+        })
+        .collect()
+}
+
+/// Converts a vector into an array of numbers.
+fn to_array(vector: &[f32]) -> Array {
+    vector.iter().copied().map(Dynamic::from_float).collect()
+}
@@ -0,0 +1,400 @@
+//! The state shared by the connections of a server, and the dispatch of the commands
+//! they send to their handlers.
+
+use crate::audit::AuditLog;
+use crate::cluster::Cluster;
+use crate::commands::Command;
+use crate::config::Config;
+use crate::handlers::*;
+use crate::health::Health;
+use crate::model::TextModel;
+use crate::monitor::Monitor;
+use crate::pubsub::PubSub;
+use crate::raft::{self, Raft};
+use crate::replication::Replication;
+use crate::slowlog::SlowLog;
+use crate::stats::Stats;
+use crate::Vemcache;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The state shared by all client connections.
+pub struct Server {
+    /// The logical databases, selected by number.
+    pub databases: Vec<Arc<tokio::sync::Mutex<Vemcache>>>,
+    pub replication: Arc<Mutex<Replication>>,
+    pub cluster: Option<Arc<Cluster>>,
+    /// The membership of the server in its Raft group, if Raft mode is enabled.
+    pub raft: Option<Arc<Raft>>,
+    pub config: Arc<RwLock<Config>>,
+    pub health: Arc<Health>,
+    pub slowlog: Mutex<SlowLog>,
+    pub stats: Mutex<Stats>,
+    pub monitor: Monitor,
+    /// The channels the events of the server are published on.
+    pub pubsub: PubSub,
+    /// The model embedding text for `insert_text` and `query_text`, if one is loaded.
+    pub text_model: Option<Arc<dyn TextModel>>,
+    /// The log the commands changing the database or the server are recorded in, if
+    /// auditing is enabled.
+    pub audit: Option<Arc<AuditLog>>,
+}
+
+impl Server {
+    /// Runs `command` against the logical database numbered `selected`, which
+    /// `select` changes, and writes its response. `raft_stream` records what the
+    /// connection sent to a Raft follower.
+    ///
+    /// Immediate commands are answered without waiting for the database. Commands
+    /// reading from the connection, `monitor`, `subscribe` and those followed by a payload, must
+    /// be handled by the connection instead: bulk payloads are dispatched as the
+    /// commands they are read into.
+    pub async fn dispatch(
+        &self,
+        selected: &mut usize,
+        raft_stream: &mut raft::Stream,
+        command: Command,
+        writer: &mut ResponseWriter<'_>,
+    ) {
+        let Server {
+            databases,
+            replication,
+            cluster,
+            raft,
+            config,
+            health,
+            slowlog,
+            stats,
+            monitor: _,
+            pubsub: _,
+            text_model,
+            audit: _,
+        } = self;
+        match &command {
+            Command::Health => return handle_health(health, writer).await,
+            Command::Hello(version) => return handle_hello(*version, writer).await,
+            Command::Format(format, notation) => {
+                return handle_format(*format, *notation, writer).await
+            }
+            Command::Help(name) => return handle_help(name.as_deref(), writer).await,
+            _ => {}
+        }
+        let shared_db = &databases[*selected];
+        let mut guard = shared_db.lock().await;
+        let db = &mut *guard;
+        // Like a full disk, a failed write-ahead log would lose every later write on restart
+        if let (Some(err), true) = (db.wal_error(), command.is_write()) {
+            health.set_wal_failed();
+            let error_msg = format!(
+                "MISCONF Changes can no longer be written to the write-ahead log: {}",
+                err
+            );
+            drop(guard);
+            return handle_error(&error_msg, writer).await;
+        }
+        match command {
+            Command::Ping => {
+                handle_ping(writer).await;
+            }
+            Command::Select(index) => {
+                drop(guard);
+                let (cluster, raft) = (cluster.as_deref(), raft.as_deref());
+                if handle_select(index, databases.len(), cluster, raft, writer).await {
+                    *selected = index;
+                }
+            }
+            Command::Health | Command::Hello(_) | Command::Format(_, _) | Command::Help(_) => {
+                unreachable!("immediate commands are answered before locking the database");
+            }
+            Command::Insert(values) => {
+                handle_insert(db, values, writer).await;
+            }
+            Command::NamedInsert(key, values) | Command::BinaryInsert(key, values) => {
+                handle_named_insert(db, key, values, writer).await;
+            }
+            Command::Alias(key, existing) => {
+                handle_alias(db, key, existing, writer).await;
+            }
+            Command::Get(key, options) => {
+                handle_get(db, key, options, writer).await;
+            }
+            Command::Dim(key) => {
+                handle_dim(db, key, writer).await;
+            }
+            Command::Describe(key) => {
+                handle_describe(db, key, writer).await;
+            }
+            Command::RandomKey => {
+                handle_random_key(db, writer).await;
+            }
+            Command::Sample(n) => {
+                handle_sample(db, n, writer).await;
+            }
+            Command::Count(prefix) => {
+                handle_count(db, prefix, writer).await;
+            }
+            Command::Aggregate(aggregation, prefix) => {
+                handle_aggregate(db, aggregation, prefix, writer).await;
+            }
+            Command::Remove(key) => {
+                handle_remove(db, key, writer).await;
+            }
+            Command::Delete(keys) => {
+                handle_delete(db, keys, writer).await;
+            }
+            Command::RemovePrefix(prefix) => {
+                handle_remove_prefix(db, prefix, writer).await;
+            }
+            Command::KNearestNeighbors(key, k, options) => {
+                let query_timeout = config.read().unwrap().query_timeout;
+                handle_k_nearest_neighbors(db, key, k, options, query_timeout, writer).await;
+            }
+            Command::KnnIn(k, query, candidates) => {
+                handle_knn_in(db, k, query, candidates, writer).await;
+            }
+            Command::ExplainKnn(key, k, options) => {
+                let query_timeout = config.read().unwrap().query_timeout;
+                handle_explain_knn(db, key, k, options, query_timeout, writer).await;
+            }
+            Command::BenchRecall(sample_size, k) => {
+                handle_bench_recall(db, sample_size, k, writer).await;
+            }
+            Command::StatsDims(prefix) => {
+                handle_stats_dims(db, prefix, writer).await;
+            }
+            Command::CollectionStats(prefix) => {
+                handle_collection_stats(db, prefix, writer).await;
+            }
+            Command::Scan(after, count) => {
+                handle_scan(db, after, count, writer).await;
+            }
+            Command::VectorAddition(key1, key2) => {
+                handle_vector_addition(db, key1, key2, writer).await;
+            }
+            Command::VectorSubtraction(key1, key2) => {
+                handle_vector_subtraction(db, key1, key2, writer).await;
+            }
+            Command::VectorScaling(key, scalar) => {
+                handle_vector_scaling(db, key, scalar, writer).await;
+            }
+            Command::CosineSimilarity(key1, key2) => {
+                handle_cosine_similarity(db, key1, key2, writer).await;
+            }
+            Command::Dump(file_path, prefix) => {
+                let full = prefix.is_none();
+                let snapshot = match prefix {
+                    Some(prefix) => Ok(db.snapshot_with_prefix(&prefix)),
+                    None => db.snapshot_with_indexes(),
+                };
+                drop(guard);
+                let database = *selected;
+                handle_dump(
+                    shared_db, snapshot, full, config, database, file_path, writer,
+                )
+                .await;
+            }
+            Command::Changes(seq) => {
+                handle_changes(db, seq, writer).await;
+            }
+            Command::Sync => {
+                let (seq, snapshot) = db.sync_snapshot();
+                drop(guard);
+                raft_stream.sent(seq);
+                handle_sync(seq, snapshot, writer).await;
+            }
+            Command::ReplicaOf(primary) => {
+                // Only the first database is replicated
+                let raft = raft.as_deref();
+                handle_replicaof(&databases[0], replication, raft, config, primary, writer).await;
+            }
+            Command::ClusterNodes => {
+                handle_cluster_nodes(cluster.as_deref(), writer).await;
+            }
+            Command::RaftStatus => {
+                handle_raft_status(raft.as_deref(), writer).await;
+            }
+            // The selected database is the first one, as `select` is rejected in Raft mode
+            Command::RaftVote(request) => {
+                handle_raft_vote(raft.as_ref(), db, request, writer).await;
+            }
+            Command::RaftHeartbeat(term, leader) => {
+                handle_raft_heartbeat(raft.as_ref(), db, term, leader, writer).await;
+            }
+            Command::RaftChanges(term, node, seq) => {
+                let raft = raft.as_deref();
+                handle_raft_changes(raft, db, raft_stream, term, node, seq, writer).await;
+            }
+            Command::ConfigGet(pattern) => {
+                handle_config_get(config, pattern, writer).await;
+            }
+            Command::ConfigSet(name, value) => {
+                drop(guard);
+                handle_config_set(databases, config, slowlog, name, value, writer).await;
+            }
+            Command::ImportCsv(file_path, options) => {
+                drop(guard);
+                handle_import_csv(shared_db, config, file_path, options, writer).await;
+            }
+            Command::ImportNpy(file_path, keys_path) => {
+                drop(guard);
+                handle_import_npy(shared_db, config, file_path, keys_path, writer).await;
+            }
+            Command::ExportNpy(file_path, keys_path) => {
+                let snapshot = db.snapshot();
+                drop(guard);
+                handle_export_npy(snapshot, config, file_path, keys_path, writer).await;
+            }
+            Command::Monitor
+            | Command::Subscribe(_)
+            | Command::Auth(_)
+            | Command::Bulk(_)
+            | Command::ArrowUpload(_)
+            | Command::MetricUpload(_, _) => {
+                unreachable!(
+                    "monitoring, subscriptions, authentication and payloads are handled by the connection"
+                );
+            }
+            Command::BulkInsert(vectors) => {
+                handle_bulk_insert(db, vectors, writer).await;
+            }
+            Command::ArrowImport(stream) => {
+                drop(guard);
+                handle_arrow_import(shared_db, stream, writer).await;
+            }
+            Command::MetricRegister(name, wasm) => {
+                handle_metric_register(db, config, *selected, name, wasm, writer).await;
+            }
+            Command::MetricList => {
+                handle_metric_list(db, writer).await;
+            }
+            Command::ArrowDownload => {
+                let snapshot = db.snapshot();
+                drop(guard);
+                handle_arrow_download(snapshot, writer).await;
+            }
+            Command::ImportJsonl(file_path) => {
+                drop(guard);
+                handle_import_jsonl(shared_db, config, file_path, writer).await;
+            }
+            Command::ExportJsonl(file_path) => {
+                let snapshot = db.snapshot();
+                drop(guard);
+                handle_export_jsonl(snapshot, config, file_path, writer).await;
+            }
+            Command::ExportVec(file_path) => {
+                let snapshot = db.snapshot();
+                drop(guard);
+                handle_export_vec(snapshot, config, file_path, writer).await;
+            }
+            Command::IndexCreate(prefix, kind, metric) => {
+                drop(guard);
+                handle_index_create(shared_db, health, prefix, kind, metric, writer).await;
+            }
+            Command::IndexRebuild(prefix, params) => {
+                drop(guard);
+                handle_index_rebuild(shared_db, health, prefix, params, writer).await;
+            }
+            Command::IndexDrop(prefix) => {
+                handle_index_drop(db, prefix, writer).await;
+            }
+            Command::IndexList => {
+                handle_index_list(db, writer).await;
+            }
+            Command::IndexStatus => {
+                handle_index_status(db, writer).await;
+            }
+            Command::Compact => {
+                drop(guard);
+                handle_compact(shared_db, health, writer).await;
+            }
+            Command::Warmup(prefix, indexes) => {
+                drop(guard);
+                handle_warmup(shared_db, prefix, indexes, writer).await;
+            }
+            Command::Reduce(prefix, kind, dim) => {
+                drop(guard);
+                handle_reduce(shared_db, health, prefix, kind, dim, writer).await;
+            }
+            Command::ReduceDrop(prefix) => {
+                handle_reduce_drop(db, prefix, writer).await;
+            }
+            Command::ReduceList => {
+                handle_reduce_list(db, writer).await;
+            }
+            Command::PipelineSet(prefix, words) => {
+                handle_pipeline_set(db, prefix, words, writer).await;
+            }
+            Command::PipelineDrop(prefix) => {
+                handle_pipeline_drop(db, prefix, writer).await;
+            }
+            Command::PipelineList => {
+                handle_pipeline_list(db, writer).await;
+            }
+            Command::TtlSet(prefix, ttl) => {
+                handle_ttl_set(db, prefix, ttl, writer).await;
+            }
+            Command::TtlDrop(prefix) => {
+                handle_ttl_drop(db, prefix, writer).await;
+            }
+            Command::TtlList => {
+                handle_ttl_list(db, writer).await;
+            }
+            Command::Embed(key, text) => {
+                drop(guard);
+                handle_embed(shared_db, config, key, text, writer).await;
+            }
+            Command::SearchText(text, k, prefix) => {
+                drop(guard);
+                handle_search_text(shared_db, config, text, k, prefix, writer).await;
+            }
+            Command::InsertText(key, text) => {
+                drop(guard);
+                let text_model = text_model.as_ref();
+                handle_insert_text(shared_db, text_model, key, text, writer).await;
+            }
+            Command::QueryText(text, k, prefix) => {
+                drop(guard);
+                let text_model = text_model.as_ref();
+                handle_query_text(shared_db, config, text_model, text, k, prefix, writer).await;
+            }
+            Command::Eval(script, args) => {
+                drop(guard);
+                handle_eval(shared_db, script, args, writer).await;
+            }
+            Command::SlowlogGet(count) => {
+                handle_slowlog_get(slowlog, count, writer).await;
+            }
+            Command::SlowlogLen => {
+                handle_slowlog_len(slowlog, writer).await;
+            }
+            Command::SlowlogReset => {
+                handle_slowlog_reset(slowlog, writer).await;
+            }
+            Command::Stats => {
+                handle_stats(stats, writer).await;
+            }
+            Command::StatsReset => {
+                handle_stats_reset(stats, writer).await;
+            }
+            Command::BackupNow => {
+                drop(guard);
+                handle_backup(databases, config, writer).await;
+            }
+            Command::RestoreUntil(point) => {
+                handle_restore_until(db, config, *selected, point, writer).await;
+            }
+            Command::SnapshotsList => {
+                handle_snapshots_list(config, *selected, writer).await;
+            }
+            Command::SnapshotsInfo(name) => {
+                handle_snapshots_info(config, *selected, name, writer).await;
+            }
+            Command::SnapshotsDelete(name) => {
+                handle_snapshots_delete(config, *selected, name, writer).await;
+            }
+            Command::Shutdown(save) => {
+                drop(guard);
+                handle_shutdown(databases, config, save, writer).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The default number of slow commands retained.
+pub const DEFAULT_SLOWLOG_SIZE: usize = 128;
+
+/// The default latency, in microseconds, above which a command is logged.
+pub const DEFAULT_SLOWLOG_THRESHOLD: u64 = 10_000;
+
+/// The number of arguments of a command kept in the log.
+const MAX_ARGUMENTS: usize = 32;
+
+/// The number of bytes of an argument kept in the log.
+const MAX_ARGUMENT_LENGTH: usize = 128;
+
+/// A command that took longer than the slow log threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlowCommand {
+    /// The identifier of the entry, increasing with every logged command.
+    pub id: u64,
+    /// When the command completed, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// How long the command took.
+    pub duration: Duration,
+    /// The command, with long arguments and long argument lists truncated.
+    pub command: String,
+}
+
+/// A bounded, in-memory log of the commands that took longest to run.
+///
+/// Once the log is full, the oldest entry is discarded for every new one.
+pub struct SlowLog {
+    entries: VecDeque<SlowCommand>,
+    capacity: usize,
+    next_id: u64,
+}
+
+impl SlowLog {
+    /// Creates an empty slow log retaining at most `capacity` commands.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity,
+            next_id: 0,
+        }
+    }
+
+    /// Logs a command that took `duration` to run.
+    pub fn record(&mut self, command: &str, duration: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        self.entries.push_back(SlowCommand {
+            id: self.next_id,
+            timestamp,
+            duration,
+            command: truncate(command),
+        });
+        self.next_id += 1;
+    }
+
+    /// Returns up to `count` logged commands, newest first.
+    pub fn get(&self, count: usize) -> Vec<&SlowCommand> {
+        self.entries.iter().rev().take(count).collect()
+    }
+
+    /// Returns the number of logged commands.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no command has been logged.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Discards every logged command.
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Changes the number of retained commands, discarding the oldest ones if the
+    /// log currently holds more than `capacity`.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Keeps the first `MAX_ARGUMENTS` arguments of a command and the first
+/// `MAX_ARGUMENT_LENGTH` bytes of each, so vectors with thousands of components
+/// do not flood the log.
+fn truncate(command: &str) -> String {
+    let tokens = command.split_whitespace().collect::<Vec<&str>>();
+    let mut truncated = tokens
+        .iter()
+        .take(MAX_ARGUMENTS)
+        .map(|token| {
+            if token.len() <= MAX_ARGUMENT_LENGTH {
+                return token.to_string();
+            }
+            let end = (0..=MAX_ARGUMENT_LENGTH)
+                .rev()
+                .find(|end| token.is_char_boundary(*end))
+                .unwrap_or(0);
+            format!("{}... ({} more bytes)", &token[..end], token.len() - end)
+        })
+        .collect::<Vec<String>>();
+    if tokens.len() > MAX_ARGUMENTS {
+        truncated.push(format!(
+            "... ({} more arguments)",
+            tokens.len() - MAX_ARGUMENTS
+        ));
+    }
+    truncated.join(" ")
+}
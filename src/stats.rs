@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The number of buckets each power of two of the latency histogram is split into.
+const SUB_BUCKETS: u64 = 4;
+
+/// The number of buckets of the latency histogram, enough for any `u64` latency in
+/// microseconds.
+const BUCKETS: usize = 63 * SUB_BUCKETS as usize;
+
+/// The statistics of one type of command.
+#[derive(Clone)]
+pub struct CommandStats {
+    calls: u64,
+    errors: u64,
+    // The number of calls whose latency, in microseconds, falls in each bucket
+    latencies: Vec<u64>,
+}
+
+impl CommandStats {
+    fn new() -> Self {
+        Self {
+            calls: 0,
+            errors: 0,
+            latencies: vec![0; BUCKETS],
+        }
+    }
+
+    /// The number of times the command was run.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// The number of times the command failed.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Returns the latency below which a `quantile` of the calls completed, e.g. the
+    /// median for 0.5. Latencies are bucketed, so the value returned is the upper
+    /// bound of a bucket, at most 25% above the exact latency.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        if self.calls == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile * self.calls as f64).ceil() as u64).clamp(1, self.calls);
+        let mut seen = 0;
+        for (bucket, count) in self.latencies.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(bucket_upper_bound(bucket));
+            }
+        }
+        Duration::MAX
+    }
+}
+
+/// Call counts, error counts and latency histograms of every type of command run by
+/// the server.
+///
+/// Latencies are kept in log-linear histograms with 4 buckets per power of two,
+/// so the memory used does not grow with the number of calls.
+#[derive(Default)]
+pub struct Stats {
+    commands: BTreeMap<&'static str, CommandStats>,
+}
+
+impl Stats {
+    /// Creates empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a call of the command named `command` that took `duration`.
+    pub fn record(&mut self, command: &'static str, duration: Duration, failed: bool) {
+        let stats = self
+            .commands
+            .entry(command)
+            .or_insert_with(CommandStats::new);
+        stats.calls += 1;
+        if failed {
+            stats.errors += 1;
+        }
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        stats.latencies[bucket(micros)] += 1;
+    }
+
+    /// Returns the statistics of every command run at least once, by command name.
+    pub fn commands(&self) -> impl Iterator<Item = (&'static str, &CommandStats)> {
+        self.commands.iter().map(|(name, stats)| (*name, stats))
+    }
+
+    /// Discards all statistics.
+    pub fn reset(&mut self) {
+        self.commands.clear();
+    }
+}
+
+/// Returns the bucket of a latency in microseconds. Latencies below `SUB_BUCKETS`
+/// each have their own bucket, larger ones share a bucket with the latencies
+/// having the same 3 most significant bits.
+fn bucket(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let magnitude = u64::from(63 - micros.leading_zeros());
+    let sub_bucket = (micros >> (magnitude - 2)) & (SUB_BUCKETS - 1);
+    ((magnitude - 1) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Returns the largest latency, in microseconds, falling in `bucket`.
+fn bucket_upper_bound(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket;
+    }
+    let magnitude = bucket / SUB_BUCKETS + 1;
+    let sub_bucket = bucket % SUB_BUCKETS;
+    let lower_bound = (SUB_BUCKETS + sub_bucket) << (magnitude - 2);
+    lower_bound + ((1 << (magnitude - 2)) - 1)
+}
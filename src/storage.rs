@@ -0,0 +1,161 @@
+use std::io::Result as IoResult;
+use std::sync::Arc;
+
+use crate::keys::KeyMap;
+
+#[cfg(feature = "disk")]
+mod disk;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+
+#[cfg(feature = "disk")]
+pub use disk::{DiskStorage, DEFAULT_CACHE_SIZE};
+#[cfg(feature = "mmap")]
+pub use mmap::{MmapStorage, HEADER_SIZE, MAGIC, VERSION};
+
+/// Where the vectors of a database are kept.
+///
+/// Vectors are handed out as shared pointers, so backends that do not keep every
+/// vector in memory can load them on demand. The database never holds on to the
+/// vectors it reads, except through snapshots and indexes.
+///
+/// Backends that write to disk report I/O errors on the console rather than to
+/// the caller, like the write-ahead log, so a failing disk does not make the
+/// in-memory state of the database diverge from what clients were told.
+pub trait Storage: Send + Sync {
+    /// Returns the vector stored under `key`, if any.
+    fn get(&self, key: &str) -> Option<Arc<Vec<f32>>>;
+
+    /// Stores a vector under `key`, replacing any vector stored under it.
+    fn insert(&mut self, key: String, vector: Arc<Vec<f32>>);
+
+    /// Removes the vector stored under `key` and returns it, if any.
+    fn remove(&mut self, key: &str) -> Option<Arc<Vec<f32>>>;
+
+    /// Removes every vector.
+    fn clear(&mut self);
+
+    /// Returns the number of stored vectors.
+    fn len(&self) -> usize;
+
+    /// Returns whether no vectors are stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits until every change has been written to disk. Backends that do not
+    /// write to disk have nothing to do.
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    /// Returns every key and vector, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Arc<Vec<f32>>)> + '_>;
+
+    /// Calls `visit` with every key and vector, in no particular order, in blocks of
+    /// up to `SCAN_BLOCK` entries so they can be scored together.
+    ///
+    /// Searches use this rather than `iter`, so backends that can lend their
+    /// vectors should override it to avoid copying them.
+    fn scan(&self, visit: &mut dyn FnMut(&[Entry])) {
+        let entries = self.iter().collect::<Vec<(String, Arc<Vec<f32>>)>>();
+        scan_blocks(
+            entries
+                .iter()
+                .map(|(key, vector)| (key.as_str(), vector.as_slice())),
+            visit,
+        );
+    }
+
+    /// Reads every vector whose key starts with `prefix`, so that the searches that
+    /// follow do not wait for them to be loaded from disk, and returns how many
+    /// were read.
+    ///
+    /// Reading every component through `scan` faults in the pages of memory-mapped
+    /// vectors. Backends caching vectors in memory should override it to fill
+    /// their cache.
+    fn warm(&self, prefix: &str) -> usize {
+        let mut count = 0;
+        self.scan(&mut |block| {
+            for (_, vector) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                std::hint::black_box(vector.iter().sum::<f32>());
+                count += 1;
+            }
+        });
+        count
+    }
+}
+
+/// A key and its vector, as lent by `Storage::scan`.
+pub type Entry<'a> = (&'a str, &'a [f32]);
+
+/// The number of entries handed to the visitor of `Storage::scan` at a time.
+pub const SCAN_BLOCK: usize = 256;
+
+/// Hands `entries` to `visit` in blocks of `SCAN_BLOCK`.
+fn scan_blocks<'a>(entries: impl Iterator<Item = Entry<'a>>, visit: &mut dyn FnMut(&[Entry])) {
+    let mut block = Vec::with_capacity(SCAN_BLOCK);
+    for entry in entries {
+        block.push(entry);
+        if block.len() == SCAN_BLOCK {
+            visit(&block);
+            block.clear();
+        }
+    }
+    if !block.is_empty() {
+        visit(&block);
+    }
+}
+
+/// Keeps every vector in memory, with its key interned. This is the default storage.
+#[derive(Default)]
+pub struct MemoryStorage {
+    vectors: KeyMap<Arc<Vec<f32>>>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<Arc<Vec<f32>>> {
+        self.vectors.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: String, vector: Arc<Vec<f32>>) {
+        self.vectors.insert(&key, vector);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Arc<Vec<f32>>> {
+        self.vectors.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.vectors.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Arc<Vec<f32>>)> + '_> {
+        Box::new(
+            self.vectors
+                .iter()
+                .map(|(key, vector)| (key.to_string(), Arc::clone(vector))),
+        )
+    }
+
+    fn scan(&self, visit: &mut dyn FnMut(&[Entry])) {
+        scan_blocks(
+            self.vectors
+                .iter()
+                .map(|(key, vector)| (key, vector.as_slice())),
+            visit,
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::Result as IoResult;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+
+use super::Storage;
+
+/// The default number of vectors kept in memory by a `DiskStorage`.
+pub const DEFAULT_CACHE_SIZE: usize = 100_000;
+
+/// Keeps the vectors in an on-disk sled database, with the most recently used
+/// vectors cached in memory.
+///
+/// Datasets larger than the available memory can be served this way, as long as
+/// the vectors that are read often fit in the cache. Searches that are not
+/// answered by an index read every vector, so they are much slower than with
+/// the in-memory storage once the dataset no longer fits in the cache.
+///
+/// Vectors are stored as little-endian `f32` components, under their key.
+pub struct DiskStorage {
+    db: sled::Db,
+    cache: Mutex<LruCache<String, Arc<Vec<f32>>>>,
+    // sled can only count its entries by scanning them
+    len: usize,
+}
+
+impl DiskStorage {
+    /// Opens the storage in the directory `path`, creating it if needed, and caches
+    /// up to `cache_size` vectors in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created, or does not hold a
+    /// valid database.
+    pub fn open(path: &Path, cache_size: usize) -> IoResult<Self> {
+        let db = sled::open(path)?;
+        let len = db.len();
+        let cache_size = NonZeroUsize::new(cache_size).unwrap_or(NonZeroUsize::MIN);
+        Ok(Self {
+            db,
+            cache: Mutex::new(LruCache::new(cache_size)),
+            len,
+        })
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, LruCache<String, Arc<Vec<f32>>>> {
+        // The cache is always left consistent, even if a holder panicked
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn encode(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+impl Storage for DiskStorage {
+    fn get(&self, key: &str) -> Option<Arc<Vec<f32>>> {
+        if let Some(vector) = self.cache().get(key) {
+            return Some(Arc::clone(vector));
+        }
+        let bytes = match self.db.get(key) {
+            Ok(bytes) => bytes?,
+            Err(err) => {
+                println!("Error reading from the disk storage: {}", err);
+                return None;
+            }
+        };
+        let vector = Arc::new(decode(&bytes));
+        self.cache().put(key.to_string(), Arc::clone(&vector));
+        Some(vector)
+    }
+
+    fn insert(&mut self, key: String, vector: Arc<Vec<f32>>) {
+        match self.db.insert(key.as_bytes(), encode(&vector)) {
+            Ok(None) => self.len += 1,
+            Ok(Some(_)) => {}
+            Err(err) => println!("Error writing to the disk storage: {}", err),
+        }
+        self.cache().put(key, vector);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Arc<Vec<f32>>> {
+        let cached = self.cache().pop(key);
+        match self.db.remove(key) {
+            Ok(Some(bytes)) => {
+                self.len -= 1;
+                Some(cached.unwrap_or_else(|| Arc::new(decode(&bytes))))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                println!("Error writing to the disk storage: {}", err);
+                None
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Err(err) = self.db.clear() {
+            println!("Error writing to the disk storage: {}", err);
+        }
+        self.cache().clear();
+        self.len = self.db.len();
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Arc<Vec<f32>>)> + '_> {
+        Box::new(self.db.iter().filter_map(|entry| match entry {
+            Ok((key, bytes)) => Some((
+                String::from_utf8_lossy(&key).into_owned(),
+                Arc::new(decode(&bytes)),
+            )),
+            Err(err) => {
+                println!("Error reading from the disk storage: {}", err);
+                None
+            }
+        }))
+    }
+
+    fn warm(&self, prefix: &str) -> usize {
+        // Vectors read beyond the capacity of the cache would evict the first ones
+        let capacity = self.cache().cap().get();
+        let mut count = 0;
+        for entry in self.db.scan_prefix(prefix).take(capacity) {
+            match entry {
+                Ok((key, bytes)) => {
+                    let key = String::from_utf8_lossy(&key).into_owned();
+                    self.cache().put(key, Arc::new(decode(&bytes)));
+                    count += 1;
+                }
+                Err(err) => println!("Error reading from the disk storage: {}", err),
+            }
+        }
+        count
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use super::{scan_blocks, Entry, Storage};
+
+/// The first bytes of a vector file.
+pub const MAGIC: &[u8; 4] = b"VMCV";
+
+/// The version of the vector file format.
+pub const VERSION: u32 = 1;
+
+/// The size of the header of a vector file, which keeps the vectors 4-byte aligned.
+pub const HEADER_SIZE: usize = 24;
+
+/// Serves the vectors of a prepared vector file straight from a read-only memory map.
+///
+/// The vectors are never copied onto the heap, except for the ones returned by
+/// `get` or `iter`, so opening a file of any size is nearly instant and the
+/// operating system decides which pages stay in memory. Only the keys are read
+/// up front, to look vectors up.
+///
+/// A vector file starts with a 24-byte header: the magic bytes `VMCV`, the format
+/// version, the number of vectors as a `u64` and their dimension as a `u32`, all
+/// little-endian, followed by 4 reserved bytes. The vectors follow as little-endian
+/// `f32` components, then the key of each vector in the same order, as a
+/// little-endian `u16` length followed by the UTF-8 key. Vector files are written
+/// with `export::write_vector_file`.
+///
+/// The storage is read-only: inserts and removes are reported on the console and
+/// ignored, so the database should be used in read-only mode.
+pub struct MmapStorage {
+    mmap: Mmap,
+    dim: usize,
+    keys: Vec<String>,
+    rows: HashMap<String, usize>,
+}
+
+impl MmapStorage {
+    /// Maps the vector file at `path` into memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid vector file.
+    pub fn open(path: &Path) -> IoResult<Self> {
+        let file = File::open(path)?;
+        // SAFETY: The map is read-only. Vector files are not expected to be modified
+        // while they are served, which is the caller's responsibility.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let header = mmap
+            .get(..HEADER_SIZE)
+            .ok_or_else(|| invalid_data("Truncated header"))?;
+        if &header[..4] != MAGIC {
+            return Err(invalid_data("Not a vector file"));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data(&format!("Unsupported version {}", version)));
+        }
+        let count = u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize;
+        let dim = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+        if cfg!(target_endian = "big") {
+            return Err(IoError::new(
+                ErrorKind::Unsupported,
+                "Vector files can only be served on little-endian platforms",
+            ));
+        }
+
+        let keys_offset = count
+            .checked_mul(dim)
+            .and_then(|components| components.checked_mul(4))
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .filter(|offset| *offset <= mmap.len())
+            .ok_or_else(|| invalid_data("Truncated vectors"))?;
+        let mut keys = Vec::with_capacity(count);
+        let mut rest = &mmap[keys_offset..];
+        for _ in 0..count {
+            let len = rest
+                .get(..2)
+                .ok_or_else(|| invalid_data("Truncated keys"))?;
+            let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+            let key = rest
+                .get(2..2 + len)
+                .ok_or_else(|| invalid_data("Truncated keys"))?;
+            let key = std::str::from_utf8(key).map_err(|_| invalid_data("Invalid key"))?;
+            keys.push(key.to_string());
+            rest = &rest[2 + len..];
+        }
+        let rows = keys
+            .iter()
+            .enumerate()
+            .map(|(row, key)| (key.clone(), row))
+            .collect();
+
+        Ok(Self {
+            mmap,
+            dim,
+            keys,
+            rows,
+        })
+    }
+
+    /// Returns the vector of row `row`, straight from the map.
+    fn row(&self, row: usize) -> &[f32] {
+        let start = HEADER_SIZE + row * self.dim * 4;
+        let bytes = &self.mmap[start..start + self.dim * 4];
+        // SAFETY: Maps start on a page boundary and the header is 24 bytes long, so
+        // the components are 4-byte aligned; `open` checked that they lie within the
+        // map and that the platform is little-endian, like the file.
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, self.dim) }
+    }
+}
+
+fn invalid_data(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, message.to_string())
+}
+
+impl Storage for MmapStorage {
+    fn get(&self, key: &str) -> Option<Arc<Vec<f32>>> {
+        let row = *self.rows.get(key)?;
+        Some(Arc::new(self.row(row).to_vec()))
+    }
+
+    fn insert(&mut self, key: String, _vector: Arc<Vec<f32>>) {
+        println!(
+            "Error inserting {}: the memory-mapped storage is read-only",
+            key
+        );
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Arc<Vec<f32>>> {
+        println!(
+            "Error removing {}: the memory-mapped storage is read-only",
+            key
+        );
+        None
+    }
+
+    fn clear(&mut self) {
+        println!("Error clearing the database: the memory-mapped storage is read-only");
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Arc<Vec<f32>>)> + '_> {
+        Box::new(
+            self.keys
+                .iter()
+                .enumerate()
+                .map(|(row, key)| (key.clone(), Arc::new(self.row(row).to_vec()))),
+        )
+    }
+
+    fn scan(&self, visit: &mut dyn FnMut(&[Entry])) {
+        scan_blocks(
+            self.keys
+                .iter()
+                .enumerate()
+                .map(|(row, key)| (key.as_str(), self.row(row))),
+            visit,
+        );
+    }
+}
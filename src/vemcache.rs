@@ -1,16 +1,342 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
 use uuid::Uuid;
 
+use crate::changelog::{Change, ChangeLog, DEFAULT_CHANGELOG_CAPACITY};
+use crate::checksum::{self, Header};
+use crate::compression;
+#[cfg(feature = "gpu")]
+use crate::gpu::Gpu;
+use crate::index::{self, IndexKind, SavedIndex, VectorIndex};
+use crate::keys::KeyMap;
+use crate::metric::{self, Euclidean, Metric, DEFAULT_METRIC};
+use crate::pipeline::Pipeline;
+use crate::quote;
+use crate::reduce::{self, Projection, ReductionKind, PCA_SAMPLE_SIZE};
+use crate::storage::{MemoryStorage, Storage};
+use crate::wal::Wal;
+
 type VectorId = String;
 type Vector = Vec<f32>;
 
-use serde::Serialize;
-use std::fs::File;
+/// The suffix appended to the path of a snapshot to name the file holding the
+/// indexes saved alongside it.
+pub const INDEX_FILE_SUFFIX: &str = ".idx";
+
+/// The suffix appended to the path of a snapshot to name the file holding the
+/// projections saved alongside it.
+pub const PROJECTION_FILE_SUFFIX: &str = ".proj";
+
+/// The suffix appended to the path of a snapshot to name the file holding the
+/// insert pipelines saved alongside it.
+pub const PIPELINE_FILE_SUFFIX: &str = ".pipe";
+
+/// The suffix appended to the path of a snapshot to name the file holding the
+/// times to live of the collections saved alongside it.
+pub const TTL_FILE_SUFFIX: &str = ".ttl";
+
 use std::io::Result as IoResult;
 
-#[derive(Serialize)]
+/// When a vector was inserted and last retrieved, in milliseconds since the Unix epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timestamps {
+    /// When the vector was inserted, or last replaced.
+    pub created_at: u64,
+    /// When the vector was last retrieved with `get`, or inserted if it never was.
+    pub accessed_at: u64,
+}
+
+// The access time is atomic so `get` can update it through a shared reference
+struct EntryTimestamps {
+    created_at: u64,
+    accessed_at: AtomicU64,
+}
+
+impl EntryTimestamps {
+    fn now() -> Self {
+        let now = now_millis();
+        Self {
+            created_at: now,
+            accessed_at: AtomicU64::new(now),
+        }
+    }
+}
+
+/// An index being built over the vectors of its collection without holding on to
+/// the database, created by `Vemcache::start_index_build`.
+pub struct IndexBuilder {
+    id: u64,
+    prefix: String,
+    index: Box<dyn VectorIndex>,
+    vectors: Vec<(VectorId, Arc<Vector>)>,
+    progress: Arc<AtomicUsize>,
+}
+
+impl IndexBuilder {
+    /// Identifies the build, e.g. to abort it with `Vemcache::abort_index_build`.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Inserts the vectors of the collection into the index. This is the costly part
+    /// of the build, so it is meant to run on another thread than the database.
+    pub fn run(&mut self) {
+        for (key, vector) in self.vectors.drain(..) {
+            self.index.insert(&key, vector);
+            self.progress.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The progress of an index build, as reported by `Vemcache::index_builds`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexBuildProgress {
+    /// The key prefix of the collection being indexed.
+    pub prefix: String,
+    /// The kind of index being built.
+    pub kind: String,
+    /// The number of vectors indexed so far.
+    pub indexed: usize,
+    /// The number of vectors of the collection when the build started.
+    pub total: usize,
+}
+
+/// The error returned by searches that did not complete before their deadline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The search did not complete before its deadline")
+    }
+}
+
+impl error::Error for DeadlineExceeded {}
+
+/// Statistics of one component across the vectors of a collection, as returned by
+/// `Vemcache::dimension_stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DimensionStats {
+    /// The number of vectors having this component.
+    pub count: usize,
+    /// The mean of the component.
+    pub mean: f64,
+    /// The population standard deviation of the component.
+    pub std: f64,
+    /// The smallest value of the component.
+    pub min: f32,
+    /// The largest value of the component.
+    pub max: f32,
+}
+
+/// The state of a collection and of its index, as returned by
+/// `Vemcache::collection_stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollectionStats {
+    /// The number of vectors of the collection.
+    pub count: usize,
+    /// The dimension of the vectors, `None` if the collection is empty or its
+    /// vectors have different dimensions.
+    pub dim: Option<usize>,
+    /// The name of the metric searches of the collection rank vectors by: the
+    /// metric of its index, or else the default metric.
+    pub metric: String,
+    /// The number of bytes of the keys and components of the vectors.
+    pub memory: usize,
+    /// The index searches of the collection use, if any.
+    pub index: Option<IndexStats>,
+}
+
+/// The state of an index, as reported by `Vemcache::collection_stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    /// The key prefix of the indexed collection, which may be broader than the
+    /// collection the statistics are about.
+    pub prefix: String,
+    /// The kind of index.
+    pub kind: String,
+    /// The parameters the index was built with.
+    pub params: Vec<(&'static str, usize)>,
+    /// The number of indexed vectors.
+    pub len: usize,
+    /// The number of removed vectors the index still holds until it is compacted.
+    pub removed: usize,
+    /// An estimate of the memory held by the index, in bytes.
+    pub memory: usize,
+    /// Whether the index holds more removed vectors than live ones, in which case
+    /// it is compacted by the next `compact_indexes`.
+    pub needs_compaction: bool,
+}
+
+/// The ways `Vemcache::aggregate` combines the vectors of a collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    /// The sum of the vectors, component by component.
+    Sum,
+    /// The mean of the vectors, component by component: the centroid of the collection.
+    Mean,
+}
+
+impl Aggregation {
+    /// Returns the aggregation named `name`, e.g. `"mean"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sum" => Some(Aggregation::Sum),
+            "mean" => Some(Aggregation::Mean),
+            _ => None,
+        }
+    }
+}
+
+/// What happens when a vector identical to one already stored, component by
+/// component, is inserted under another key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dedup {
+    /// Duplicates are stored like any other vector.
+    #[default]
+    Off,
+    /// Duplicates are rejected.
+    Reject,
+    /// Duplicates share the vector already stored, which is freed once no key
+    /// holds it anymore.
+    Share,
+}
+
+impl Dedup {
+    /// Returns the deduplication mode named `name`, e.g. `"share"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Dedup::Off),
+            "reject" => Some(Dedup::Reject),
+            "share" => Some(Dedup::Share),
+            _ => None,
+        }
+    }
+}
+
+/// How a search was carried out, as reported by `Vemcache::explain_search`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchExplanation {
+    /// The key prefix of the collection searched, empty for the whole database.
+    pub collection: String,
+    /// The kind of the index of the collection, if it has one.
+    pub index: Option<String>,
+    /// Whether the index answered the search. If not, every vector of the
+    /// collection was compared with the query.
+    pub used_index: bool,
+    /// The name of the metric the vectors were ranked by.
+    pub metric: String,
+    /// The number of vectors in the collection.
+    pub collection_size: usize,
+    /// The number of vectors, or index centroids, compared with the query.
+    pub candidates: usize,
+    /// The number of candidates returned by the index and re-scored exactly before
+    /// keeping the nearest ones, 0 if the results of the index were kept as they are.
+    pub reranked: usize,
+    /// The keys of the nearest vectors along with their distance, nearest first.
+    pub neighbors: Vec<(String, f32)>,
+}
+
+/// The recall of the index of a collection, as measured by `Vemcache::recall`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recall {
+    /// The key prefix of the collection, empty for the whole database.
+    pub collection: String,
+    /// The kind of the index of the collection.
+    pub index: String,
+    /// The number of vectors of the collection used as queries.
+    pub queries: usize,
+    /// The share of the exact k-nearest neighbors of the queries the index returned,
+    /// averaged over the queries.
+    pub recall: f64,
+}
+
+/// The search parameter chosen for the index of a collection by
+/// `Vemcache::tune_indexes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    /// The key prefix of the collection, empty for the whole database.
+    pub collection: String,
+    /// The name of the parameter, as returned by `VectorIndex::search_param`.
+    pub param: &'static str,
+    /// The value of the parameter before tuning.
+    pub previous: usize,
+    /// The value chosen.
+    pub value: usize,
+    /// The recall@k measured with the value chosen.
+    pub recall: f64,
+    /// The mean time an index search took with the value chosen.
+    pub latency: Duration,
+}
+
+// A vector of a collection searched for to measure the recall of its index, along
+// with its key and exact nearest neighbors
+struct RecallSample {
+    key: String,
+    query: Arc<Vector>,
+    exact: HashSet<String>,
+}
+
+// The database side of an index build
+struct PendingBuild {
+    id: u64,
+    prefix: String,
+    kind: String,
+    total: usize,
+    progress: Arc<AtomicUsize>,
+    // The keys of the collection changed since the build started
+    changed: HashSet<VectorId>,
+}
+
 pub struct Vemcache {
-    storage: HashMap<VectorId, Vector>,
+    // Vectors are shared so snapshots can be taken without copying them
+    storage: Box<dyn Storage>,
+    // The timestamps of the vectors inserted since the database was created, by key
+    timestamps: KeyMap<EntryTimestamps>,
+    changelog: ChangeLog,
+    metrics: HashMap<String, Arc<dyn Metric>>,
+    // The metric used by `k_nearest_neighbors`
+    metric: Arc<dyn Metric>,
+    // The indexes of the collections, identified by key prefix
+    indexes: Vec<(String, Box<dyn VectorIndex>)>,
+    builds: Vec<PendingBuild>,
+    next_build: u64,
+    // The name of the full snapshot delta snapshots are taken against, and the keys
+    // changed since it was taken
+    delta: Option<(String, HashSet<VectorId>)>,
+    // The projections applied to the vectors inserted into collections, by key prefix
+    projections: Vec<(String, Projection)>,
+    // The transforms applied to the vectors inserted into collections, by key prefix
+    pipelines: Vec<(String, Pipeline)>,
+    // The time to live of the vectors inserted into collections, in seconds, by key prefix
+    ttls: Vec<(String, u64)>,
+    // When the vectors of collections with a time to live expire, in milliseconds
+    // since the Unix epoch, by key and in order of expiry
+    expirations: HashMap<VectorId, u64>,
+    expiry_queue: BTreeSet<(u64, VectorId)>,
+    dedup: Dedup,
+    // While deduplication is enabled, the stored vectors by hash of their components,
+    // along with the number of keys they are stored under
+    shared: HashMap<u64, Vec<(Arc<Vector>, usize)>>,
+    // The GPU exhaustive searches are offloaded to, and the number of vectors a
+    // search has to scan to be offloaded
+    #[cfg(feature = "gpu")]
+    gpu: Option<(Gpu, usize)>,
+    // Incremented on every change, so the GPU knows when its copy of the vectors is stale
+    #[cfg(feature = "gpu")]
+    generation: u64,
+}
+
+impl Default for Vemcache {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Vemcache {
@@ -34,16 +360,333 @@ impl Vemcache {
     ///
     /// A new instance of the Vemcache database with an empty storage.
     pub fn new() -> Self {
+        Self::with_storage(Box::new(MemoryStorage::new()))
+    }
+
+    /// Creates a database keeping its vectors in `storage`, e.g. a `DiskStorage` for
+    /// datasets larger than the available memory. Vectors already in the storage
+    /// are served right away.
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        let metrics = metric::builtin_metrics()
+            .into_iter()
+            .map(|metric| (metric.name().to_string(), metric))
+            .collect::<HashMap<_, _>>();
         Self {
-            storage: HashMap::new(),
+            storage,
+            timestamps: KeyMap::new(),
+            changelog: ChangeLog::new(DEFAULT_CHANGELOG_CAPACITY),
+            metric: Arc::clone(&metrics[DEFAULT_METRIC]),
+            metrics,
+            indexes: Vec::new(),
+            builds: Vec::new(),
+            next_build: 0,
+            delta: None,
+            projections: Vec::new(),
+            pipelines: Vec::new(),
+            ttls: Vec::new(),
+            expirations: HashMap::new(),
+            expiry_queue: BTreeSet::new(),
+            dedup: Dedup::Off,
+            shared: HashMap::new(),
+            #[cfg(feature = "gpu")]
+            gpu: None,
+            #[cfg(feature = "gpu")]
+            generation: 0,
         }
     }
 
+    /// Returns the number of vectors stored in the database.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns `true` if the database holds no vectors.
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
+    /// Returns up to `count` keys of the database in order, starting after `after`,
+    /// or at the first key if `after` is `None`. Passing the last key returned as
+    /// `after` returns the next page of keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// for key in ["c", "a", "d", "b"] {
+    ///     db.insert_with_key(key.to_string(), vec![1.0]);
+    /// }
+    ///
+    /// assert_eq!(db.scan_keys(None, 3), vec!["a", "b", "c"]);
+    /// assert_eq!(db.scan_keys(Some("c"), 3), vec!["d"]);
+    /// ```
+    pub fn scan_keys(&self, after: Option<&str>, count: usize) -> Vec<String> {
+        // The `count` smallest keys seen so far, the largest on top
+        let mut keys = BinaryHeap::with_capacity(count + 1);
+        self.storage.scan(&mut |block| {
+            for (key, _) in block {
+                if after.is_some_and(|after| *key <= after) {
+                    continue;
+                }
+                if keys.len() < count {
+                    keys.push(key.to_string());
+                } else if keys
+                    .peek()
+                    .is_some_and(|largest: &String| *key < largest.as_str())
+                {
+                    keys.pop();
+                    keys.push(key.to_string());
+                }
+            }
+        });
+        keys.into_sorted_vec()
+    }
+
+    /// Returns the number of vectors whose key starts with `prefix`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("images:vector1".to_string(), vec![3.0, 4.0]);
+    ///
+    /// assert_eq!(db.count("docs:"), 1);
+    /// assert_eq!(db.count(""), 2);
+    /// ```
+    pub fn count(&self, prefix: &str) -> usize {
+        if prefix.is_empty() {
+            return self.len();
+        }
+        let mut count = 0;
+        self.storage.scan(&mut |block| {
+            count += block
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .count();
+        });
+        count
+    }
+
+    /// Combines the vectors whose key starts with `prefix` component by component, in
+    /// a single pass, or returns `None` if there is none. Like `dimension_stats`, each
+    /// component is computed over the vectors having it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::vemcache::Aggregation;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("docs:vector2".to_string(), vec![3.0, 2.0]);
+    ///
+    /// assert_eq!(db.aggregate("docs:", Aggregation::Sum), Some(vec![4.0, 4.0]));
+    /// assert_eq!(db.aggregate("docs:", Aggregation::Mean), Some(vec![2.0, 2.0]));
+    /// assert_eq!(db.aggregate("images:", Aggregation::Mean), None);
+    /// ```
+    pub fn aggregate(&self, prefix: &str, aggregation: Aggregation) -> Option<Vec<f32>> {
+        // Sums are accumulated in double precision, so large collections stay accurate
+        let mut sums = Vec::<(f64, usize)>::new();
+        let mut found = false;
+        self.storage.scan(&mut |block| {
+            for (_, vector) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                found = true;
+                if sums.len() < vector.len() {
+                    sums.resize(vector.len(), (0.0, 0));
+                }
+                for ((sum, count), &value) in sums.iter_mut().zip(vector.iter()) {
+                    *sum += value as f64;
+                    *count += 1;
+                }
+            }
+        });
+        if !found {
+            return None;
+        }
+        let aggregated = sums.into_iter().map(|(sum, count)| match aggregation {
+            Aggregation::Sum => sum as f32,
+            Aggregation::Mean => (sum / count as f64) as f32,
+        });
+        Some(aggregated.collect())
+    }
+
+    /// Computes the mean, standard deviation, minimum and maximum of every component
+    /// across the vectors whose key starts with `prefix`, in a single pass. Vectors
+    /// of a collection usually have the same dimension; if not, each component is
+    /// computed over the vectors having it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("docs:vector2".to_string(), vec![3.0, 2.0]);
+    /// db.insert_with_key("images:vector1".to_string(), vec![100.0, 100.0]);
+    ///
+    /// let stats = db.dimension_stats("docs:");
+    /// assert_eq!(stats.len(), 2);
+    /// assert_eq!((stats[0].mean, stats[0].std), (2.0, 1.0));
+    /// assert_eq!((stats[1].min, stats[1].max), (2.0, 2.0));
+    /// ```
+    pub fn dimension_stats(&self, prefix: &str) -> Vec<DimensionStats> {
+        // Welford's algorithm, where `std` holds the sum of squared differences from
+        // the mean until the end
+        let mut stats = Vec::<DimensionStats>::new();
+        self.storage.scan(&mut |block| {
+            for (_, vector) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                if stats.len() < vector.len() {
+                    stats.resize(
+                        vector.len(),
+                        DimensionStats {
+                            count: 0,
+                            mean: 0.0,
+                            std: 0.0,
+                            min: f32::INFINITY,
+                            max: f32::NEG_INFINITY,
+                        },
+                    );
+                }
+                for (stats, &value) in stats.iter_mut().zip(vector.iter()) {
+                    stats.count += 1;
+                    let delta = value as f64 - stats.mean;
+                    stats.mean += delta / stats.count as f64;
+                    stats.std += delta * (value as f64 - stats.mean);
+                    stats.min = stats.min.min(value);
+                    stats.max = stats.max.max(value);
+                }
+            }
+        });
+        for stats in &mut stats {
+            stats.std = (stats.std / stats.count as f64).sqrt();
+        }
+        stats
+    }
+
+    /// Returns the state of the collection of the vectors whose key starts with
+    /// `prefix`, and of the index its searches use, in a single pass over the
+    /// database.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("docs:vector2".to_string(), vec![3.0, 2.0]);
+    /// db.create_index("docs:".to_string(), IndexKind::Hnsw.build(db.default_metric()));
+    /// db.remove("docs:vector2".to_string());
+    ///
+    /// let stats = db.collection_stats("docs:");
+    /// assert_eq!((stats.count, stats.dim), (1, Some(2)));
+    /// let index = stats.index.unwrap();
+    /// assert_eq!((index.kind.as_str(), index.len, index.removed), ("hnsw", 1, 1));
+    /// assert!(!index.needs_compaction);
+    /// ```
+    pub fn collection_stats(&self, prefix: &str) -> CollectionStats {
+        let mut count = 0;
+        let mut memory = 0;
+        let mut dims = None;
+        self.storage.scan(&mut |block| {
+            for (key, vector) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                count += 1;
+                memory += key.len() + std::mem::size_of_val(&vector[..]);
+                let (min, max) = dims.unwrap_or((vector.len(), vector.len()));
+                dims = Some((min.min(vector.len()), max.max(vector.len())));
+            }
+        });
+        let indexed = self.index_for(prefix);
+        let metric = indexed.map_or_else(|| self.metric.name(), |(_, index)| index.metric().name());
+        let index = indexed.map(|(index_prefix, index)| IndexStats {
+            prefix: index_prefix.to_string(),
+            kind: index.kind().to_string(),
+            params: index.params(),
+            len: index.len(),
+            removed: index.removed(),
+            memory: index.memory_usage(),
+            needs_compaction: index.removed() > index.len(),
+        });
+        CollectionStats {
+            count,
+            dim: dims.filter(|(min, max)| min == max).map(|(dim, _)| dim),
+            metric: metric.to_string(),
+            memory,
+            index,
+        }
+    }
+
+    /// Returns up to `n` vectors of the database drawn at random, each vector being
+    /// as likely to be drawn as any other. The vectors are drawn by reservoir
+    /// sampling, in a single pass over the database.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// for i in 0..10 {
+    ///     db.insert_with_key(format!("vector{}", i), vec![i as f32]);
+    /// }
+    ///
+    /// assert_eq!(db.sample(3).len(), 3);
+    /// assert_eq!(db.sample(20).len(), 10);
+    /// ```
+    pub fn sample(&self, n: usize) -> Vec<(String, Arc<Vector>)> {
+        self.sample_collection("", n)
+    }
+
+    /// Samples like `sample`, among the vectors whose key starts with `prefix`.
+    fn sample_collection(&self, prefix: &str, n: usize) -> Vec<(String, Arc<Vector>)> {
+        let mut rng = Uuid::new_v4().as_u64_pair().0;
+        let mut reservoir = Vec::with_capacity(n.min(self.len()));
+        let mut seen = 0u64;
+        self.storage.scan(&mut |block| {
+            for (key, _) in block.iter().filter(|(key, _)| key.starts_with(prefix)) {
+                seen += 1;
+                if reservoir.len() < n {
+                    reservoir.push(key.to_string());
+                    continue;
+                }
+                // Keeps the vector with probability n / seen
+                let slot = (reduce::splitmix64(&mut rng) % seen) as usize;
+                if slot < n {
+                    reservoir[slot] = key.to_string();
+                }
+            }
+        });
+        reservoir
+            .into_iter()
+            .filter_map(|key| {
+                let vector = self.storage.get(&key)?;
+                Some((key, vector))
+            })
+            .collect()
+    }
+
+    /// Returns the key of a vector drawn at random, or `None` if the database is empty.
+    pub fn random_key(&self) -> Option<String> {
+        self.sample(1).pop().map(|(key, _)| key)
+    }
+
     /// Inserts a vector into the Vemcache database with a specified key.
     ///
     /// The key is provided by the user and must be unique. If a vector with the
     /// same key already exists in the database, it will be overwritten.
     ///
+    /// The vector goes through the projection and the pipeline of its collection, if
+    /// any. Vectors rejected by `try_insert_with_key`, like those holding NaN or
+    /// infinite components, are silently dropped; use it to find out.
+    ///
     /// # Arguments
     ///
     /// * `key` - A unique string identifier for the vector.
@@ -61,7 +704,110 @@ impl Vemcache {
     /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
     /// ```
     pub fn insert_with_key(&mut self, key: String, vector: Vec<f32>) {
-        self.storage.insert(key, vector);
+        // Rejections are reported by `try_insert_with_key`
+        let _ = self.try_insert_with_key(key, vector);
+    }
+
+    /// Inserts a vector like `insert_with_key`, reporting whether it was rejected.
+    /// Vectors holding NaN or infinite components, which cannot be ranked by
+    /// distance, are rejected along with those rejected by the pipeline of their
+    /// collection or by deduplication.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the vector was rejected, in which case the
+    /// database is left untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::pipeline::Pipeline;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.set_pipeline("docs:", Pipeline::parse(&["reject", "0", "1"]).unwrap());
+    ///
+    /// assert!(db.try_insert_with_key("docs:vector1".to_string(), vec![0.5, 2.0]).is_err());
+    /// assert!(db.try_insert_with_key("vector2".to_string(), vec![f32::NAN, 1.0]).is_err());
+    /// assert!(db.get("docs:vector1".to_string()).is_none());
+    /// ```
+    pub fn try_insert_with_key(&mut self, key: String, vector: Vec<f32>) -> Result<(), String> {
+        if !vector.iter().all(|value| value.is_finite()) {
+            return Err("Vector components must be finite".to_string());
+        }
+        let vector = self.project(&key, vector);
+        let vector = match self.pipeline_for(&key) {
+            Some(pipeline) => pipeline.apply(vector)?,
+            None => vector,
+        };
+        if self.dedup == Dedup::Reject
+            && self.find_shared(&vector).is_some()
+            && !self
+                .storage
+                .get(&key)
+                .is_some_and(|current| same_components(&current, &vector))
+        {
+            return Err("Duplicate of a stored vector".to_string());
+        }
+        self.insert_raw(key, vector);
+        Ok(())
+    }
+
+    /// Stores a vector under `key` as it is, without going through the projection,
+    /// the pipeline and the deduplication checks of `try_insert_with_key`. Meant for
+    /// vectors that already went through them, like the changes replayed from the
+    /// write-ahead log or received from a primary, which would otherwise be
+    /// transformed twice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::pipeline::Pipeline;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.set_pipeline("docs:", Pipeline::parse(&["normalize"]).unwrap());
+    ///
+    /// db.insert_raw("docs:vector1".to_string(), vec![3.0, 4.0]);
+    /// assert_eq!(*db.get("docs:vector1".to_string()).unwrap(), vec![3.0, 4.0]);
+    /// ```
+    pub fn insert_raw(&mut self, key: String, vector: Vec<f32>) {
+        self.log_insert(&key, &vector);
+        self.store(key, Arc::new(vector));
+    }
+
+    /// Stores the vector stored under `existing` under `key` as well, without copying
+    /// it, and returns whether there was one. The vector is shared by both keys and
+    /// freed once neither holds it: replacing or removing one of the keys leaves the
+    /// other untouched.
+    ///
+    /// The vector is stored as it is, without going through the projection and the
+    /// pipeline of the collection of `key`. The change log records a copy of the
+    /// vector, so replicas and the write-ahead log hold one per key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("animals:cat".to_string(), vec![1.0, 2.0]);
+    ///
+    /// assert!(db.alias("pets:cat".to_string(), "animals:cat"));
+    /// assert!(Arc::ptr_eq(&db.peek("pets:cat").unwrap(), &db.peek("animals:cat").unwrap()));
+    ///
+    /// db.remove("animals:cat".to_string());
+    /// assert_eq!(*db.peek("pets:cat").unwrap(), vec![1.0, 2.0]);
+    /// assert!(!db.alias("pets:dog".to_string(), "animals:dog"));
+    /// ```
+    pub fn alias(&mut self, key: String, existing: &str) -> bool {
+        let Some(vector) = self.storage.get(existing) else {
+            return false;
+        };
+        self.log_insert(&key, &vector);
+        self.store(key, vector);
+        true
     }
 
     /// Inserts a vector into the Vemcache database and generates a unique UUID as the key.
@@ -90,10 +836,23 @@ impl Vemcache {
     /// ```
     pub fn insert_with_uuid(&mut self, vector: Vec<f32>) -> String {
         let id = Uuid::new_v4().to_string();
-        self.storage.insert(id.clone(), vector);
+        self.insert_with_key(id.clone(), vector);
         id
     }
 
+    /// Inserts a vector like `insert_with_uuid`, reporting whether it was rejected
+    /// like `try_insert_with_key` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the vector was rejected, in which case the
+    /// database is left untouched.
+    pub fn try_insert_with_uuid(&mut self, vector: Vec<f32>) -> Result<String, String> {
+        let id = Uuid::new_v4().to_string();
+        self.try_insert_with_key(id.clone(), vector)?;
+        Ok(id)
+    }
+
     /// Removes a vector from the Vemcache database by its key (ID).
     ///
     /// # Arguments
@@ -121,10 +880,54 @@ impl Vemcache {
     /// assert_eq!(removed_vector, Some(vec![1.0, 2.0, 3.0]));
     /// ```
     pub fn remove(&mut self, id: VectorId) -> Option<Vector> {
-        self.storage.remove(&id)
+        let removed = self.unstore(&id)?;
+        self.changelog
+            .append(format!("remove {}", quote::quote(&id)));
+        Some(Arc::try_unwrap(removed).unwrap_or_else(|shared| (*shared).clone()))
+    }
+
+    /// Removes every vector whose key starts with `prefix`, and returns how many were
+    /// removed. Every removal is recorded in the change log like with `remove`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("doc1:chunk1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("doc1:chunk2".to_string(), vec![3.0, 4.0]);
+    /// db.insert_with_key("doc2:chunk1".to_string(), vec![5.0, 6.0]);
+    ///
+    /// assert_eq!(db.remove_prefix("doc1:"), 2);
+    /// assert_eq!(db.len(), 1);
+    /// ```
+    pub fn remove_prefix(&mut self, prefix: &str) -> usize {
+        let keys = self.keys_with_prefix(prefix);
+        let removed = keys.len();
+        for key in keys {
+            self.remove(key);
+        }
+        removed
+    }
+
+    /// Returns the keys of the vectors whose key starts with `prefix`, in no
+    /// particular order.
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<VectorId> {
+        let mut keys = Vec::new();
+        self.storage.scan(&mut |block| {
+            keys.extend(
+                block
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, _)| key.to_string()),
+            );
+        });
+        keys
     }
 
-    /// Retrieves a vector from the Vemcache database by its key (ID).
+    /// Retrieves a vector from the Vemcache database by its key (ID), and records
+    /// the access in its timestamps.
     ///
     /// # Arguments
     ///
@@ -132,7 +935,7 @@ impl Vemcache {
     ///
     /// # Returns
     ///
-    /// An `Option` containing a reference to the retrieved vector, if it exists in the database.
+    /// An `Option` containing the retrieved vector, if it exists in the database.
     /// Returns `None` if the vector with the specified key was not found.
     ///
     /// # Example
@@ -148,10 +951,47 @@ impl Vemcache {
     ///
     /// // Retrieve the vector by its key
     /// let vector = db.get("vector1".to_string());
-    /// assert_eq!(vector, Some(&vec![1.0, 2.0, 3.0]));
+    /// assert_eq!(vector.as_deref(), Some(&vec![1.0, 2.0, 3.0]));
+    /// ```
+    pub fn get(&self, id: VectorId) -> Option<Arc<Vector>> {
+        let vector = self.storage.get(&id)?;
+        if let Some(timestamps) = self.timestamps.get(&id) {
+            timestamps
+                .accessed_at
+                .store(now_millis(), Ordering::Relaxed);
+        }
+        Some(vector)
+    }
+
+    /// Retrieves a vector like `get`, without recording the access, so inspecting a
+    /// vector does not make it look recently used.
+    pub fn peek(&self, id: &str) -> Option<Arc<Vector>> {
+        self.storage.get(id)
+    }
+
+    /// Returns when the vector stored under `id` was inserted and last retrieved.
+    ///
+    /// Timestamps are kept in memory only: vectors loaded from a snapshot, replayed
+    /// from the write-ahead log or replicated are stamped with the time they were
+    /// loaded, and vectors that were already in the storage when the database was
+    /// created have no timestamps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let timestamps = db.timestamps("vector1").unwrap();
+    /// assert!(timestamps.accessed_at >= timestamps.created_at);
     /// ```
-    pub fn get(&self, id: VectorId) -> Option<&Vector> {
-        self.storage.get(&id)
+    pub fn timestamps(&self, id: &str) -> Option<Timestamps> {
+        self.timestamps.get(id).map(|timestamps| Timestamps {
+            created_at: timestamps.created_at,
+            accessed_at: timestamps.accessed_at.load(Ordering::Relaxed),
+        })
     }
 
     /// Calculates the Euclidean distance between two vectors.
@@ -174,29 +1014,93 @@ impl Vemcache {
     /// ```
     /// use vemcache::Vemcache;
     ///
-    /// // Create a new Vemcache database instance
-    /// let db = Vemcache::new();
-    ///
     /// // Define two vectors
     /// let vector1 = vec![1.0, 2.0, 3.0];
     /// let vector2 = vec![4.0, 5.0, 6.0];
     ///
     /// // Calculate the Euclidean distance between the vectors
-    /// let distance = db.euclidean_distance(&vector1, &vector2);
+    /// let distance = Vemcache::euclidean_distance(&vector1, &vector2);
     /// assert_eq!(distance, (27.0 as f32).sqrt());
     /// ```
     pub fn euclidean_distance(v1: &Vector, v2: &Vector) -> f32 {
-        v1.iter()
-            .zip(v2.iter())
-            .map(|(x, y)| (x - y).powi(2))
-            .sum::<f32>()
-            .sqrt()
+        Euclidean.distance(v1, v2)
+    }
+
+    /// Registers a custom metric, which can then be selected by its name with
+    /// `metric` or `set_default_metric`. A metric registered under the name of an
+    /// existing metric replaces it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use vemcache::metric::Metric;
+    /// use vemcache::Vemcache;
+    ///
+    /// // The largest difference between two components
+    /// struct Chebyshev;
+    ///
+    /// impl Metric for Chebyshev {
+    ///     fn name(&self) -> &str {
+    ///         "chebyshev"
+    ///     }
+    ///
+    ///     fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+    ///         v1.iter().zip(v2).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+    ///     }
+    /// }
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.register_metric(Arc::new(Chebyshev));
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let chebyshev = db.metric("chebyshev").unwrap();
+    /// let neighbors = db.k_nearest_neighbors_with_metric(&[1.0, 2.0, 5.0], 1, chebyshev.as_ref());
+    /// assert_eq!(neighbors[0].0, "vector1");
+    /// ```
+    pub fn register_metric(&mut self, metric: Arc<dyn Metric>) {
+        let name = metric.name().to_string();
+        if self.metric.name() == name {
+            self.metric = Arc::clone(&metric);
+        }
+        self.metrics.insert(name, metric);
+    }
+
+    /// Returns the metric registered under `name`, if any.
+    pub fn metric(&self, name: &str) -> Option<Arc<dyn Metric>> {
+        self.metrics.get(name).cloned()
+    }
+
+    /// Returns the names of the registered metrics, in alphabetical order.
+    pub fn metric_names(&self) -> Vec<&str> {
+        let mut names = self.metrics.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the metric used by `k_nearest_neighbors`.
+    pub fn default_metric(&self) -> Arc<dyn Metric> {
+        Arc::clone(&self.metric)
+    }
+
+    /// Selects the metric used by `k_nearest_neighbors`, which is euclidean by default.
+    ///
+    /// Returns `false` if no metric is registered under `name`.
+    pub fn set_default_metric(&mut self, name: &str) -> bool {
+        match self.metric(name) {
+            Some(metric) => {
+                self.metric = metric;
+                true
+            }
+            None => false,
+        }
     }
 
     /// Finds the k-nearest neighbors to a given query vector in the Vemcache database.
     ///
-    /// The k-nearest neighbors are determined based on the Euclidean distance between
-    /// the query vector and the vectors stored in the database. The function returns
+    /// The k-nearest neighbors are determined based on the distance between the query
+    /// vector and the vectors stored in the database, according to the default metric
+    /// (Euclidean unless changed with `set_default_metric`). The function returns
     /// a vector of tuples, where each tuple contains the key (ID) and a reference to
     /// one of the k-nearest neighbor vectors.
     ///
@@ -207,13 +1111,13 @@ impl Vemcache {
     ///
     /// # Returns
     ///
-    /// A vector of tuples containing the keys (IDs) and references to the k-nearest
-    /// neighbor vectors. If there are fewer than k vectors in the database, the function
+    /// A vector of tuples containing the keys (IDs) and the k-nearest neighbor vectors. If there are fewer than k vectors in the database, the function
     /// returns all available vectors.
     ///
     /// # Example
     ///
     /// ```
+    /// use std::sync::Arc;
     /// use vemcache::Vemcache;
     ///
     /// // Create a new Vemcache database instance
@@ -230,184 +1134,1960 @@ impl Vemcache {
     /// // Find the 2 nearest neighbors to the query vector
     /// let nearest_neighbors = db.k_nearest_neighbors(&query_vector, 2);
     /// assert_eq!(nearest_neighbors, vec![
-    ///     ("vector1".to_string(), &vec![1.0, 2.0, 3.0]),
-    ///     ("vector2".to_string(), &vec![4.0, 5.0, 6.0])
+    ///     ("vector1".to_string(), Arc::new(vec![1.0, 2.0, 3.0])),
+    ///     ("vector2".to_string(), Arc::new(vec![4.0, 5.0, 6.0]))
     /// ]);
     /// ```
-    pub fn k_nearest_neighbors(&self, query: &Vec<f32>, k: usize) -> Vec<(String, &Vec<f32>)> {
-        let mut neighbors = self
-            .storage
-            .iter()
-            .map(|(id, vector)| (id.clone(), Vemcache::euclidean_distance(query, vector)))
-            .collect::<Vec<_>>();
-        neighbors.sort_by(|(_, dist1), (_, dist2)| dist1.partial_cmp(dist2).unwrap());
-        neighbors
+    pub fn k_nearest_neighbors(&self, query: &[f32], k: usize) -> Vec<(String, Arc<Vector>)> {
+        self.k_nearest_neighbors_with_metric(query, k, self.metric.as_ref())
+    }
+
+    /// Finds the k-nearest neighbors to a given query vector according to `metric`,
+    /// nearest first.
+    pub fn k_nearest_neighbors_with_metric(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: &dyn Metric,
+    ) -> Vec<(String, Arc<Vector>)> {
+        self.resolve(self.exact_search("", query, k, metric))
+    }
+
+    /// Finds the k-nearest neighbors of `query` among the vectors stored under
+    /// `candidates`, nearest first, e.g. to re-rank the results of another system.
+    /// Candidates holding no vector are ignored. Vectors are ranked by `metric`, or
+    /// by the default metric if `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0]);
+    /// db.insert_with_key("vector3".to_string(), vec![7.0, 8.0]);
+    ///
+    /// let candidates = ["vector3".to_string(), "vector2".to_string(), "vector4".to_string()];
+    /// let neighbors = db.nearest_among(&[1.0, 2.0], 1, &candidates, None);
+    /// assert_eq!(neighbors[0].0, "vector2");
+    /// ```
+    pub fn nearest_among(
+        &self,
+        query: &[f32],
+        k: usize,
+        candidates: &[String],
+        metric: Option<&dyn Metric>,
+    ) -> Vec<(String, Arc<Vector>)> {
+        let metric = metric.unwrap_or(self.metric.as_ref());
+        let mut seen = HashSet::new();
+        let keys = candidates
+            .iter()
+            .map(String::as_str)
+            .filter(|key| seen.insert(*key));
+        self.resolve(nearest(self.score(query, keys, metric), k))
+    }
+
+    /// Returns each of `keys` holding a vector along with the distance of its
+    /// vector to `query` according to `metric`.
+    fn score<'a>(
+        &self,
+        query: &[f32],
+        keys: impl Iterator<Item = &'a str>,
+        metric: &dyn Metric,
+    ) -> Vec<(String, f32)> {
+        let (keys, vectors): (Vec<&str>, Vec<Arc<Vector>>) = keys
+            .filter_map(|key| Some((key, self.storage.get(key)?)))
+            .unzip();
+        let mut distances = Vec::with_capacity(vectors.len());
+        let slices = vectors
+            .iter()
+            .map(|vector| vector.as_slice())
+            .collect::<Vec<_>>();
+        metric.distances(query, &slices, &mut distances);
+        keys.into_iter()
+            .map(str::to_string)
+            .zip(distances)
+            .collect()
+    }
+
+    /// Returns the keys of the k-nearest neighbors of `query` among the vectors whose
+    /// key starts with `prefix` along with their distance, by comparing it with
+    /// every one of them.
+    fn exact_search(
+        &self,
+        prefix: &str,
+        query: &[f32],
+        k: usize,
+        metric: &dyn Metric,
+    ) -> Vec<(String, f32)> {
+        // Without a deadline, the distances are always computed
+        nearest(
+            self.distances(prefix, query, metric, None)
+                .unwrap_or_default(),
+            k,
+        )
+    }
+
+    /// Returns the key of every vector whose key starts with `prefix` along with its
+    /// distance to `query`, scoring the vectors a block at a time, or on the GPU if
+    /// one is attached and the database is large enough.
+    ///
+    /// Scoring on the CPU stops at the first block reached after `deadline`.
+    fn distances(
+        &self,
+        prefix: &str,
+        query: &[f32],
+        metric: &dyn Metric,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<(String, f32)>, DeadlineExceeded> {
+        #[cfg(feature = "gpu")]
+        if let Some((gpu, threshold)) = &self.gpu {
+            if self.storage.len() >= *threshold {
+                let distances = gpu.distances(
+                    self.storage.as_ref(),
+                    self.generation,
+                    prefix,
+                    query,
+                    metric.name(),
+                );
+                if let Some(distances) = distances {
+                    return Ok(distances);
+                }
+            }
+        }
+
+        let mut distances = Vec::new();
+        let mut scores = Vec::new();
+        let mut exceeded = false;
+        self.storage.scan(&mut |block| {
+            if exceeded || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                exceeded = true;
+                return;
+            }
+            let (ids, vectors): (Vec<&str>, Vec<&[f32]>) = block
+                .iter()
+                .filter(|(id, _)| id.starts_with(prefix))
+                .copied()
+                .unzip();
+            scores.clear();
+            metric.distances(query, &vectors, &mut scores);
+            distances.extend(ids.into_iter().map(str::to_string).zip(scores.drain(..)));
+        });
+        if exceeded {
+            return Err(DeadlineExceeded);
+        }
+        Ok(distances)
+    }
+
+    /// Looks up the vectors of search results, in order.
+    fn resolve(&self, results: Vec<(String, f32)>) -> Vec<(String, Arc<Vector>)> {
+        results
             .into_iter()
-            .take(k)
-            .map(|(id, _)| (id.clone(), self.storage.get(&id).unwrap()))
+            .filter_map(|(id, _)| {
+                let vector = self.storage.get(&id)?;
+                Some((id, vector))
+            })
             .collect()
     }
 
+    /// Indexes the collection of vectors whose key starts with `prefix` with `index`,
+    /// replacing any index of the same collection. The vectors already in the
+    /// collection are added to the index right away.
+    ///
+    /// Collections may be nested, in which case a vector is added to the index of
+    /// every collection it belongs to, and searches use the index of the innermost one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("images:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let index = IndexKind::Hnsw.build(db.default_metric());
+    /// db.create_index("docs:".to_string(), index);
+    ///
+    /// // Only the vectors of the collection are searched
+    /// let neighbors = db.search("docs:vector1", &[1.0, 2.0, 3.0], 10, None);
+    /// assert_eq!(neighbors.len(), 1);
+    /// ```
+    pub fn create_index(&mut self, prefix: String, index: Box<dyn VectorIndex>) {
+        let mut builder = self.start_index_build(prefix, index);
+        builder.run();
+        self.finish_index_build(builder);
+    }
+
+    /// Starts building `index` over the collection of vectors whose key starts with
+    /// `prefix`, like `create_index`, but lets the database serve other commands in
+    /// the meantime: the vectors are indexed by `IndexBuilder::run`, which only
+    /// needs the returned builder, then `finish_index_build` catches up with the
+    /// changes made to the collection during the build and installs the index.
+    ///
+    /// Searches keep using the previous index of the collection, if any, or an
+    /// exhaustive search until the build is finished.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let index = IndexKind::Hnsw.build(db.default_metric());
+    /// let mut builder = db.start_index_build("docs:".to_string(), index);
+    /// let builder = std::thread::spawn(move || {
+    ///     builder.run();
+    ///     builder
+    /// });
+    /// db.insert_with_key("docs:vector2".to_string(), vec![4.0, 5.0, 6.0]);
+    ///
+    /// assert!(db.finish_index_build(builder.join().unwrap()));
+    /// assert_eq!(db.indexes()[0].1.len(), 2);
+    /// ```
+    pub fn start_index_build(
+        &mut self,
+        prefix: String,
+        index: Box<dyn VectorIndex>,
+    ) -> IndexBuilder {
+        let vectors = self
+            .storage
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        let progress = Arc::new(AtomicUsize::new(0));
+        self.next_build += 1;
+        self.builds.push(PendingBuild {
+            id: self.next_build,
+            prefix: prefix.clone(),
+            kind: index.kind().to_string(),
+            total: vectors.len(),
+            progress: Arc::clone(&progress),
+            changed: HashSet::new(),
+        });
+        IndexBuilder {
+            id: self.next_build,
+            prefix,
+            index,
+            vectors,
+            progress,
+        }
+    }
+
+    /// Starts building a fresh index of the collection of vectors whose key starts
+    /// with `prefix`, like `start_index_build`, of the same kind and metric as its
+    /// current index, and with the same parameters except those in `params`. The
+    /// current index keeps answering searches until `finish_index_build` replaces
+    /// it, so rebuilding reclaims the memory of removed vectors, or changes the
+    /// parameters of an index, without searches falling back to exhaustive ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the collection has no index, if its index is not
+    /// of a built-in kind, or if it has no parameter of one of the names given.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.create_index("docs:".to_string(), IndexKind::Hnsw.build(db.default_metric()));
+    ///
+    /// let params = [("m".to_string(), 32)];
+    /// let mut builder = db.start_index_rebuild("docs:", &params).unwrap();
+    /// builder.run();
+    /// assert!(db.finish_index_build(builder));
+    /// assert_eq!(db.indexes()[0].1.params()[0], ("m", 32));
+    /// assert!(db.start_index_rebuild("images:", &[]).is_err());
+    /// ```
+    pub fn start_index_rebuild(
+        &mut self,
+        prefix: &str,
+        params: &[(String, usize)],
+    ) -> Result<IndexBuilder, String> {
+        let (_, index) = self
+            .indexes
+            .iter()
+            .find(|(other, _)| other == prefix)
+            .ok_or("The collection has no index")?;
+        let kind = IndexKind::from_name(index.kind())
+            .ok_or_else(|| format!("{} indexes cannot be rebuilt", index.kind()))?;
+        let metric = self
+            .metric(index.metric().name())
+            .ok_or("The metric of the index is no longer registered")?;
+        let mut merged = index
+            .params()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect::<Vec<_>>();
+        for (name, value) in params {
+            match merged.iter_mut().find(|(other, _)| other == name) {
+                Some((_, current)) => *current = *value,
+                None => merged.push((name.clone(), *value)),
+            }
+        }
+        let index = kind.build_with_params(metric, &merged)?;
+        Ok(self.start_index_build(prefix.to_string(), index))
+    }
+
+    /// Installs an index built by `builder`, replacing any index of its collection,
+    /// once the changes made to the collection since the build started are applied
+    /// to it.
+    ///
+    /// Returns `false` if the build was aborted or the index of the collection
+    /// dropped in the meantime, in which case the index is discarded.
+    pub fn finish_index_build(&mut self, mut builder: IndexBuilder) -> bool {
+        let Some(position) = self.builds.iter().position(|build| build.id == builder.id) else {
+            return false;
+        };
+        let build = self.builds.remove(position);
+        builder.run();
+        for key in build.changed {
+            match self.storage.get(&key) {
+                Some(vector) => builder.index.insert(&key, vector),
+                None => builder.index.remove(&key),
+            }
+        }
+        self.indexes.retain(|(other, _)| *other != builder.prefix);
+        self.indexes.push((builder.prefix, builder.index));
+        true
+    }
+
+    /// Forgets the index build identified by `id`, e.g. because it failed.
+    pub fn abort_index_build(&mut self, id: u64) {
+        self.builds.retain(|build| build.id != id);
+    }
+
+    /// Returns the progress of the index builds started with `start_index_build`
+    /// that are not finished yet, oldest first.
+    pub fn index_builds(&self) -> Vec<IndexBuildProgress> {
+        self.builds
+            .iter()
+            .map(|build| IndexBuildProgress {
+                prefix: build.prefix.clone(),
+                kind: build.kind.clone(),
+                indexed: build.progress.load(Ordering::Relaxed),
+                total: build.total,
+            })
+            .collect()
+    }
+
+    /// Drops the index of the collection of vectors whose key starts with `prefix`,
+    /// and aborts the builds of an index for it.
+    ///
+    /// Returns `false` if the collection is neither indexed nor being indexed.
+    pub fn drop_index(&mut self, prefix: &str) -> bool {
+        let count = self.indexes.len() + self.builds.len();
+        self.indexes.retain(|(other, _)| other != prefix);
+        self.builds.retain(|build| build.prefix != prefix);
+        self.indexes.len() + self.builds.len() != count
+    }
+
+    /// Rebuilds the indexes that still hold removed vectors, so they stop slowing
+    /// down searches and their memory is reclaimed. Returns the number of indexes
+    /// rebuilt.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.create_index(String::new(), IndexKind::Hnsw.build(db.default_metric()));
+    /// db.remove("vector1".to_string());
+    ///
+    /// assert_eq!(db.compact_indexes(), 1);
+    /// assert_eq!(db.indexes()[0].1.removed(), 0);
+    /// ```
+    pub fn compact_indexes(&mut self) -> usize {
+        let mut compacted = 0;
+        for (_, index) in &mut self.indexes {
+            if index.removed() > 0 {
+                index.compact();
+                compacted += 1;
+            }
+        }
+        compacted
+    }
+
+    /// Reduces the dimension of the vectors of the collection whose key starts with
+    /// `prefix` to `target_dim` with a projection of the given kind, and returns
+    /// how many vectors were projected. Vectors inserted into the collection later
+    /// are projected as well, until `drop_projection` is called.
+    ///
+    /// PCA is fitted on up to `PCA_SAMPLE_SIZE` vectors of the collection drawn at
+    /// random. Only the vectors having the dimension of the first vector sampled
+    /// are projected. The indexes are rebuilt once every vector is projected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the collection is empty, or if `target_dim` is 0
+    /// or not smaller than the dimension of its vectors.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::reduce::ReductionKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0, 3.0, 4.0]);
+    /// db.insert_with_key("docs:vector2".to_string(), vec![4.0, 3.0, 2.0, 1.0]);
+    ///
+    /// assert_eq!(db.reduce("docs:", ReductionKind::Random, 2), Ok(2));
+    /// assert_eq!(db.get("docs:vector1".to_string()).unwrap().len(), 2);
+    ///
+    /// // Later inserts into the collection are projected too
+    /// db.insert_with_key("docs:vector3".to_string(), vec![1.0, 1.0, 1.0, 1.0]);
+    /// assert_eq!(db.get("docs:vector3".to_string()).unwrap().len(), 2);
+    /// ```
+    pub fn reduce(
+        &mut self,
+        prefix: &str,
+        kind: ReductionKind,
+        target_dim: usize,
+    ) -> Result<usize, String> {
+        let sample = self.sample_collection(prefix, PCA_SAMPLE_SIZE);
+        let dim = sample
+            .first()
+            .map(|(_, vector)| vector.len())
+            .ok_or("Collection is empty")?;
+        if target_dim == 0 || target_dim >= dim {
+            return Err(format!(
+                "Target dimension must be between 1 and {}",
+                dim.saturating_sub(1)
+            ));
+        }
+        let projection = match kind {
+            ReductionKind::Pca => {
+                let vectors = sample
+                    .iter()
+                    .map(|(_, vector)| vector.as_slice())
+                    .collect::<Vec<&[f32]>>();
+                Projection::pca(&vectors, target_dim)
+            }
+            ReductionKind::Random => {
+                Projection::random(dim, target_dim, Uuid::new_v4().as_u64_pair().0)
+            }
+        }
+        .ok_or("Could not fit the projection")?;
+        drop(sample);
+
+        // The vectors are replaced before the projection is registered, so they are
+        // only projected once
+        let vectors = self
+            .storage
+            .iter()
+            .filter(|(key, vector)| key.starts_with(prefix) && vector.len() == dim)
+            .collect::<Vec<(VectorId, Arc<Vector>)>>();
+        let count = vectors.len();
+        // Stored vectors already went through the pipeline
+        for (key, vector) in vectors {
+            self.insert_raw(key, projection.project(&vector));
+        }
+        self.projections.retain(|(other, _)| other != prefix);
+        self.projections.push((prefix.to_string(), projection));
+        self.compact_indexes();
+        Ok(count)
+    }
+
+    /// Stops projecting the vectors inserted into the collection whose key starts
+    /// with `prefix`, and returns whether it had a projection. Vectors already
+    /// projected are kept as they are.
+    pub fn drop_projection(&mut self, prefix: &str) -> bool {
+        let len = self.projections.len();
+        self.projections.retain(|(other, _)| other != prefix);
+        self.projections.len() < len
+    }
+
+    /// Returns the projections of the collections along with their key prefix.
+    pub fn projections(&self) -> impl Iterator<Item = (&str, &Projection)> {
+        self.projections
+            .iter()
+            .map(|(prefix, projection)| (prefix.as_str(), projection))
+    }
+
+    /// Projects a vector inserted under `key` with the projection of the innermost
+    /// collection it belongs to, if it has the dimension the projection applies to.
+    fn project(&self, key: &str, vector: Vec<f32>) -> Vec<f32> {
+        match innermost(&self.projections, key) {
+            Some(projection) if projection.input_dim() == vector.len() => {
+                projection.project(&vector)
+            }
+            _ => vector,
+        }
+    }
+
+    /// Sets the pipeline applied to the vectors inserted into the collection whose
+    /// key starts with `prefix`, after their projection if any, replacing its
+    /// previous pipeline. The vectors already in the collection are left as they are.
+    ///
+    /// Vectors belong to the collection with the longest prefix of their key that
+    /// has a pipeline.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::pipeline::Pipeline;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.set_pipeline("docs:", Pipeline::parse(&["normalize"]).unwrap());
+    ///
+    /// db.insert_with_key("docs:vector1".to_string(), vec![3.0, 4.0]);
+    /// assert_eq!(*db.get("docs:vector1".to_string()).unwrap(), vec![0.6, 0.8]);
+    /// ```
+    pub fn set_pipeline(&mut self, prefix: &str, pipeline: Pipeline) {
+        self.pipelines.retain(|(other, _)| other != prefix);
+        self.pipelines.push((prefix.to_string(), pipeline));
+    }
+
+    /// Removes the pipeline of the collection whose key starts with `prefix`, and
+    /// returns whether it had one.
+    pub fn drop_pipeline(&mut self, prefix: &str) -> bool {
+        let len = self.pipelines.len();
+        self.pipelines.retain(|(other, _)| other != prefix);
+        self.pipelines.len() < len
+    }
+
+    /// Returns the pipelines of the collections along with their key prefix.
+    pub fn pipelines(&self) -> impl Iterator<Item = (&str, &Pipeline)> {
+        self.pipelines
+            .iter()
+            .map(|(prefix, pipeline)| (prefix.as_str(), pipeline))
+    }
+
+    /// Returns the pipeline applied to the vectors inserted under `key`, if any.
+    pub fn pipeline_for(&self, key: &str) -> Option<&Pipeline> {
+        innermost(&self.pipelines, key)
+    }
+
+    /// Sets the time to live of the vectors of the collection whose key starts with
+    /// `prefix`, in seconds, replacing its previous one: every vector inserted into
+    /// the collection is removed `ttl` seconds later by `remove_expired`, unless it is
+    /// inserted again in the meantime, which starts its time to live over. The
+    /// vectors already in the collection expire `ttl` seconds from now.
+    ///
+    /// Vectors belong to the collection with the longest prefix of their key that
+    /// has a time to live.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.set_ttl("sessions:", 0);
+    ///
+    /// db.insert_with_key("sessions:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("docs:vector1".to_string(), vec![3.0, 4.0]);
+    /// assert!(db.expires_at("sessions:vector1").is_some());
+    ///
+    /// assert_eq!(db.remove_expired(), vec!["sessions:vector1".to_string()]);
+    /// assert_eq!(db.len(), 1);
+    /// ```
+    pub fn set_ttl(&mut self, prefix: &str, ttl: u64) {
+        self.ttls.retain(|(other, _)| other != prefix);
+        self.ttls.push((prefix.to_string(), ttl));
+        for key in self.keys_with_prefix(prefix) {
+            self.schedule_expiration(&key);
+        }
+    }
+
+    /// Removes the time to live of the collection whose key starts with `prefix`,
+    /// and returns whether it had one. Its vectors no longer expire, unless they
+    /// belong to an enclosing collection with a time to live, in which case they
+    /// expire like vectors inserted into it now.
+    pub fn drop_ttl(&mut self, prefix: &str) -> bool {
+        let len = self.ttls.len();
+        self.ttls.retain(|(other, _)| other != prefix);
+        if self.ttls.len() == len {
+            return false;
+        }
+        for key in self.keys_with_prefix(prefix) {
+            self.schedule_expiration(&key);
+        }
+        true
+    }
+
+    /// Returns the times to live of the collections, in seconds, along with their key
+    /// prefix.
+    pub fn ttls(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.ttls
+            .iter()
+            .map(|(prefix, ttl)| (prefix.as_str(), *ttl))
+    }
+
+    /// Returns when the vector stored under `key` expires, in milliseconds since the
+    /// Unix epoch, or `None` if it does not.
+    pub fn expires_at(&self, key: &str) -> Option<u64> {
+        self.expirations.get(key).copied()
+    }
+
+    /// Removes the vectors whose time to live has elapsed, like `remove`, and
+    /// returns their keys, in order of expiry.
+    pub fn remove_expired(&mut self) -> Vec<VectorId> {
+        let now = now_millis();
+        let mut expired = Vec::new();
+        while let Some((expires_at, key)) = self.expiry_queue.pop_first() {
+            if expires_at > now {
+                self.expiry_queue.insert((expires_at, key));
+                break;
+            }
+            self.expirations.remove(&key);
+            if self.remove(key.clone()).is_some() {
+                expired.push(key);
+            }
+        }
+        expired
+    }
+
+    /// Starts the time to live of the vector stored under `key` over, according to
+    /// the collection it belongs to.
+    fn schedule_expiration(&mut self, key: &str) {
+        self.cancel_expiration(key);
+        if let Some(&ttl) = innermost(&self.ttls, key) {
+            let expires_at = now_millis().saturating_add(ttl.saturating_mul(1000));
+            self.expirations.insert(key.to_string(), expires_at);
+            self.expiry_queue.insert((expires_at, key.to_string()));
+        }
+    }
+
+    /// Stops the vector stored under `key` from expiring.
+    fn cancel_expiration(&mut self, key: &str) {
+        if let Some(expires_at) = self.expirations.remove(key) {
+            self.expiry_queue.remove(&(expires_at, key.to_string()));
+        }
+    }
+
+    /// Returns whether an index holds more removed vectors than live ones, in which
+    /// case it should be compacted with `compact_indexes`.
+    pub fn needs_compaction(&self) -> bool {
+        self.indexes
+            .iter()
+            .any(|(_, index)| index.removed() > index.len())
+    }
+
+    /// Returns the key prefix and index of every indexed collection, ordered by prefix.
+    pub fn indexes(&self) -> Vec<(&str, &dyn VectorIndex)> {
+        let mut indexes = self
+            .indexes
+            .iter()
+            .map(|(prefix, index)| (prefix.as_str(), index.as_ref()))
+            .collect::<Vec<_>>();
+        indexes.sort_unstable_by_key(|(prefix, _)| *prefix);
+        indexes
+    }
+
+    /// Returns the key prefix and index of the innermost indexed collection `key`
+    /// belongs to, if any.
+    pub fn index_for(&self, key: &str) -> Option<(&str, &dyn VectorIndex)> {
+        self.indexes
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, index)| (prefix.as_str(), index.as_ref()))
+    }
+
+    /// Reads every vector whose key starts with `prefix` from the storage, so the
+    /// first searches after startup do not wait for them to be paged in from disk.
+    /// With `indexes`, the indexes of the collection and of the collections nested
+    /// in it are also searched once for one of their vectors, so the structures
+    /// every search goes through are in memory too.
+    ///
+    /// Returns the number of vectors read and of indexes searched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0]);
+    /// db.insert_with_key("images:vector1".to_string(), vec![2.0, 1.0]);
+    /// db.create_index("docs:".to_string(), IndexKind::Hnsw.build(db.default_metric()));
+    ///
+    /// assert_eq!(db.warm_up("docs:", true), (1, 1));
+    /// assert_eq!(db.warm_up("", false), (2, 0));
+    /// ```
+    pub fn warm_up(&self, prefix: &str, indexes: bool) -> (usize, usize) {
+        let vectors = self.storage.warm(prefix);
+        if !indexes {
+            return (vectors, 0);
+        }
+        let mut searched = 0;
+        for (collection, index) in &self.indexes {
+            if !collection.starts_with(prefix) && !prefix.starts_with(collection.as_str()) {
+                continue;
+            }
+            let query = self
+                .storage
+                .iter()
+                .find(|(key, _)| key.starts_with(collection.as_str()));
+            if let Some((_, query)) = query {
+                index.search(&query, 1);
+                searched += 1;
+            }
+        }
+        (vectors, searched)
+    }
+
+    /// Measures the recall@k of the index of every indexed collection: up to
+    /// `sample_size` vectors of the collection, evenly spread across its keys, are
+    /// searched for through the index and by comparing them with every vector of the
+    /// collection, and the share of the exact neighbors the index returned is
+    /// averaged over them. A query is not counted among its own neighbors.
+    ///
+    /// Exact searches compare each query with the whole collection, so measuring
+    /// takes as long as `sample_size` exhaustive searches per collection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// for i in 0..20 {
+    ///     db.insert_with_key(format!("docs:{}", i), vec![i as f32, 1.0]);
+    /// }
+    /// db.create_index("docs:".to_string(), IndexKind::Flat.build(db.default_metric()));
+    ///
+    /// let recall = db.recall(5, 3);
+    /// assert_eq!(recall[0].collection, "docs:");
+    /// assert_eq!(recall[0].queries, 5);
+    /// assert_eq!(recall[0].recall, 1.0);
+    /// ```
+    pub fn recall(&self, sample_size: usize, k: usize) -> Vec<Recall> {
+        let mut recalls = Vec::new();
+        for (prefix, index) in self.indexes() {
+            let samples = self.recall_samples(prefix, index, sample_size, k);
+            recalls.push(Recall {
+                collection: prefix.to_string(),
+                index: index.kind().to_string(),
+                queries: samples.len(),
+                recall: measure_recall(index, &samples, k).0,
+            });
+        }
+        recalls
+    }
+
+    /// Sets the search parameter of every approximate index to the smallest value in
+    /// its range, and so the fastest searches, at which its recall@k reaches
+    /// `target`, or to the largest value in its range if none does. The recall is measured as by
+    /// `recall`, with up to `sample_size` vectors of the collection as queries,
+    /// and is assumed to grow with the parameter.
+    ///
+    /// Returns the parameters chosen, along with the recall and latency measured.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// for i in 0..50 {
+    ///     db.insert_with_key(format!("docs:{}", i), vec![i as f32, (i % 7) as f32]);
+    /// }
+    /// db.create_index("docs:".to_string(), IndexKind::Hnsw.build(db.default_metric()));
+    ///
+    /// let tunings = db.tune_indexes(0.9, 10, 3);
+    /// assert_eq!(tunings[0].param, "ef_search");
+    /// assert!(tunings[0].recall >= 0.9);
+    /// assert_eq!(db.indexes()[0].1.search_param().unwrap().1, tunings[0].value);
+    /// ```
+    pub fn tune_indexes(&mut self, target: f64, sample_size: usize, k: usize) -> Vec<Tuning> {
+        let mut tunings = Vec::new();
+        for position in 0..self.indexes.len() {
+            let (prefix, index) = &self.indexes[position];
+            let Some((param, previous, range)) = index.search_param() else {
+                continue;
+            };
+            let samples = self.recall_samples(prefix, index.as_ref(), sample_size, k);
+            if samples.is_empty() {
+                continue;
+            }
+            let collection = prefix.clone();
+            let index = &mut self.indexes[position].1;
+            let (mut low, mut high) = range.into_inner();
+            while low < high {
+                let middle = low + (high - low) / 2;
+                index.set_search_param(middle);
+                if measure_recall(index.as_ref(), &samples, k).0 >= target {
+                    high = middle;
+                } else {
+                    low = middle + 1;
+                }
+            }
+            index.set_search_param(low);
+            let (recall, latency) = measure_recall(index.as_ref(), &samples, k);
+            tunings.push(Tuning {
+                collection,
+                param,
+                previous,
+                value: low,
+                recall,
+                latency,
+            });
+        }
+        tunings
+    }
+
+    /// Picks up to `sample_size` vectors of the collection indexed by `index`,
+    /// evenly spread across its keys, and finds their exact k-nearest neighbors,
+    /// leaving out those without any.
+    fn recall_samples(
+        &self,
+        prefix: &str,
+        index: &dyn VectorIndex,
+        sample_size: usize,
+        k: usize,
+    ) -> Vec<RecallSample> {
+        let mut keys = self
+            .storage
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        let count = sample_size.min(keys.len());
+        let mut samples = Vec::with_capacity(count);
+        for i in 0..count {
+            let key = &keys[i * keys.len() / count];
+            let Some(query) = self.storage.get(key) else {
+                continue;
+            };
+            let exact = recall_neighbors(
+                key,
+                self.exact_search(prefix, &query, k + 1, index.metric()),
+                k,
+            );
+            if !exact.is_empty() {
+                samples.push(RecallSample {
+                    key: key.clone(),
+                    query,
+                    exact,
+                });
+            }
+        }
+        samples
+    }
+
+    /// Finds the k-nearest neighbors of `query` in the collection `key` belongs to.
+    ///
+    /// If the collection is indexed, only its vectors are searched, through its index
+    /// unless `metric` differs from the metric of the index. Otherwise, the whole
+    /// database is searched. Vectors are ranked by `metric`, or by the metric of
+    /// the index or the default metric if `metric` is `None`. Vectors at the same
+    /// distance are ranked by key.
+    pub fn search(
+        &self,
+        key: &str,
+        query: &[f32],
+        k: usize,
+        metric: Option<&dyn Metric>,
+    ) -> Vec<(String, Arc<Vector>)> {
+        // Without a deadline, the search always completes
+        self.search_with_deadline(key, query, k, metric, 1, None)
+            .unwrap_or_default()
+    }
+
+    /// Searches like `search`, but gives up once `deadline` has passed, so a search
+    /// comparing the query with a large collection cannot hold on to the database
+    /// for too long. Searches answered by an index always complete.
+    ///
+    /// Approximate indexes may miss some of the nearest vectors. If `oversample` is
+    /// more than 1, a search answered by an index fetches `k * oversample`
+    /// candidates from it instead, re-scores them exactly against the stored
+    /// vectors, and keeps the k nearest, trading some speed for recall.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DeadlineExceeded` if the search did not complete before `deadline`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Instant;
+    /// use vemcache::vemcache::DeadlineExceeded;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let expired = Some(Instant::now());
+    /// let neighbors = db.search_with_deadline("vector1", &[1.0, 2.0, 3.0], 1, None, 1, expired);
+    /// assert_eq!(neighbors, Err(DeadlineExceeded));
+    /// ```
+    pub fn search_with_deadline(
+        &self,
+        key: &str,
+        query: &[f32],
+        k: usize,
+        metric: Option<&dyn Metric>,
+        oversample: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<(String, Arc<Vector>)>, DeadlineExceeded> {
+        let explanation = self.explain_search(key, query, k, metric, oversample, deadline)?;
+        Ok(self.resolve(explanation.neighbors))
+    }
+
+    /// Searches like `search_with_deadline`, but returns how the search was carried
+    /// out along with the keys of the nearest vectors, e.g. to tell whether it was
+    /// answered by the index of the collection or by comparing the query with all of
+    /// its vectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DeadlineExceeded` if the search did not complete before `deadline`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::index::IndexKind;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("docs:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("images:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let explanation = db.explain_search("docs:vector1", &[1.0, 2.0, 3.0], 10, None, 1, None)?;
+    /// assert!(!explanation.used_index);
+    /// assert_eq!(explanation.candidates, 2);
+    ///
+    /// let index = IndexKind::Flat.build(db.default_metric());
+    /// db.create_index("docs:".to_string(), index);
+    /// let explanation = db.explain_search("docs:vector1", &[1.0, 2.0, 3.0], 10, None, 1, None)?;
+    /// assert!(explanation.used_index);
+    /// assert_eq!(explanation.collection, "docs:");
+    /// assert_eq!(explanation.candidates, 1);
+    /// assert_eq!(explanation.reranked, 0);
+    ///
+    /// let explanation = db.explain_search("docs:vector1", &[1.0, 2.0, 3.0], 1, None, 4, None)?;
+    /// assert_eq!(explanation.reranked, 1);
+    /// assert_eq!(explanation.neighbors[0].0, "docs:vector1");
+    /// # Ok::<(), vemcache::vemcache::DeadlineExceeded>(())
+    /// ```
+    pub fn explain_search(
+        &self,
+        key: &str,
+        query: &[f32],
+        k: usize,
+        metric: Option<&dyn Metric>,
+        oversample: usize,
+        deadline: Option<Instant>,
+    ) -> Result<SearchExplanation, DeadlineExceeded> {
+        let (collection, index) = match self.index_for(key) {
+            Some((prefix, index)) => (prefix, Some(index)),
+            None => ("", None),
+        };
+        let kind = index.map(|index| index.kind().to_string());
+        match index {
+            Some(index) if metric.is_none_or(|metric| metric.name() == index.metric().name()) => {
+                if oversample <= 1 {
+                    let (neighbors, candidates) = index.search_counted(query, k);
+                    return Ok(SearchExplanation {
+                        collection: collection.to_string(),
+                        index: kind,
+                        used_index: true,
+                        metric: index.metric().name().to_string(),
+                        collection_size: index.len(),
+                        candidates,
+                        neighbors,
+                        reranked: 0,
+                    });
+                }
+                let (fetched, candidates) =
+                    index.search_counted(query, k.saturating_mul(oversample));
+                let reranked = fetched.len();
+                let keys = fetched.iter().map(|(key, _)| key.as_str());
+                let neighbors = nearest(self.score(query, keys, index.metric()), k);
+                Ok(SearchExplanation {
+                    collection: collection.to_string(),
+                    index: kind,
+                    used_index: true,
+                    metric: index.metric().name().to_string(),
+                    collection_size: index.len(),
+                    candidates: candidates + reranked,
+                    neighbors,
+                    reranked,
+                })
+            }
+            _ => {
+                let metric = metric.unwrap_or(self.metric.as_ref());
+                let distances = self.distances(collection, query, metric, deadline)?;
+                let candidates = distances.len();
+                Ok(SearchExplanation {
+                    collection: collection.to_string(),
+                    index: kind,
+                    used_index: false,
+                    metric: metric.name().to_string(),
+                    collection_size: candidates,
+                    candidates,
+                    neighbors: nearest(distances, k),
+                    reranked: 0,
+                })
+            }
+        }
+    }
+
+    /// Finds every vector within `radius` of a query vector according to `metric`,
+    /// nearest first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::metric::L1;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
+    ///
+    /// let matches = db.range_search(&[1.0, 2.0, 4.0], 2.0, &L1);
+    /// assert_eq!(matches.len(), 1);
+    /// assert_eq!(matches[0].0, "vector1");
+    /// ```
+    pub fn range_search(
+        &self,
+        query: &[f32],
+        radius: f32,
+        metric: &dyn Metric,
+    ) -> Vec<(String, Arc<Vector>)> {
+        // Without a deadline, the distances are always computed
+        let mut matches = self.distances("", query, metric, None).unwrap_or_default();
+        matches.retain(|(_, distance)| *distance <= radius);
+        matches.sort_by(index::by_distance);
+        self.resolve(matches)
+    }
+
     /// Performs element-wise addition of two vectors stored in the Vemcache database.
     ///
     /// The vectors are identified by their keys (IDs). The function returns the result
     /// of the addition as a new vector. The vectors must have the same number of dimensions.
     ///
-    /// # Arguments
+    /// # Arguments
+    ///
+    /// * `key1` - The key (ID) of the first vector to be added.
+    /// * `key2` - The key (ID) of the second vector to be added.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the result of the vector addition as a new vector.
+    /// Returns `None` if either of the keys is not found in the database, or if the
+    /// vectors have different dimensions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// // Create a new Vemcache database instance
+    /// let mut db = Vemcache::new();
+    ///
+    /// // Insert vectors into the database
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
+    ///
+    /// // Perform vector addition
+    /// let result = db.vector_addition("vector1", "vector2");
+    /// assert_eq!(result, Some(vec![5.0, 7.0, 9.0]));
+    /// ```
+    pub fn vector_addition(&self, key1: &str, key2: &str) -> Option<Vec<f32>> {
+        let v1 = self.storage.get(key1)?;
+        let v2 = self.storage.get(key2)?;
+        if v1.len() != v2.len() {
+            return None;
+        }
+        Some(v1.iter().zip(v2.iter()).map(|(x, y)| x + y).collect())
+    }
+
+    /// Performs element-wise subtraction of two vectors stored in the Vemcache database.
+    ///
+    /// The vectors are identified by their keys (IDs). The function returns the result
+    /// of the subtraction as a new vector. The vectors must have the same number of dimensions.
+    ///
+    /// # Arguments
+    ///
+    /// * `key1` - The key (ID) of the minuend vector.
+    /// * `key2` - The key (ID) of the subtrahend vector.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the result of the vector subtraction as a new vector.
+    /// Returns `None` if either of the keys is not found in the database, or if the
+    /// vectors have different dimensions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// // Create a new Vemcache database instance
+    /// let mut db = Vemcache::new();
+    ///
+    /// // Insert vectors into the database
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
+    ///
+    /// // Perform vector subtraction
+    /// let result = db.vector_subtraction("vector1", "vector2");
+    /// assert_eq!(result, Some(vec![-3.0, -3.0, -3.0]));
+    /// ```
+    pub fn vector_subtraction(&self, key1: &str, key2: &str) -> Option<Vec<f32>> {
+        let v1 = self.storage.get(key1)?;
+        let v2 = self.storage.get(key2)?;
+        if v1.len() != v2.len() {
+            return None;
+        }
+        Some(v1.iter().zip(v2.iter()).map(|(x, y)| x - y).collect())
+    }
+
+    /// Performs scalar multiplication of a vector stored in the Vemcache database.
+    ///
+    /// The vector is identified by its key (ID). The function returns the result
+    /// of the scalar multiplication as a new vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key (ID) of the vector to be scaled.
+    /// * `scalar` - The scalar value by which the vector is to be multiplied.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the result of the vector scaling as a new vector.
+    /// Returns `None` if the key is not found in the database.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// // Create a new Vemcache database instance
+    /// let mut db = Vemcache::new();
+    ///
+    /// // Insert a vector into the database
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// // Perform vector scaling
+    /// let result = db.vector_scaling("vector1", 2.0);
+    /// assert_eq!(result, Some(vec![2.0, 4.0, 6.0]));
+    /// ```
+    pub fn vector_scaling(&self, key: &str, scalar: f32) -> Option<Vec<f32>> {
+        let v = self.storage.get(key)?;
+        Some(v.iter().map(|x| x * scalar).collect())
+    }
+
+    /// Calculates the cosine similarity between two vectors.
+    ///
+    /// The cosine similarity is a measure of similarity between two vectors in a
+    /// multi-dimensional space. It is defined as the cosine of the angle between
+    /// the vectors. The vectors must have the same number of dimensions.
+    ///
+    /// # Arguments
+    ///
+    /// * `v1` - The first vector.
+    /// * `v2` - The second vector.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the cosine similarity between the two vectors as a floating-point value.
+    /// Returns `None` if the vectors have different dimensions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// // Create a new Vemcache database instance
+    /// let db = Vemcache::new();
+    ///
+    /// // Define two vectors
+    /// let vector1 = vec![1.0, 2.0, 3.0];
+    /// let vector2 = vec![4.0, 5.0, 6.0];
+    ///
+    /// // Calculate the cosine similarity between the vectors
+    /// let similarity = db.cosine_similarity(&vector1, &vector2).unwrap();
+    /// assert!((similarity - 0.9746318).abs() < 1e-6);
+    /// ```
+    pub fn cosine_similarity(&self, v1: &[f32], v2: &[f32]) -> Option<f32> {
+        if v1.len() != v2.len() {
+            return None;
+        }
+        Some(metric::cosine_similarity(v1, v2))
+    }
+
+    /// Takes a point-in-time snapshot of the database, which can be dumped to a file
+    /// without holding on to the database.
+    ///
+    /// Vectors are shared between the database and the snapshot rather than copied,
+    /// so taking a snapshot only costs a copy of the keys. Changes made to the database
+    /// afterwards are not visible in the snapshot.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    ///
+    /// let snapshot = db.snapshot();
+    /// db.remove("vector1".to_string());
+    ///
+    /// // The snapshot still contains "vector1"
+    /// let path = std::env::temp_dir().join("vemcache_snapshot.json");
+    /// let file_path = path.to_string_lossy().to_string();
+    /// std::thread::spawn(move || snapshot.dump(&file_path)).join().unwrap().unwrap();
+    /// std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            seq: self.changelog.last_seq(),
+            partial: false,
+            storage: self.storage.iter().collect(),
+            indexes: Vec::new(),
+            projections: Vec::new(),
+            pipelines: Vec::new(),
+            ttls: Vec::new(),
+        }
+    }
+
+    /// Takes a snapshot like `snapshot`, which also saves the indexes, the
+    /// projections, the pipelines and the TTLs of the database when dumped, so `load_indexes` can restore them
+    /// without building them again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an index cannot be serialized.
+    pub fn snapshot_with_indexes(&self) -> serde_json::Result<Snapshot> {
+        let indexes = self
+            .indexes
+            .iter()
+            .map(|(prefix, index)| {
+                Ok(SavedIndex {
+                    prefix: prefix.clone(),
+                    kind: index.kind().to_string(),
+                    metric: index.metric().name().to_string(),
+                    state: index.save()?,
+                })
+            })
+            .collect::<serde_json::Result<Vec<SavedIndex>>>()?;
+        Ok(Snapshot {
+            seq: self.changelog.last_seq(),
+            partial: false,
+            storage: self.storage.iter().collect(),
+            indexes,
+            projections: self.projections.clone(),
+            pipelines: self.pipelines.clone(),
+            ttls: self.ttls.clone(),
+        })
+    }
+
+    /// Starts recording the keys changed from now on, so `delta_snapshot` can take a
+    /// snapshot of the changes since the full snapshot named `base`, which should be
+    /// taken at the same time, or stops recording them if `base` is `None`. Changes
+    /// are no longer recorded against the previous full snapshot, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
     ///
-    /// * `key1` - The key (ID) of the first vector to be added.
-    /// * `key2` - The key (ID) of the second vector to be added.
+    /// let base = std::env::temp_dir().join("vemcache_base.json");
+    /// let delta = std::env::temp_dir().join("vemcache_delta.json");
+    /// let (base, delta) = (base.to_str().unwrap(), delta.to_str().unwrap());
     ///
-    /// # Returns
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0]);
+    /// db.snapshot().dump(base).unwrap();
+    /// db.track_delta(Some(base));
+    ///
+    /// db.insert_with_key("vector2".to_string(), vec![3.0, 4.0]);
+    /// db.delta_snapshot(base).unwrap().dump(delta).unwrap();
+    ///
+    /// let mut restored = Vemcache::new();
+    /// restored.load(base).unwrap();
+    /// restored.load_delta(delta, base).unwrap();
+    /// assert_eq!(restored.len(), 2);
+    /// std::fs::remove_file(base).unwrap();
+    /// std::fs::remove_file(delta).unwrap();
+    /// ```
+    pub fn track_delta(&mut self, base: Option<&str>) {
+        self.delta = base.map(|base| (base.to_string(), HashSet::new()));
+    }
+
+    /// Takes a snapshot of the vectors inserted and removed since the full snapshot
+    /// named `base`, or returns `None` if changes are not recorded against it with
+    /// `track_delta`, as after a restart.
+    pub fn delta_snapshot(&self, base: &str) -> Option<DeltaSnapshot> {
+        let (tracked, changed) = self.delta.as_ref().filter(|(tracked, _)| tracked == base)?;
+        let mut vectors = HashMap::new();
+        let mut removed = Vec::new();
+        for key in changed {
+            match self.storage.get(key) {
+                Some(vector) => {
+                    vectors.insert(key.clone(), vector);
+                }
+                None => removed.push(key.clone()),
+            }
+        }
+        Some(DeltaSnapshot {
+            seq: self.changelog.last_seq(),
+            base: tracked.clone(),
+            vectors,
+            removed,
+        })
+    }
+
+    /// Takes a point-in-time snapshot of the vectors whose key starts with `prefix`.
     ///
-    /// An `Option` containing the result of the vector addition as a new vector.
-    /// Returns `None` if either of the keys is not found in the database, or if the
-    /// vectors have different dimensions.
+    /// This allows a single tenant's or collection's vectors to be exported without
+    /// exporting the entire database. The dump is marked as partial, so it is never
+    /// restored at startup in place of a snapshot of the whole database.
     ///
     /// # Example
     ///
     /// ```
     /// use vemcache::Vemcache;
     ///
-    /// // Create a new Vemcache database instance
     /// let mut db = Vemcache::new();
+    /// db.insert_with_key("tenant1:vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.insert_with_key("tenant2:vector1".to_string(), vec![4.0, 5.0, 6.0]);
     ///
-    /// // Insert vectors into the database
-    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
-    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
-    ///
-    /// // Perform vector addition
-    /// let result = db.vector_addition("vector1", "vector2");
-    /// assert_eq!(result, Some(vec![5.0, 7.0, 9.0]));
+    /// // Only dump the vectors of tenant1
+    /// let path = std::env::temp_dir().join("vemcache_tenant1.json");
+    /// db.snapshot_with_prefix("tenant1:").dump(&path.to_string_lossy()).unwrap();
+    /// std::fs::remove_file(path).unwrap();
     /// ```
-    pub fn vector_addition(&self, key1: &str, key2: &str) -> Option<Vec<f32>> {
-        let v1 = self.storage.get(key1)?;
-        let v2 = self.storage.get(key2)?;
-        if v1.len() != v2.len() {
-            return None;
+    pub fn snapshot_with_prefix(&self, prefix: &str) -> Snapshot {
+        Snapshot {
+            seq: self.changelog.last_seq(),
+            partial: true,
+            storage: self
+                .storage
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .collect(),
+            indexes: Vec::new(),
+            projections: Vec::new(),
+            pipelines: Vec::new(),
+            ttls: Vec::new(),
         }
-        Some(v1.iter().zip(v2.iter()).map(|(x, y)| x + y).collect())
     }
 
-    /// Performs element-wise subtraction of two vectors stored in the Vemcache database.
+    /// Loads the contents of the Vemcache database from a JSON file created by `Snapshot::dump`.
     ///
-    /// The vectors are identified by their keys (IDs). The function returns the result
-    /// of the subtraction as a new vector. The vectors must have the same number of dimensions.
+    /// The vectors in the file replace the current contents of the database. Gzip and
+    /// zstd-compressed dumps are detected and decompressed automatically.
     ///
     /// # Arguments
     ///
-    /// * `key1` - The key (ID) of the minuend vector.
-    /// * `key2` - The key (ID) of the subtrahend vector.
-    ///
-    /// # Returns
+    /// * `file_path` - The path to the database dump to load.
     ///
-    /// An `Option` containing the result of the vector subtraction as a new vector.
-    /// Returns `None` if either of the keys is not found in the database, or if the
-    /// vectors have different dimensions.
+    /// # Examples
     ///
-    /// # Example
+    /// ```
+    /// # use vemcache::Vemcache;
+    /// let mut db = Vemcache::new();
     ///
+    /// // Load the database from a dump
+    /// let path = std::env::temp_dir().join("vemcache_dump.json");
+    /// match db.load(&path.to_string_lossy()) {
+    ///     Ok(_) => println!("Database load successful."),
+    ///     Err(err) => eprintln!("Error loading database dump: {}", err),
+    /// }
     /// ```
-    /// use vemcache::Vemcache;
     ///
-    /// // Create a new Vemcache database instance
-    /// let mut db = Vemcache::new();
+    /// # Errors
     ///
-    /// // Insert vectors into the database
-    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
-    /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
+    /// Returns an error if the file cannot be read, is truncated or corrupted, or is not a
+    /// valid dump; the database is left untouched.
+    pub fn load(&mut self, file_path: &str) -> IoResult<()> {
+        let file = compression::open_file(file_path)?;
+        let storage = checksum::read(file, |json| Ok(serde_json::from_reader(json)?))?;
+        self.replace_storage(storage);
+        Ok(())
+    }
+
+    /// Applies the changes dumped by `DeltaSnapshot::dump` to the database, which must
+    /// hold the contents of the full snapshot named `base` the delta was taken
+    /// against, possibly with earlier deltas of it applied. Like `load`, the changes
+    /// are not recorded in the change log.
     ///
-    /// // Perform vector subtraction
-    /// let result = db.vector_subtraction("vector1", "vector2");
-    /// assert_eq!(result, Some(vec![-3.0, -3.0, -3.0]));
-    /// ```
-    pub fn vector_subtraction(&self, key1: &str, key2: &str) -> Option<Vec<f32>> {
-        let v1 = self.storage.get(key1)?;
-        let v2 = self.storage.get(key2)?;
-        if v1.len() != v2.len() {
-            return None;
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is truncated or corrupted, is not
+    /// a delta snapshot, or was taken against another full snapshot; the database is
+    /// left untouched.
+    pub fn load_delta(&mut self, file_path: &str, base: &str) -> IoResult<()> {
+        let file = compression::open_file(file_path)?;
+        let delta = checksum::read(file, |json| {
+            Ok(serde_json::from_reader::<_, DeltaSnapshot>(json)?)
+        })?;
+        if delta.base != base {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{} is a delta of {}, not of {}",
+                    file_path, delta.base, base
+                ),
+            ));
         }
-        Some(v1.iter().zip(v2.iter()).map(|(x, y)| x - y).collect())
+        for key in delta.removed {
+            self.unstore(&key);
+        }
+        for (key, vector) in delta.vectors {
+            self.store(key, vector);
+        }
+        Ok(())
     }
 
-    /// Performs scalar multiplication of a vector stored in the Vemcache database.
+    /// Loads the indexes, the projections, the pipelines and the TTLs saved alongside the
+    /// snapshot at `file_path` by `snapshot_with_indexes`, replacing those of the
+    /// same collections, and returns how many indexes were loaded. The snapshot
+    /// itself must have been loaded first.
     ///
-    /// The vector is identified by its key (ID). The function returns the result
-    /// of the scalar multiplication as a new vector.
+    /// # Errors
+    ///
+    /// Returns an error if the indexes, the projections or the pipelines cannot be
+    /// read or do not match the contents of the database; the indexes loaded so far
+    /// are kept.
+    pub fn load_indexes(&mut self, file_path: &str) -> IoResult<usize> {
+        for (prefix, projection) in
+            read_sidecar::<(String, Projection)>(file_path, PROJECTION_FILE_SUFFIX)?
+        {
+            self.projections.retain(|(other, _)| *other != prefix);
+            self.projections.push((prefix, projection));
+        }
+        for (prefix, pipeline) in
+            read_sidecar::<(String, Pipeline)>(file_path, PIPELINE_FILE_SUFFIX)?
+        {
+            self.set_pipeline(&prefix, pipeline);
+        }
+        for (prefix, ttl) in read_sidecar::<(String, u64)>(file_path, TTL_FILE_SUFFIX)? {
+            self.set_ttl(&prefix, ttl);
+        }
+        let path = format!("{}{}", file_path, INDEX_FILE_SUFFIX);
+        let file = match compression::open_file(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let saved = checksum::read(file, |json| {
+            Ok(serde_json::from_reader::<_, Vec<SavedIndex>>(json)?)
+        })?;
+        let count = saved.len();
+        for saved in saved {
+            let invalid = |what: &str| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unknown {} of index {}: {}", what, saved.prefix, saved.kind),
+                )
+            };
+            let kind = IndexKind::from_name(&saved.kind).ok_or_else(|| invalid("kind"))?;
+            let metric = self
+                .metric(&saved.metric)
+                .ok_or_else(|| invalid("metric"))?;
+            let index = kind.load(metric, &saved.state, &saved.prefix, self.storage.as_ref())?;
+            self.indexes.retain(|(other, _)| *other != saved.prefix);
+            self.indexes.push((saved.prefix, index));
+        }
+        Ok(count)
+    }
+
+    /// Retrieves the changes applied to the database after a given sequence number.
+    ///
+    /// Every mutation (insert or remove) is recorded in a bounded change log with a
+    /// monotonically increasing sequence number, in the form of the write command that
+    /// reproduces it. Consumers can remember the last sequence number they processed
+    /// and resume from it after a disconnect without missing mutations.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key (ID) of the vector to be scaled.
-    /// * `scalar` - The scalar value by which the vector is to be multiplied.
+    /// * `seq` - The last sequence number already seen by the consumer (0 for everything retained).
     ///
     /// # Returns
     ///
-    /// An `Option` containing the result of the vector scaling as a new vector.
-    /// Returns `None` if the key is not found in the database.
+    /// A `Result` containing the changes with a sequence number greater than `seq`, in order.
+    /// Returns `Err` with the oldest available sequence number if changes after `seq` have
+    /// already been discarded from the log.
     ///
     /// # Example
     ///
     /// ```
     /// use vemcache::Vemcache;
     ///
-    /// // Create a new Vemcache database instance
     /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// db.remove("vector1".to_string());
     ///
-    /// // Insert a vector into the database
+    /// let changes = db.changes_since(1).unwrap();
+    /// assert_eq!(changes[0].seq, 2);
+    /// assert_eq!(changes[0].command, "remove vector1");
+    /// ```
+    pub fn changes_since(&self, seq: u64) -> Result<Vec<&Change>, u64> {
+        self.changelog.since(seq)
+    }
+
+    /// Returns the sequence number of the last change applied to the database, or 0
+    /// if none has been.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// assert_eq!(db.last_seq(), 0);
     /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0, 3.0]);
+    /// assert_eq!(db.last_seq(), 1);
+    /// ```
+    pub fn last_seq(&self) -> u64 {
+        self.changelog.last_seq()
+    }
+
+    /// Returns the error of the first change that could not be appended to the
+    /// write-ahead log, if any. The change was applied all the same, but is lost on
+    /// restart unless a snapshot holding it is taken.
+    ///
+    /// # Example
     ///
-    /// // Perform vector scaling
-    /// let result = db.vector_scaling("vector1", 2.0);
-    /// assert_eq!(result, Some(vec![2.0, 4.0, 6.0]));
     /// ```
-    pub fn vector_scaling(&self, key: &str, scalar: f32) -> Option<Vec<f32>> {
-        let v = self.storage.get(key)?;
-        Some(v.iter().map(|x| x * scalar).collect())
+    /// use std::path::Path;
+    /// use vemcache::wal::Wal;
+    /// use vemcache::Vemcache;
+    ///
+    /// # if cfg!(target_os = "linux") {
+    /// // Every write to /dev/full fails, like on a full disk
+    /// let mut db = Vemcache::new();
+    /// db.attach_wal(Wal::open(Path::new("/dev/full")).unwrap());
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0]);
+    /// assert!(db.wal_error().is_some());
+    /// assert_eq!(db.len(), 1);
+    /// # }
+    /// ```
+    pub fn wal_error(&self) -> Option<&str> {
+        self.changelog.wal_error()
     }
 
-    /// Calculates the cosine similarity between two vectors.
+    /// Appends every subsequent change to the write-ahead log `wal`, so it can be
+    /// replayed after a restart.
+    pub fn attach_wal(&mut self, wal: Wal) {
+        self.changelog.attach_wal(wal);
+    }
+
+    /// Waits until the write-ahead log and the storage have written every change to
+    /// disk, so none is lost if the process stops right after.
+    pub fn flush(&mut self) -> IoResult<()> {
+        self.changelog.sync_wal()?;
+        self.storage.flush()
+    }
+
+    /// Drops the changes before `seq` from the write-ahead log, once a snapshot
+    /// holding every change up to it is written, so the log does not grow without
+    /// bound. The storage is flushed first, as the log no longer covers them.
+    pub fn compact_wal(&mut self, seq: u64) -> IoResult<()> {
+        self.storage.flush()?;
+        self.changelog.compact_wal(seq)
+    }
+
+    /// Offloads exhaustive searches to `gpu` once the database holds at least
+    /// `threshold` vectors, for the metrics the GPU supports. See the `gpu` module.
+    #[cfg(feature = "gpu")]
+    pub fn attach_gpu(&mut self, gpu: Gpu, threshold: usize) {
+        self.gpu = Some((gpu, threshold));
+    }
+
+    /// Discards the retained changes and continues numbering changes after `seq`.
     ///
-    /// The cosine similarity is a measure of similarity between two vectors in a
-    /// multi-dimensional space. It is defined as the cosine of the angle between
-    /// the vectors. The vectors must have the same number of dimensions.
+    /// Used once the database has been restored from disk, so sequence numbers keep
+    /// increasing across restarts.
+    pub fn resume_changelog(&mut self, seq: u64) {
+        self.changelog.resume_from(seq);
+    }
+
+    /// Changes the number of changes retained in the change log.
     ///
-    /// # Arguments
+    /// Shrinking the change log discards the oldest changes; consumers that have not
+    /// processed them yet will have to resynchronize from a snapshot.
+    pub fn set_changelog_capacity(&mut self, capacity: usize) {
+        self.changelog.set_capacity(capacity);
+    }
+
+    /// Takes a snapshot of the database for a replica, together with the sequence
+    /// number of the last change it includes. The snapshot is serialized with
+    /// `Snapshot::to_sync_json`, which does not need the database.
+    ///
+    /// Replicas load the snapshot with `restore_snapshot` and then follow the
+    /// change log from the returned sequence number.
+    pub fn sync_snapshot(&self) -> (u64, Snapshot) {
+        (self.changelog.last_seq(), self.snapshot())
+    }
+
+    /// Replaces the contents of the database with a JSON snapshot produced by
+    /// `Snapshot::to_sync_json`.
     ///
-    /// * `v1` - The first vector.
-    /// * `v2` - The second vector.
+    /// The retained change log is discarded, so consumers of this database's changes
+    /// that have not caught up will be told to resynchronize.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// An `Option` containing the cosine similarity between the two vectors as a floating-point value.
-    /// Returns `None` if the vectors have different dimensions.
+    /// Returns an error if the snapshot is not valid JSON; the database is left untouched.
+    pub fn restore_snapshot(&mut self, snapshot: &str) -> serde_json::Result<()> {
+        let storage = serde_json::from_str(snapshot)?;
+        self.replace_storage(storage);
+        self.changelog.truncate();
+        Ok(())
+    }
+
+    /// Brings the contents of the database back to those of `snapshot` through
+    /// ordinary changes: the vectors missing from the snapshot are removed, and
+    /// those that are missing from the database or differ are inserted as they are,
+    /// without their projection or pipeline being applied again. Unlike
+    /// `restore_snapshot`, every change is recorded in the change log and the
+    /// write-ahead log, so replicas follow and a restart keeps the result. Returns
+    /// the number of vectors removed and inserted.
     ///
     /// # Example
     ///
     /// ```
     /// use vemcache::Vemcache;
     ///
-    /// // Define two vectors
-    /// let vector1 = vec![1.0, 2.0, 3.0];
-    /// let vector2 = vec![4.0, 5.0, 6.0];
+    /// let mut db = Vemcache::new();
+    /// db.insert_with_key("vector1".to_string(), vec![1.0, 2.0]);
+    /// let snapshot = db.snapshot();
+    ///
+    /// db.remove("vector1".to_string());
+    /// db.insert_with_key("vector2".to_string(), vec![3.0, 4.0]);
+    /// assert_eq!(db.revert_to(snapshot), (1, 1));
+    /// assert_eq!(*db.get("vector1".to_string()).unwrap(), vec![1.0, 2.0]);
+    /// assert!(db.get("vector2".to_string()).is_none());
+    /// ```
+    pub fn revert_to(&mut self, snapshot: Snapshot) -> (usize, usize) {
+        let removed = self
+            .storage
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| !snapshot.storage.contains_key(key))
+            .collect::<Vec<_>>();
+        for key in &removed {
+            self.remove(key.clone());
+        }
+        let mut inserted = 0;
+        for (key, vector) in snapshot.storage {
+            if self
+                .storage
+                .get(&key)
+                .is_some_and(|current| current == vector)
+            {
+                continue;
+            }
+            self.log_insert(&key, &vector);
+            self.store(key, vector);
+            inserted += 1;
+        }
+        (removed.len(), inserted)
+    }
+
+    /// Stores `vector` under `key`, replacing any vector stored under it, and keeps
+    /// the indexes, the timestamps, the expirations and the shared vectors in sync.
+    /// The change is not recorded in the change log.
+    fn store(&mut self, key: String, vector: Arc<Vector>) {
+        let vector = self.share(vector);
+        if self.dedup != Dedup::Off {
+            if let Some(previous) = self.storage.get(&key) {
+                self.unshare(&previous);
+            }
+        }
+        self.index_insert(&key, &vector);
+        self.timestamps.insert(&key, EntryTimestamps::now());
+        self.schedule_expiration(&key);
+        self.storage.insert(key, vector);
+    }
+
+    /// Removes the vector stored under `key` like `store` inserts it, and returns
+    /// it, if any.
+    fn unstore(&mut self, key: &str) -> Option<Arc<Vector>> {
+        let removed = self.storage.remove(key)?;
+        self.unshare(&removed);
+        self.index_remove(key);
+        self.timestamps.remove(key);
+        self.cancel_expiration(key);
+        Some(removed)
+    }
+
+    /// Sets what happens when a vector identical to one already stored is inserted
+    /// under another key. Enabling deduplication makes the duplicates already stored
+    /// share a single vector.
+    ///
+    /// # Example
     ///
-    /// // Calculate the cosine similarity between the vectors
-    /// let similarity = Vemcache::cosine_similarity(&vector1, &vector2).unwrap();
-    /// assert_eq!(similarity, 0.9746318461970762);
     /// ```
-    pub fn cosine_similarity(&self, v1: &Vec<f32>, v2: &Vec<f32>) -> Option<f32> {
-        if v1.len() != v2.len() {
-            return None;
+    /// use vemcache::vemcache::Dedup;
+    /// use vemcache::Vemcache;
+    ///
+    /// let mut db = Vemcache::new();
+    /// db.set_dedup(Dedup::Reject);
+    ///
+    /// assert!(db.try_insert_with_key("vector1".to_string(), vec![1.0, 2.0]).is_ok());
+    /// assert!(db.try_insert_with_key("vector2".to_string(), vec![1.0, 2.0]).is_err());
+    /// assert!(db.try_insert_with_key("vector1".to_string(), vec![1.0, 2.0]).is_ok());
+    /// ```
+    pub fn set_dedup(&mut self, dedup: Dedup) {
+        if dedup == self.dedup {
+            return;
+        }
+        self.dedup = dedup;
+        self.shared.clear();
+        if dedup == Dedup::Off {
+            return;
+        }
+        for (key, vector) in self.storage.iter().collect::<Vec<_>>() {
+            let shared = self.share(Arc::clone(&vector));
+            if !Arc::ptr_eq(&shared, &vector) {
+                self.storage.insert(key, shared);
+            }
+        }
+    }
+
+    /// Returns the vector identical to `vector` stored under some key, if
+    /// deduplication is enabled and there is one.
+    fn find_shared(&self, vector: &[f32]) -> Option<&Arc<Vector>> {
+        self.shared
+            .get(&hash_components(vector))?
+            .iter()
+            .map(|(shared, _)| shared)
+            .find(|shared| same_components(shared, vector))
+    }
+
+    /// Returns the vector identical to `vector` already stored, counting one more
+    /// key holding it, or `vector` itself if there is none. Does nothing while
+    /// deduplication is disabled.
+    fn share(&mut self, vector: Arc<Vector>) -> Arc<Vector> {
+        if self.dedup == Dedup::Off {
+            return vector;
+        }
+        let candidates = self.shared.entry(hash_components(&vector)).or_default();
+        match candidates
+            .iter_mut()
+            .find(|(shared, _)| same_components(shared, &vector))
+        {
+            Some((shared, count)) => {
+                *count += 1;
+                Arc::clone(shared)
+            }
+            None => {
+                candidates.push((Arc::clone(&vector), 1));
+                vector
+            }
+        }
+    }
+
+    /// Counts one less key holding `vector`, forgetting it once none does.
+    fn unshare(&mut self, vector: &[f32]) {
+        let hash = hash_components(vector);
+        let Some(candidates) = self.shared.get_mut(&hash) else {
+            return;
+        };
+        if let Some(position) = candidates
+            .iter()
+            .position(|(shared, _)| same_components(shared, vector))
+        {
+            candidates[position].1 -= 1;
+            if candidates[position].1 == 0 {
+                candidates.swap_remove(position);
+            }
+        }
+        if candidates.is_empty() {
+            self.shared.remove(&hash);
+        }
+    }
+
+    fn index_insert(&mut self, key: &str, vector: &Arc<Vector>) {
+        #[cfg(feature = "gpu")]
+        {
+            self.generation += 1;
+        }
+        self.track_change(key);
+        for (prefix, index) in &mut self.indexes {
+            if key.starts_with(prefix.as_str()) {
+                index.insert(key, Arc::clone(vector));
+            }
+        }
+    }
+
+    fn index_remove(&mut self, key: &str) {
+        #[cfg(feature = "gpu")]
+        {
+            self.generation += 1;
+        }
+        self.track_change(key);
+        for (prefix, index) in &mut self.indexes {
+            if key.starts_with(prefix.as_str()) {
+                index.remove(key);
+            }
+        }
+    }
+
+    /// Records a change to `key` for the index builds of its collection, which
+    /// cannot see it in the vectors they were given.
+    fn track_change(&mut self, key: &str) {
+        for build in &mut self.builds {
+            if key.starts_with(build.prefix.as_str()) {
+                build.changed.insert(key.to_string());
+            }
+        }
+        if let Some((_, changed)) = &mut self.delta {
+            changed.insert(key.to_string());
+        }
+    }
+
+    /// Replaces the contents of the database, keeping the indexes in sync.
+    fn replace_storage(&mut self, vectors: HashMap<VectorId, Arc<Vector>>) {
+        let previous = self.storage.iter().map(|(key, _)| key).collect::<Vec<_>>();
+        for key in previous {
+            self.index_remove(&key);
+        }
+        self.storage.clear();
+        self.timestamps.clear();
+        self.expirations.clear();
+        self.expiry_queue.clear();
+        self.shared.clear();
+        for (key, vector) in vectors {
+            self.store(key, vector);
         }
-        let dot_product = v1.iter().zip(v2.iter()).map(|(x, y)| x * y).sum::<f32>();
-        let magnitude_v1 = (v1.iter().map(|x| x.powi(2)).sum::<f32>()).sqrt();
-        let magnitude_v2 = (v2.iter().map(|x| x.powi(2)).sum::<f32>()).sqrt();
-        Some(dot_product / (magnitude_v1 * magnitude_v2))
+        // Every previous vector was removed from the indexes
+        self.compact_indexes();
+    }
+
+    fn log_insert(&mut self, key: &str, vector: &Vector) {
+        let values = vector
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.changelog
+            .append(format!("named_insert {} {}", quote::quote(key), values));
+    }
+}
+
+/// Keeps the `k` scored keys nearest to the query, nearest first.
+fn nearest(mut scored: Vec<(String, f32)>, k: usize) -> Vec<(String, f32)> {
+    if scored.len() > k && k > 0 {
+        scored.select_nth_unstable_by(k - 1, index::by_distance);
+    }
+    scored.truncate(k);
+    scored.sort_by(index::by_distance);
+    scored
+}
+
+/// Returns the keys of the first `k` of the neighbors found when searching for the
+/// vector stored under `key`, which is not counted among its own neighbors.
+/// Searches ask for one more neighbor to make up for it.
+fn recall_neighbors(key: &str, results: Vec<(String, f32)>, k: usize) -> HashSet<String> {
+    results
+        .into_iter()
+        .map(|(id, _)| id)
+        .filter(|id| id != key)
+        .take(k)
+        .collect()
+}
+
+/// Returns the share of the exact neighbors of `samples` that `index` finds,
+/// averaged over them, or 1 if there are none, along with the mean time a search
+/// took.
+fn measure_recall(index: &dyn VectorIndex, samples: &[RecallSample], k: usize) -> (f64, Duration) {
+    if samples.is_empty() {
+        return (1.0, Duration::ZERO);
+    }
+    let mut total = 0.0;
+    let mut elapsed = Duration::ZERO;
+    for sample in samples {
+        let start = Instant::now();
+        let results = index.search(&sample.query, k + 1);
+        elapsed += start.elapsed();
+        let found = recall_neighbors(&sample.key, results, k);
+        total += found.intersection(&sample.exact).count() as f64 / sample.exact.len() as f64;
+    }
+    (total / samples.len() as f64, elapsed / samples.len() as u32)
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+fn now_millis() -> u64 {
+    // The standard clock is not available in browsers
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    return js_sys::Date::now() as u64;
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// A point-in-time, immutable view of the database, created by `Vemcache::snapshot`.
+pub struct Snapshot {
+    // The sequence number of the last change the snapshot holds
+    seq: u64,
+    // Set if taken with `snapshot_with_prefix`, so the dump is not restored
+    partial: bool,
+    storage: HashMap<VectorId, Arc<Vector>>,
+    // Empty unless taken with `snapshot_with_indexes`
+    indexes: Vec<SavedIndex>,
+    projections: Vec<(String, Projection)>,
+    pipelines: Vec<(String, Pipeline)>,
+    ttls: Vec<(String, u64)>,
+}
+
+impl Snapshot {
+    /// Returns the sequence number of the last change the snapshot holds, which its
+    /// dump records so the write-ahead log is replayed from the next one.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the keys and vectors of the snapshot, ordered by key.
+    pub fn vectors(&self) -> Vec<(&str, &[f32])> {
+        let mut vectors = self
+            .storage
+            .iter()
+            .map(|(key, vector)| (key.as_str(), vector.as_slice()))
+            .collect::<Vec<_>>();
+        vectors.sort_unstable_by_key(|(key, _)| *key);
+        vectors
+    }
+
+    /// Serializes the vectors of the snapshot into the JSON document a replica
+    /// loads with `Vemcache::restore_snapshot`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the vectors could not be serialized.
+    pub fn to_sync_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.storage)
     }
 
-    /// Dumps the contents of the Vemcache database to a JSON file.
+    /// Dumps the contents of the snapshot to a JSON file.
+    ///
+    /// This function serializes the entire contents of the snapshot into a JSON file
+    /// specified by the `file_path` argument. If the path ends in `.gz` or `.zst`, the
+    /// dump is compressed with gzip or zstd respectively.
     ///
-    /// This function serializes the entire contents of the database (i.e., the `storage` field)
-    /// into a JSON file specified by the `file_path` argument.
+    /// The indexes of snapshots taken with `Vemcache::snapshot_with_indexes` are
+    /// saved next to the dump, in a file named after it with the `.idx` suffix, their
+    /// projections in one with the `.proj` suffix and their pipelines in one with the
+    /// `.pipe` suffix. Otherwise, any such file left by a previous dump is removed.
     ///
     /// # Arguments
     ///
@@ -424,22 +3104,132 @@ impl Vemcache {
     /// db.insert_with_key("vector2".to_string(), vec![4.0, 5.0, 6.0]);
     ///
     /// // Dump the database to a file
-    /// match db.dump("vemcache_dump.json") {
+    /// let path = std::env::temp_dir().join("vemcache_dump.json");
+    /// match db.snapshot().dump(&path.to_string_lossy()) {
     ///     Ok(_) => println!("Database dump successful."),
     ///     Err(err) => eprintln!("Error creating database dump: {}", err),
     /// }
+    /// # let _ = std::fs::remove_file(path);
     /// ```
     ///
     /// # Errors
     ///
     /// Returns an `IoResult<()>` to indicate whether the operation was successful or if there was an I/O error.
     pub fn dump(&self, file_path: &str) -> IoResult<()> {
-        // Open the file for writing
-        let file = File::create(file_path)?;
+        // Open the file for writing, compressing it if requested by the extension, and
+        // serialize the storage into JSON format
+        compression::write_file(file_path, |file| {
+            let header = Header {
+                seq: self.seq,
+                partial: self.partial,
+            };
+            checksum::write(file, header, |json| {
+                Ok(serde_json::to_writer(json, &self.storage)?)
+            })
+        })?;
 
-        // Serialize the storage field into JSON format and write it to the file
-        serde_json::to_writer(file, &self.storage)?;
+        // Indexes and projections saved with a previous dump to the same path no
+        // longer match it
+        write_sidecar(
+            &format!("{}{}", file_path, PROJECTION_FILE_SUFFIX),
+            &self.projections,
+        )?;
+        write_sidecar(
+            &format!("{}{}", file_path, PIPELINE_FILE_SUFFIX),
+            &self.pipelines,
+        )?;
+        write_sidecar(&format!("{}{}", file_path, TTL_FILE_SUFFIX), &self.ttls)?;
+        write_sidecar(
+            &format!("{}{}", file_path, INDEX_FILE_SUFFIX),
+            &self.indexes,
+        )
+    }
+}
 
-        Ok(())
+/// The vectors inserted and removed since a full snapshot, created by
+/// `Vemcache::delta_snapshot`.
+#[derive(Serialize, Deserialize)]
+pub struct DeltaSnapshot {
+    // The sequence number of the last change the delta holds, kept in the header
+    #[serde(skip)]
+    seq: u64,
+    // The name of the full snapshot the delta was taken against
+    base: String,
+    vectors: HashMap<VectorId, Arc<Vector>>,
+    removed: Vec<VectorId>,
+}
+
+impl DeltaSnapshot {
+    /// Returns the sequence number of the last change the delta holds.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Dumps the changes to a file, in the format of `Snapshot::dump`, compressed or
+    /// encrypted the same way. Only vectors are saved: the indexes, the projections
+    /// and the pipelines are those saved with the full snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn dump(&self, file_path: &str) -> IoResult<()> {
+        compression::write_file(file_path, |file| {
+            let header = Header {
+                seq: self.seq,
+                partial: false,
+            };
+            checksum::write(file, header, |json| Ok(serde_json::to_writer(json, self)?))
+        })
+    }
+}
+
+/// Returns the setting of the collection with the longest prefix of `key` among
+/// `settings`, if any.
+fn innermost<'a, T>(settings: &'a [(String, T)], key: &str) -> Option<&'a T> {
+    settings
+        .iter()
+        .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, setting)| setting)
+}
+
+/// Reads the items saved alongside the snapshot at `file_path` in the file with
+/// the given suffix, or nothing if there is no such file.
+fn read_sidecar<T: serde::de::DeserializeOwned>(file_path: &str, suffix: &str) -> IoResult<Vec<T>> {
+    let path = format!("{}{}", file_path, suffix);
+    match compression::open_file(&path) {
+        Ok(file) => checksum::read(file, |json| Ok(serde_json::from_reader(json)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
     }
 }
+
+/// Writes `items` to the file at `path`, or removes the file if there are none.
+fn write_sidecar<T: serde::Serialize>(path: &str, items: &[T]) -> IoResult<()> {
+    if items.is_empty() {
+        return match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+    compression::write_file(path, |file| {
+        checksum::write(file, Header::default(), |json| {
+            Ok(serde_json::to_writer(json, items)?)
+        })
+    })
+}
+
+/// Hashes the bits of the components of a vector, so that only identical vectors
+/// are found to be duplicates.
+fn hash_components(vector: &[f32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in vector {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Returns whether two vectors have the same components, bit for bit.
+fn same_components(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+}
@@ -0,0 +1,160 @@
+use crate::encryption;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The name of the write-ahead log file inside the data directory.
+pub const WAL_FILE_NAME: &str = "vemcache.wal";
+
+/// An entry read back from the write-ahead log.
+pub struct WalEntry {
+    /// The sequence number of the change.
+    pub seq: u64,
+    /// The time the change was applied, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The write command that reproduces the change.
+    pub command: String,
+}
+
+/// An append-only file recording every change applied to the database.
+///
+/// Each line holds the sequence number of a change, the time it was applied and
+/// the write command reproducing it. Replaying the log on top of the newest
+/// snapshot restores the database as it was before the server stopped. Once an
+/// encryption key is installed, every line is encrypted on its own.
+///
+/// Once a snapshot holding the changes up to a sequence number is written, the log
+/// is compacted: the entries before that change are dropped.
+pub struct Wal {
+    file: File,
+    path: PathBuf,
+}
+
+impl Wal {
+    /// Opens the write-ahead log at `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> IoResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Appends a change to the log.
+    pub fn append(&mut self, seq: u64, command: &str) -> IoResult<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let line = encode_line(seq, timestamp as u64, command)?;
+        self.file.write_all(line.as_bytes())
+    }
+
+    /// Drops the entries before the change `seq`, once a snapshot holding every
+    /// change up to it is written. The entry of `seq` itself is kept, so the log
+    /// tells which snapshots it can be replayed on top of.
+    ///
+    /// The remaining entries are written to a new file, which then replaces the log,
+    /// so a crash leaves either log whole.
+    pub fn compact(&mut self, seq: u64) -> IoResult<()> {
+        self.file.sync_all()?;
+        let written = self.path.with_extension("wal.new");
+        let mut file = File::create(&written)?;
+        for entry in Wal::read(&self.path)? {
+            if entry.seq >= seq {
+                file.write_all(
+                    encode_line(entry.seq, entry.timestamp, &entry.command)?.as_bytes(),
+                )?;
+            }
+        }
+        file.sync_all()?;
+        fs::rename(&written, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Waits until every appended change has reached the disk.
+    pub fn sync(&mut self) -> IoResult<()> {
+        self.file.sync_all()
+    }
+
+    /// Reads every entry of the write-ahead log at `path`, in order.
+    ///
+    /// Returns no entries if the file does not exist. A truncated last line, as
+    /// left by a crash in the middle of a write, is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an encrypted line, other than the last one, cannot be
+    /// decrypted with the installed key.
+    pub fn read(path: &Path) -> IoResult<Vec<WalEntry>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = Vec::new();
+        let mut lines = BufReader::new(file).lines().peekable();
+        while let Some(line) = lines.next() {
+            let mut line = line?;
+            if line.starts_with(encryption::WAL_PREFIX) {
+                match decrypt_line(&line) {
+                    Ok(plain) => line = plain,
+                    // The last line may have been cut short by a crash
+                    Err(err)
+                        if err.kind() == std::io::ErrorKind::InvalidData
+                            && lines.peek().is_none() =>
+                    {
+                        println!("Skipping truncated write-ahead log entry");
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            let mut parts = line.splitn(3, ' ');
+            let entry = match (parts.next(), parts.next(), parts.next()) {
+                (Some(seq), Some(timestamp), Some(command)) => {
+                    match (seq.parse(), timestamp.parse()) {
+                        (Ok(seq), Ok(timestamp)) => Some(WalEntry {
+                            seq,
+                            timestamp,
+                            command: command.to_string(),
+                        }),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => println!("Skipping malformed write-ahead log entry: {}", line),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Encodes an entry of the write-ahead log as a line, encrypted with the installed
+/// key, if any.
+fn encode_line(seq: u64, timestamp: u64, command: &str) -> IoResult<String> {
+    let line = format!("{} {} {}", seq, timestamp, command);
+    #[cfg(feature = "encryption")]
+    let line = match encryption::key() {
+        Some(key) => key.seal_line(&line)?,
+        None => line,
+    };
+    Ok(format!("{}\n", line))
+}
+
+/// Decrypts an encrypted line of the write-ahead log with the installed key.
+fn decrypt_line(line: &str) -> IoResult<String> {
+    #[cfg(feature = "encryption")]
+    return encryption::require_key("write-ahead log")?.open_line(line);
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = line;
+        Err(encryption::unsupported())
+    }
+}
@@ -0,0 +1,170 @@
+//! Metrics implemented by WebAssembly modules.
+//!
+//! A module implementing a metric exports:
+//!
+//! - `memory`, its linear memory;
+//! - `alloc(size: i32) -> i32`, which returns the address of `size` bytes the
+//!   vectors to compare are written to. It is called again whenever longer
+//!   vectors are compared;
+//! - `distance(a: i32, b: i32, len: i32) -> f32`, which returns the distance
+//!   between the vectors of `len` little-endian `f32` components at `a` and `b`.
+//!
+//! Modules cannot import anything, so they can only compute. Every call runs on a
+//! budget of fuel proportional to the length of the vectors, and calls that run
+//! out of fuel or trap rank the vector last.
+
+use crate::metric::Metric;
+use std::sync::Mutex;
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// The maximum size of a module, in bytes.
+pub const MAX_MODULE_SIZE: usize = 1024 * 1024;
+
+/// The fuel every call starts with, on top of `FUEL_PER_COMPONENT` per component.
+const BASE_FUEL: u64 = 10_000;
+
+/// The fuel granted to a call for every component of the vectors it compares.
+const FUEL_PER_COMPONENT: u64 = 1_000;
+
+/// A metric computed by a WebAssembly module.
+pub struct WasmMetric {
+    name: String,
+    // Modules are not reentrant, so calls are serialized
+    module: Mutex<LoadedModule>,
+}
+
+/// An instance of a module, along with the buffer vectors are written to.
+struct LoadedModule {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    distance: TypedFunc<(i32, i32, i32), f32>,
+    // The address and capacity, in components per vector, of the buffer
+    buffer: Option<(i32, usize)>,
+}
+
+impl WasmMetric {
+    /// Compiles and instantiates the module `wasm` as a metric named `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the name is not made of letters, digits, `_`
+    /// and `-`, if the module is too large or invalid, imports anything, or does
+    /// not export the memory and functions of a metric.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use vemcache::wasm_metric::WasmMetric;
+    /// use vemcache::Vemcache;
+    ///
+    /// let wasm = std::fs::read("chebyshev.wasm").unwrap();
+    /// let mut db = Vemcache::new();
+    /// db.register_metric(Arc::new(WasmMetric::new("chebyshev", &wasm).unwrap()));
+    /// ```
+    pub fn new(name: &str, wasm: &[u8]) -> Result<Self, String> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err(format!("Invalid metric name: {}", name));
+        }
+        if wasm.len() > MAX_MODULE_SIZE {
+            return Err("Module too large".to_string());
+        }
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|err| err.to_string())?;
+        let mut store = Store::new(&engine, ());
+        store.set_fuel(BASE_FUEL).map_err(|err| err.to_string())?;
+        let instance = Linker::<()>::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|err| err.to_string())?;
+        let module = LoadedModule {
+            memory: instance
+                .get_memory(&store, "memory")
+                .ok_or("Module does not export its memory")?,
+            alloc: typed_func(&instance, &store, "alloc")?,
+            distance: typed_func(&instance, &store, "distance")?,
+            store,
+            buffer: None,
+        };
+        Ok(Self {
+            name: name.to_string(),
+            module: Mutex::new(module),
+        })
+    }
+}
+
+impl Metric for WasmMetric {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn distance(&self, v1: &[f32], v2: &[f32]) -> f32 {
+        self.module.lock().unwrap().distance(v1, v2)
+    }
+
+    fn distances(&self, query: &[f32], vectors: &[&[f32]], out: &mut Vec<f32>) {
+        let mut module = self.module.lock().unwrap();
+        out.extend(vectors.iter().map(|vector| module.distance(query, vector)));
+    }
+}
+
+impl LoadedModule {
+    /// Returns the distance computed by the module, or infinity if it fails.
+    fn distance(&mut self, v1: &[f32], v2: &[f32]) -> f32 {
+        let len = v1.len().min(v2.len());
+        match self.call(&v1[..len], &v2[..len]) {
+            Some(distance) if !distance.is_nan() => distance,
+            _ => f32::INFINITY,
+        }
+    }
+
+    fn call(&mut self, v1: &[f32], v2: &[f32]) -> Option<f32> {
+        let len = v1.len();
+        let fuel = BASE_FUEL + FUEL_PER_COMPONENT * len as u64;
+        self.store.set_fuel(fuel).ok()?;
+        let address = match self.buffer {
+            Some((address, capacity)) if capacity >= len => address,
+            _ => {
+                let size = i32::try_from(len * 2 * std::mem::size_of::<f32>()).ok()?;
+                let address = self.alloc.call(&mut self.store, size).ok()?;
+                self.buffer = Some((address, len));
+                address
+            }
+        };
+        let bytes = v1
+            .iter()
+            .chain(v2)
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>();
+        self.memory
+            .write(&mut self.store, address as u32 as usize, &bytes)
+            .ok()?;
+        let b = address + std::mem::size_of_val(v1) as i32;
+        self.distance
+            .call(&mut self.store, (address, b, len as i32))
+            .ok()
+    }
+}
+
+/// Returns the function `name` exported by `instance`, with the given signature.
+fn typed_func<Params, Results>(
+    instance: &Instance,
+    store: &Store<()>,
+    name: &str,
+) -> Result<TypedFunc<Params, Results>, String>
+where
+    Params: wasmi::WasmParams,
+    Results: wasmi::WasmResults,
+{
+    instance.get_typed_func(store, name).map_err(|_| {
+        format!(
+            "Module does not export {} with the expected signature",
+            name
+        )
+    })
+}
@@ -0,0 +1,81 @@
+//! Dumping a database and loading the dump back preserves every vector exactly.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use proptest::prelude::*;
+use vemcache::Vemcache;
+
+/// Returns a path in the temporary directory no other case uses.
+fn dump_path(extension: &str) -> PathBuf {
+    static CASES: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "vemcache-dump-{}-{}.{}",
+        std::process::id(),
+        CASES.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
+
+/// Every finite `f32`, including subnormals and negative zero.
+fn component() -> impl Strategy<Value = f32> {
+    any::<f32>().prop_filter("finite", |value| value.is_finite())
+}
+
+/// Databases with arbitrary keys, quotes and line breaks included, and vectors of
+/// any dimension.
+fn contents() -> impl Strategy<Value = Vec<(String, Vec<f32>)>> {
+    prop::collection::vec(
+        (".{1,16}", prop::collection::vec(component(), 0..32)),
+        0..50,
+    )
+}
+
+/// Returns the vectors of `db` by key, with their components as bits so that
+/// negative zero and NaN payloads are told apart.
+fn bits(db: &Vemcache) -> Vec<(String, Vec<u32>)> {
+    db.snapshot()
+        .vectors()
+        .into_iter()
+        .map(|(key, vector)| {
+            (
+                key.to_string(),
+                vector.iter().map(|value| value.to_bits()).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Dumps `db` to a new file and loads it into a new database.
+fn round_trip(db: &Vemcache, extension: &str) -> Vemcache {
+    let path = dump_path(extension);
+    let path = path.to_str().unwrap();
+    db.snapshot().dump(path).unwrap();
+    let mut loaded = Vemcache::new();
+    loaded.load(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    loaded
+}
+
+proptest! {
+    #[test]
+    fn dump_load_dump_is_lossless(contents in contents()) {
+        let mut db = Vemcache::new();
+        for (key, vector) in contents {
+            db.insert_with_key(key, vector);
+        }
+        let loaded = round_trip(&db, "json");
+        prop_assert_eq!(bits(&loaded), bits(&db));
+        let reloaded = round_trip(&loaded, "json");
+        prop_assert_eq!(bits(&reloaded), bits(&db));
+    }
+
+    #[test]
+    fn compressed_dumps_are_lossless(contents in contents()) {
+        let mut db = Vemcache::new();
+        for (key, vector) in contents {
+            db.insert_with_key(key, vector);
+        }
+        prop_assert_eq!(bits(&round_trip(&db, "json.gz")), bits(&db));
+    }
+}
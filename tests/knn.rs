@@ -0,0 +1,84 @@
+//! Nearest-neighbor searches return what comparing the query with every vector does.
+
+use proptest::prelude::*;
+use vemcache::index::IndexKind;
+use vemcache::metric::{Euclidean, Metric};
+use vemcache::Vemcache;
+
+/// A database of up to 200 vectors of the same dimension, a query and k.
+fn searches() -> impl Strategy<Value = (Vec<Vec<f32>>, Vec<f32>, usize)> {
+    (1..16usize).prop_flat_map(|dim| {
+        (
+            prop::collection::vec(prop::collection::vec(-10.0f32..10.0, dim), 1..200),
+            prop::collection::vec(-10.0f32..10.0, dim),
+            1..20usize,
+        )
+    })
+}
+
+/// Returns the distances of the k vectors nearest to `query`, nearest first.
+fn brute_force(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<f32> {
+    let mut distances = vectors
+        .iter()
+        .map(|vector| Euclidean.distance(query, vector))
+        .collect::<Vec<_>>();
+    distances.sort_by(f32::total_cmp);
+    distances.truncate(k);
+    distances
+}
+
+/// Checks that `neighbors` are as near to `query` as the exact k nearest vectors.
+/// Distances are compared rather than keys, since vectors at the same distance may
+/// be ranked either way.
+fn check(
+    neighbors: &[(String, std::sync::Arc<Vec<f32>>)],
+    vectors: &[Vec<f32>],
+    query: &[f32],
+    k: usize,
+) -> Result<(), TestCaseError> {
+    let expected = brute_force(vectors, query, k);
+    prop_assert_eq!(neighbors.len(), expected.len());
+    for ((_, vector), expected) in neighbors.iter().zip(expected) {
+        let distance = Euclidean.distance(query, vector);
+        prop_assert!((distance - expected).abs() <= 1e-4 * expected.max(1.0));
+    }
+    Ok(())
+}
+
+fn database(vectors: &[Vec<f32>]) -> Vemcache {
+    let mut db = Vemcache::new();
+    for (i, vector) in vectors.iter().enumerate() {
+        db.insert_with_key(format!("docs:{}", i), vector.clone());
+    }
+    db
+}
+
+proptest! {
+    #[test]
+    fn exhaustive_search_matches_brute_force((vectors, query, k) in searches()) {
+        let db = database(&vectors);
+        check(&db.search("docs:query", &query, k, None), &vectors, &query, k)?;
+    }
+
+    #[test]
+    fn flat_index_matches_brute_force((vectors, query, k) in searches()) {
+        let mut db = database(&vectors);
+        db.create_index("docs:".to_string(), IndexKind::Flat.build(db.default_metric()));
+        check(&db.search("docs:query", &query, k, None), &vectors, &query, k)?;
+    }
+
+    #[test]
+    fn restricted_search_matches_brute_force(
+        (vectors, query, k) in searches(),
+        picks in prop::collection::vec(any::<prop::sample::Index>(), 1..50),
+    ) {
+        let db = database(&vectors);
+        let candidates = picks
+            .iter()
+            .map(|pick| pick.index(vectors.len()))
+            .collect::<std::collections::BTreeSet<_>>();
+        let keys = candidates.iter().map(|i| format!("docs:{}", i)).collect::<Vec<_>>();
+        let subset = candidates.iter().map(|&i| vectors[i].clone()).collect::<Vec<_>>();
+        check(&db.nearest_among(&query, k, &keys, None), &subset, &query, k)?;
+    }
+}
@@ -0,0 +1,103 @@
+//! Properties of the built-in metrics.
+
+use proptest::prelude::*;
+use vemcache::metric::{builtin_metrics, Euclidean, Hamming, Metric, L1};
+
+/// Returns whether `a` and `b` are equal up to the rounding of `f32` sums.
+fn close(a: f32, b: f32) -> bool {
+    close_within(a, b, a.abs().max(b.abs()))
+}
+
+/// Returns whether `a` and `b` are equal up to the rounding of `f32` sums whose
+/// terms are of the order of `scale`. Sums of terms of opposite signs, such as dot
+/// products, are rounded relative to their terms rather than to their result.
+fn close_within(a: f32, b: f32, scale: f32) -> bool {
+    (a - b).abs() <= 1e-4 * scale.max(1.0)
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Vectors of the same dimension, with components small enough that their sums
+/// cannot overflow.
+fn vectors(count: usize) -> impl Strategy<Value = Vec<Vec<f32>>> {
+    (1..64usize).prop_flat_map(move |dim| {
+        prop::collection::vec(prop::collection::vec(-100.0f32..100.0, dim), count)
+    })
+}
+
+/// Binary vectors of the same dimension, as `hamming` expects.
+fn binary_vectors(count: usize) -> impl Strategy<Value = Vec<Vec<f32>>> {
+    (1..64usize).prop_flat_map(move |dim| {
+        prop::collection::vec(
+            prop::collection::vec(prop_oneof![Just(0.0f32), Just(1.0f32)], dim),
+            count,
+        )
+    })
+}
+
+proptest! {
+    #[test]
+    fn metrics_are_symmetric(vectors in vectors(2)) {
+        for metric in builtin_metrics() {
+            let ab = metric.distance(&vectors[0], &vectors[1]);
+            let ba = metric.distance(&vectors[1], &vectors[0]);
+            prop_assert!(close(ab, ba), "{}: {} != {}", metric.name(), ab, ba);
+        }
+    }
+
+    #[test]
+    fn block_distances_match_pairwise_distances(vectors in vectors(9)) {
+        let (query, block) = vectors.split_first().unwrap();
+        let block = block.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        for metric in builtin_metrics() {
+            let mut distances = Vec::new();
+            metric.distances(query, &block, &mut distances);
+            prop_assert_eq!(distances.len(), block.len());
+            for (vector, distance) in block.iter().zip(distances) {
+                let expected = metric.distance(query, vector);
+                prop_assert!(
+                    close_within(distance, expected, norm(query) * norm(vector)),
+                    "{}: {} != {}",
+                    metric.name(),
+                    distance,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn true_metrics_satisfy_the_triangle_inequality(vectors in vectors(3)) {
+        let (a, b, c) = (&vectors[0], &vectors[1], &vectors[2]);
+        let metrics: [&dyn Metric; 2] = [&Euclidean, &L1];
+        for metric in metrics {
+            prop_assert!(metric.distance(a, a) == 0.0, "{}", metric.name());
+            prop_assert!(metric.distance(a, b) >= 0.0, "{}", metric.name());
+            let direct = metric.distance(a, c);
+            let detour = metric.distance(a, b) + metric.distance(b, c);
+            prop_assert!(
+                direct <= detour || close(direct, detour),
+                "{}: {} > {}",
+                metric.name(),
+                direct,
+                detour
+            );
+        }
+    }
+
+    #[test]
+    fn hamming_satisfies_the_triangle_inequality(vectors in binary_vectors(3)) {
+        let (a, b, c) = (&vectors[0], &vectors[1], &vectors[2]);
+        prop_assert_eq!(Hamming.distance(a, a), 0.0);
+        prop_assert!(Hamming.distance(a, c) <= Hamming.distance(a, b) + Hamming.distance(b, c));
+    }
+
+    #[test]
+    fn cosine_distance_is_bounded(vectors in vectors(2)) {
+        let metric = vemcache::metric::Cosine;
+        let distance = metric.distance(&vectors[0], &vectors[1]);
+        prop_assert!((-1e-5..=2.0 + 1e-5).contains(&distance), "{}", distance);
+    }
+}
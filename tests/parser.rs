@@ -0,0 +1,79 @@
+//! The fast path parsing `insert`, `named_insert` and `binsert` reads vectors
+//! exactly as the general parser of the text protocol does.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use proptest::prelude::*;
+use vemcache::commands::{parse_command, Command};
+
+/// Every finite `f32`, including subnormals and negative zero.
+fn component() -> impl Strategy<Value = f32> {
+    any::<f32>().prop_filter("finite", |value| value.is_finite())
+}
+
+/// Arguments that may or may not be numbers, as clients send them.
+fn argument() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[-+]?[0-9]{0,4}(\\.[0-9]{0,4})?([eE][-+]?[0-9]{0,3})?",
+        "[-+]?(inf|infinity|nan|NaN|Inf)",
+        "[a-z.,_-]{1,4}",
+    ]
+}
+
+/// Returns the key and the components of the vector `line` inserts, as bits so
+/// that negative zero is told apart, or the error parsing it.
+fn inserted(line: &str) -> Result<(Option<String>, Vec<u32>), String> {
+    let bits = |values: Vec<f32>| values.iter().map(|value| value.to_bits()).collect();
+    match parse_command(line) {
+        Ok(Command::Insert(values)) => Ok((None, bits(values))),
+        Ok(Command::NamedInsert(key, values)) => Ok((Some(key), bits(values))),
+        Ok(Command::BinaryInsert(key, values)) => Ok((Some(key), bits(values))),
+        Ok(command) => Err(format!("Unexpected {}", command.name())),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+proptest! {
+    #[test]
+    fn formatted_components_are_read_back_exactly(values in prop::collection::vec(component(), 1..32)) {
+        let shortest = values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+        let exponent = values.iter().map(|value| format!("{:e}", value)).collect::<Vec<_>>();
+        let bits = values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+        for components in [shortest, exponent] {
+            let line = format!("named_insert docs:1 {}", components.join(" "));
+            prop_assert_eq!(inserted(&line), Ok((Some("docs:1".to_string()), bits.clone())));
+        }
+    }
+
+    #[test]
+    fn binary_inserts_keep_the_exact_bits(values in prop::collection::vec(component(), 1..32)) {
+        let payload = values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>();
+        let payload = BASE64.encode(payload);
+        let bits = values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+        let line = format!("binsert docs:1 {} {}", values.len(), payload);
+        prop_assert_eq!(inserted(&line), Ok((Some("docs:1".to_string()), bits.clone())));
+        // Quoting the key leaves the line to the general parser
+        let line = format!("BINSERT \"docs:1\" {} {}", values.len(), payload);
+        prop_assert_eq!(inserted(&line), Ok((Some("docs:1".to_string()), bits)));
+        let line = format!("binsert docs:1 {} {}", values.len() + 1, payload);
+        prop_assert_eq!(
+            inserted(&line),
+            Err("Payload length does not match the dimension".to_string())
+        );
+    }
+
+    #[test]
+    fn fast_path_matches_the_general_parser(
+        arguments in prop::collection::vec(argument(), 0..16),
+        separator in "[ \t]{1,3}",
+    ) {
+        let components = arguments.join(&separator);
+        // Quotes and non-ASCII whitespace leave the line to the general parser
+        let fast = inserted(&format!("NAMED_INSERT{}key{}{}", separator, separator, components));
+        let general = inserted(&format!("named_insert \"key\" {}", components));
+        prop_assert_eq!(fast, general);
+        let fast = inserted(&format!("insert{}{}\r\n", separator, components));
+        let general = inserted(&format!("insert {}\u{3000}", components));
+        prop_assert_eq!(fast, general);
+    }
+}
@@ -0,0 +1,1003 @@
+//! Sessions with a running server, over TCP.
+//!
+//! Every test starts its own `vemcache` process on an ephemeral port, without a
+//! data directory unless it needs one, and stops it when done.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// A `vemcache` process, killed when dropped.
+struct Server {
+    child: Child,
+    addr: String,
+    probe_addr: Option<String>,
+}
+
+impl Server {
+    /// Starts a server with the given command-line flags and waits until it
+    /// accepts connections.
+    fn start(flags: &[&str]) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_vemcache"));
+        // The environment of the test run must not configure the server
+        for (name, _) in std::env::vars() {
+            if name.starts_with("VEMCACHE_") {
+                command.env_remove(name);
+            }
+        }
+        let mut child = command
+            .args(["--host", "127.0.0.1", "--port", "0"])
+            .args(flags)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Could not start the server");
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let addr = Self::printed_addr(&mut stdout, " listening on ");
+        let probe_addr = flags
+            .contains(&"--probe-port")
+            .then(|| Self::printed_addr(&mut stdout, "Answering probes on "));
+        // Keep reading what the server prints, so it never blocks on a full pipe
+        std::thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+        Self {
+            child,
+            addr,
+            probe_addr,
+        }
+    }
+
+    /// Reads what the server prints until a line announcing an address after
+    /// `announcement`, and returns the address.
+    fn printed_addr(stdout: &mut impl BufRead, announcement: &str) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stdout.read_line(&mut line).unwrap() == 0 {
+                panic!("The server exited before printing {:?}", announcement);
+            }
+            if let Some((_, addr)) = line.trim_end().split_once(announcement) {
+                return addr.to_string();
+            }
+        }
+    }
+
+    fn connect(&self) -> Session {
+        let stream = TcpStream::connect(&self.addr).expect("Could not connect to the server");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Session {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+}
+
+/// Sends an HTTP request for `path` to the probe port of `server`, and returns the
+/// status code and the body of the response.
+fn probe(server: &Server, path: &str) -> (u16, String) {
+    let addr = server.probe_addr.as_ref().expect("Probes are not enabled");
+    let mut stream = TcpStream::connect(addr).expect("Could not connect to the probe port");
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .expect("Incomplete response");
+    let status = head.split(' ').nth(1).and_then(|code| code.parse().ok());
+    (status.expect("Invalid status line"), body.to_string())
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A client connection.
+struct Session {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Session {
+    /// Sends a command and returns the first line of the response.
+    fn send(&mut self, command: &str) -> String {
+        self.write(format!("{}\n", command).as_bytes());
+        self.line()
+    }
+
+    /// Sends a command and returns the whole response, whose first line is a
+    /// `<Header>: <count>` line followed by `count` lines.
+    fn send_multi(&mut self, command: &str) -> Vec<String> {
+        let header = self.send(command);
+        let count = header
+            .split_once(": ")
+            .and_then(|(_, count)| count.parse().ok())
+            .unwrap_or_else(|| panic!("Not a multi-line response: {}", header));
+        let mut response = vec![header];
+        response.extend((0..count).map(|_| self.line()));
+        response
+    }
+
+    /// Sends a command and returns the `count` lines of its response.
+    fn send_multi_lines(&mut self, command: &str, count: usize) -> Vec<String> {
+        let mut response = vec![self.send(command)];
+        response.extend((1..count).map(|_| self.line()));
+        response
+    }
+
+    /// Sends a command and returns its encoded response, checking that the
+    /// `<format>: <size>` line in front of it names `format`.
+    fn send_encoded(&mut self, command: &str, format: &str) -> Vec<u8> {
+        let header = self.send(command);
+        let size = header
+            .strip_prefix(&format!("{}: ", format))
+            .and_then(|size| size.parse().ok())
+            .unwrap_or_else(|| panic!("Not a {} response: {}", format, header));
+        let mut encoded = vec![0; size];
+        self.reader.read_exact(&mut encoded).unwrap();
+        encoded
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.writer.write_all(bytes).unwrap();
+    }
+
+    /// Reads a line of the response, without its line break.
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end_matches(['\r', '\n']).to_string()
+    }
+}
+
+#[test]
+fn insert_get_and_remove() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("ping"), "pong");
+    assert_eq!(session.send("named_insert vector1 1 2 3"), "OK");
+    assert_eq!(session.send("get vector1"), "[1.0, 2.0, 3.0]");
+    assert_eq!(session.send("remove vector1"), "OK");
+    assert_eq!(session.send("get vector1"), "null");
+}
+
+#[test]
+fn get_flags_choose_the_fields_of_the_response() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert v 3 4"), "OK");
+    assert_eq!(session.send("get v"), "[3.0, 4.0]");
+    assert_eq!(
+        session.send_multi_lines("get v withnorm", 2),
+        ["vector [3.0, 4.0]", "norm 5"]
+    );
+    // 3.0 and 4.0 as little-endian f32s, then f16s
+    assert_eq!(
+        session.send_multi_lines("get v raw", 2),
+        ["raw AABAQAAAgEA=", "dtype f32"]
+    );
+    assert_eq!(
+        session.send_multi_lines("get v RAW f16 withnorm", 3),
+        ["raw AEIARA==", "dtype f16", "norm 5"]
+    );
+    let response = session.send_multi_lines("get v withmeta", 4);
+    assert_eq!(response[..2], ["vector [3.0, 4.0]", "dim 2"]);
+    assert!(response[2].starts_with("created_at "));
+    assert!(response[3].starts_with("accessed_at "));
+    assert_eq!(session.send("get missing withmeta"), "null");
+    assert_eq!(
+        session.send("get v withcolor"),
+        "Error: Invalid GET command"
+    );
+}
+
+#[test]
+fn knn_returns_the_nearest_vectors_first() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    for command in [
+        "named_insert a 0 0",
+        "named_insert b 1 0",
+        "named_insert c 5 5",
+        "named_insert d 0 2",
+    ] {
+        assert_eq!(session.send(command), "OK");
+    }
+    assert_eq!(
+        session.send_multi("knn a 3"),
+        [
+            "Neighbors: 3",
+            "ID: a, Vector: [0.0, 0.0]",
+            "ID: b, Vector: [1.0, 0.0]",
+            "ID: d, Vector: [0.0, 2.0]",
+        ]
+    );
+    assert_eq!(
+        session.send_multi("knn a 2 offset 1"),
+        [
+            "Neighbors: 2",
+            "ID: b, Vector: [1.0, 0.0]",
+            "ID: d, Vector: [0.0, 2.0]",
+        ]
+    );
+}
+
+#[test]
+fn vectors_are_encoded_in_the_format_of_the_connection() {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Neighbor {
+        id: String,
+        vector: Vec<f32>,
+    }
+
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert a 0 0.5"), "OK");
+    assert_eq!(session.send("named_insert b 1 0"), "OK");
+    assert_eq!(session.send("format"), "text shortest");
+    assert_eq!(session.send("format msgpack"), "OK");
+    assert_eq!(session.send("format"), "msgpack");
+
+    let vector = session.send_encoded("get a", "msgpack");
+    assert_eq!(
+        rmp_serde::from_slice::<Option<Vec<f32>>>(&vector).unwrap(),
+        Some(vec![0.0, 0.5])
+    );
+    let missing = session.send_encoded("get missing", "msgpack");
+    assert_eq!(
+        rmp_serde::from_slice::<Option<Vec<f32>>>(&missing).unwrap(),
+        None
+    );
+    let neighbors = session.send_encoded("knn a 2", "msgpack");
+    assert_eq!(
+        rmp_serde::from_slice::<Vec<Neighbor>>(&neighbors).unwrap(),
+        [
+            Neighbor {
+                id: "a".to_string(),
+                vector: vec![0.0, 0.5]
+            },
+            Neighbor {
+                id: "b".to_string(),
+                vector: vec![1.0, 0.0]
+            },
+        ]
+    );
+    // Responses without vectors are still text
+    assert_eq!(session.send("knn missing 2"), "Key not found");
+
+    assert_eq!(session.send("format cbor"), "OK");
+    let sum = session.send_encoded("vadd a b", "cbor");
+    assert_eq!(
+        ciborium::from_reader::<Vec<f32>, _>(&sum[..]).unwrap(),
+        [1.0, 0.5]
+    );
+    assert_eq!(session.send("format yaml"), "Error: Unknown format");
+    assert_eq!(session.send("format text"), "OK");
+    assert_eq!(session.send("get a"), "[0.0, 0.5]");
+}
+
+#[test]
+fn components_are_written_in_the_notation_of_the_connection() {
+    let server = Server::start(&["--float-format", "2"]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert a 0.1 -2 1e-7"), "OK");
+    assert_eq!(session.send("format"), "text 2");
+    assert_eq!(session.send("get a"), "[0.10, -2.00, 0.00]");
+    assert_eq!(session.send("format text shortest"), "OK");
+    assert_eq!(session.send("get a"), "[0.1, -2.0, 1e-7]");
+    assert_eq!(session.send("format text hex"), "OK");
+    assert_eq!(
+        session.send("get a"),
+        "[0x1.99999ap-4, -0x1p+1, 0x1.ad7f2ap-24]"
+    );
+    assert_eq!(
+        session.send_multi("knn a 1"),
+        [
+            "Neighbors: 1",
+            "ID: a, Vector: [0x1.99999ap-4, -0x1p+1, 0x1.ad7f2ap-24]"
+        ]
+    );
+    assert_eq!(session.send("format text 99"), "Error: Unknown notation");
+
+    // Connections opened afterwards use the new float_format
+    assert_eq!(session.send("config set float_format 1"), "OK");
+    assert_eq!(
+        session.send("get a"),
+        "[0x1.99999ap-4, -0x1p+1, 0x1.ad7f2ap-24]"
+    );
+    assert_eq!(server.connect().send("get a"), "[0.1, -2.0, 0.0]");
+    assert_eq!(
+        session.send("config set float_format sci"),
+        "Error: Invalid value for float_format: sci"
+    );
+}
+
+#[test]
+fn keys_are_quoted_in_responses() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send(r#"named_insert "my key" 1 2"#), "OK");
+    assert_eq!(
+        session.send_multi(r#"knn "my key" 1"#),
+        ["Neighbors: 1", r#"ID: "my key", Vector: [1.0, 2.0]"#]
+    );
+}
+
+#[test]
+fn malformed_commands_are_answered_with_errors() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("frobnicate"), "Error: Unknown command");
+    assert_eq!(session.send("knn vector1 many"), "Error: Invalid k value");
+    assert_eq!(
+        session.send("knn vector1 3 rerank 0"),
+        "Error: Invalid rerank factor"
+    );
+    assert!(session.send(r#"get "unterminated"#).starts_with("Error: "));
+    session.write(b"get \xff\xfe\n");
+    assert_eq!(session.line(), "Error: Commands must be valid UTF-8");
+    // The connection is still in sync
+    assert_eq!(session.send("ping"), "pong");
+}
+
+#[test]
+fn lines_longer_than_the_limit_are_skipped() {
+    let server = Server::start(&["--max-line-length", "32"]);
+    let mut session = server.connect();
+    let line = format!("named_insert vector1 {}", "1 ".repeat(100));
+    assert_eq!(
+        session.send(&line),
+        "Error: Line too long, the limit is 32 bytes"
+    );
+    assert_eq!(session.send("get vector1"), "null");
+}
+
+#[test]
+fn subscribers_skip_lines_longer_than_the_limit() {
+    let server = Server::start(&["--max-line-length", "32"]);
+    let mut subscriber = server.connect();
+    assert_eq!(subscriber.send("subscribe __keyevent@0__:expired"), "OK");
+    // Ignored like any line sent while subscribed, without being kept in memory
+    subscriber.write(format!("{}\n", "1 ".repeat(100)).as_bytes());
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert session:1 1 2"), "OK");
+    assert_eq!(session.send("ttl set session: 1"), "OK");
+    assert_eq!(
+        subscriber.line(),
+        "message __keyevent@0__:expired session:1"
+    );
+}
+
+#[test]
+fn pipelined_commands_are_answered_in_order() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    session.write(b"named_insert a 1\nnamed_insert b 2\nget a\nget b\nget c\n");
+    let responses = (0..5).map(|_| session.line()).collect::<Vec<_>>();
+    assert_eq!(responses, ["OK", "OK", "[1.0]", "[2.0]", "null"]);
+}
+
+#[test]
+fn pipelined_commands_are_answered_before_the_client_closes() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    let mut commands = String::new();
+    for i in 0..1000 {
+        commands.push_str(&format!("named_insert v{} {}\nget v{}\n", i, i, i));
+    }
+    // The responses still buffered are sent even though the client sends nothing more
+    session.write(commands.as_bytes());
+    session.writer.shutdown(Shutdown::Write).unwrap();
+    for i in 0..1000 {
+        assert_eq!(session.line(), "OK");
+        assert_eq!(session.line(), format!("[{}.0]", i));
+    }
+    let mut rest = Vec::new();
+    assert_eq!(session.reader.read_to_end(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn pipelined_resp_requests_are_answered_in_order() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    let mut requests = Vec::new();
+    for i in 0..100 {
+        let key = format!("v{}", i);
+        requests.extend_from_slice(
+            format!(
+                "*3\r\n$12\r\nnamed_insert\r\n${}\r\n{}\r\n$1\r\n1\r\n",
+                key.len(),
+                key
+            )
+            .as_bytes(),
+        );
+        requests.extend_from_slice(
+            format!("*2\r\n$3\r\nget\r\n${}\r\n{}\r\n", key.len(), key).as_bytes(),
+        );
+    }
+    session.write(&requests);
+    for _ in 0..100 {
+        assert_eq!(session.line(), "+OK");
+        assert_eq!(session.line(), "+[1.0]");
+    }
+}
+
+#[test]
+fn clients_that_do_not_read_are_not_buffered_for() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    let components = vec!["1"; 1000].join(" ");
+    assert_eq!(
+        session.send(&format!("named_insert a {}", components)),
+        "OK"
+    );
+    let response = format!("[{}]", vec!["1.0"; 1000].join(", "));
+
+    // Pipeline commands without reading until the server stops reading them too,
+    // which it does once the socket buffers are full of its responses. Commands
+    // are padded so that fewer fill the buffers.
+    let command = format!("get{}a\n", " ".repeat(1000));
+    let command = command.as_bytes();
+    const LIMIT: usize = 256 << 20;
+    session.writer.set_nonblocking(true).unwrap();
+    let mut written = 0;
+    while written < LIMIT {
+        match session.writer.write(&command[written % command.len()..]) {
+            Ok(n) => written += n,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(err) => panic!("Could not send the commands: {}", err),
+        }
+    }
+    assert!(written < LIMIT, "The server kept reading commands");
+    session.writer.set_nonblocking(false).unwrap();
+
+    // The commands sent are all answered, in order, once the client reads again
+    let commands = written.div_ceil(command.len());
+    let rest = command[written - (commands - 1) * command.len()..].to_vec();
+    let mut writer = session.writer.try_clone().unwrap();
+    let sender = std::thread::spawn(move || writer.write_all(&rest).unwrap());
+    for _ in 0..commands {
+        assert_eq!(session.line(), response);
+    }
+    sender.join().unwrap();
+    assert_eq!(session.send("ping"), "pong");
+}
+
+#[test]
+fn resp_requests_are_answered_in_resp() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    session.write(b"*4\r\n$12\r\nnamed_insert\r\n$6\r\nmy key\r\n$1\r\n1\r\n$1\r\n2\r\n");
+    assert_eq!(session.line(), "+OK");
+    session.write(b"*2\r\n$3\r\nget\r\n$6\r\nmy key\r\n");
+    assert_eq!(session.line(), "+[1.0, 2.0]");
+    session.write(b"*1\r\n$4\r\nnope\r\n");
+    assert_eq!(session.line(), "-ERR Unknown command");
+    // Encoded responses are bulk strings
+    session.write(b"*2\r\n$6\r\nformat\r\n$7\r\nmsgpack\r\n");
+    assert_eq!(session.line(), "+OK");
+    session.write(b"*2\r\n$3\r\nget\r\n$7\r\nmissing\r\n");
+    assert_eq!(session.line(), "$1");
+    let mut nil = [0; 3];
+    session.reader.read_exact(&mut nil).unwrap();
+    assert_eq!(nil, *b"\xc0\r\n");
+    session.write(b"*2\r\n$3\r\nget\r\n$6\r\nmy key\r\n");
+    assert_eq!(session.line(), "$11");
+}
+
+#[test]
+fn disconnects_do_not_affect_other_clients() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert vector1 1 2"), "OK");
+
+    // A client leaving in the middle of a command
+    let mut partial = server.connect();
+    partial.write(b"knn vector1");
+    partial.writer.shutdown(Shutdown::Both).unwrap();
+    // A client leaving before reading its response
+    let mut hasty = server.connect();
+    hasty.write(b"knn vector1 1\n");
+    drop(hasty);
+    // A client leaving in the middle of a RESP request
+    let mut resp = server.connect();
+    resp.write(b"*2\r\n$3\r\nget\r\n$7\r\nvect");
+    drop(resp);
+
+    assert_eq!(session.send("get vector1"), "[1.0, 2.0]");
+    assert_eq!(server.connect().send("ping"), "pong");
+}
+
+#[test]
+fn writes_are_rejected_in_read_only_mode() {
+    let server = Server::start(&["--read-only"]);
+    let mut session = server.connect();
+    assert!(session
+        .send("named_insert vector1 1 2")
+        .starts_with("Error: READONLY"));
+    assert_eq!(session.send("get vector1"), "null");
+    // Expiring vectors removes them
+    assert!(session
+        .send("ttl set \"\" 1")
+        .starts_with("Error: READONLY"));
+    assert!(session
+        .send("ttl drop docs:")
+        .starts_with("Error: READONLY"));
+}
+
+#[test]
+fn the_server_closes_connections_it_cannot_resync() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    // A RESP request announcing an invalid bulk length, answered in the text
+    // protocol since the connection has not switched to RESP yet
+    session.write(b"*1\r\n$x\r\n");
+    assert_eq!(session.line(), "Error: Protocol error: invalid bulk length");
+    let mut rest = Vec::new();
+    assert_eq!(session.reader.read_to_end(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn probes_report_readiness_until_the_databases_are_saved() {
+    // 0 disables the probes, so a free port is picked here
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+        .port()
+        .to_string();
+    let server = Server::start(&["--probe-port", &port]);
+    assert_eq!(probe(&server, "/livez"), (200, "ok\n".to_string()));
+    let (status, report) = probe(&server, "/readyz");
+    assert_eq!(status, 200);
+    assert!(report.starts_with("status ready\n"));
+    assert_eq!(probe(&server, "/unknown").0, 404);
+
+    assert_eq!(probe(&server, "/prestop"), (200, "saved\n".to_string()));
+    let (status, report) = probe(&server, "/readyz");
+    assert_eq!(status, 503);
+    assert!(report.starts_with("status draining\n"));
+    // Saving again is a no-op, and the server is still alive
+    assert_eq!(probe(&server, "/prestop"), (200, "saved\n".to_string()));
+    assert_eq!(probe(&server, "/livez").0, 200);
+    assert!(server
+        .connect()
+        .send("health")
+        .starts_with("status draining"));
+}
+
+#[test]
+fn warmup_reads_the_vectors_of_a_collection() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert docs:1 1 2"), "OK");
+    assert_eq!(session.send("named_insert docs:2 2 1"), "OK");
+    assert_eq!(session.send("named_insert images:1 1 1"), "OK");
+    assert_eq!(session.send("index create docs: hnsw"), "OK");
+    assert_eq!(session.send("warmup"), "Warmed: 3 vectors, 0 indexes");
+    assert_eq!(
+        session.send("warmup docs:* withindexes"),
+        "Warmed: 2 vectors, 1 indexes"
+    );
+    assert_eq!(session.send("warmup a b"), "Error: Invalid WARMUP command");
+}
+
+#[test]
+fn rebuilt_indexes_replace_the_index_of_their_collection() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    for i in 0..20 {
+        let inserted = session.send(&format!("named_insert docs:{} {} 1", i, i));
+        assert_eq!(inserted, "OK");
+    }
+    assert_eq!(session.send("index create docs: hnsw"), "OK");
+    // The index is built in the background
+    while session.send_multi("index list").len() == 1 {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(session.send("index rebuild docs: m 8 ef_search 100"), "OK");
+    let stats = loop {
+        let stats = session.send_multi_lines("collection stats docs:", 13);
+        if stats.contains(&"index_m 8".to_string()) {
+            break stats;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    assert!(stats.contains(&"index_ef_search 100".to_string()));
+    assert!(stats.contains(&"index_ef_construction 200".to_string()));
+    assert_eq!(
+        session.send("index rebuild docs: lists 4"),
+        "Error: hnsw indexes have no parameter lists"
+    );
+    assert_eq!(
+        session.send("index rebuild images:"),
+        "Error: The collection has no index"
+    );
+}
+
+#[test]
+fn snapshots_are_listed_described_and_deleted() {
+    let data_dir = std::env::temp_dir().join(format!("vemcache-snapshots-{}", std::process::id()));
+    let server = Server::start(&["--data-dir", data_dir.to_str().unwrap()]);
+    let mut session = server.connect();
+    // Commands are rejected until the data directory is restored
+    let mut inserted = session.send("named_insert vector1 1 2");
+    while inserted.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        inserted = session.send("named_insert vector1 1 2");
+    }
+    assert_eq!(inserted, "OK");
+    assert!(session
+        .send("dump first.json.gz")
+        .starts_with("Database dump successful"));
+    assert!(session
+        .send("dump second.json")
+        .starts_with("Database dump successful"));
+
+    let listed = session.send_multi("snapshots list");
+    assert_eq!(listed[0], "Snapshots: 2");
+    assert!(listed.iter().any(|line| line.starts_with("first.json.gz ")));
+    assert!(listed[1..].iter().all(|line| line.ends_with(" 4")));
+
+    let info = session.send_multi_lines("snapshots info first.json.gz", 8);
+    assert_eq!(info[0], "name first.json.gz");
+    assert_eq!(info[3], "version 4");
+    assert_eq!(info[4], "compression gzip");
+    assert_eq!(info[6], "base null");
+    assert_eq!(
+        session.send("snapshots info missing.json"),
+        "Error: No snapshot named missing.json"
+    );
+    assert!(session
+        .send("snapshots delete ../second.json")
+        .starts_with("Error:"));
+
+    assert_eq!(session.send("snapshots delete second.json"), "OK");
+    assert!(!data_dir.join("second.json").exists());
+    assert_eq!(session.send("snapshots list"), "Snapshots: 1");
+    drop(server);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn replayed_changes_are_not_transformed_again_on_restart() {
+    let data_dir = std::env::temp_dir().join(format!("vemcache-replay-{}", std::process::id()));
+    let flags = ["--data-dir", data_dir.to_str().unwrap()];
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut piped = session.send("pipeline set docs: clip -0.5 0.5 normalize");
+    while piped.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        piped = session.send("pipeline set docs: clip -0.5 0.5 normalize");
+    }
+    assert_eq!(piped, "OK");
+    // The snapshot keeps the pipeline, the write-ahead log keeps the vector
+    assert!(session
+        .send("dump pipeline.json")
+        .starts_with("Database dump successful"));
+    assert_eq!(session.send("named_insert docs:a 1 0.1"), "OK");
+    let stored = session.send("get docs:a");
+    assert_eq!(stored, "[0.9805807, 0.19611615]");
+    drop(server);
+
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut restored = session.send("get docs:a");
+    while restored.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        restored = session.send("get docs:a");
+    }
+    assert_eq!(restored, stored);
+    drop(server);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn dumps_compact_the_write_ahead_log() {
+    let data_dir = std::env::temp_dir().join(format!("vemcache-compact-{}", std::process::id()));
+    let flags = ["--data-dir", data_dir.to_str().unwrap()];
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut inserted = session.send("named_insert docs:1 1 2");
+    while inserted.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        inserted = session.send("named_insert docs:1 1 2");
+    }
+    assert_eq!(inserted, "OK");
+    assert_eq!(session.send("named_insert docs:2 2 1"), "OK");
+    assert!(session
+        .send("dump docs.json")
+        .starts_with("Database dump successful"));
+    assert_eq!(session.send("named_insert docs:3 3 3"), "OK");
+    // Only the last change the dump holds is kept, along with the newer ones
+    let wal = std::fs::read_to_string(data_dir.join("vemcache.wal")).unwrap();
+    let seqs: Vec<_> = wal
+        .lines()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(seqs, ["2", "3"]);
+    drop(server);
+
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut restored = session.send("count");
+    while restored.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        restored = session.send("count");
+    }
+    assert_eq!(restored, "3");
+    assert_eq!(session.send("named_insert docs:4 4 4"), "OK");
+    let wal = std::fs::read_to_string(data_dir.join("vemcache.wal")).unwrap();
+    assert!(wal.lines().last().unwrap().starts_with("4 "));
+    drop(server);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn dumps_under_a_prefix_are_not_restored_at_startup() {
+    let data_dir = std::env::temp_dir().join(format!("vemcache-partial-{}", std::process::id()));
+    let flags = ["--data-dir", data_dir.to_str().unwrap()];
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut inserted = session.send("named_insert docs:1 1 2");
+    while inserted.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        inserted = session.send("named_insert docs:1 1 2");
+    }
+    assert_eq!(inserted, "OK");
+    assert_eq!(session.send("named_insert images:1 2 1"), "OK");
+    assert!(session
+        .send("dump all.json")
+        .starts_with("Database dump successful"));
+    // Newer than the full dump, but only holds part of the database
+    assert!(session
+        .send("dump docs.json prefix docs:")
+        .starts_with("Database dump successful"));
+    assert_eq!(
+        session.send("snapshots list").lines().next(),
+        Some("Snapshots: 1")
+    );
+    drop(server);
+
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut restored = session.send("count");
+    while restored.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        restored = session.send("count");
+    }
+    assert_eq!(restored, "2");
+    drop(server);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn files_that_are_not_dumps_are_not_restored_at_startup() {
+    let data_dir = std::env::temp_dir().join(format!("vemcache-not-dumps-{}", std::process::id()));
+    let flags = ["--data-dir", data_dir.to_str().unwrap()];
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut inserted = session.send("named_insert vector1 1 2");
+    while inserted.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        inserted = session.send("named_insert vector1 1 2");
+    }
+    assert_eq!(inserted, "OK");
+    drop(server);
+    // Newer than every dump, but neither is one
+    std::fs::write(data_dir.join("notes.json"), "{\"key\": \"a\"}\n").unwrap();
+    let mut truncated = b"VEMDUMP\0".to_vec();
+    truncated.extend(2u32.to_le_bytes());
+    std::fs::write(data_dir.join("truncated.json"), truncated).unwrap();
+
+    let server = Server::start(&flags);
+    let mut session = server.connect();
+    let mut restored = session.send("get vector1");
+    while restored.starts_with("Error: LOADING") {
+        std::thread::sleep(Duration::from_millis(20));
+        restored = session.send("get vector1");
+    }
+    assert_eq!(restored, "[1.0, 2.0]");
+    drop(server);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn exports_may_not_be_named_like_snapshots() {
+    let server = Server::start(&[]);
+    let mut session = server.connect();
+    assert_eq!(session.send("named_insert vector1 1 2"), "OK");
+    assert_eq!(
+        session.send("export jsonl vectors.json.gz"),
+        "Error exporting vectors.json.gz: Names ending in .json, .json.gz or .json.zst \
+         are reserved for dumps: vectors.json.gz"
+    );
+}
+
+/// Starts a server with the configuration file holding `config`, expecting it to
+/// exit at once, and returns what it printed to stderr.
+fn startup_error(config: &str) -> String {
+    static CONFIGS: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "vemcache-config-{}-{}.toml",
+        std::process::id(),
+        CONFIGS.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, config).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_vemcache"));
+    for (name, _) in std::env::vars() {
+        if name.starts_with("VEMCACHE_") {
+            command.env_remove(name);
+        }
+    }
+    let output = command
+        .args(["--port", "0", "--config", path.to_str().unwrap()])
+        .output()
+        .expect("Could not start the server");
+    let _ = std::fs::remove_file(path);
+    assert!(!output.status.success());
+    String::from_utf8_lossy(&output.stderr)
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn configuration_files_with_invalid_values_are_rejected() {
+    assert_eq!(
+        startup_error("databases = 0\n"),
+        "Error: Invalid value for databases: 0"
+    );
+    assert_eq!(
+        startup_error("metric = \"nope\"\n"),
+        "Error: Invalid value for metric: nope"
+    );
+    assert_eq!(
+        startup_error("autotune_interval = 0\n"),
+        "Error: Invalid value for autotune_interval: 0"
+    );
+    assert_eq!(
+        startup_error("cluster_nodes = [\"a:1\", \"b:2\"]\ncluster_self = \"c:3\"\n"),
+        "Error: c:3 is not one of the cluster nodes"
+    );
+    assert_eq!(
+        startup_error("raft_nodes = [\"a:1\"]\n"),
+        "Error: raft_nodes requires acl_tokens, and a primary_token granting the admin role \
+         the nodes authenticate with"
+    );
+    let tokens = "acl_tokens = [\"admin:s3cret\"]\nprimary_token = \"s3cret\"\n";
+    assert_eq!(
+        startup_error(&format!(
+            "{}raft_nodes = [\"a:1\", \"b:2\"]\nraft_self = \"c:3\"\n",
+            tokens
+        )),
+        "Error: c:3 is not one of the raft nodes"
+    );
+    assert_eq!(
+        startup_error("raft_nodes = [\"a:1\"]\nreplicaof = \"b:2\"\n"),
+        "Error: raft_nodes cannot be combined with cluster_nodes or replicaof, the Raft group \
+         elects the node to replicate from"
+    );
+}
+
+#[test]
+fn admin_commands_are_rejected_on_the_main_port() {
+    // Ask the system for a free port for the admin port
+    let admin_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+        .to_string();
+    let server = Server::start(&["--admin-port", &admin_port]);
+    let mut session = server.connect();
+    for command in [
+        "snapshots list",
+        "snapshots delete snapshot.json",
+        "restore until 1",
+        "backup now",
+        "dump snapshot.json",
+    ] {
+        assert_eq!(
+            session.send(command),
+            "Error: NOPERM Admin commands are only accepted on the admin port"
+        );
+    }
+}
+
+#[test]
+fn commands_writing_unknown_keys_are_rejected_in_cluster_mode() {
+    let server = Server::start(&[
+        "--cluster-nodes",
+        "127.0.0.1:1,127.0.0.1:2",
+        "--cluster-self",
+        "127.0.0.1:1",
+    ]);
+    let mut session = server.connect();
+    assert_eq!(
+        session.send("import jsonl vectors.jsonl"),
+        "Error: CLUSTER import jsonl is not supported in cluster mode, since it writes keys \
+         other nodes may own"
+    );
+}
+
+/// Waits until one of the running `servers` leads its Raft group, and returns its index.
+fn raft_leader(servers: &[Option<Server>]) -> usize {
+    for _ in 0..500 {
+        for (index, server) in servers.iter().enumerate() {
+            let Some(server) = server else {
+                continue;
+            };
+            let mut session = server.connect();
+            session.send("auth s3cret");
+            if session.send("raft status") == "role leader" {
+                return index;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("No leader was elected");
+}
+
+#[test]
+fn raft_groups_keep_accepting_writes_when_the_leader_fails() {
+    // Ask the system for free ports for the nodes, all bound at once so they differ
+    let listeners = (0..3)
+        .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect::<Vec<_>>();
+    let ports = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap().port().to_string())
+        .collect::<Vec<String>>();
+    drop(listeners);
+    let nodes = ports
+        .iter()
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect::<Vec<String>>()
+        .join(",");
+    let mut servers = ports
+        .iter()
+        .map(|port| {
+            Some(Server::start(&[
+                "--port",
+                port,
+                "--raft-nodes",
+                &nodes,
+                "--acl-tokens",
+                "admin:s3cret,writer:wr1te",
+                "--primary-token",
+                "s3cret",
+            ]))
+        })
+        .collect::<Vec<Option<Server>>>();
+
+    let leader = raft_leader(&servers);
+    let leader_addr = servers[leader].as_ref().unwrap().addr.clone();
+    let mut session = servers[leader].as_ref().unwrap().connect();
+    assert_eq!(session.send("auth wr1te"), "OK");
+    // Only the other nodes may acknowledge writes
+    assert_eq!(
+        session.send("raft changes 1 127.0.0.1:1 99"),
+        "Error: NOPERM This command requires the admin role"
+    );
+    assert_eq!(session.send("named_insert vector1 1 2"), "OK");
+    let mut session = servers[(leader + 1) % 3].as_ref().unwrap().connect();
+    assert_eq!(session.send("auth wr1te"), "OK");
+    let mut redirected = session.send("named_insert vector2 1 2");
+    while redirected.starts_with("Error: TRYAGAIN") {
+        std::thread::sleep(Duration::from_millis(20));
+        redirected = session.send("named_insert vector2 1 2");
+    }
+    assert_eq!(redirected, format!("Error: MOVED {}", leader_addr));
+
+    // The write was acknowledged once a majority held it, so it survives the leader
+    servers[leader] = None;
+    let leader = raft_leader(&servers);
+    let mut session = servers[leader].as_ref().unwrap().connect();
+    assert_eq!(session.send("auth wr1te"), "OK");
+    assert_eq!(session.send("get vector1"), "[1.0, 2.0]");
+    assert_eq!(session.send("named_insert vector2 3 4"), "OK");
+}
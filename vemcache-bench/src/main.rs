@@ -0,0 +1,291 @@
+//! A load generator for Vemcache.
+//!
+//! The benchmark first loads a keyspace of random vectors, then sends a mix of
+//! inserts and `knn` searches on random keys from concurrent clients, and reports
+//! the throughput and latency percentiles of each operation. Runs with the same
+//! seed send the same requests, so their results can be compared across builds.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use vemcache_client::Client;
+
+/// The options of a run.
+struct Options {
+    addr: String,
+    token: Option<String>,
+    clients: usize,
+    requests: usize,
+    keys: usize,
+    dim: usize,
+    k: usize,
+    search_ratio: f64,
+    prefix: String,
+    seed: u64,
+}
+
+/// The latencies of the requests of an operation, and the number that failed.
+#[derive(Default)]
+struct Samples {
+    latencies: Vec<Duration>,
+    errors: usize,
+    first_error: Option<String>,
+}
+
+impl Samples {
+    fn record<T, E: ToString>(&mut self, started: Instant, result: Result<T, E>) {
+        match result {
+            Ok(_) => self.latencies.push(started.elapsed()),
+            Err(err) => {
+                self.errors += 1;
+                self.first_error.get_or_insert_with(|| err.to_string());
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Samples) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+        if self.first_error.is_none() {
+            self.first_error = other.first_error;
+        }
+    }
+}
+
+/// A SplitMix64 generator, which is plenty for picking keys and components.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a number in `[0, n)`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.unit() * n as f64) as usize
+    }
+
+    fn vector(&mut self, dim: usize) -> Vec<f32> {
+        (0..dim).map(|_| self.unit() as f32).collect()
+    }
+}
+
+fn print_usage() {
+    println!("Usage: vemcache-bench [options]");
+    println!();
+    println!("Loads a keyspace of random vectors into a Vemcache server, then sends a");
+    println!("mix of inserts and knn searches on random keys from concurrent clients,");
+    println!("and reports the throughput and latency percentiles of each operation.");
+    println!();
+    println!("Options:");
+    println!("  --host <host>          Server host (default: 127.0.0.1)");
+    println!("  --port <port>          Server port (default: 7070)");
+    println!("  --token <token>        Token to authenticate with, also read from VEMCACHE_TOKEN");
+    println!("  --clients <n>          Concurrent clients (default: 8)");
+    println!("  --requests <n>         Requests of the mixed workload (default: 10000)");
+    println!("  --keys <n>             Vectors loaded before the mixed workload (default: 10000)");
+    println!("  --dim <n>              Dimension of the vectors (default: 128)");
+    println!("  --k <n>                Neighbors returned by searches (default: 10)");
+    println!("  --search-ratio <r>     Share of searches in the mixed workload (default: 0.5)");
+    println!("  --prefix <prefix>      Prefix of the keys written (default: bench:)");
+    println!("  --seed <n>             Seed of the random keys and vectors (default: 1)");
+}
+
+/// Parses the command line, or returns `None` after printing the usage.
+fn parse_args() -> Result<Option<Options>, String> {
+    let mut host = "127.0.0.1".to_string();
+    let mut port = "7070".to_string();
+    let mut options = Options {
+        addr: String::new(),
+        token: std::env::var("VEMCACHE_TOKEN").ok(),
+        clients: 8,
+        requests: 10_000,
+        keys: 10_000,
+        dim: 128,
+        k: 10,
+        search_ratio: 0.5,
+        prefix: "bench:".to_string(),
+        seed: 1,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--help" {
+            print_usage();
+            return Ok(None);
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--host" | "-h" => host = value,
+            "--port" | "-p" => port = value,
+            "--token" => options.token = Some(value),
+            "--clients" => options.clients = value.parse().map_err(|_| invalid())?,
+            "--requests" => options.requests = value.parse().map_err(|_| invalid())?,
+            "--keys" => options.keys = value.parse().map_err(|_| invalid())?,
+            "--dim" => options.dim = value.parse().map_err(|_| invalid())?,
+            "--k" => options.k = value.parse().map_err(|_| invalid())?,
+            "--search-ratio" => {
+                options.search_ratio = value
+                    .parse()
+                    .ok()
+                    .filter(|ratio| (0.0..=1.0).contains(ratio))
+                    .ok_or_else(invalid)?
+            }
+            "--prefix" => options.prefix = value,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+    if options.clients == 0 || options.keys == 0 || options.dim == 0 {
+        return Err("--clients, --keys and --dim must be at least 1".to_string());
+    }
+    options.addr = format!("{}:{}", host, port);
+    Ok(Some(options))
+}
+
+/// Inserts the vectors of the keyspace, split between the clients.
+async fn load(client: Arc<Client>, options: Arc<Options>) -> Samples {
+    let tasks = (0..options.clients)
+        .map(|task| {
+            let client = client.clone();
+            let options = options.clone();
+            tokio::spawn(async move {
+                let mut rng = Rng(options.seed ^ task as u64);
+                let mut samples = Samples::default();
+                for key in (task..options.keys).step_by(options.clients) {
+                    let vector = rng.vector(options.dim);
+                    let key = format!("{}{}", options.prefix, key);
+                    let started = Instant::now();
+                    samples.record(started, client.insert(&key, &vector).await);
+                }
+                samples
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut samples = Samples::default();
+    for task in tasks {
+        samples.merge(task.await.expect("Benchmark task panicked"));
+    }
+    samples
+}
+
+/// Sends the mixed workload, split between the clients, and returns the samples
+/// of the inserts and of the searches.
+async fn mixed(client: Arc<Client>, options: Arc<Options>) -> (Samples, Samples) {
+    let tasks = (0..options.clients)
+        .map(|task| {
+            let client = client.clone();
+            let options = options.clone();
+            tokio::spawn(async move {
+                // A different stream than the one of the load
+                let mut rng = Rng(!(options.seed ^ task as u64));
+                let mut inserts = Samples::default();
+                let mut searches = Samples::default();
+                for _ in (task..options.requests).step_by(options.clients) {
+                    let key = format!("{}{}", options.prefix, rng.below(options.keys));
+                    if rng.unit() < options.search_ratio {
+                        let started = Instant::now();
+                        searches.record(started, client.knn(&key, options.k).await);
+                    } else {
+                        let vector = rng.vector(options.dim);
+                        let started = Instant::now();
+                        inserts.record(started, client.insert(&key, &vector).await);
+                    }
+                }
+                (inserts, searches)
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut inserts = Samples::default();
+    let mut searches = Samples::default();
+    for task in tasks {
+        let (task_inserts, task_searches) = task.await.expect("Benchmark task panicked");
+        inserts.merge(task_inserts);
+        searches.merge(task_searches);
+    }
+    (inserts, searches)
+}
+
+/// Prints a line of the report, with the throughput over `elapsed` and the
+/// latency percentiles in milliseconds.
+fn report(operation: &str, mut samples: Samples, elapsed: Duration) {
+    samples.latencies.sort_unstable();
+    let percentile = |p: f64| match samples.latencies.len() {
+        0 => 0.0,
+        len => {
+            let index = ((len - 1) as f64 * p).round() as usize;
+            samples.latencies[index].as_secs_f64() * 1000.0
+        }
+    };
+    println!(
+        "{:<10} {:>9} {:>7} {:>10.0} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+        operation,
+        samples.latencies.len(),
+        samples.errors,
+        samples.latencies.len() as f64 / elapsed.as_secs_f64(),
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0),
+    );
+    if let Some(err) = samples.first_error {
+        eprintln!("First {} error: {}", operation, err);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args() {
+        Ok(Some(options)) => Arc::new(options),
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let mut builder = Client::builder(&options.addr).pool_size(options.clients);
+    if let Some(token) = &options.token {
+        builder = builder.token(token);
+    }
+    let client = match builder.connect().await {
+        Ok(client) => Arc::new(client),
+        Err(err) => {
+            eprintln!("Could not connect to {}: {}", options.addr, err);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "{} clients, {} keys of dimension {}, {} requests, {:.0}% searches with k = {}",
+        options.clients,
+        options.keys,
+        options.dim,
+        options.requests,
+        options.search_ratio * 100.0,
+        options.k
+    );
+    println!(
+        "{:<10} {:>9} {:>7} {:>10} {:>8} {:>8} {:>8} {:>8}",
+        "operation", "requests", "errors", "req/s", "p50_ms", "p90_ms", "p99_ms", "max_ms"
+    );
+    let started = Instant::now();
+    let samples = load(client.clone(), options.clone()).await;
+    report("load", samples, started.elapsed());
+
+    let started = Instant::now();
+    let (inserts, searches) = mixed(client, options).await;
+    let elapsed = started.elapsed();
+    report("insert", inserts, elapsed);
+    report("knn", searches, elapsed);
+}
@@ -0,0 +1,480 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+/// The commands offered by tab completion.
+const COMMANDS: &[&str] = &[
+    "aggregate",
+    "alias",
+    "auth",
+    "backup",
+    "bench",
+    "binsert",
+    "changes",
+    "cluster",
+    "collection",
+    "compact",
+    "config",
+    "count",
+    "del",
+    "delprefix",
+    "describe",
+    "dim",
+    "dump",
+    "embed",
+    "eval",
+    "exit",
+    "explain",
+    "export",
+    "format",
+    "get",
+    "health",
+    "hello",
+    "help",
+    "import",
+    "index",
+    "insert",
+    "insert_text",
+    "knn",
+    "knn_in",
+    "metric",
+    "monitor",
+    "named_insert",
+    "ping",
+    "pipeline",
+    "query_text",
+    "quit",
+    "randomkey",
+    "reduce",
+    "remove",
+    "replicaof",
+    "restore",
+    "sample",
+    "scan",
+    "search_text",
+    "select",
+    "shutdown",
+    "slowlog",
+    "snapshots",
+    "stats",
+    "subscribe",
+    "sync",
+    "ttl",
+    "vadd",
+    "vcosine",
+    "vscale",
+    "vsub",
+    "warmup",
+];
+
+/// The subcommands offered by tab completion after a command.
+const SUBCOMMANDS: &[(&str, &[&str])] = &[
+    ("aggregate", &["mean", "sum"]),
+    ("backup", &["now"]),
+    ("bench", &["recall"]),
+    ("cluster", &["nodes"]),
+    ("collection", &["stats"]),
+    ("config", &["get", "set"]),
+    ("explain", &["knn"]),
+    ("export", &["jsonl", "npy", "vec"]),
+    ("import", &["csv", "jsonl", "npy"]),
+    ("index", &["create", "drop", "list", "rebuild", "status"]),
+    ("metric", &["list", "upload"]),
+    ("pipeline", &["drop", "list", "set"]),
+    ("reduce", &["drop", "list", "pca", "random"]),
+    ("restore", &["until"]),
+    ("shutdown", &["nosave"]),
+    ("slowlog", &["get", "len", "reset"]),
+    ("snapshots", &["delete", "info", "list"]),
+    ("stats", &["dims", "reset"]),
+    ("ttl", &["drop", "list", "set"]),
+];
+
+/// How long to wait for more lines once the first line of a response has arrived.
+///
+/// Most responses fit on a single line, but some, such as `config get` or the
+/// errors of an import, span several lines without announcing how many.
+const RESPONSE_WAIT: Duration = Duration::from_millis(50);
+
+/// The number of vector components shown in a table before the rest is elided.
+const MAX_TABLE_COMPONENTS: usize = 8;
+
+/// A connection to a Vemcache server.
+struct Connection {
+    addr: String,
+    // The token the connection authenticates with, again after reconnecting
+    token: Option<String>,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(addr: &str, token: Option<String>) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let mut connection = Self {
+            addr: addr.to_string(),
+            token,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        if let Some(token) = connection.token.clone() {
+            let response = connection.request(&format!("auth {}", quote(&token)))?;
+            if response[0] != "OK" {
+                return Err(io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    response.join("\n"),
+                ));
+            }
+        }
+        Ok(connection)
+    }
+
+    /// Sends a command, reconnecting once if the server closed the connection,
+    /// and returns the lines of the response.
+    fn send(&mut self, command: &str) -> io::Result<Vec<String>> {
+        match self.request(command) {
+            Ok(response) => Ok(response),
+            Err(_) => {
+                *self = Self::open(&self.addr, self.token.clone())?;
+                self.request(command)
+            }
+        }
+    }
+
+    fn request(&mut self, command: &str) -> io::Result<Vec<String>> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+
+        self.reader.get_ref().set_read_timeout(None)?;
+        let first = self
+            .read_line()?
+            .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "Connection closed"))?;
+        let mut response = vec![first];
+
+        // Responses with a header announce how many lines follow
+        let count = match response[0].split_once(": ") {
+            Some((
+                "Neighbors" | "Sample" | "Changes" | "Keys" | "Indexes" | "Builds" | "Slowlog"
+                | "Stats" | "Dims" | "Reductions" | "Metrics" | "Pipelines",
+                count,
+            )) => count.parse::<usize>().ok(),
+            Some(("Sync", _)) => Some(1),
+            _ => None,
+        };
+        match count {
+            Some(count) => {
+                for _ in 0..count {
+                    if let Some(line) = self.read_line()? {
+                        response.push(line);
+                    }
+                }
+            }
+            None => {
+                self.reader
+                    .get_ref()
+                    .set_read_timeout(Some(RESPONSE_WAIT))?;
+                loop {
+                    match self.read_line() {
+                        Ok(Some(line)) => response.push(line),
+                        Ok(None) => break,
+                        Err(err)
+                            if matches!(
+                                err.kind(),
+                                ErrorKind::WouldBlock | ErrorKind::TimedOut
+                            ) =>
+                        {
+                            break
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    /// Sends a command streaming lines, `monitor` or `subscribe`, and prints the lines
+    /// as they arrive, until the server closes the connection or the CLI is
+    /// interrupted.
+    fn stream(&mut self, command: &str) -> io::Result<()> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+        self.reader.get_ref().set_read_timeout(None)?;
+        while let Some(line) = self.read_line()? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end().to_string()))
+    }
+}
+
+/// Completes command names, and the subcommands of commands that have them.
+struct CommandCompleter;
+
+impl Completer for CommandCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let words = line[..start].split_whitespace().collect::<Vec<&str>>();
+        let candidates: &[&str] = match words.as_slice() {
+            [] => COMMANDS,
+            [command] => SUBCOMMANDS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(command))
+                .map_or(&[], |(_, subcommands)| subcommands),
+            _ => &[],
+        };
+        let prefix = line[start..].to_lowercase();
+        let matches = candidates
+            .iter()
+            .filter(|candidate| candidate.starts_with(&prefix))
+            .map(|candidate| candidate.to_string())
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+impl Helper for CommandCompleter {}
+
+/// Prints a response, formatting nearest neighbors and sampled vectors as a table.
+fn print_response(response: &[String]) {
+    let empty = match response[0].split_once(": ") {
+        Some(("Neighbors", _)) => "(no neighbors)",
+        Some(("Sample", _)) => "(no vectors)",
+        _ => {
+            for line in response {
+                println!("{}", line);
+            }
+            return;
+        }
+    };
+
+    let rows = response[1..]
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let (id, vector) = line
+                .strip_prefix("ID: ")
+                .and_then(|line| line.split_once(", Vector: "))
+                .unwrap_or(("", line));
+            ((index + 1).to_string(), id.to_string(), elide(vector))
+        })
+        .collect::<Vec<(String, String, String)>>();
+    if rows.is_empty() {
+        println!("{}", empty);
+        return;
+    }
+
+    let rank_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0).max(1);
+    let id_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0).max(2);
+    println!("{:>rank_width$} | {:id_width$} | Vector", "#", "ID");
+    println!(
+        "{}-+-{}-+-{}",
+        "-".repeat(rank_width),
+        "-".repeat(id_width),
+        "-".repeat(6)
+    );
+    for (rank, id, vector) in rows {
+        println!("{:>rank_width$} | {:id_width$} | {}", rank, id, vector);
+    }
+}
+
+/// Shortens a vector formatted as `[1.0, 2.0, ...]` to its first components.
+fn elide(vector: &str) -> String {
+    let components = vector
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(", ")
+        .collect::<Vec<&str>>();
+    if components.len() <= MAX_TABLE_COMPONENTS {
+        return vector.to_string();
+    }
+    format!(
+        "[{}, ...] ({} dims)",
+        components[..MAX_TABLE_COMPONENTS].join(", "),
+        components.len()
+    )
+}
+
+/// Quotes a token so it is sent as a single argument, whatever it holds.
+fn quote(token: &str) -> String {
+    format!("\"{}\"", token.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".vemcache_history"))
+}
+
+fn print_usage() {
+    println!("Usage: vemcache-cli [--host <host>] [--port <port>] [--token <token>] [command...]");
+    println!();
+    println!("Starts an interactive session with a Vemcache server, or runs a single");
+    println!("command if one is given. The token, also read from VEMCACHE_TOKEN, is");
+    println!("used to authenticate if the server requires it.");
+}
+
+fn main() {
+    let mut host = "127.0.0.1".to_string();
+    let mut port = "7070".to_string();
+    let mut token = std::env::var("VEMCACHE_TOKEN").ok();
+    let mut command = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" | "-h" => host = args.next().unwrap_or(host),
+            "--port" | "-p" => port = args.next().unwrap_or(port),
+            "--token" => token = args.next().or(token),
+            "--help" => {
+                print_usage();
+                return;
+            }
+            _ => command.push(arg),
+        }
+    }
+
+    let addr = format!("{}:{}", host, port);
+    let mut connection = match Connection::open(&addr, token) {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Could not connect to {}: {}", addr, err);
+            std::process::exit(1);
+        }
+    };
+
+    let is_stream = |command: &str| {
+        let mut words = command.split_whitespace();
+        match words.next().map(str::to_lowercase).as_deref() {
+            Some("monitor") => words.next().is_none(),
+            Some("subscribe") => words.next().is_some(),
+            _ => false,
+        }
+    };
+    if !command.is_empty() {
+        if is_stream(&command.join(" ")) {
+            if let Err(err) = connection.stream(&command.join(" ")) {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        match connection.send(&command.join(" ")) {
+            Ok(response) => print_response(&response),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut editor = match Editor::<CommandCompleter, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("Could not start the line editor: {}", err);
+            std::process::exit(1);
+        }
+    };
+    editor.set_helper(Some(CommandCompleter));
+    let history = history_path();
+    if let Some(history) = &history {
+        // The history file does not exist on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let prompt = format!("{}> ", addr);
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let name = line
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        // Tokens are kept out of the history file
+        if name != "auth" {
+            let _ = editor.add_history_entry(line);
+        }
+        match name.as_str() {
+            "quit" | "exit" => break,
+            "bulk" | "arrow" => {
+                println!("Binary transfers are not supported by the CLI");
+                continue;
+            }
+            "format"
+                if line
+                    .split_whitespace()
+                    .nth(1)
+                    .is_some_and(|format| !format.eq_ignore_ascii_case("text")) =>
+            {
+                println!("Binary formats are not supported by the CLI");
+                continue;
+            }
+            "metric"
+                if line
+                    .split_whitespace()
+                    .nth(1)
+                    .is_some_and(|subcommand| subcommand.eq_ignore_ascii_case("upload")) =>
+            {
+                println!("Binary transfers are not supported by the CLI");
+                continue;
+            }
+            "monitor" | "subscribe" if is_stream(line) => {
+                // Streaming lasts until the CLI is interrupted
+                if let Err(err) = connection.stream(line) {
+                    eprintln!("Error: {}", err);
+                }
+                break;
+            }
+            _ => {}
+        }
+        match connection.send(line) {
+            Ok(response) => print_response(&response),
+            Err(err) => eprintln!("Error: {}", err),
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("Could not save the command history: {}", err);
+        }
+    }
+}
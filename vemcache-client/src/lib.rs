@@ -0,0 +1,411 @@
+//! An async client for the Vemcache vector database.
+//!
+//! The client speaks the Vemcache text protocol over TCP, keeps a pool of open
+//! connections so concurrent tasks don't wait on each other, and transparently
+//! reconnects when a connection is dropped by the server.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vemcache_client::Client;
+//!
+//! # async fn example() -> vemcache_client::Result<()> {
+//! let client = Client::connect("127.0.0.1:7070").await?;
+//! client.insert("vector1", &[0.5, 0.7, 0.2]).await?;
+//! client.insert("vector2", &[0.1, 0.9, 0.4]).await?;
+//!
+//! let vector = client.get("vector1").await?;
+//! assert_eq!(vector, Some(vec![0.5, 0.7, 0.2]));
+//!
+//! for neighbor in client.knn("vector1", 2).await? {
+//!     println!("{}: {:?}", neighbor.id, neighbor.vector);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// The default number of idle connections kept open by a client.
+pub const DEFAULT_POOL_SIZE: usize = 8;
+
+/// The default time to wait for a connection to be established.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The errors returned by the client.
+#[derive(Debug)]
+pub enum Error {
+    /// The server could not be reached, or the connection failed.
+    Io(io::Error),
+    /// The server rejected the command.
+    Server(String),
+    /// The key of the command does not exist.
+    KeyNotFound,
+    /// The server sent a response the client does not understand.
+    Protocol(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Server(message) => write!(f, "{}", message),
+            Error::KeyNotFound => write!(f, "Key not found"),
+            Error::Protocol(response) => write!(f, "Unexpected response: {}", response),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// The result type of client operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A vector returned by a nearest-neighbor search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Neighbor {
+    /// The key of the vector.
+    pub id: String,
+    /// The components of the vector.
+    pub vector: Vec<f32>,
+}
+
+/// A pooled, reconnecting connection to a Vemcache server.
+///
+/// The client is cheap to share between tasks, e.g. behind an `Arc`. Every
+/// operation borrows an idle connection from the pool, or opens a new one if none
+/// is available, and returns it to the pool once the response has been read.
+pub struct Client {
+    addr: String,
+    pool: Mutex<Vec<Connection>>,
+    pool_size: usize,
+    connect_timeout: Duration,
+    token: Option<String>,
+}
+
+impl Client {
+    /// Connects to the server at `addr` (e.g. `"127.0.0.1:7070"`) with the default options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server cannot be reached.
+    pub async fn connect(addr: &str) -> Result<Self> {
+        Self::builder(addr).connect().await
+    }
+
+    /// Returns a builder to configure the client before connecting.
+    pub fn builder(addr: &str) -> ClientBuilder {
+        ClientBuilder {
+            addr: addr.to_string(),
+            pool_size: DEFAULT_POOL_SIZE,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            token: None,
+        }
+    }
+
+    /// Checks that the server is reachable.
+    pub async fn ping(&self) -> Result<()> {
+        let response = self.request("ping".to_string(), false).await?;
+        expect(&response[0], "pong")
+    }
+
+    /// Inserts a vector with the given key, replacing any vector stored under it.
+    pub async fn insert(&self, key: &str, vector: &[f32]) -> Result<()> {
+        let values = vector
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let response = self
+            .request(format!("named_insert {} {}", quote(key), values), false)
+            .await?;
+        expect(&response[0], "OK")
+    }
+
+    /// Returns the vector stored under `key`, if any.
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<f32>>> {
+        let response = self.request(format!("get {}", quote(key)), false).await?;
+        match response[0].as_str() {
+            "null" => Ok(None),
+            line => parse_vector(line).map(Some),
+        }
+    }
+
+    /// Removes the vector stored under `key`, if any.
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        let response = self
+            .request(format!("remove {}", quote(key)), false)
+            .await?;
+        expect(&response[0], "OK")
+    }
+
+    /// Returns the `k` vectors nearest to the vector stored under `key`, nearest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::KeyNotFound` if there is no vector stored under `key`.
+    pub async fn knn(&self, key: &str, k: usize) -> Result<Vec<Neighbor>> {
+        let response = self
+            .request(format!("knn {} {}", quote(key), k), true)
+            .await?;
+        response[1..]
+            .iter()
+            .map(|line| {
+                let (id, vector) = line
+                    .strip_prefix("ID: ")
+                    .and_then(unquote)
+                    .and_then(|(id, rest)| Some((id, rest.strip_prefix(", Vector: ")?)))
+                    .ok_or_else(|| Error::Protocol(line.clone()))?;
+                Ok(Neighbor {
+                    id,
+                    vector: parse_vector(vector)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the cosine similarity of the vectors stored under `key1` and `key2`.
+    pub async fn cosine_similarity(&self, key1: &str, key2: &str) -> Result<f32> {
+        let response = self
+            .request(format!("vcosine {} {}", quote(key1), quote(key2)), false)
+            .await?;
+        response[0]
+            .strip_prefix("Cosine Similarity: ")
+            .and_then(|similarity| similarity.parse().ok())
+            .ok_or_else(|| Error::Server(response[0].clone()))
+    }
+
+    /// Sends `command` and returns the lines of the response.
+    ///
+    /// If the pooled connection turns out to be closed, the command is retried once
+    /// on a new connection. Every command sent by the client is idempotent, so a
+    /// retry cannot apply a change twice.
+    async fn request(&self, command: String, multi_line: bool) -> Result<Vec<String>> {
+        let pooled = self.pool.lock().await.pop();
+        let mut connection = match pooled {
+            Some(mut connection) => match connection.request(&command, multi_line).await {
+                Ok(response) => return self.finish(connection, response).await,
+                Err(_) => self.open().await?,
+            },
+            None => self.open().await?,
+        };
+        let response = connection.request(&command, multi_line).await?;
+        self.finish(connection, response).await
+    }
+
+    /// Returns a connection to the pool and turns error responses into errors.
+    async fn finish(&self, connection: Connection, response: Vec<String>) -> Result<Vec<String>> {
+        let mut pool = self.pool.lock().await;
+        if pool.len() < self.pool_size {
+            pool.push(connection);
+        }
+        drop(pool);
+
+        match response[0].as_str() {
+            "Key not found" => Err(Error::KeyNotFound),
+            line if line.starts_with("Error: ") => {
+                Err(Error::Server(line["Error: ".len()..].to_string()))
+            }
+            _ => Ok(response),
+        }
+    }
+
+    async fn open(&self) -> Result<Connection> {
+        let stream = tokio::time::timeout(self.connect_timeout, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Connection timed out"))??;
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        let mut connection = Connection {
+            reader: BufReader::new(reader),
+            writer,
+        };
+        if let Some(token) = &self.token {
+            let response = connection
+                .request(&format!("auth {}", quote(token)), false)
+                .await?;
+            if let Some(message) = response[0].strip_prefix("Error: ") {
+                return Err(Error::Server(message.to_string()));
+            }
+        }
+        Ok(connection)
+    }
+}
+
+/// Configures a `Client` before connecting.
+pub struct ClientBuilder {
+    addr: String,
+    pool_size: usize,
+    connect_timeout: Duration,
+    token: Option<String>,
+}
+
+impl ClientBuilder {
+    /// Sets the maximum number of idle connections kept open.
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// Sets the time to wait for a connection to be established.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the token every connection authenticates with, for servers that
+    /// require one.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Connects to the server, failing early if it cannot be reached.
+    pub async fn connect(self) -> Result<Client> {
+        let client = Client {
+            addr: self.addr,
+            pool: Mutex::new(Vec::new()),
+            pool_size: self.pool_size,
+            connect_timeout: self.connect_timeout,
+            token: self.token,
+        };
+        let connection = client.open().await?;
+        client.pool.lock().await.push(connection);
+        Ok(client)
+    }
+}
+
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    /// Sends `command` and reads its response. Multi-line responses, such as the
+    /// response to `knn`, start with a `<Header>: <count>` line followed by `count` lines.
+    async fn request(&mut self, command: &str, multi_line: bool) -> io::Result<Vec<String>> {
+        self.writer
+            .write_all(format!("{}\n", command).as_bytes())
+            .await?;
+        let first = self.read_line().await?;
+        let count = if multi_line {
+            first
+                .split_once(": ")
+                .and_then(|(_, count)| count.parse::<usize>().ok())
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let mut response = vec![first];
+        for _ in 0..count {
+            response.push(self.read_line().await?);
+        }
+        Ok(response)
+    }
+
+    async fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed by the server",
+            ));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+/// Quotes a key so it is sent as a single argument, whatever it holds.
+fn quote(key: &str) -> String {
+    let mut quoted = String::with_capacity(key.len() + 2);
+    quoted.push('"');
+    for c in key.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reads a key at the start of `text`, as the server writes it in responses:
+/// as is, or in double quotes with escapes if it holds special characters.
+/// Returns the key and the rest of `text`.
+fn unquote(text: &str) -> Option<(String, &str)> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        let end = text.find(", ").unwrap_or(text.len());
+        return Some((text[..end].to_string(), &text[end..]));
+    };
+    let mut bytes = Vec::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((String::from_utf8(bytes).ok()?, &quoted[index + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => bytes.push(b'\n'),
+                'r' => bytes.push(b'\r'),
+                't' => bytes.push(b'\t'),
+                'x' => {
+                    let high = chars.next()?.1.to_digit(16)?;
+                    let low = chars.next()?.1.to_digit(16)?;
+                    bytes.push((high * 16 + low) as u8);
+                }
+                c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            },
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    None
+}
+
+fn expect(line: &str, expected: &str) -> Result<()> {
+    if line == expected {
+        Ok(())
+    } else {
+        Err(Error::Protocol(line.to_string()))
+    }
+}
+
+/// Parses a vector formatted as `[1.0, 2.0, 3.0]`.
+fn parse_vector(line: &str) -> Result<Vec<f32>> {
+    let values = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(|| Error::Protocol(line.to_string()))?;
+    if values.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    values
+        .split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| Error::Protocol(line.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+#ifndef VEMCACHE_H
+#define VEMCACHE_H
+
+/* Generated by cbindgen from vemcache-ffi/src/lib.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The outcome of a call.
+ */
+typedef enum VemcacheStatus {
+  /**
+   * The call succeeded.
+   */
+  VEMCACHE_STATUS_OK = 0,
+  /**
+   * There is no vector stored under the key.
+   */
+  VEMCACHE_STATUS_NOT_FOUND = 1,
+  /**
+   * A pointer was null, a key was not valid UTF-8, or a vector was empty or was
+   * rejected, like for holding NaN or infinite components.
+   */
+  VEMCACHE_STATUS_INVALID_ARGUMENT = 2,
+} VemcacheStatus;
+
+/**
+ * An in-memory vector database.
+ */
+typedef struct VemcacheDb VemcacheDb;
+
+/**
+ * A vector allocated by the library.
+ */
+typedef struct VemcacheVector {
+  /**
+   * The components of the vector.
+   */
+  float *data;
+  /**
+   * The number of components.
+   */
+  size_t len;
+} VemcacheVector;
+
+/**
+ * A vector returned by a nearest-neighbor search.
+ */
+typedef struct VemcacheNeighbor {
+  /**
+   * The NUL-terminated key of the vector.
+   */
+  char *key;
+  /**
+   * The components of the vector.
+   */
+  struct VemcacheVector vector;
+} VemcacheNeighbor;
+
+/**
+ * The result of a nearest-neighbor search, nearest first.
+ */
+typedef struct VemcacheNeighbors {
+  /**
+   * The neighbors.
+   */
+  struct VemcacheNeighbor *items;
+  /**
+   * The number of neighbors.
+   */
+  size_t len;
+} VemcacheNeighbors;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an empty database.
+ *
+ * The database must be released with `vemcache_free`.
+ */
+struct VemcacheDb *vemcache_new(void);
+
+/**
+ * Releases a database created by `vemcache_new`. Does nothing if `db` is null.
+ *
+ * # Safety
+ *
+ * `db` must be null or a database created by `vemcache_new` that was not freed yet.
+ */
+void vemcache_free(struct VemcacheDb *db);
+
+/**
+ * Returns the number of vectors stored in the database, or 0 if `db` is null.
+ *
+ * # Safety
+ *
+ * `db` must be null or a valid database.
+ */
+size_t vemcache_len(const struct VemcacheDb *db);
+
+/**
+ * Inserts a copy of the `len` components at `data` under `key`, replacing any
+ * vector stored under it. Vectors with NaN or infinite components are rejected.
+ *
+ * # Safety
+ *
+ * `db` must be null or a valid database, `key` must be null or a NUL-terminated
+ * string, and `data` must be null or point to `len` readable floats.
+ */
+enum VemcacheStatus vemcache_insert(struct VemcacheDb *db,
+                                    const char *key,
+                                    const float *data,
+                                    size_t len);
+
+/**
+ * Removes the vector stored under `key`.
+ *
+ * # Safety
+ *
+ * `db` must be null or a valid database, and `key` must be null or a
+ * NUL-terminated string.
+ */
+enum VemcacheStatus vemcache_remove(struct VemcacheDb *db, const char *key);
+
+/**
+ * Copies the vector stored under `key` into `out`.
+ *
+ * On success, the vector must be released with `vemcache_vector_free`. `out` is
+ * left untouched otherwise.
+ *
+ * # Safety
+ *
+ * `db` must be null or a valid database, `key` must be null or a NUL-terminated
+ * string, and `out` must be null or writable.
+ */
+enum VemcacheStatus vemcache_get(const struct VemcacheDb *db,
+                                 const char *key,
+                                 struct VemcacheVector *out);
+
+/**
+ * Releases a vector returned by `vemcache_get`.
+ *
+ * # Safety
+ *
+ * `vector` must have been returned by `vemcache_get` and not freed yet.
+ */
+void vemcache_vector_free(struct VemcacheVector vector);
+
+/**
+ * Finds the `k` vectors nearest to the `len` components at `query` by euclidean
+ * distance, and writes them into `out`, nearest first.
+ *
+ * On success, the neighbors must be released with `vemcache_neighbors_free`. `out`
+ * is left untouched otherwise.
+ *
+ * # Safety
+ *
+ * `db` must be null or a valid database, `query` must be null or point to `len`
+ * readable floats, and `out` must be null or writable.
+ */
+enum VemcacheStatus vemcache_knn(const struct VemcacheDb *db,
+                                 const float *query,
+                                 size_t len,
+                                 size_t k,
+                                 struct VemcacheNeighbors *out);
+
+/**
+ * Releases the neighbors returned by `vemcache_knn`.
+ *
+ * # Safety
+ *
+ * `neighbors` must have been returned by `vemcache_knn` and not freed yet.
+ */
+void vemcache_neighbors_free(struct VemcacheNeighbors neighbors);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VEMCACHE_H */
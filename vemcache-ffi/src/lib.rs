@@ -0,0 +1,284 @@
+//! C bindings for the Vemcache engine.
+//!
+//! The functions declared in `include/vemcache.h` run the database in-process, so
+//! applications written in C, C++, Go, Swift or any other language with a C FFI can
+//! embed it without a server.
+//!
+//! A database is created with `vemcache_new` and released with `vemcache_free`.
+//! Keys are NUL-terminated UTF-8 strings. Vectors and neighbors returned by the
+//! library are owned by the caller, and must be released with `vemcache_vector_free`
+//! and `vemcache_neighbors_free` respectively.
+//!
+//! A database handle is not synchronized: calls that modify it must not run
+//! concurrently with any other call on the same handle.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use vemcache::Vemcache;
+
+/// An in-memory vector database.
+pub struct VemcacheDb {
+    db: Vemcache,
+}
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VemcacheStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// There is no vector stored under the key.
+    NotFound = 1,
+    /// A pointer was null, a key was not valid UTF-8, or a vector was empty or was
+    /// rejected, like for holding NaN or infinite components.
+    InvalidArgument = 2,
+}
+
+/// A vector allocated by the library.
+#[repr(C)]
+pub struct VemcacheVector {
+    /// The components of the vector.
+    pub data: *mut f32,
+    /// The number of components.
+    pub len: usize,
+}
+
+/// A vector returned by a nearest-neighbor search.
+#[repr(C)]
+pub struct VemcacheNeighbor {
+    /// The NUL-terminated key of the vector.
+    pub key: *mut c_char,
+    /// The components of the vector.
+    pub vector: VemcacheVector,
+}
+
+/// The result of a nearest-neighbor search, nearest first.
+#[repr(C)]
+pub struct VemcacheNeighbors {
+    /// The neighbors.
+    pub items: *mut VemcacheNeighbor,
+    /// The number of neighbors.
+    pub len: usize,
+}
+
+impl VemcacheVector {
+    fn new(vector: &[f32]) -> Self {
+        let len = vector.len();
+        let data = Box::into_raw(Box::<[f32]>::from(vector)) as *mut f32;
+        Self { data, len }
+    }
+
+    /// # Safety
+    ///
+    /// The vector must have been created by `VemcacheVector::new` and not freed yet.
+    unsafe fn free(self) {
+        if !self.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                self.data, self.len,
+            )));
+        }
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 key, or returns `None` if it is null or not UTF-8.
+///
+/// # Safety
+///
+/// `key` must be null or point to a NUL-terminated string.
+unsafe fn read_key(key: *const c_char) -> Option<String> {
+    if key.is_null() {
+        return None;
+    }
+    CStr::from_ptr(key).to_str().ok().map(str::to_string)
+}
+
+/// Reads a vector of `len` components, or returns `None` if it is null or empty.
+///
+/// # Safety
+///
+/// `data` must be null or point to `len` readable floats.
+unsafe fn read_vector(data: *const f32, len: usize) -> Option<Vec<f32>> {
+    if data.is_null() || len == 0 {
+        return None;
+    }
+    Some(std::slice::from_raw_parts(data, len).to_vec())
+}
+
+/// Creates an empty database.
+///
+/// The database must be released with `vemcache_free`.
+#[no_mangle]
+pub extern "C" fn vemcache_new() -> *mut VemcacheDb {
+    Box::into_raw(Box::new(VemcacheDb {
+        db: Vemcache::new(),
+    }))
+}
+
+/// Releases a database created by `vemcache_new`. Does nothing if `db` is null.
+///
+/// # Safety
+///
+/// `db` must be null or a database created by `vemcache_new` that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_free(db: *mut VemcacheDb) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Returns the number of vectors stored in the database, or 0 if `db` is null.
+///
+/// # Safety
+///
+/// `db` must be null or a valid database.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_len(db: *const VemcacheDb) -> usize {
+    db.as_ref().map_or(0, |db| db.db.len())
+}
+
+/// Inserts a copy of the `len` components at `data` under `key`, replacing any
+/// vector stored under it. Vectors with NaN or infinite components are rejected.
+///
+/// # Safety
+///
+/// `db` must be null or a valid database, `key` must be null or a NUL-terminated
+/// string, and `data` must be null or point to `len` readable floats.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_insert(
+    db: *mut VemcacheDb,
+    key: *const c_char,
+    data: *const f32,
+    len: usize,
+) -> VemcacheStatus {
+    let (Some(db), Some(key), Some(vector)) = (db.as_mut(), read_key(key), read_vector(data, len))
+    else {
+        return VemcacheStatus::InvalidArgument;
+    };
+    match db.db.try_insert_with_key(key, vector) {
+        Ok(()) => VemcacheStatus::Ok,
+        Err(_) => VemcacheStatus::InvalidArgument,
+    }
+}
+
+/// Removes the vector stored under `key`.
+///
+/// # Safety
+///
+/// `db` must be null or a valid database, and `key` must be null or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_remove(
+    db: *mut VemcacheDb,
+    key: *const c_char,
+) -> VemcacheStatus {
+    let (Some(db), Some(key)) = (db.as_mut(), read_key(key)) else {
+        return VemcacheStatus::InvalidArgument;
+    };
+    match db.db.remove(key) {
+        Some(_) => VemcacheStatus::Ok,
+        None => VemcacheStatus::NotFound,
+    }
+}
+
+/// Copies the vector stored under `key` into `out`.
+///
+/// On success, the vector must be released with `vemcache_vector_free`. `out` is
+/// left untouched otherwise.
+///
+/// # Safety
+///
+/// `db` must be null or a valid database, `key` must be null or a NUL-terminated
+/// string, and `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_get(
+    db: *const VemcacheDb,
+    key: *const c_char,
+    out: *mut VemcacheVector,
+) -> VemcacheStatus {
+    let (Some(db), Some(key)) = (db.as_ref(), read_key(key)) else {
+        return VemcacheStatus::InvalidArgument;
+    };
+    if out.is_null() {
+        return VemcacheStatus::InvalidArgument;
+    }
+    match db.db.get(key) {
+        Some(vector) => {
+            out.write(VemcacheVector::new(&vector));
+            VemcacheStatus::Ok
+        }
+        None => VemcacheStatus::NotFound,
+    }
+}
+
+/// Releases a vector returned by `vemcache_get`.
+///
+/// # Safety
+///
+/// `vector` must have been returned by `vemcache_get` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_vector_free(vector: VemcacheVector) {
+    vector.free();
+}
+
+/// Finds the `k` vectors nearest to the `len` components at `query` by euclidean
+/// distance, and writes them into `out`, nearest first.
+///
+/// On success, the neighbors must be released with `vemcache_neighbors_free`. `out`
+/// is left untouched otherwise.
+///
+/// # Safety
+///
+/// `db` must be null or a valid database, `query` must be null or point to `len`
+/// readable floats, and `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_knn(
+    db: *const VemcacheDb,
+    query: *const f32,
+    len: usize,
+    k: usize,
+    out: *mut VemcacheNeighbors,
+) -> VemcacheStatus {
+    let (Some(db), Some(query)) = (db.as_ref(), read_vector(query, len)) else {
+        return VemcacheStatus::InvalidArgument;
+    };
+    if out.is_null() {
+        return VemcacheStatus::InvalidArgument;
+    }
+    let neighbors = db
+        .db
+        .k_nearest_neighbors(&query, k)
+        .into_iter()
+        .map(|(key, vector)| VemcacheNeighbor {
+            // Keys are inserted from C strings, so they never hold a NUL byte
+            key: CString::new(key).unwrap_or_default().into_raw(),
+            vector: VemcacheVector::new(&vector),
+        })
+        .collect::<Box<[VemcacheNeighbor]>>();
+    let len = neighbors.len();
+    out.write(VemcacheNeighbors {
+        items: Box::into_raw(neighbors) as *mut VemcacheNeighbor,
+        len,
+    });
+    VemcacheStatus::Ok
+}
+
+/// Releases the neighbors returned by `vemcache_knn`.
+///
+/// # Safety
+///
+/// `neighbors` must have been returned by `vemcache_knn` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vemcache_neighbors_free(neighbors: VemcacheNeighbors) {
+    if neighbors.items.is_null() {
+        return;
+    }
+    let items = Box::from_raw(ptr::slice_from_raw_parts_mut(
+        neighbors.items,
+        neighbors.len,
+    ));
+    for neighbor in items.into_vec() {
+        drop(CString::from_raw(neighbor.key));
+        neighbor.vector.free();
+    }
+}
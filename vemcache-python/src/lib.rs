@@ -0,0 +1,132 @@
+//! Python bindings for the Vemcache engine.
+//!
+//! The `vemcache` Python module runs the database in-process, without a server.
+//! Vectors are accepted as NumPy arrays or any sequence of numbers, and returned
+//! as `float32` NumPy arrays.
+
+use numpy::{AllowTypeChange, PyArray1, PyArrayLike1, PyArrayLike2};
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+
+use vemcache::Vemcache;
+
+/// An in-memory vector database.
+#[pyclass(name = "Vemcache")]
+struct PyVemcache {
+    db: Vemcache,
+}
+
+#[pymethods]
+impl PyVemcache {
+    #[new]
+    fn new() -> Self {
+        Self {
+            db: Vemcache::new(),
+        }
+    }
+
+    /// Inserts a vector under `key`, replacing any vector stored under it. Raises
+    /// `ValueError` if the vector is rejected, like for holding NaN or infinite
+    /// components.
+    fn insert(
+        &mut self,
+        key: String,
+        vector: PyArrayLike1<'_, f32, AllowTypeChange>,
+    ) -> PyResult<()> {
+        self.db
+            .try_insert_with_key(key, vector.as_array().to_vec())
+            .map_err(PyValueError::new_err)
+    }
+
+    /// Inserts the rows of a 2-D array, each under the key at the same position in `keys`.
+    /// Stops with `ValueError` at the first rejected row, keeping the rows before it.
+    fn insert_many(
+        &mut self,
+        keys: Vec<String>,
+        vectors: PyArrayLike2<'_, f32, AllowTypeChange>,
+    ) -> PyResult<()> {
+        let vectors = vectors.as_array();
+        if vectors.nrows() != keys.len() {
+            return Err(PyValueError::new_err(format!(
+                "Found {} keys for {} vectors",
+                keys.len(),
+                vectors.nrows()
+            )));
+        }
+        for (key, vector) in keys.into_iter().zip(vectors.rows()) {
+            self.db
+                .try_insert_with_key(key, vector.to_vec())
+                .map_err(PyValueError::new_err)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the vector stored under `key`, or `None`.
+    fn get<'py>(&self, py: Python<'py>, key: String) -> Option<Bound<'py, PyArray1<f32>>> {
+        self.db
+            .get(key)
+            .map(|vector| PyArray1::from_slice(py, &vector))
+    }
+
+    /// Removes the vector stored under `key` and returns whether it existed.
+    fn remove(&mut self, key: String) -> bool {
+        self.db.remove(key).is_some()
+    }
+
+    /// Returns the `k` nearest neighbors of `query` as `(key, vector)` pairs,
+    /// nearest first. `query` is either the key of a stored vector or a vector.
+    fn knn<'py>(
+        &self,
+        py: Python<'py>,
+        query: &Bound<'py, PyAny>,
+        k: usize,
+    ) -> PyResult<Vec<(String, Bound<'py, PyArray1<f32>>)>> {
+        let query = match query.extract::<String>() {
+            Ok(key) => self
+                .db
+                .get(key.clone())
+                .map(|vector| vector.to_vec())
+                .ok_or_else(|| PyKeyError::new_err(key))?,
+            Err(_) => query
+                .extract::<PyArrayLike1<'_, f32, AllowTypeChange>>()?
+                .as_array()
+                .to_vec(),
+        };
+        Ok(self
+            .db
+            .k_nearest_neighbors(&query, k)
+            .into_iter()
+            .map(|(key, vector)| (key, PyArray1::from_slice(py, &vector)))
+            .collect())
+    }
+
+    /// Writes the database to a JSON file, compressed if the path ends in `.gz` or `.zst`.
+    fn dump(&self, path: &str) -> PyResult<()> {
+        self.db
+            .snapshot()
+            .dump(path)
+            .map_err(|err| PyIOError::new_err(err.to_string()))
+    }
+
+    /// Loads the vectors of a dump created by `dump` or by the server.
+    fn load(&mut self, path: &str) -> PyResult<()> {
+        self.db
+            .load(path)
+            .map_err(|err| PyIOError::new_err(err.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.db.len()
+    }
+
+    fn __contains__(&self, key: String) -> bool {
+        self.db.get(key).is_some()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "vemcache")]
+fn py_vemcache(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyVemcache>()?;
+    Ok(())
+}